@@ -0,0 +1,72 @@
+/*
+ * C interface of fast-pinball-utilities, built with the `ffi` feature:
+ *
+ *     cargo rustc --release --lib --features ffi --crate-type cdylib
+ *
+ * (or `--crate-type staticlib`). Strings are UTF-8 and NUL-terminated. Calls that fail
+ * return NULL or FAST_ERROR and leave a message for fast_last_error(). Lists are
+ * allocated by the library and must be released with the matching fast_free_* call.
+ */
+
+#ifndef FAST_PINBALL_H
+#define FAST_PINBALL_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define FAST_OK 0
+#define FAST_ERROR (-1)
+
+/* How a flash ended */
+#define FAST_FLASH_VERIFIED 0   /* the board reports the expected version */
+#define FAST_FLASH_UNVERIFIED 1 /* sent, but there was no version to check against */
+#define FAST_FLASH_MISMATCH 2   /* the board reports another version afterwards */
+#define FAST_FLASH_FAILED 3     /* the firmware could not be found, read or sent */
+#define FAST_FLASH_CANCELLED 4  /* stopped before the firmware was sent in full */
+
+typedef struct FastPinballMonitor FastPinballMonitor;
+
+typedef struct FastExpBoard {
+    char *port;
+    char *address;
+    char *board_name;
+    char *version;
+    bool is_controller;
+} FastExpBoard;
+
+typedef struct FastNetBoard {
+    char *node_id;
+    char *node_name;
+    char *firmware;
+    int switch_count; /* -1 when not reported */
+    int driver_count; /* -1 when not reported */
+} FastNetBoard;
+
+/* Message of the last failed call on this thread, or NULL; valid until the next call. */
+const char *fast_last_error(void);
+
+/* Connect; NULL ports are discovered. Returns NULL when the hardware is not found. */
+FastPinballMonitor *fast_connect(const char *net_port, const char *exp_port);
+void fast_disconnect(FastPinballMonitor *fpm);
+
+int fast_list_exp_boards(FastPinballMonitor *fpm, FastExpBoard **boards, size_t *count);
+void fast_free_exp_boards(FastExpBoard *boards, size_t count);
+
+int fast_list_net_boards(FastPinballMonitor *fpm, FastNetBoard **boards, size_t *count);
+void fast_free_net_boards(FastNetBoard *boards, size_t count);
+
+/* Flash a firmware file; a NULL expected_version is taken from the file name.
+ * Returns a FAST_FLASH_* outcome, or FAST_ERROR. */
+int fast_flash_exp(FastPinballMonitor *fpm, const char *address, const char *file,
+                   const char *expected_version);
+int fast_flash_net(FastPinballMonitor *fpm, const char *file, const char *expected_version);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* FAST_PINBALL_H */
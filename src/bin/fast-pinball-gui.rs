@@ -0,0 +1,581 @@
+// A window over the library for machine owners who never open a terminal: the connected
+// boards with their firmware, buttons to rescan, update and light a board's LEDs, and the
+// progress of a running flash. Built with the `gui` feature:
+//
+//     cargo run --release --features gui --bin fast-pinball-gui
+//
+// The hardware is driven from a worker thread, so the window keeps drawing while a scan
+// or flash runs; the two talk over channels, and the Stop button cancels the worker's
+// scan or flash through a shared `CancelToken`.
+
+#![cfg_attr(windows, windows_subsystem = "windows")]
+
+use eframe::egui;
+use fast_pinball_utilities::commands::utils::Target;
+use fast_pinball_utilities::hooks::{self, Hook};
+use fast_pinball_utilities::{
+    CancelToken, ExpBoardInfo, FastPinballMonitor, FlashEvent, FlashOutcome, NetBoardInfo,
+    PinnedPorts, board_map, config, firmware_repo,
+};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::time::Duration;
+
+fn main() -> eframe::Result<()> {
+    let mut config = config::default_path()
+        .map(|p| config::load(&p))
+        .transpose()
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to load config: {}", e);
+            None
+        })
+        .unwrap_or_default();
+    if let Some(name) = config.profile.clone()
+        && let Err(e) = config.select_profile(&name)
+    {
+        eprintln!("Failed to load config: {}", e);
+    }
+    // Nobody reads the terminal; progress is drawn in the window instead
+    config.quiet = true;
+    config::init(config);
+    if let Ok(map) = board_map::BoardMap::load() {
+        board_map::init(map);
+    }
+
+    let (requests, worker_requests) = channel();
+    let (worker_events, events) = channel();
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_title("FAST Pinball Utilities")
+            .with_inner_size([760.0, 560.0]),
+        ..Default::default()
+    };
+    eframe::run_native(
+        "FAST Pinball Utilities",
+        options,
+        Box::new(move |cc| {
+            let ctx = cc.egui_ctx.clone();
+            let cancel = CancelToken::new();
+            let worker_cancel = cancel.clone();
+            std::thread::spawn(move || {
+                Worker::new(worker_events, ctx, worker_cancel).run(worker_requests)
+            });
+            let _ = requests.send(Request::Scan);
+            Ok(Box::new(App::new(requests, events, cancel)))
+        }),
+    )
+}
+
+/// What the window asks the worker to do.
+enum Request {
+    Scan,
+    Update(Vec<Flash>),
+    TestLeds { address: String },
+    DownloadFirmware,
+}
+
+/// One board to flash to `version`.
+#[derive(Clone)]
+struct Flash {
+    board: String,
+    /// EXP address, or `None` for the NET processor.
+    address: Option<String>,
+    version: String,
+}
+
+/// What the worker reports back.
+enum Event {
+    Boards {
+        exp: Vec<ExpBoardInfo>,
+        net: Vec<NetBoardInfo>,
+    },
+    Log(String),
+    Flash(FlashEvent),
+    Idle,
+}
+
+/// Owns the hardware connection and runs one request at a time.
+struct Worker {
+    fpm: Option<FastPinballMonitor>,
+    events: Sender<Event>,
+    ctx: egui::Context,
+    cancel: CancelToken,
+}
+
+impl Worker {
+    fn new(events: Sender<Event>, ctx: egui::Context, cancel: CancelToken) -> Self {
+        Self {
+            fpm: None,
+            events,
+            ctx,
+            cancel,
+        }
+    }
+
+    fn run(mut self, requests: Receiver<Request>) {
+        for request in requests {
+            match request {
+                Request::Scan => self.scan(),
+                Request::Update(flashes) => self.update(&flashes),
+                Request::TestLeds { address } => self.test_leds(&address),
+                Request::DownloadFirmware => self.download_firmware(),
+            }
+            self.send(Event::Idle);
+        }
+    }
+
+    fn send(&self, event: Event) {
+        let _ = self.events.send(event);
+        self.ctx.request_repaint();
+    }
+
+    fn log(&self, line: impl Into<String>) {
+        self.send(Event::Log(line.into()));
+    }
+
+    /// The connection, opening it first if need be.
+    fn connect(&mut self) -> Option<&mut FastPinballMonitor> {
+        if self.fpm.is_none() {
+            let config = config::get();
+            let pins = PinnedPorts {
+                net: config.net_port.clone(),
+                exp: config.exp_ports.clone(),
+                seg: config.seg_port.clone(),
+                aud: config.aud_port.clone(),
+                dsp: config.dsp_port.clone(),
+            };
+            match FastPinballMonitor::connect_cancellable(
+                pins,
+                Some(Duration::ZERO),
+                self.cancel.clone(),
+            ) {
+                Ok(fpm) => self.fpm = Some(fpm),
+                Err(e) => self.log(e),
+            }
+        }
+        self.fpm.as_mut()
+    }
+
+    fn scan(&mut self) {
+        let Some(fpm) = self.connect() else {
+            self.send(Event::Boards {
+                exp: Vec::new(),
+                net: Vec::new(),
+            });
+            return;
+        };
+        let scan = fpm
+            .try_list_connected_exp_boards()
+            .and_then(|exp| Ok((exp, fpm.try_list_connected_net_boards()?)));
+        let Ok((exp, net)) = scan else {
+            self.log("Scan stopped.");
+            return;
+        };
+        let mut net: Vec<(usize, NetBoardInfo)> = net.into_iter().collect();
+        net.sort_by_key(|(index, _)| *index);
+        let net = net.into_iter().map(|(_, n)| n).collect();
+        self.send(Event::Boards { exp, net });
+    }
+
+    fn update(&mut self, flashes: &[Flash]) {
+        // The hooks expect the ports to be free, as they are around the CLI's flashes
+        self.fpm = None;
+        let env = [("FAST_COMMAND", "gui".to_string())];
+        if let Err(e) = hooks::run(Hook::PreFlash, &env) {
+            self.log(format!("{}; not flashing.", e));
+            return;
+        }
+        let (events, ctx) = (self.events.clone(), self.ctx.clone());
+        let mut observer = move |event: &FlashEvent| {
+            let _ = events.send(Event::Flash(event.clone()));
+            ctx.request_repaint();
+        };
+        let mut failed = 0;
+        for flash in flashes {
+            if self.cancel.is_cancelled() {
+                self.log("Stopped; the remaining boards were not flashed.");
+                break;
+            }
+            let Some(fpm) = self.connect() else {
+                failed += 1;
+                break;
+            };
+            let outcome = match &flash.address {
+                Some(address) => match fpm.exp_port_for_address(address) {
+                    Some(exp) => exp.update_firmware_with(address, &flash.version, &mut observer),
+                    None => FlashOutcome::Failed,
+                },
+                None => fpm.net.update_firmware_with(&flash.version, &mut observer),
+            };
+            if !outcome.is_verified() {
+                failed += 1;
+            }
+            let location = flash.address.as_deref().unwrap_or("NET");
+            self.log(format!(
+                "{} at {} to {}: {}",
+                flash.board, location, flash.version, outcome
+            ));
+        }
+        self.log(format!(
+            "Updated {} of {} board(s).",
+            flashes.len() - failed,
+            flashes.len()
+        ));
+        self.fpm = None;
+        let env = [
+            ("FAST_COMMAND", "gui".to_string()),
+            (
+                "FAST_EXIT_CODE",
+                if failed == 0 { "0" } else { "3" }.to_string(),
+            ),
+        ];
+        if let Err(e) = hooks::run(Hook::PostFlash, &env) {
+            self.log(format!("Warning: {}", e));
+        }
+        // Show where the boards stand, also after a stop
+        self.cancel.reset();
+        self.scan();
+    }
+
+    fn test_leds(&mut self, address: &str) {
+        let Some(fpm) = self.connect() else {
+            return;
+        };
+        let Some(exp) = fpm.exp_port_for_address(address) else {
+            self.log(format!("No EXP board answered at address {}.", address));
+            return;
+        };
+        exp.select_address(address);
+        exp.set_leds(0, &[(0xFF, 0xFF, 0xFF); 32]);
+        std::thread::sleep(Duration::from_secs(2));
+        exp.set_leds(0, &[(0, 0, 0); 32]);
+        self.log(format!(
+            "Lit the first 32 LEDs of the board at {} for 2 seconds.",
+            address
+        ));
+    }
+
+    fn download_firmware(&mut self) {
+        self.log("Downloading the latest firmware...");
+        match fast_pinball_utilities::download_latest_firmware() {
+            Ok(()) => {
+                firmware_repo::reload();
+                self.log(format!(
+                    "Firmware installed in {}.",
+                    config::firmware_dir().display()
+                ));
+                self.scan();
+            }
+            Err(e) => self.log(format!("Download failed: {}", e)),
+        }
+    }
+}
+
+/// How far the running flash is.
+struct FlashState {
+    file: String,
+    sent: u64,
+    total: u64,
+    stage: &'static str,
+}
+
+impl FlashState {
+    fn text(&self) -> String {
+        let name = std::path::Path::new(&self.file)
+            .file_name()
+            .map_or(self.file.clone(), |n| n.to_string_lossy().into_owned());
+        if self.stage.is_empty() {
+            format!("{}: {} of {} bytes", name, self.sent, self.total)
+        } else {
+            format!("{}: {}", name, self.stage)
+        }
+    }
+}
+
+struct App {
+    requests: Sender<Request>,
+    events: Receiver<Event>,
+    /// Shared with the worker; the Stop button cancels it.
+    cancel: CancelToken,
+    exp: Vec<ExpBoardInfo>,
+    net: Vec<NetBoardInfo>,
+    log: Vec<String>,
+    busy: bool,
+    /// The flash running, if any.
+    flash: Option<FlashState>,
+    /// Boards waiting for the owner to confirm updating them.
+    confirm: Option<Vec<Flash>>,
+}
+
+impl App {
+    fn new(requests: Sender<Request>, events: Receiver<Event>, cancel: CancelToken) -> Self {
+        Self {
+            requests,
+            events,
+            cancel,
+            exp: Vec::new(),
+            net: Vec::new(),
+            log: Vec::new(),
+            busy: true,
+            flash: None,
+            confirm: None,
+        }
+    }
+
+    fn flash_event(&mut self, event: &FlashEvent) {
+        if let FlashEvent::Started { file, total } = event {
+            self.flash = Some(FlashState {
+                file: file.clone(),
+                sent: 0,
+                total: *total,
+                stage: "",
+            });
+            return;
+        }
+        let Some(flash) = self.flash.as_mut() else {
+            return;
+        };
+        match event {
+            FlashEvent::ChunkSent { bytes, .. } => flash.sent = *bytes,
+            FlashEvent::Interrupted { .. } => flash.stage = "interrupted",
+            FlashEvent::Sent { .. } => flash.stage = "waiting for the bootloader",
+            FlashEvent::BootloaderAck => flash.stage = "restarting the board",
+            FlashEvent::Verifying => flash.stage = "checking the new version",
+            FlashEvent::Done(_) => self.flash = None,
+            FlashEvent::Started { .. } => {}
+        }
+    }
+
+    fn request(&mut self, request: Request) {
+        self.cancel.reset();
+        if self.requests.send(request).is_ok() {
+            self.busy = true;
+        }
+    }
+
+    /// The updates the EXP boards need.
+    fn outdated(&self) -> Vec<Flash> {
+        self.exp.iter().filter_map(exp_update).collect()
+    }
+
+    fn boards(&mut self, ui: &mut egui::Ui) {
+        let mut clicked: Option<Request> = None;
+        ui.heading("EXP boards");
+        egui::Grid::new("exp")
+            .striped(true)
+            .num_columns(5)
+            .show(ui, |ui| {
+                for header in ["Address", "Board", "Firmware", "Target", ""] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for b in self.exp.iter() {
+                    let available = b.available_versions.as_deref().unwrap_or_default();
+                    let target =
+                        Target::for_board(&b.board_name, &format!("EXP {}", b.address), available);
+                    ui.label(&b.address);
+                    ui.label(&b.board_name);
+                    firmware_label(ui, &b.version, target.as_ref());
+                    ui.label(
+                        target
+                            .as_ref()
+                            .map_or_else(|| "-".to_string(), |t| t.to_string()),
+                    );
+                    ui.horizontal(|ui| {
+                        if let Some(flash) = exp_update(b)
+                            && ui
+                                .add_enabled(!self.busy, egui::Button::new("Update"))
+                                .clicked()
+                        {
+                            clicked = Some(Request::Update(vec![flash]));
+                        }
+                        if ui
+                            .add_enabled(!self.busy, egui::Button::new("Test LEDs"))
+                            .clicked()
+                        {
+                            clicked = Some(Request::TestLeds {
+                                address: b.address.clone(),
+                            });
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        ui.add_space(12.0);
+        ui.heading("NET nodes");
+        egui::Grid::new("net")
+            .striped(true)
+            .num_columns(5)
+            .show(ui, |ui| {
+                for header in ["Node", "Board", "Firmware", "Target", ""] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for n in self.net.iter() {
+                    let available = firmware_repo::get().versions(&format!("{}_NET", n.node_name));
+                    let target =
+                        Target::for_board(&n.node_name, &format!("NET {}", n.node_id), &available);
+                    ui.label(&n.node_id);
+                    ui.label(&n.node_name);
+                    firmware_label(ui, &n.firmware, target.as_ref());
+                    ui.label(
+                        target
+                            .as_ref()
+                            .map_or_else(|| "-".to_string(), |t| t.to_string()),
+                    );
+                    // Only the controller itself takes NET firmware
+                    match target.filter(|t| n.node_id == "NC" && t.needs_update(&n.firmware)) {
+                        Some(t)
+                            if ui
+                                .add_enabled(!self.busy, egui::Button::new("Update"))
+                                .clicked() =>
+                        {
+                            clicked = Some(Request::Update(vec![Flash {
+                                board: n.node_name.clone(),
+                                address: None,
+                                version: t.version,
+                            }]));
+                        }
+                        _ => {
+                            ui.label("");
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        // Updates are confirmed first; everything else runs straight away
+        match clicked {
+            Some(Request::Update(flashes)) => self.confirm = Some(flashes),
+            Some(request) => self.request(request),
+            None => {}
+        }
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Event::Boards { exp, net } => {
+                    self.exp = exp;
+                    self.net = net;
+                }
+                Event::Log(line) => self.log.push(line),
+                Event::Flash(event) => self.flash_event(&event),
+                Event::Idle => {
+                    self.busy = false;
+                    self.flash = None;
+                }
+            }
+        }
+
+        egui::TopBottomPanel::top("actions").show(ctx, |ui| {
+            ui.add_space(4.0);
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!self.busy, egui::Button::new("Rescan"))
+                    .clicked()
+                {
+                    self.request(Request::Scan);
+                }
+                let outdated = self.outdated();
+                let label = format!("Update all ({})", outdated.len());
+                if ui
+                    .add_enabled(!self.busy && !outdated.is_empty(), egui::Button::new(label))
+                    .clicked()
+                {
+                    self.confirm = Some(outdated);
+                }
+                if ui
+                    .add_enabled(!self.busy, egui::Button::new("Download latest firmware"))
+                    .clicked()
+                {
+                    self.request(Request::DownloadFirmware);
+                }
+                if self.busy {
+                    if ui.button("Stop").clicked() {
+                        self.cancel.cancel();
+                    }
+                    ui.spinner();
+                }
+            });
+            ui.add_space(4.0);
+        });
+
+        egui::TopBottomPanel::bottom("log")
+            .resizable(true)
+            .show(ctx, |ui| {
+                if let Some(flash) = &self.flash {
+                    let fraction = match flash.total {
+                        0 => 0.0,
+                        total => flash.sent as f32 / total as f32,
+                    };
+                    ui.add(egui::ProgressBar::new(fraction).text(flash.text()));
+                }
+                egui::ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for line in self.log.iter() {
+                            ui.label(line);
+                        }
+                    });
+            });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| self.boards(ui));
+        });
+
+        if let Some(flashes) = self.confirm.clone() {
+            egui::Window::new("Update firmware")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label("Keep the machine powered on until the update finishes.");
+                    for f in flashes.iter() {
+                        ui.label(format!(
+                            "{} at {} -> {}",
+                            f.board,
+                            f.address.as_deref().unwrap_or("NET"),
+                            f.version
+                        ));
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Update").clicked() {
+                            self.confirm = None;
+                            self.request(Request::Update(flashes.clone()));
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.confirm = None;
+                        }
+                    });
+                });
+        }
+
+        // Keep the progress bar moving while the worker is busy
+        if self.busy {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+    }
+}
+
+/// The update an EXP board needs, if any, and there is firmware for it.
+fn exp_update(b: &ExpBoardInfo) -> Option<Flash> {
+    let available = b.available_versions.as_deref().unwrap_or_default();
+    let target = Target::for_board(&b.board_name, &format!("EXP {}", b.address), available)?;
+    (target.needs_update(&b.version) && available.contains(&target.version)).then(|| Flash {
+        board: b.board_name.clone(),
+        address: Some(b.address.clone()),
+        version: target.version,
+    })
+}
+
+// The board's firmware, yellow when it needs updating
+fn firmware_label(ui: &mut egui::Ui, firmware: &str, target: Option<&Target>) {
+    match target {
+        Some(t) if t.needs_update(firmware) => {
+            ui.colored_label(egui::Color32::from_rgb(230, 180, 0), firmware);
+        }
+        _ => {
+            ui.label(firmware);
+        }
+    }
+}
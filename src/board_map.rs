@@ -0,0 +1,188 @@
+// Which EXP board type answers at which address. The built-in map (`EXP_ADDRESS_MAP`)
+// follows FAST's documentation; ~/.fast/boards.toml extends or overrides it, so boards
+// and address ranges FAST adds later are supported without a new release. Scans query
+// exactly the addresses in the map, in its order.
+//
+// Example:
+//
+//     # true drops the built-in map, leaving only the addresses below
+//     replace = false
+//
+//     [exp]
+//     B8 = "FP-EXP-0072"   # a new board type
+//     30 = "FP-EXP-1314"   # a different board at a known address
+//
+// The CLI loads the map at startup and fails on a broken file. Library users can call
+// `init` with a map of their own, otherwise `get` loads it on first use.
+
+use crate::constants::EXP_ADDRESS_MAP;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+static MAP: OnceLock<BoardMap> = OnceLock::new();
+
+/// EXP address -> board type, in scan order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoardMap {
+    exp: Vec<(String, String)>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct BoardsFile {
+    replace: bool,
+    exp: BTreeMap<String, String>,
+}
+
+impl BoardMap {
+    /// The map from FAST's documentation.
+    pub fn builtin() -> Self {
+        Self {
+            exp: EXP_ADDRESS_MAP
+                .iter()
+                .map(|&(addr, board)| (addr.to_string(), board.to_string()))
+                .collect(),
+        }
+    }
+
+    /// The built-in map with ~/.fast/boards.toml applied, when there is one.
+    pub fn load() -> Result<Self, String> {
+        let path = crate::config::boards_path();
+        if !path.is_file() {
+            return Ok(Self::builtin());
+        }
+        Self::load_from(&path)
+    }
+
+    /// The built-in map with the file at `path` applied.
+    pub fn load_from(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("read {} failed: {}", path.display(), e))?;
+        let file: BoardsFile =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut map = if file.replace {
+            Self { exp: Vec::new() }
+        } else {
+            Self::builtin()
+        };
+        for (address, board) in file.exp {
+            let address = normalize_address(&address).ok_or_else(|| {
+                format!(
+                    "{}: '{}' is not an EXP address (two hex digits, e.g. B4)",
+                    path.display(),
+                    address
+                )
+            })?;
+            // Overrides keep their place in the scan order; new addresses go last
+            match map.exp.iter_mut().find(|(a, _)| *a == address) {
+                Some(entry) => entry.1 = board,
+                None => map.exp.push((address, board)),
+            }
+        }
+        Ok(map)
+    }
+
+    /// Every (address, board type) pair, in scan order.
+    pub fn exp_boards(&self) -> &[(String, String)] {
+        &self.exp
+    }
+
+    /// The board type expected at `address` (hex, any case).
+    pub fn exp_board_type(&self, address: &str) -> Option<&str> {
+        self.exp
+            .iter()
+            .find(|(a, _)| a.eq_ignore_ascii_case(address))
+            .map(|(_, board)| board.as_str())
+    }
+
+    /// Where `address` comes in the scan order.
+    pub fn exp_position(&self, address: &str) -> Option<usize> {
+        self.exp
+            .iter()
+            .position(|(a, _)| a.eq_ignore_ascii_case(address))
+    }
+}
+
+/// Make `map` the one returned by `get`. Only the first call has any effect.
+pub fn init(map: BoardMap) {
+    let _ = MAP.set(map);
+}
+
+/// The active board map (loaded on first use when `init` was never called; a broken
+/// ~/.fast/boards.toml is then ignored with a warning).
+pub fn get() -> &'static BoardMap {
+    MAP.get_or_init(|| {
+        BoardMap::load().unwrap_or_else(|e| {
+            log::warn!("Ignoring the board map file: {}", e);
+            BoardMap::builtin()
+        })
+    })
+}
+
+// `b4` -> `B4`; `None` unless one or two hex digits
+fn normalize_address(address: &str) -> Option<String> {
+    let address = address.trim();
+    if address.is_empty() || address.len() > 2 || !address.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u8::from_str_radix(address, 16)
+        .ok()
+        .map(|a| format!("{:02X}", a))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, text: &str) -> Result<BoardMap, String> {
+        let path = std::env::temp_dir().join(format!(
+            "fast-pinball-test-{}-{}.toml",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, text).unwrap();
+        let map = BoardMap::load_from(&path);
+        let _ = std::fs::remove_file(&path);
+        map
+    }
+
+    #[test]
+    fn file_extends_and_overrides_the_builtin_map() {
+        let builtin = BoardMap::builtin();
+        let map = load(
+            "boards-extend",
+            "[exp]\nb8 = \"FP-EXP-0072\"\n48 = \"FP-CPU-2001\"\n",
+        )
+        .unwrap();
+        assert_eq!(map.exp_boards().len(), builtin.exp_boards().len() + 1);
+        assert_eq!(map.exp_board_type("B8"), Some("FP-EXP-0072"));
+        assert_eq!(map.exp_position("b8"), Some(builtin.exp_boards().len()));
+        // An override keeps its place in the scan order
+        assert_eq!(map.exp_board_type("48"), Some("FP-CPU-2001"));
+        assert_eq!(map.exp_position("48"), builtin.exp_position("48"));
+    }
+
+    #[test]
+    fn replace_drops_the_builtin_map() {
+        let map = load(
+            "boards-replace",
+            "replace = true\n[exp]\n4 = \"FP-EXP-0071\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            map.exp_boards(),
+            [("04".to_string(), "FP-EXP-0071".to_string())]
+        );
+        assert_eq!(map.exp_board_type("B4"), None);
+    }
+
+    #[test]
+    fn broken_files_are_rejected() {
+        let err = load("boards-address", "[exp]\nB44 = \"FP-EXP-0071\"\n").unwrap_err();
+        assert!(err.contains("'B44' is not an EXP address"), "{}", err);
+        assert!(load("boards-unknown", "[exp]\nB4 = 1\n").is_err());
+        assert!(load("boards-key", "extend = true\n").is_err());
+    }
+}
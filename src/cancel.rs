@@ -0,0 +1,60 @@
+// Stopping a scan or flash from another thread, e.g. a GUI's Stop button. Give the
+// monitor a `CancelToken` (`FastPinballMonitor::set_cancel_token`, or
+// `connect_cancellable` to cover discovery too) and call `cancel` on a clone of it:
+// scans stop before querying the next address or node, and a flash stops before the
+// next line of firmware, so no record is cut in half. The operation then reports
+// `Cancelled` (`FlashOutcome::Cancelled` for flashes).
+//
+// A board whose flash stopped part way is left in its bootloader, waiting for the rest
+// of the firmware; `recover` (or flashing it again) brings it back. A flash cancelled
+// before its first line leaves the board as it was. Ctrl+C (`interrupt::request_stop`)
+// stops firmware streams the same way, whatever their token, but not scans: the CLI
+// still lists the boards after a stopped flash.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A flag that stops the operations it was handed to. Clones share the flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop the operations holding this token (or a clone of it) at their next check.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Clear the flag, so the token can be used for the next operation.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// `Err(Cancelled)` once cancelled.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The error of an operation stopped through its `CancelToken`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Cancelled.")
+    }
+}
+
+impl std::error::Error for Cancelled {}
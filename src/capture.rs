@@ -0,0 +1,133 @@
+// Serial traffic capture: when enabled with `start`, every byte written to or read from
+// a port opened by this crate is appended to the capture file with a timestamp and
+// direction. `replay` reads the file back (see `Recording`).
+
+use crate::protocol::transport::Transport;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+struct CaptureFile {
+    file: File,
+    started: Instant,
+}
+
+static CAPTURE: OnceLock<Mutex<CaptureFile>> = OnceLock::new();
+
+/// Start capturing serial traffic into `path` (truncating it). Only the first call has
+/// any effect.
+pub fn start(path: &str) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    let epoch_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    writeln!(
+        file,
+        "# fast-pinball-utilities serial capture, started at unix time {} ms",
+        epoch_ms
+    )?;
+    writeln!(file, "# <seconds since start> <port> <TX|RX> <data>")?;
+    let _ = CAPTURE.set(Mutex::new(CaptureFile {
+        file,
+        started: Instant::now(),
+    }));
+    Ok(())
+}
+
+pub fn is_active() -> bool {
+    CAPTURE.get().is_some()
+}
+
+/// Add a `# ` comment line to the capture, e.g. the command line or which port is NET, so
+/// `replay` can run the session again. Does nothing when no capture is active.
+pub fn note(text: &str) {
+    let Some(capture) = CAPTURE.get() else {
+        return;
+    };
+    if let Ok(mut c) = capture.lock() {
+        let _ = writeln!(c.file, "# {}", text);
+    }
+}
+
+fn record(port: &str, direction: &str, data: &[u8]) {
+    let Some(capture) = CAPTURE.get() else {
+        return;
+    };
+    if let Ok(mut c) = capture.lock() {
+        c.record(port, direction, data);
+    }
+}
+
+impl CaptureFile {
+    fn record(&mut self, port: &str, direction: &str, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let _ = writeln!(
+            self.file,
+            "{:>12.6} {} {} {:?}",
+            elapsed,
+            port,
+            direction,
+            String::from_utf8_lossy(data)
+        );
+    }
+}
+
+/// Wrap `port` so its traffic is recorded when a capture is active; otherwise return it
+/// unchanged.
+pub fn wrap(port_name: &str, port: Box<dyn Transport>) -> Box<dyn Transport> {
+    if !is_active() {
+        return port;
+    }
+    Box::new(CapturingPort {
+        name: port_name.to_string(),
+        inner: port,
+    })
+}
+
+struct CapturingPort {
+    name: String,
+    inner: Box<dyn Transport>,
+}
+
+impl Read for CapturingPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        record(&self.name, "RX", &buf[..n]);
+        Ok(n)
+    }
+}
+
+impl Write for CapturingPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Hold the capture while writing, so a reply read on another thread (see
+        // `NetEventReader`) is recorded after the command it answers
+        let mut capture = CAPTURE.get().and_then(|c| c.lock().ok());
+        let n = self.inner.write(buf)?;
+        if let Some(c) = capture.as_mut() {
+            c.record(&self.name, "TX", &buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Transport for CapturingPort {
+    fn bytes_to_read(&self) -> io::Result<u32> {
+        self.inner.bytes_to_read()
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(CapturingPort {
+            name: self.name.clone(),
+            inner: self.inner.try_clone()?,
+        }))
+    }
+}
@@ -0,0 +1,259 @@
+// Measure the NET and EXP links: round-trip latency of `ID:` (`ID@{address}:` on EXP)
+// queries sent one at a time, then throughput with several queries in flight. Slow or
+// uneven round trips and lost replies point at flaky USB hubs and cables.
+
+use crate::commands::exit::Exit;
+use crate::commands::utils::{parse_hex_address, print_document};
+use crate::config::OutputFormat;
+use crate::fast_monitor::FastPinballMonitor;
+use crate::protocol::exp_protocol::ExpProtocol;
+use crate::protocol::net_protocol::NetProtocol;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// How long to wait for a reply before counting it as lost.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+/// Queries in flight at once while measuring throughput.
+const WINDOW: usize = 8;
+
+/// Options for `benchmark`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct BenchmarkArgs {
+    /// Queries per link, for latency and again for throughput
+    #[arg(short = 'n', long, value_name = "N", default_value_t = 200, value_parser = clap::value_parser!(u32).range(1..))]
+    pub count: u32,
+    /// EXP board to query (hex, e.g. B4; default: the first expansion board on each EXP
+    /// port)
+    #[arg(long, value_parser = parse_hex_address)]
+    pub address: Option<String>,
+}
+
+/// What one link measured.
+#[derive(Serialize, Debug, Clone)]
+pub struct LinkBenchmark {
+    /// `NET` or `EXP`.
+    pub link: String,
+    pub port: String,
+    /// The query sent, e.g. `ID@B4:`.
+    pub query: String,
+    pub latency: Latency,
+    pub throughput: Throughput,
+}
+
+/// Round trips, one query at a time. Times are in milliseconds; they are `None` when no
+/// reply came back at all.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Latency {
+    pub replies: u32,
+    pub lost: u32,
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    /// Mean difference between consecutive round trips.
+    pub jitter_ms: Option<f64>,
+}
+
+/// Queries sent back to back with up to `WINDOW` awaiting their reply.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Throughput {
+    pub replies: u32,
+    pub lost: u32,
+    pub replies_per_sec: f64,
+    /// Query and reply bytes together.
+    pub bytes_per_sec: f64,
+}
+
+// Both links take a raw query and hand back reply lines
+trait Link {
+    fn port_name(&self) -> &str;
+    fn send_query(&mut self, query: &[u8]);
+    fn reply_within(&mut self, timeout: Duration) -> String;
+}
+
+impl Link for NetProtocol {
+    fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    fn send_query(&mut self, query: &[u8]) {
+        let _ = self.send(query);
+    }
+
+    fn reply_within(&mut self, timeout: Duration) -> String {
+        self.receive_within(timeout)
+    }
+}
+
+impl Link for ExpProtocol {
+    fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    fn send_query(&mut self, query: &[u8]) {
+        self.send(query.to_vec());
+    }
+
+    fn reply_within(&mut self, timeout: Duration) -> String {
+        self.receive_within(timeout)
+    }
+}
+
+/// Benchmark the NET link and the EXP link of each EXP port (or of the board at
+/// `--address`).
+pub fn run(fpm: &mut FastPinballMonitor, args: &BenchmarkArgs, format: OutputFormat) -> Exit {
+    // (port, address) of the EXP boards to query
+    let targets: Vec<(String, String)> = match &args.address {
+        Some(address) => match fpm.exp_port_for_address(address) {
+            Some(exp) => vec![(exp.port_name.clone(), address.clone())],
+            None => {
+                println!("No EXP board answered at address {}.", address);
+                return Exit::NoHardware;
+            }
+        },
+        None => {
+            // The controller's built-in EXP answers without crossing the EXP cable, so it
+            // is only queried on a port without expansion boards
+            let mut boards = fpm.list_connected_exp_boards();
+            boards.sort_by_key(|b| b.is_controller);
+            let mut targets: Vec<(String, String)> = Vec::new();
+            for b in boards {
+                if !targets.iter().any(|(port, _)| *port == b.port) {
+                    targets.push((b.port, b.address));
+                }
+            }
+            targets
+        }
+    };
+
+    if !format.is_document() {
+        status!("Sending {} queries per link, twice...", args.count);
+    }
+    let mut results = vec![measure(&mut fpm.net, "NET", "ID:", args.count)];
+    for (port, address) in targets {
+        if let Some(exp) = fpm.exp_port(&port) {
+            let query = format!("ID@{}:", address);
+            results.push(measure(exp, "EXP", &query, args.count));
+        }
+    }
+
+    if format.is_document() {
+        print_document(&results, format);
+        return Exit::Success;
+    }
+    for r in results.iter() {
+        print_link(r);
+    }
+    if results.len() == 1 {
+        println!("No EXP boards found; only the NET link was measured.");
+    }
+    Exit::Success
+}
+
+fn measure(link: &mut dyn Link, name: &str, query: &str, count: u32) -> LinkBenchmark {
+    // Stale input would be taken for the first reply
+    let _ = link.reply_within(Duration::ZERO);
+    let latency = latency(link, query, count);
+    let _ = link.reply_within(REPLY_TIMEOUT);
+    let throughput = throughput(link, query, count);
+    LinkBenchmark {
+        link: name.to_string(),
+        port: link.port_name().to_string(),
+        query: query.to_string(),
+        latency,
+        throughput,
+    }
+}
+
+fn latency(link: &mut dyn Link, query: &str, count: u32) -> Latency {
+    let bytes = format!("{}\r", query).into_bytes();
+    let mut samples: Vec<f64> = Vec::new();
+    let mut lost = 0;
+    for _ in 0..count {
+        let start = Instant::now();
+        link.send_query(&bytes);
+        if is_id_reply(&link.reply_within(REPLY_TIMEOUT)) {
+            samples.push(start.elapsed().as_secs_f64() * 1000.0);
+        } else {
+            lost += 1;
+            // A late reply must not be taken for the next one
+            let _ = link.reply_within(REPLY_TIMEOUT);
+        }
+    }
+    let mut result = Latency {
+        replies: samples.len() as u32,
+        lost,
+        ..Default::default()
+    };
+    if samples.is_empty() {
+        return result;
+    }
+    result.min_ms = samples.iter().copied().reduce(f64::min);
+    result.max_ms = samples.iter().copied().reduce(f64::max);
+    result.avg_ms = Some(samples.iter().sum::<f64>() / samples.len() as f64);
+    result.jitter_ms = Some(if samples.len() < 2 {
+        0.0
+    } else {
+        samples.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<f64>() / (samples.len() - 1) as f64
+    });
+    result
+}
+
+fn throughput(link: &mut dyn Link, query: &str, count: u32) -> Throughput {
+    let bytes = format!("{}\r", query).into_bytes();
+    let count = count as usize;
+    let (mut sent, mut replies, mut transferred) = (0usize, 0usize, 0usize);
+    let start = Instant::now();
+    while replies < count {
+        while sent < count && sent.saturating_sub(replies) < WINDOW {
+            link.send_query(&bytes);
+            sent += 1;
+            transferred += bytes.len();
+        }
+        let reply = link.reply_within(REPLY_TIMEOUT);
+        let got = reply.lines().filter(|l| is_id_reply(l)).count();
+        if got == 0 {
+            // The rest of the window was lost
+            break;
+        }
+        replies += got;
+        // Each line came with a `\r` terminator
+        transferred += reply.lines().map(|l| l.len() + 1).sum::<usize>();
+    }
+    let secs = start.elapsed().as_secs_f64();
+    Throughput {
+        replies: replies as u32,
+        lost: (count - replies.min(count)) as u32,
+        replies_per_sec: replies as f64 / secs,
+        bytes_per_sec: transferred as f64 / secs,
+    }
+}
+
+fn is_id_reply(reply: &str) -> bool {
+    crate::protocol::responses::IdResponse::find(reply).is_some()
+}
+
+fn print_link(r: &LinkBenchmark) {
+    println!("{} {} ({}):", r.link, r.port, r.query);
+    let l = &r.latency;
+    match (l.min_ms, l.avg_ms, l.max_ms, l.jitter_ms) {
+        (Some(min), Some(avg), Some(max), Some(jitter)) => println!(
+            "  Latency:    min {:.2} ms, avg {:.2} ms, max {:.2} ms, jitter {:.2} ms",
+            min, avg, max, jitter
+        ),
+        _ => println!("  Latency:    no replies"),
+    }
+    let t = &r.throughput;
+    println!(
+        "  Throughput: {:.0} replies/s, {:.1} KB/s",
+        t.replies_per_sec,
+        t.bytes_per_sec / 1024.0
+    );
+    let lost = l.lost + t.lost;
+    if lost > 0 {
+        println!(
+            "  Lost:       {} of {} replies; check the USB cable and hub",
+            lost,
+            l.replies + l.lost + t.replies + t.lost
+        );
+    }
+}
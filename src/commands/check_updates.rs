@@ -1,66 +1,325 @@
-use std::path::{Path, PathBuf};
+use crate::commands::utils::{Target, read_line_trimmed};
+use crate::fast_monitor::FastPinballMonitor;
+use crate::firmware_repo::FirmwareRepo;
+use crate::firmware_source::{
+    DEFAULT_GITHUB_REPO, FirmwareFile, FirmwareSource, GitHubBranch, GitHubRef, GitHubTag,
+    parse_source,
+};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Component, Path, PathBuf};
 
-pub fn run() -> Result<(), String> {
-    // Determine the user's home directory and target firmware storage under ~/.fast/firmware
-    let user_dirs = directories::UserDirs::new().ok_or("could not determine user home directory")?;
-    let target = user_dirs.home_dir().join(".fast").join("firmware");
+/// Options for `get-latest-firmware`.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct CheckUpdatesArgs {
+    /// Where to fetch firmware from: github[:owner/repo[@branch]], tag:<tag>[@owner/repo],
+    /// ref:<branch or sha>[@owner/repo], url:<zip url> or dir:<path>
+    #[arg(long, value_name = "SPEC", conflicts_with_all = ["tag", "git_ref"])]
+    pub source: Option<String>,
+    /// Fetch this release tag of the official repository instead of main
+    #[arg(long, value_name = "RELEASE", conflicts_with = "git_ref")]
+    pub tag: Option<String>,
+    /// Fetch this branch or commit of the official repository instead of main
+    #[arg(long = "ref", value_name = "BRANCH_OR_SHA")]
+    pub git_ref: Option<String>,
+    /// Download the archive again even if the cached copy is unchanged upstream
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// CLI entry point: install firmware from `--source` (see `parse_source`), `--tag` or
+/// `--ref`, defaulting to the main branch of the official repository.
+pub fn run(args: &CheckUpdatesArgs) -> Result<(), String> {
+    if let Some(spec) = args.source.as_deref() {
+        return install_from(parse_source(spec, args.force)?.as_ref());
+    }
+    if let Some(tag) = &args.tag {
+        return install_from(&GitHubTag {
+            repo: DEFAULT_GITHUB_REPO.to_string(),
+            tag: tag.clone(),
+            force: args.force,
+        });
+    }
+    if let Some(reference) = &args.git_ref {
+        return install_from(&GitHubRef {
+            repo: DEFAULT_GITHUB_REPO.to_string(),
+            reference: reference.clone(),
+            force: args.force,
+        });
+    }
+    install_from(&GitHubBranch {
+        force: args.force,
+        ..GitHubBranch::default()
+    })
+}
+
+/// Download the latest firmware from the official fastpinball/fast-firmware repository.
+pub fn download_latest_firmware() -> Result<(), String> {
+    install_from(&GitHubBranch::default())
+}
+
+/// Fetch firmware files from `source`, verify them against the source's checksum
+/// manifest (when it has one), and install them into the firmware directory
+/// (~/.fast/firmware unless configured otherwise).
+///
+/// The directory is only replaced once every file has been written, so a failed
+/// install leaves the previous firmware in place.
+pub fn install_from(source: &dyn FirmwareSource) -> Result<(), String> {
+    status!("Fetching firmware from {} ...", source.describe());
+    let files = source.fetch()?;
+    let installed = install_files(source, files, |rel_path| Some(rel_path.to_path_buf()))?;
+    if installed == 0 {
+        println!("No firmware files were found at {}.", source.describe());
+    } else {
+        println!(
+            "Downloaded and updated {} firmware files into {}.",
+            installed,
+            crate::config::firmware_dir().display()
+        );
+    }
+    Ok(())
+}
+
+/// Verify `files` against the source's checksum manifest and write them into the
+/// firmware directory at the path `place` gives for their relative path; files it
+/// returns `None` for are skipped. Returns how many firmware files (not counting release
+/// notes) were installed.
+pub(crate) fn install_files(
+    source: &dyn FirmwareSource,
+    files: Vec<FirmwareFile>,
+    mut place: impl FnMut(&Path) -> Option<PathBuf>,
+) -> Result<usize, String> {
+    let target = crate::config::firmware_dir();
+    if target.as_os_str().is_empty() {
+        return Err("could not determine user home directory".to_string());
+    }
 
-    let url = "https://github.com/fastpinball/fast-firmware/archive/refs/heads/main.zip";
-    println!("Downloading firmware archive from {} ...", url);
-    let resp = reqwest::blocking::get(url).map_err(|e| format!("download failed: {}", e))?;
-    if !resp.status().is_success() {
-        return Err(format!("HTTP error: {}", resp.status()));
+    let checksums = source.checksums()?;
+    if checksums.is_none() {
+        eprintln!(
+            "Warning: {} publishes no checksum manifest; firmware files cannot be verified.",
+            source.describe()
+        );
     }
-    let bytes = resp.bytes().map_err(|e| format!("read body failed: {}", e))?;
-    let reader = std::io::Cursor::new(bytes);
-    let mut zip = zip::ZipArchive::new(reader).map_err(|e| format!("invalid zip: {}", e))?;
 
-    std::fs::create_dir_all(&target).map_err(|e| format!("create target dir failed: {}", e))?;
+    // Build the new firmware directory next to the old one (starting from what is already
+    // installed) and only swap it in once every file has been written
+    let staging = sibling(&target, "partial");
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)
+            .map_err(|e| format!("remove stale {} failed: {}", staging.display(), e))?;
+    }
+    if target.is_dir() {
+        copy_dir(&target, &staging)?;
+    }
+    std::fs::create_dir_all(&staging).map_err(|e| format!("create target dir failed: {}", e))?;
 
     let mut extracted = 0usize;
-    for i in 0..zip.len() {
-        let mut file = zip.by_index(i).map_err(|e| format!("zip read failed: {}", e))?;
-        if file.is_dir() {
-            continue;
+    let mut installed: Vec<String> = Vec::new();
+    let mut rejected: Vec<String> = Vec::new();
+    for file in files {
+        let notes = crate::release_notes::is_notes_file(&file.rel_path);
+        // Verify against the manifest before anything touches the firmware directory
+        if let Some(sums) = checksums.as_ref() {
+            let rel_key = file.rel_path.to_string_lossy().replace('\\', "/");
+            let actual = hex_digest(&file.contents);
+            match sums.get(&rel_key) {
+                Some(expected) if expected.eq_ignore_ascii_case(&actual) => {}
+                Some(_) => {
+                    eprintln!("Checksum mismatch for {}; not installing it.", rel_key);
+                    rejected.push(rel_key);
+                    continue;
+                }
+                // Manifests need not list release notes; unlisted notes are left out
+                None if notes => {
+                    log::debug!("No checksum listed for {}; not installing it.", rel_key);
+                    continue;
+                }
+                None => {
+                    eprintln!("No checksum listed for {}; not installing it.", rel_key);
+                    rejected.push(rel_key);
+                    continue;
+                }
+            }
         }
-        let name_in_zip = file.name().to_string();
-        // Skip top-level folder in GitHub zip (e.g., fast-firmware-main/)
-        let rel_path: PathBuf = {
-            let p = Path::new(&name_in_zip);
-            let mut comps = p.components();
-            let _top = comps.next();
-            comps.collect()
-        };
-        if rel_path.as_os_str().is_empty() {
+
+        let Some(rel_path) = place(&file.rel_path) else {
             continue;
-        }
-        // Only extract .txt firmware files
-        if rel_path
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|e| e.eq_ignore_ascii_case("txt"))
-            .unwrap_or(false)
+        };
+        // Only plain folder and file names may be joined onto the firmware directory
+        if !rel_path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
         {
-            let out_path = target.join(&rel_path);
-            if let Some(parent) = out_path.parent() {
-                std::fs::create_dir_all(parent)
-                    .map_err(|e| format!("create dir failed: {}", e))?;
-            }
-            let mut out = std::fs::File::create(&out_path)
-                .map_err(|e| format!("create file {} failed: {}", out_path.display(), e))?;
-            std::io::copy(&mut file, &mut out)
-                .map_err(|e| format!("write file {} failed: {}", out_path.display(), e))?;
+            return Err(format!(
+                "refusing to install {}: it points outside the firmware directory",
+                rel_path.display()
+            ));
+        }
+        let out_path = staging.join(&rel_path);
+        if let Some(parent) = out_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("create dir failed: {}", e))?;
+        }
+        std::fs::write(&out_path, &file.contents)
+            .map_err(|e| format!("write file {} failed: {}", out_path.display(), e))?;
+        if !notes {
             extracted += 1;
+            installed.push(rel_path.to_string_lossy().replace('\\', "/"));
         }
     }
-    if extracted == 0 {
-        println!("No .txt firmware files were found in the archive.");
-    } else {
+    crate::firmware_repo::record_sources(&staging, &installed, &source.describe())?;
+    replace_dir(&staging, &target)?;
+    if !rejected.is_empty() {
+        if extracted > 0 {
+            println!(
+                "Installed {} firmware files into {}.",
+                extracted,
+                target.display()
+            );
+        }
+        return Err(format!(
+            "{} firmware file(s) failed checksum verification and were not installed",
+            rejected.len()
+        ));
+    }
+    Ok(extracted)
+}
+
+/// Load the firmware repo for a command that flashes from the firmware directory.
+///
+/// When the directory holds no firmware, offer to download the latest firmware; the
+/// offer is only made on an interactive terminal and never when `offline` is set, in
+/// which case only a hint on how to get firmware is printed.
+pub fn load_firmware(offline: bool) -> FirmwareRepo {
+    let repo = FirmwareRepo::load();
+    if !repo.is_empty() {
+        return repo;
+    }
+    eprintln!("No firmware files found in {}.", repo.dir().display());
+    let hint = "Run get-latest-firmware, or import-firmware <zip-or-dir> on a machine without internet access.";
+    if offline || !io::stdin().is_terminal() {
+        eprintln!("{}", hint);
+        return repo;
+    }
+    print!("Download the latest firmware now? [Y/n]: ");
+    let _ = io::stdout().flush();
+    if matches!(read_line_trimmed().as_str(), "n" | "N" | "no" | "NO") {
+        eprintln!("{}", hint);
+        return repo;
+    }
+    if let Err(e) = download_latest_firmware() {
+        eprintln!("Failed to download firmware: {}", e);
+        return repo;
+    }
+    FirmwareRepo::load()
+}
+
+/// Print each detected board's installed firmware next to the newest version in the
+/// firmware directory (or its pinned version), flagging boards that are behind.
+pub fn report_versions(fpm: &mut FastPinballMonitor) {
+    // (board, location, installed, firmware key)
+    let mut rows: Vec<(String, String, String, String)> = Vec::new();
+    for b in fpm.list_connected_exp_boards() {
+        let key = format!("{}_EXP", b.board_name);
+        rows.push((b.board_name, format!("EXP {}", b.address), b.version, key));
+    }
+    let nodes: BTreeMap<_, _> = fpm.list_connected_net_boards().into_iter().collect();
+    for n in nodes.into_values() {
+        let key = format!("{}_NET", n.node_name);
+        rows.push((n.node_name, format!("NET {}", n.node_id), n.firmware, key));
+    }
+    if rows.is_empty() {
+        println!("No boards found to compare against.");
+        return;
+    }
+
+    println!();
+    println!(
+        "  {:<14} {:<10} {:<10} {:<13} Status",
+        "Board", "Location", "Installed", "Newest"
+    );
+    let mut behind = 0usize;
+    for (board, location, installed, key) in rows.iter() {
+        let versions = crate::firmware_repo::get().versions(key);
+        let target = Target::for_board(board, location, &versions);
+        let status = match &target {
+            None => "no firmware available",
+            Some(t) if !t.needs_update(installed) => "up to date",
+            _ => {
+                behind += 1;
+                "UPDATE AVAILABLE"
+            }
+        };
         println!(
-            "Downloaded and updated {} firmware files into {}.",
-            extracted,
-            target.display()
+            "  {:<14} {:<10} {:<10} {:<13} {}",
+            board,
+            location,
+            installed,
+            target.map_or_else(|| "-".to_string(), |t| t.to_string()),
+            status
+        );
+    }
+    if behind > 0 {
+        println!(
+            "{} board(s) can be updated (update-all-exp / update-net).",
+            behind
         );
     }
+}
+
+/// `dir` with `.{suffix}` appended to its last component, e.g. `firmware.partial`.
+fn sibling(dir: &Path, suffix: &str) -> PathBuf {
+    let mut name = dir.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    dir.with_file_name(name)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(to)
+        .map_err(|e| format!("create dir {} failed: {}", to.display(), e))?;
+    let entries = std::fs::read_dir(from)
+        .map_err(|e| format!("read dir {} failed: {}", from.display(), e))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let dest = to.join(entry.file_name());
+        if path.is_dir() {
+            copy_dir(&path, &dest)?;
+        } else {
+            std::fs::copy(&path, &dest)
+                .map_err(|e| format!("copy {} failed: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Swap `staged` into place at `target`, restoring the old directory if the swap fails.
+fn replace_dir(staged: &Path, target: &Path) -> Result<(), String> {
+    let backup = sibling(target, "old");
+    if backup.exists() {
+        let _ = std::fs::remove_dir_all(&backup);
+    }
+    let had_target = target.exists();
+    if had_target {
+        std::fs::rename(target, &backup)
+            .map_err(|e| format!("move {} aside failed: {}", target.display(), e))?;
+    }
+    if let Err(e) = std::fs::rename(staged, target) {
+        if had_target {
+            let _ = std::fs::rename(&backup, target);
+        }
+        return Err(format!("install into {} failed: {}", target.display(), e));
+    }
+    if had_target {
+        let _ = std::fs::remove_dir_all(&backup);
+    }
     Ok(())
 }
+
+fn hex_digest(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
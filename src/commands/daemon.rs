@@ -0,0 +1,107 @@
+// Stay connected and keep an eye on the machine: every `--interval` the boards are listed
+// again and compared with the last check (see `status`), and each change is logged with a
+// timestamp: boards disappearing, appearing or moving, firmware changing. When the NET
+// processor stops answering (e.g. the USB link dropped), the ports are closed and opened
+// again as soon as the hardware is back, found anew unless pinned.
+
+use crate::commands::exit::Exit;
+use crate::commands::status::MachineStatus;
+use crate::fast_monitor::{FastPinballMonitor, PinnedPorts};
+use std::time::Duration;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+/// How often to try again while the hardware is gone.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Options for `daemon`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct DaemonArgs {
+    /// Seconds between checks of the boards and their firmware
+    #[arg(long, value_name = "SECS", default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: u64,
+}
+
+/// Watch the hardware until interrupted.
+pub fn run(pins: PinnedPorts, args: &DaemonArgs) -> Exit {
+    let interval = Duration::from_secs(args.interval);
+    log_event(&format!(
+        "Watching the FAST hardware every {}s; press Ctrl+C to stop.",
+        args.interval
+    ));
+    let mut fpm: Option<FastPinballMonitor> = None;
+    let mut last: Option<MachineStatus> = None;
+    let mut waiting_logged = false;
+    loop {
+        let Some(connected) = fpm.as_mut() else {
+            match FastPinballMonitor::connect_with_ports(pins.clone()) {
+                Ok(connected) => {
+                    let exp: Vec<&str> = connected
+                        .exp_ports
+                        .iter()
+                        .map(|e| e.port_name.as_str())
+                        .collect();
+                    log_event(&format!(
+                        "Connected: NET on {}, EXP on {}.",
+                        connected.net.port_name,
+                        exp.join(", ")
+                    ));
+                    fpm = Some(connected);
+                    waiting_logged = false;
+                }
+                Err(e) => {
+                    if !waiting_logged {
+                        log_event("Waiting for FAST hardware ...");
+                        waiting_logged = true;
+                    }
+                    log::debug!("Not connected: {}", e);
+                    std::thread::sleep(RECONNECT_INTERVAL);
+                }
+            }
+            continue;
+        };
+
+        if connected.net.id().is_none() {
+            log_event(&format!(
+                "Lost the connection to NET on {}; reconnecting.",
+                connected.net.port_name
+            ));
+            // Close the ports before opening them again
+            fpm = None;
+            continue;
+        }
+        let now = MachineStatus::capture(connected);
+        match &last {
+            None => log_event(&format!(
+                "{} boards found, {} with a firmware update available.",
+                now.boards().len(),
+                now.updates_available()
+            )),
+            Some(before) => {
+                for change in now.compare(before) {
+                    log_event(&change.to_string());
+                }
+                let (was, is) = (before.updates_available(), now.updates_available());
+                if was != is {
+                    log_event(&format!(
+                        "{} boards with a firmware update available (was {}).",
+                        is, was
+                    ));
+                }
+            }
+        }
+        last = Some(now);
+        std::thread::sleep(interval);
+    }
+}
+
+// One line on stdout, prefixed with the time (RFC 3339, UTC)
+fn log_event(message: &str) {
+    let now = OffsetDateTime::now_utc();
+    let timestamp = now
+        .replace_nanosecond(0)
+        .unwrap_or(now)
+        .format(&Rfc3339)
+        .unwrap_or_default();
+    println!("{} {}", timestamp, message);
+}
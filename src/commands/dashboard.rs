@@ -0,0 +1,287 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::Target;
+use crate::fast_monitor::{ExpBoardInfo, FastPinballMonitor, NetBoardInfo};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, List, ListItem, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Options for `dashboard`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct DashboardArgs {
+    /// Seconds between board rescans
+    #[arg(long, value_name = "SECS", default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    pub refresh: u64,
+}
+
+/// Events kept for the scrolling pane.
+const MAX_EVENTS: usize = 500;
+
+/// Full-screen view of the connected boards and live serial traffic, rescanning the
+/// boards periodically until `q` is pressed.
+pub fn run(fpm: &mut FastPinballMonitor, args: &DashboardArgs) -> Exit {
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, fpm, Duration::from_secs(args.refresh));
+    ratatui::restore();
+    match result {
+        Ok(()) => Exit::Success,
+        Err(e) => {
+            eprintln!("Dashboard failed: {}", e);
+            Exit::Error
+        }
+    }
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    fpm: &mut FastPinballMonitor,
+    refresh: Duration,
+) -> std::io::Result<()> {
+    let mut dash = Dashboard::new();
+    loop {
+        if dash.last_scan.is_none_or(|t| t.elapsed() >= refresh) {
+            terminal.draw(|frame| dash.draw(frame, "scanning..."))?;
+            dash.rescan(fpm);
+        }
+        dash.poll_serial(fpm);
+        let status = match dash.last_scan {
+            Some(t) => format!("last scan {}s ago", t.elapsed().as_secs()),
+            None => String::new(),
+        };
+        terminal.draw(|frame| dash.draw(frame, &status))?;
+
+        if event::poll(Duration::from_millis(100))?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('r') => dash.last_scan = None,
+                _ => {}
+            }
+        }
+    }
+}
+
+struct Dashboard {
+    exp: Vec<ExpBoardInfo>,
+    net: Vec<NetBoardInfo>,
+    events: VecDeque<String>,
+    started: Instant,
+    last_scan: Option<Instant>,
+}
+
+impl Dashboard {
+    fn new() -> Self {
+        Self {
+            exp: Vec::new(),
+            net: Vec::new(),
+            events: VecDeque::new(),
+            started: Instant::now(),
+            last_scan: None,
+        }
+    }
+
+    fn log(&mut self, message: String) {
+        let elapsed = self.started.elapsed();
+        self.events.push_back(format!(
+            "[{:>5}.{:03}s] {}",
+            elapsed.as_secs(),
+            elapsed.subsec_millis(),
+            message
+        ));
+        while self.events.len() > MAX_EVENTS {
+            self.events.pop_front();
+        }
+    }
+
+    /// Re-list every board and log what appeared, disappeared or changed version.
+    fn rescan(&mut self, fpm: &mut FastPinballMonitor) {
+        let exp = fpm.list_connected_exp_boards();
+        let mut net: Vec<(usize, NetBoardInfo)> =
+            fpm.list_connected_net_boards().into_iter().collect();
+        net.sort_by_key(|(i, _)| *i);
+        let net: Vec<NetBoardInfo> = net.into_iter().map(|(_, n)| n).collect();
+
+        if self.last_scan.is_none() && self.exp.is_empty() && self.net.is_empty() {
+            self.log(format!(
+                "Found {} EXP board(s) and {} NET board(s)",
+                exp.len(),
+                net.len()
+            ));
+        } else {
+            let exp_old: Vec<(String, String, String)> = self
+                .exp
+                .iter()
+                .map(|b| {
+                    (
+                        format!("EXP {}", b.address),
+                        b.board_name.clone(),
+                        b.version.clone(),
+                    )
+                })
+                .collect();
+            let exp_new: Vec<(String, String, String)> = exp
+                .iter()
+                .map(|b| {
+                    (
+                        format!("EXP {}", b.address),
+                        b.board_name.clone(),
+                        b.version.clone(),
+                    )
+                })
+                .collect();
+            let net_old: Vec<(String, String, String)> = self
+                .net
+                .iter()
+                .map(|n| {
+                    (
+                        format!("NET {}", n.node_id),
+                        n.node_name.clone(),
+                        n.firmware.clone(),
+                    )
+                })
+                .collect();
+            let net_new: Vec<(String, String, String)> = net
+                .iter()
+                .map(|n| {
+                    (
+                        format!("NET {}", n.node_id),
+                        n.node_name.clone(),
+                        n.firmware.clone(),
+                    )
+                })
+                .collect();
+            self.log_changes(&exp_old, &exp_new);
+            self.log_changes(&net_old, &net_new);
+        }
+        self.exp = exp;
+        self.net = net;
+        self.last_scan = Some(Instant::now());
+    }
+
+    /// Compare (location, board, version) lists from two scans.
+    fn log_changes(&mut self, old: &[(String, String, String)], new: &[(String, String, String)]) {
+        for (location, board, version) in new {
+            match old.iter().find(|(l, _, _)| l == location) {
+                None => self.log(format!("{} {} ({}) connected", location, board, version)),
+                Some((_, _, before)) if before != version => self.log(format!(
+                    "{} {} version {} -> {}",
+                    location, board, before, version
+                )),
+                _ => {}
+            }
+        }
+        for (location, board, _) in old {
+            if !new.iter().any(|(l, _, _)| l == location) {
+                self.log(format!("{} {} disconnected", location, board));
+            }
+        }
+    }
+
+    /// Log whatever the boards sent on their own since the last poll.
+    fn poll_serial(&mut self, fpm: &mut FastPinballMonitor) {
+        for line in fpm.net.receive_lines() {
+            self.log(format!("NET {}", line));
+        }
+        let show_port = fpm.exp_ports.len() > 1;
+        for exp in fpm.exp_ports.iter_mut() {
+            let lines = exp.receive_lines();
+            for line in lines {
+                let message = if show_port {
+                    format!("EXP {} {}", exp.port_name, line)
+                } else {
+                    format!("EXP {}", line)
+                };
+                self.log(message);
+            }
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame, status: &str) {
+        let [boards_area, events_area, help_area] = Layout::vertical([
+            Constraint::Percentage(45),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [exp_area, net_area] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(boards_area);
+        let header_style = Style::default().add_modifier(Modifier::BOLD);
+
+        let exp_rows = self.exp.iter().map(|b| {
+            let target = Target::for_board(
+                &b.board_name,
+                &format!("EXP {}", b.address),
+                b.available_versions.as_deref().unwrap_or_default(),
+            );
+            Row::new(vec![
+                b.address.clone(),
+                b.board_name.clone(),
+                b.version.clone(),
+                target.map_or_else(|| "-".to_string(), |t| t.to_string()),
+            ])
+        });
+        let exp_table = Table::new(
+            exp_rows,
+            [
+                Constraint::Length(8),
+                Constraint::Length(14),
+                Constraint::Length(9),
+                Constraint::Length(13),
+            ],
+        )
+        .header(Row::new(vec!["Address", "Board", "Version", "Newest"]).style(header_style))
+        .block(Block::bordered().title(" EXP boards "));
+        frame.render_widget(exp_table, exp_area);
+
+        let count = |c: Option<u16>| c.map_or_else(|| "-".to_string(), |c| c.to_string());
+        let net_rows = self.net.iter().map(|n| {
+            Row::new(vec![
+                n.node_id.clone(),
+                n.node_name.clone(),
+                n.firmware.clone(),
+                count(n.switch_count),
+                count(n.driver_count),
+            ])
+        });
+        let net_table = Table::new(
+            net_rows,
+            [
+                Constraint::Length(6),
+                Constraint::Length(14),
+                Constraint::Length(9),
+                Constraint::Length(9),
+                Constraint::Length(8),
+            ],
+        )
+        .header(
+            Row::new(vec!["Node", "Board", "Firmware", "Switches", "Drivers"]).style(header_style),
+        )
+        .block(Block::bordered().title(" NET boards "));
+        frame.render_widget(net_table, net_area);
+
+        // Keep the newest events in view
+        let visible = events_area.height.saturating_sub(2) as usize;
+        let skip = self.events.len().saturating_sub(visible);
+        let items: Vec<ListItem> = self
+            .events
+            .iter()
+            .skip(skip)
+            .map(|e| ListItem::new(e.as_str()))
+            .collect();
+        frame.render_widget(
+            List::new(items).block(Block::bordered().title(" Events ")),
+            events_area,
+        );
+
+        frame.render_widget(
+            Paragraph::new(format!(" q quit  r rescan  {}", status)),
+            help_area,
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::{load_document, print_document, read_line_trimmed, save_document};
+use crate::config::OutputFormat;
+use crate::fast_monitor::FastPinballMonitor;
+use crate::protocol::net_commands::DriverConfig;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Options for `drivers`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct DriversArgs {
+    #[command(subcommand)]
+    pub command: DriversCommand,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum DriversCommand {
+    /// Read every driver's configuration from the Neuron into a file (.yaml/.yml for
+    /// YAML, JSON otherwise)
+    Dump { file: PathBuf },
+    /// Write the driver configurations in a file from `drivers dump` to the Neuron
+    Apply {
+        file: PathBuf,
+        /// Apply without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Show which drivers are enabled, disabled (e.g. shut off by the controller after an
+    /// over-current trip) or not answering
+    Status,
+}
+
+/// The driver configurations of a machine, as written by `drivers dump`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DriverDump {
+    pub drivers: Vec<DriverConfig>,
+}
+
+/// The state of one driver, as `drivers status` reports it.
+#[derive(Serialize, Debug, Clone)]
+pub struct DriverStatus {
+    pub driver: u8,
+    /// Node ID of the I/O board the driver is on.
+    pub node: String,
+    pub state: DriverState,
+    /// The configuration read back; `None` when the driver did not answer.
+    pub config: Option<DriverConfig>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DriverState {
+    Enabled,
+    /// Configured, but shut off: by the host, or by the controller after a fault.
+    Disabled,
+    /// No mode set.
+    Unconfigured,
+    /// No reply to `DL:`.
+    NoAnswer,
+}
+
+impl std::fmt::Display for DriverState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DriverState::Enabled => "enabled",
+            DriverState::Disabled => "DISABLED",
+            DriverState::Unconfigured => "not configured",
+            DriverState::NoAnswer => "NO ANSWER",
+        })
+    }
+}
+
+pub fn run(fpm: &mut FastPinballMonitor, args: &DriversArgs, format: OutputFormat) -> Exit {
+    match &args.command {
+        DriversCommand::Dump { file } => dump(fpm, file),
+        DriversCommand::Apply { file, yes } => apply(fpm, file, *yes),
+        DriversCommand::Status => status(fpm, format),
+    }
+}
+
+fn status(fpm: &mut FastPinballMonitor, format: OutputFormat) -> Exit {
+    // Drivers are numbered on from one node to the next
+    let nodes: Vec<(String, u16)> = fpm
+        .net
+        .list_nodes()
+        .into_iter()
+        .filter_map(|n| n.driver_count.map(|count| (n.node_id, count)))
+        .collect();
+    if nodes.iter().all(|(_, count)| *count == 0) {
+        eprintln!("No drivers found on the NET loop.");
+        return Exit::NoHardware;
+    }
+    let mut drivers = Vec::new();
+    let mut driver: u16 = 0;
+    for (node, count) in nodes {
+        for _ in 0..count {
+            if driver > u8::MAX as u16 {
+                break;
+            }
+            let config = fpm.net.driver_config(driver as u8);
+            let state = match &config {
+                None => DriverState::NoAnswer,
+                Some(c) if !c.is_configured() => DriverState::Unconfigured,
+                Some(c) if c.is_enabled() => DriverState::Enabled,
+                Some(_) => DriverState::Disabled,
+            };
+            drivers.push(DriverStatus {
+                driver: driver as u8,
+                node: node.clone(),
+                state,
+                config,
+            });
+            driver += 1;
+        }
+    }
+    let faults = drivers
+        .iter()
+        .filter(|d| matches!(d.state, DriverState::Disabled | DriverState::NoAnswer))
+        .count();
+
+    if format.is_document() {
+        print_document(&drivers, format);
+    } else {
+        println!("  {:<6} {:<4} {:<16} Mode", "Driver", "Node", "State");
+        for d in drivers.iter() {
+            let mode = d
+                .config
+                .filter(|c| c.is_configured())
+                .map(|c| format!("{:02X} (trigger {:02X})", c.mode, c.trigger))
+                .unwrap_or_else(|| "-".to_string());
+            println!(
+                "  {:<6} {:<4} {:<16} {}",
+                format!("{:02X}", d.driver),
+                d.node,
+                d.state.to_string(),
+                mode
+            );
+        }
+        if faults > 0 {
+            println!(
+                "{} driver(s) disabled or not answering. Unless your game code disabled them, check their coils and driver transistors.",
+                faults
+            );
+        }
+    }
+    Exit::Success
+}
+
+fn dump(fpm: &mut FastPinballMonitor, file: &Path) -> Exit {
+    let count: u16 = fpm
+        .net
+        .list_nodes()
+        .iter()
+        .filter_map(|n| n.driver_count)
+        .sum();
+    if count == 0 {
+        eprintln!("No drivers found on the NET loop.");
+        return Exit::NoHardware;
+    }
+    status!("Reading {} driver(s) ...", count);
+
+    let mut dump = DriverDump::default();
+    let mut missing = 0usize;
+    for driver in 0..count.min(u8::MAX as u16 + 1) {
+        let driver = driver as u8;
+        match fpm.net.driver_config(driver) {
+            Some(config) => dump.drivers.push(config),
+            None => {
+                eprintln!("Driver {:02X} did not report its configuration.", driver);
+                missing += 1;
+            }
+        }
+    }
+    if let Err(e) = save_document(file, &dump) {
+        eprintln!("{}", e);
+        return Exit::Error;
+    }
+    println!(
+        "Saved {} driver(s) to {}.",
+        dump.drivers.len(),
+        file.display()
+    );
+    if missing > 0 {
+        Exit::Error
+    } else {
+        Exit::Success
+    }
+}
+
+fn apply(fpm: &mut FastPinballMonitor, file: &Path, yes: bool) -> Exit {
+    let dump: DriverDump = match load_document(file) {
+        Ok(dump) => dump,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Exit::Error;
+        }
+    };
+    if dump.drivers.is_empty() {
+        println!("No drivers in {}.", file.display());
+        return Exit::Success;
+    }
+    if !yes {
+        print!(
+            "Configure {} driver(s) from {}? [y/N]: ",
+            dump.drivers.len(),
+            file.display()
+        );
+        let _ = io::stdout().flush();
+        let confirm = read_line_trimmed();
+        if !matches!(confirm.as_str(), "y" | "Y" | "yes" | "YES") {
+            println!("Canceled.");
+            return Exit::Canceled;
+        }
+    }
+
+    let mut applied = 0usize;
+    for config in dump.drivers.iter() {
+        if fpm.net.configure_driver(*config) {
+            applied += 1;
+        } else {
+            eprintln!("Driver {:02X} was not configured.", config.driver);
+        }
+    }
+    println!(
+        "Applied {} of {} driver configuration(s).",
+        applied,
+        dump.drivers.len()
+    );
+    if applied == dump.drivers.len() {
+        Exit::Success
+    } else {
+        Exit::Error
+    }
+}
@@ -0,0 +1,38 @@
+use crate::commands::utils::{parse_hex_address, parse_version_arg};
+use crate::emulator::{Emulator, EmulatorConfig};
+
+/// Options for `emulate`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct EmulateArgs {
+    /// Address to serve the NET endpoint on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:7700")]
+    pub net_listen: String,
+    /// Address to serve the EXP endpoint on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:7701")]
+    pub exp_listen: String,
+    /// Version boards report after being flashed (default: keep their current version)
+    #[arg(long, value_name = "X.YY", value_parser = parse_version_arg)]
+    pub flashed_version: Option<String>,
+    /// Address of an EXP board to leave stuck in its bootloader until flashed (repeatable)
+    #[arg(long, value_name = "ADDR", value_parser = parse_hex_address)]
+    pub bootloader: Vec<String>,
+}
+
+/// Serve an emulated Neuron with a few node and EXP boards over TCP until interrupted.
+pub fn run(args: &EmulateArgs) -> Result<(), String> {
+    let mut config = EmulatorConfig {
+        flashed_version: args.flashed_version.clone(),
+        ..EmulatorConfig::default()
+    };
+    for board in config.exp_boards.iter_mut() {
+        board.in_bootloader = args.bootloader.contains(&board.address);
+    }
+    println!(
+        "Emulating a FAST Neuron. Connect with: --net-port tcp://{} --exp-port tcp://{}",
+        args.net_listen, args.exp_listen
+    );
+    println!("Press Ctrl+C to stop.");
+    Emulator::new(config)
+        .serve_tcp(&args.net_listen, &args.exp_listen)
+        .map_err(|e| format!("cannot listen: {}", e))
+}
@@ -0,0 +1,153 @@
+// Live hardware events for the WebSocket clients of `serve` (`GET /events`): switch
+// changes as the NET processor reports them, and boards connecting, disconnecting or
+// changing firmware between rescans. Each event is one JSON text message:
+//
+//     {"type":"switch","switch":12,"state":"closed"}
+//     {"type":"board_changed","before":{...},"after":{...}}
+//
+// A client first gets a `boards` event listing every board, to start from.
+
+use crate::commands::hardware_event::HardwareEvent;
+use crate::commands::inventory::Inventory;
+use crate::protocol::net_commands::{NEURON_HARDWARE, NetCommand};
+use crate::shared_monitor::SharedMonitor;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tiny_http::{Header, ReadWrite, Request, Response};
+use tungstenite::protocol::{Role, WebSocket};
+use tungstenite::{Message, handshake::derive_accept_key};
+
+type Client = WebSocket<Box<dyn ReadWrite + Send>>;
+
+/// The WebSocket clients, and the board list their events are based on.
+#[derive(Default)]
+pub struct EventStream {
+    clients: Arc<Mutex<Vec<Client>>>,
+    /// Boards at the last rescan; `None` while nobody listens.
+    boards: Option<Inventory>,
+    switch_events: bool,
+}
+
+impl EventStream {
+    /// Complete the WebSocket handshake of `request` and start sending it events.
+    pub fn accept(&mut self, fpm: &SharedMonitor, request: Request) {
+        let key = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+            .map(|h| h.value.to_string());
+        let Some(key) = key else {
+            let body = serde_json::json!({ "error": "/events is a WebSocket endpoint" });
+            let _ = request.respond(Response::from_string(body.to_string()).with_status_code(400));
+            return;
+        };
+        if !self.switch_events {
+            match enable_switch_events(fpm, Arc::clone(&self.clients)) {
+                Ok(()) => self.switch_events = true,
+                Err(e) => log::warn!("Switch events unavailable: {}", e),
+            }
+        }
+
+        // `upgrade` adds the `Upgrade` and `Connection` headers
+        let accept = Header::from_bytes(
+            &b"Sec-WebSocket-Accept"[..],
+            derive_accept_key(key.as_bytes()),
+        )
+        .expect("accept key is a valid header value");
+        let response = Response::empty(101).with_header(accept);
+        let mut client =
+            WebSocket::from_raw_socket(request.upgrade("websocket", response), Role::Server, None);
+
+        // Bring the other clients up to date first, so the snapshot is the new baseline
+        self.rescan(fpm);
+        let boards = match self.boards.take() {
+            Some(boards) => boards,
+            None => Inventory::capture_shared(fpm),
+        };
+        let snapshot = HardwareEvent::Boards {
+            boards: boards.boards.clone(),
+        };
+        if send(&mut client, &snapshot) {
+            lock(&self.clients).push(client);
+        }
+        self.boards = Some(boards);
+    }
+
+    /// Re-list the boards and tell the clients what changed since the last rescan.
+    pub fn rescan(&mut self, fpm: &SharedMonitor) {
+        if lock(&self.clients).is_empty() {
+            self.boards = None;
+            return;
+        }
+        let now = Inventory::capture_shared(fpm);
+        if let Some(before) = &self.boards {
+            let diff = now.diff(before);
+            let events = diff
+                .added
+                .into_iter()
+                .map(|board| HardwareEvent::BoardConnected { board })
+                .chain(
+                    diff.removed
+                        .into_iter()
+                        .map(|board| HardwareEvent::BoardDisconnected { board }),
+                )
+                .chain(
+                    diff.changed
+                        .into_iter()
+                        .map(|c| HardwareEvent::BoardChanged {
+                            before: c.before,
+                            after: c.after,
+                        }),
+                );
+            for event in events {
+                broadcast(&self.clients, &event);
+            }
+        }
+        self.boards = Some(now);
+    }
+}
+
+/// Turn on switch reporting and forward switch changes to the clients from a thread of
+/// their own, so a slow client does not hold up the NET reader.
+fn enable_switch_events(
+    fpm: &SharedMonitor,
+    clients: Arc<Mutex<Vec<Client>>>,
+) -> Result<(), String> {
+    let events = fpm.subscribe_net_events()?;
+    let configure = NetCommand::ConfigureHardware {
+        hardware: NEURON_HARDWARE,
+        switch_reporting: 0xFF,
+    };
+    let mut net = fpm.net();
+    net.send_command(&configure)
+        .map_err(|e| format!("Failed to configure switch reporting: {}", e))?;
+    // Keep the `CH:P` reply out of the next command's response
+    let _ = net.receive();
+    std::thread::spawn(move || {
+        for response in events {
+            if let Some(event) = HardwareEvent::from_net(&response) {
+                broadcast(&clients, &event);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Send `event` to every client, dropping the ones that are gone.
+fn broadcast(clients: &Mutex<Vec<Client>>, event: &HardwareEvent) {
+    lock(clients).retain_mut(|client| send(client, event));
+}
+
+fn send(client: &mut Client, event: &HardwareEvent) -> bool {
+    let text = serde_json::to_string(event).unwrap_or_default();
+    match client.send(Message::text(text)) {
+        Ok(()) => true,
+        Err(e) => {
+            log::debug!("Dropping WebSocket client: {}", e);
+            false
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,58 @@
+use crate::protocol::FlashOutcome;
+
+/// Process exit codes, so scripts can tell failure modes apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Success = 0,
+    /// Anything not covered below, e.g. a bad config file or a missing firmware file.
+    Error = 1,
+    /// No FAST hardware, or not the requested board, was found.
+    NoHardware = 2,
+    /// The firmware could not be sent to the board.
+    FlashFailed = 3,
+    /// The board does not report the expected version after flashing.
+    VerifyMismatch = 4,
+    /// Fetching or installing firmware files failed.
+    DownloadFailed = 5,
+    /// The user declined a prompt.
+    Canceled = 6,
+    /// The connected boards differ from a saved inventory or status report.
+    Differs = 7,
+    /// Stopped by Ctrl+C with no flash cut short (see `interrupt`).
+    Interrupted = crate::interrupt::INTERRUPTED_EXIT_CODE as isize,
+}
+
+impl Exit {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    /// `NoHardware` unless anything answered, for commands that list or drive boards.
+    pub fn found(any: bool) -> Self {
+        if any { Exit::Success } else { Exit::NoHardware }
+    }
+
+    /// The worst of several flashes: a failed or stopped flash wins over a version
+    /// mismatch.
+    pub fn for_flashes(outcomes: impl IntoIterator<Item = FlashOutcome>) -> Self {
+        outcomes
+            .into_iter()
+            .map(Exit::from)
+            .fold(Exit::Success, |worst, exit| match (worst, exit) {
+                (Exit::FlashFailed, _) | (_, Exit::FlashFailed) => Exit::FlashFailed,
+                (Exit::VerifyMismatch, _) | (_, Exit::VerifyMismatch) => Exit::VerifyMismatch,
+                _ => Exit::Success,
+            })
+    }
+}
+
+impl From<FlashOutcome> for Exit {
+    fn from(outcome: FlashOutcome) -> Self {
+        match outcome {
+            FlashOutcome::Verified | FlashOutcome::Unverified => Exit::Success,
+            FlashOutcome::Mismatch => Exit::VerifyMismatch,
+            // A cancelled flash did not get the firmware onto the board either
+            FlashOutcome::Failed | FlashOutcome::Cancelled => Exit::FlashFailed,
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::{print_document, read_line_trimmed};
+use crate::config::OutputFormat;
+use crate::firmware_repo::FirmwareEntry;
+use std::io::{self, Write};
+
+/// Options for `firmware`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct FirmwareArgs {
+    #[command(subcommand)]
+    pub command: FirmwareCommand,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum FirmwareCommand {
+    /// List every firmware file in ~/.fast/firmware
+    #[command(visible_alias = "ls")]
+    List,
+    /// Delete all but the newest versions of each board's firmware
+    Prune(PruneArgs),
+}
+
+/// Options for `firmware prune`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct PruneArgs {
+    /// Versions to keep per board and protocol
+    #[arg(long, value_name = "N", default_value_t = 2, value_parser = clap::value_parser!(u32).range(1..))]
+    pub keep: u32,
+    /// Only show what would be deleted
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Delete without asking for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+pub fn run(args: &FirmwareArgs, format: OutputFormat) -> Exit {
+    match &args.command {
+        FirmwareCommand::List => {
+            list(format);
+            Exit::Success
+        }
+        FirmwareCommand::Prune(prune_args) => prune(prune_args),
+    }
+}
+
+/// Every available firmware file, ordered by board, protocol and then version.
+pub fn entries() -> Vec<FirmwareEntry> {
+    crate::firmware_repo::get().catalog().entries().to_vec()
+}
+
+fn list(format: OutputFormat) {
+    let entries = entries();
+    if format.is_document() {
+        print_document(&entries, format);
+        return;
+    }
+    if entries.is_empty() {
+        println!(
+            "No firmware files in {}.",
+            crate::firmware_repo::get().dir().display()
+        );
+        return;
+    }
+    println!(
+        "  {:<14} {:<8} {:<8} {:>8}  Path",
+        "Board", "Protocol", "Version", "Size"
+    );
+    for e in entries.iter() {
+        println!(
+            "  {:<14} {:<8} {:<8} {:>8}  {}",
+            e.board,
+            e.protocol,
+            e.version,
+            format_size(e.size),
+            e.path
+        );
+    }
+    let total: u64 = entries.iter().map(|e| e.size).sum();
+    println!(
+        "{} firmware file(s), {} in total.",
+        entries.len(),
+        format_size(total)
+    );
+}
+
+fn prune(args: &PruneArgs) -> Exit {
+    let entries = entries();
+    // Entries are sorted oldest first within each board/protocol, so everything but the
+    // last `keep` of each group goes
+    let mut doomed: Vec<&FirmwareEntry> = Vec::new();
+    for (i, e) in entries.iter().enumerate() {
+        let newer = entries[i + 1..]
+            .iter()
+            .take_while(|n| n.board == e.board && n.protocol == e.protocol)
+            .count();
+        if newer >= args.keep as usize {
+            doomed.push(e);
+        }
+    }
+
+    if doomed.is_empty() {
+        println!(
+            "Nothing to prune; no board has more than {} firmware version(s).",
+            args.keep
+        );
+        return Exit::Success;
+    }
+
+    let total: u64 = doomed.iter().map(|e| e.size).sum();
+    status!(
+        "Firmware to delete (keeping the newest {} per board):",
+        args.keep
+    );
+    for e in doomed.iter() {
+        status!("  {} {} {}  {}", e.board, e.protocol, e.version, e.path);
+    }
+    if args.dry_run {
+        println!(
+            "Would delete {} firmware file(s), {}.",
+            doomed.len(),
+            format_size(total)
+        );
+        return Exit::Success;
+    }
+    if !args.yes {
+        print!("Delete {} file(s)? [y/N]: ", doomed.len());
+        let _ = io::stdout().flush();
+        let confirm = read_line_trimmed();
+        if !matches!(confirm.as_str(), "y" | "Y" | "yes" | "YES") {
+            println!("Canceled.");
+            return Exit::Canceled;
+        }
+    }
+
+    let mut deleted = 0usize;
+    let mut freed = 0u64;
+    let mut failed = 0usize;
+    for e in doomed {
+        match std::fs::remove_file(&e.path) {
+            Ok(()) => {
+                deleted += 1;
+                freed += e.size;
+                // Release notes of a deleted version go with it
+                let _ = std::fs::remove_file(std::path::Path::new(&e.path).with_extension("md"));
+            }
+            Err(err) => {
+                eprintln!("Failed to delete {}: {}", e.path, err);
+                failed += 1;
+            }
+        }
+    }
+    println!(
+        "Deleted {} firmware file(s), freeing {}.",
+        deleted,
+        format_size(freed)
+    );
+    if failed > 0 {
+        Exit::Error
+    } else {
+        Exit::Success
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
@@ -0,0 +1,63 @@
+// What happens on the hardware, as told to the clients of `serve` (`GET /events`, see
+// `event_stream`) and to MQTT subscribers (`mqtt`).
+
+use crate::commands::inventory::InventoryBoard;
+use crate::protocol::responses::NetResponse;
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HardwareEvent {
+    /// Every board, sent to a client when it connects.
+    Boards {
+        boards: Vec<InventoryBoard>,
+    },
+    Switch {
+        switch: u16,
+        state: SwitchState,
+    },
+    BoardConnected {
+        board: InventoryBoard,
+    },
+    BoardDisconnected {
+        board: InventoryBoard,
+    },
+    /// A board at the same location now reports a different board or firmware.
+    BoardChanged {
+        before: InventoryBoard,
+        after: InventoryBoard,
+    },
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SwitchState {
+    Closed,
+    Open,
+}
+
+impl SwitchState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SwitchState::Closed => "closed",
+            SwitchState::Open => "open",
+        }
+    }
+}
+
+impl HardwareEvent {
+    /// The event for a NET message, if it is one clients are told about.
+    pub fn from_net(response: &NetResponse) -> Option<Self> {
+        match *response {
+            NetResponse::SwitchClosed { switch } => Some(HardwareEvent::Switch {
+                switch,
+                state: SwitchState::Closed,
+            }),
+            NetResponse::SwitchOpened { switch } => Some(HardwareEvent::Switch {
+                switch,
+                state: SwitchState::Open,
+            }),
+            _ => None,
+        }
+    }
+}
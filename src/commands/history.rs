@@ -0,0 +1,81 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::print_document;
+use crate::config::OutputFormat;
+use crate::history::{self, FlashRecord};
+
+/// Options for `history`.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct HistoryArgs {
+    /// Show only the most recent N flashes
+    #[arg(short = 'n', long, value_name = "N")]
+    pub limit: Option<usize>,
+    /// Only flashes of this target (EXP, NET or DSP)
+    #[arg(long, value_name = "TARGET")]
+    pub target: Option<String>,
+    /// Only flashes of the EXP board at this address (e.g. B4)
+    #[arg(short, long, value_name = "HEX")]
+    pub address: Option<String>,
+}
+
+/// Show the flash journal, oldest first.
+pub fn run(args: &HistoryArgs, format: OutputFormat) -> Exit {
+    let path = crate::config::history_path();
+    let records = match history::load(&path) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Exit::Error;
+        }
+    };
+    let matching: Vec<&FlashRecord> = records
+        .iter()
+        .filter(|r| {
+            args.target
+                .as_ref()
+                .is_none_or(|t| r.target.eq_ignore_ascii_case(t))
+        })
+        .filter(|r| {
+            args.address.as_ref().is_none_or(|a| {
+                r.address
+                    .as_ref()
+                    .is_some_and(|ra| ra.eq_ignore_ascii_case(a))
+            })
+        })
+        .collect();
+    let skip = args.limit.map_or(0, |n| matching.len().saturating_sub(n));
+    let shown = &matching[skip..];
+
+    if format.is_document() {
+        print_document(shown, format);
+        return Exit::Success;
+    }
+    if shown.is_empty() {
+        println!("No flashes recorded in {}.", path.display());
+        return Exit::Success;
+    }
+    println!(
+        "  {:<20} {:<6} {:<14} {:<7} {:<17} {:<16} {:>8}",
+        "Time", "Target", "Board", "Address", "Version", "Result", "Duration"
+    );
+    for r in shown {
+        let versions = format!(
+            "{} -> {}",
+            r.old_version.as_deref().unwrap_or("?"),
+            r.new_version.as_deref().unwrap_or("?")
+        );
+        println!(
+            "  {:<20} {:<6} {:<14} {:<7} {:<17} {:<16} {:>7.1}s",
+            r.timestamp,
+            r.target,
+            r.board.as_deref().unwrap_or("-"),
+            r.address.as_deref().unwrap_or("-"),
+            versions,
+            r.result.to_string(),
+            r.duration_secs
+        );
+    }
+    if skip > 0 {
+        println!("{} older flash(es) not shown.", skip);
+    }
+    Exit::Success
+}
@@ -0,0 +1,107 @@
+use crate::commands::check_updates::install_files;
+use crate::firmware_repo::parse_firmware_filename;
+use crate::firmware_source::{FirmwareSource, LocalArchive, LocalDirectory};
+use std::path::{Component, Path, PathBuf};
+
+/// Options for `import-firmware`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ImportFirmwareArgs {
+    /// ZIP archive or directory holding firmware files
+    #[arg(value_name = "ZIP_OR_DIR")]
+    pub path: PathBuf,
+}
+
+/// Copy firmware files from a local ZIP or directory into the firmware directory, for
+/// machines without internet access.
+///
+/// Files are filed under their protocol folder (e.g. `EXP/`) by their name, so it does
+/// not matter how the source is laid out; firmware files whose name is not a firmware
+/// file name are skipped.
+pub fn run(args: &ImportFirmwareArgs) -> Result<(), String> {
+    let source: Box<dyn FirmwareSource> = if args.path.is_dir() {
+        Box::new(LocalDirectory {
+            path: args.path.clone(),
+        })
+    } else if args.path.is_file() {
+        Box::new(LocalArchive {
+            path: args.path.clone(),
+        })
+    } else {
+        return Err(format!("{} does not exist", args.path.display()));
+    };
+
+    status!("Importing firmware from {} ...", source.describe());
+    let files = source.fetch()?;
+    let mut skipped = 0usize;
+    let installed = install_files(source.as_ref(), files, |rel_path| {
+        let location = firmware_location(rel_path);
+        match &location {
+            Some(dest) => status!("  {}", dest.display()),
+            None => {
+                eprintln!("Skipping {}: not a firmware file name.", rel_path.display());
+                skipped += 1;
+            }
+        }
+        location
+    })?;
+
+    let target = crate::config::firmware_dir();
+    if installed == 0 {
+        return Err(format!(
+            "no firmware files ({{board}}_{{protocol}}_firmware_v_{{major}}_{{minor}}.txt, .hex or .bin) found in {}",
+            source.describe()
+        ));
+    }
+    println!(
+        "Imported {} firmware files into {}.",
+        installed,
+        target.display()
+    );
+    if skipped > 0 {
+        println!("{} file(s) with unrecognized names were skipped.", skipped);
+    }
+    Ok(())
+}
+
+/// Where a firmware file belongs relative to the firmware directory, e.g.
+/// `EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt`, or `None` if the name does not parse or
+/// its protocol is not a plain folder name.
+fn firmware_location(rel_path: &Path) -> Option<PathBuf> {
+    let (_, protocol, _) = parse_firmware_filename(rel_path)?;
+    // The protocol comes from the file name, so `..` or a drive must not reach the join
+    let mut components = Path::new(&protocol).components();
+    let (Some(Component::Normal(_)), None) = (components.next(), components.next()) else {
+        return None;
+    };
+    Some(Path::new(&protocol).join(rel_path.file_name()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_go_under_their_protocol_folder() {
+        assert_eq!(
+            firmware_location(Path::new("any/where/FP-EXP-0071_EXP_firmware_v_0_50.txt")),
+            Some(PathBuf::from("EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt"))
+        );
+        assert_eq!(firmware_location(Path::new("readme.txt")), None);
+    }
+
+    #[test]
+    fn protocol_must_be_a_plain_folder_name() {
+        assert_eq!(
+            firmware_location(Path::new("FP-EXP-0071_.._firmware_v_0_50.txt")),
+            None
+        );
+        assert_eq!(
+            firmware_location(Path::new("FP-EXP-0071_._firmware_v_0_50.txt")),
+            None
+        );
+        assert_eq!(
+            firmware_location(Path::new("FP-EXP-0071__firmware_v_0_50.txt")),
+            None
+        );
+    }
+}
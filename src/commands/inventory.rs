@@ -0,0 +1,364 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::{load_document, print_document, save_document};
+use crate::config::OutputFormat;
+use crate::fast_monitor::{ExpBoardInfo, FastPinballMonitor, NetBoardInfo, ProcessorInfo};
+use crate::shared_monitor::SharedMonitor;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Options for `inventory`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct InventoryArgs {
+    #[command(subcommand)]
+    pub command: InventoryCommand,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum InventoryCommand {
+    /// Write every connected board and its firmware to a file (.yaml/.yml for YAML,
+    /// JSON otherwise; default: the profile's inventory)
+    Save { file: Option<PathBuf> },
+    /// Compare the connected boards against a file written by `inventory save` (default:
+    /// the profile's inventory)
+    Diff { file: Option<PathBuf> },
+}
+
+/// The boards of a machine, as written by `inventory save`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Inventory {
+    pub boards: Vec<InventoryBoard>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InventoryBoard {
+    /// Where the board sits, e.g. `EXP B4`, `NET 00` or `SEG`.
+    pub location: String,
+    pub board: String,
+    pub firmware: String,
+    /// Fields a NET node reports after its firmware, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra: Vec<String>,
+}
+
+/// A board present in both inventories that differs between them.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChangedBoard {
+    pub before: InventoryBoard,
+    pub after: InventoryBoard,
+}
+
+/// How the connected boards differ from a saved inventory.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct InventoryDiff {
+    pub added: Vec<InventoryBoard>,
+    pub removed: Vec<InventoryBoard>,
+    pub changed: Vec<ChangedBoard>,
+}
+
+impl Inventory {
+    /// Every board and processor connected to `fpm`.
+    pub fn capture(fpm: &mut FastPinballMonitor) -> Self {
+        let exp = fpm.list_connected_exp_boards();
+        let nodes = fpm.list_connected_net_boards();
+        let processors = [
+            ("SEG", fpm.seg_info()),
+            ("AUD", fpm.aud_info()),
+            ("DSP", fpm.dsp_info()),
+        ];
+        Self::from_scan(exp, nodes, processors)
+    }
+
+    /// `capture` over a `SharedMonitor`: an EXP interface busy with a flash contributes
+    /// the boards of its last scan.
+    pub fn capture_shared(fpm: &SharedMonitor) -> Self {
+        let exp = fpm.list_connected_exp_boards();
+        let nodes = fpm.list_connected_net_boards();
+        let processors = [
+            ("SEG", fpm.seg_info()),
+            ("AUD", fpm.aud_info()),
+            ("DSP", fpm.dsp_info()),
+        ];
+        Self::from_scan(exp, nodes, processors)
+    }
+
+    fn from_scan(
+        exp: Vec<ExpBoardInfo>,
+        nodes: HashMap<usize, NetBoardInfo>,
+        processors: [(&str, Option<ProcessorInfo>); 3],
+    ) -> Self {
+        let mut boards: Vec<InventoryBoard> = exp
+            .into_iter()
+            .map(|b| InventoryBoard {
+                location: format!("EXP {}", b.address),
+                board: b.board_name,
+                firmware: b.version,
+                extra: Vec::new(),
+            })
+            .collect();
+        let nodes: BTreeMap<usize, NetBoardInfo> = nodes.into_iter().collect();
+        boards.extend(nodes.into_values().map(|n| InventoryBoard {
+            location: format!("NET {}", n.node_id),
+            board: n.node_name,
+            firmware: n.firmware,
+            extra: n.extra_fields,
+        }));
+        boards.extend(processors.into_iter().filter_map(|(location, info)| {
+            info.map(|p| InventoryBoard {
+                location: location.to_string(),
+                board: p.board_name,
+                firmware: p.version,
+                extra: Vec::new(),
+            })
+        }));
+        Self { boards }
+    }
+
+    /// Read an inventory file, YAML or JSON depending on its extension.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        load_document(path)
+    }
+
+    /// Write the inventory, as YAML or JSON depending on the extension of `path`.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        save_document(path, self)
+    }
+
+    /// What changed going from `baseline` to `self`, matching boards by location.
+    pub fn diff(&self, baseline: &Inventory) -> InventoryDiff {
+        let before: BTreeMap<&str, &InventoryBoard> = baseline
+            .boards
+            .iter()
+            .map(|b| (b.location.as_str(), b))
+            .collect();
+        let after: BTreeMap<&str, &InventoryBoard> = self
+            .boards
+            .iter()
+            .map(|b| (b.location.as_str(), b))
+            .collect();
+        let mut diff = InventoryDiff::default();
+        for board in baseline.boards.iter() {
+            match after.get(board.location.as_str()) {
+                None => diff.removed.push(board.clone()),
+                Some(now) if *now != board => diff.changed.push(ChangedBoard {
+                    before: board.clone(),
+                    after: (*now).clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        diff.added = self
+            .boards
+            .iter()
+            .filter(|b| !before.contains_key(b.location.as_str()))
+            .cloned()
+            .collect();
+        diff
+    }
+}
+
+impl InventoryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+pub fn run(fpm: &mut FastPinballMonitor, args: &InventoryArgs, format: OutputFormat) -> Exit {
+    let file = match &args.command {
+        InventoryCommand::Save { file } | InventoryCommand::Diff { file } => file.clone(),
+    };
+    let Some(file) = file.or_else(default_file) else {
+        eprintln!("No inventory file given, and no inventory set in the config file or profile.");
+        return Exit::Error;
+    };
+    match &args.command {
+        InventoryCommand::Save { .. } => save(fpm, &file),
+        InventoryCommand::Diff { .. } => diff(fpm, &file, format),
+    }
+}
+
+/// The inventory file of the config file or the selected profile.
+fn default_file() -> Option<PathBuf> {
+    crate::config::get()
+        .inventory
+        .as_deref()
+        .map(crate::config::expand_home)
+}
+
+fn save(fpm: &mut FastPinballMonitor, file: &Path) -> Exit {
+    let inventory = Inventory::capture(fpm);
+    if let Err(e) = inventory.save(file) {
+        eprintln!("{}", e);
+        return Exit::Error;
+    }
+    println!(
+        "Saved {} boards to {}.",
+        inventory.boards.len(),
+        file.display()
+    );
+    Exit::Success
+}
+
+fn diff(fpm: &mut FastPinballMonitor, file: &Path, format: OutputFormat) -> Exit {
+    let baseline = match Inventory::load(file) {
+        Ok(baseline) => baseline,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Exit::Error;
+        }
+    };
+    let diff = Inventory::capture(fpm).diff(&baseline);
+    let exit = if diff.is_empty() {
+        Exit::Success
+    } else {
+        Exit::Differs
+    };
+
+    if format.is_document() {
+        print_document(&diff, format);
+        return exit;
+    }
+    if diff.is_empty() {
+        println!("All boards match {}.", file.display());
+        return exit;
+    }
+    println!("Differences from {}:", file.display());
+    for board in diff.added.iter() {
+        println!(
+            "  + {:<10} {:<14} {:<10} added",
+            board.location, board.board, board.firmware
+        );
+    }
+    for board in diff.removed.iter() {
+        println!(
+            "  - {:<10} {:<14} {:<10} missing",
+            board.location, board.board, board.firmware
+        );
+    }
+    for change in diff.changed.iter() {
+        let (before, after) = (&change.before, &change.after);
+        let note = if before.board != after.board {
+            format!("was {} {}", before.board, before.firmware)
+        } else if before.firmware != after.firmware {
+            format!("was {}", before.firmware)
+        } else {
+            format!("details were {}", before.extra.join(","))
+        };
+        println!(
+            "  ~ {:<10} {:<14} {:<10} {}",
+            after.location, after.board, after.firmware, note
+        );
+    }
+    exit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(location: &str, board: &str, firmware: &str) -> InventoryBoard {
+        InventoryBoard {
+            location: location.to_string(),
+            board: board.to_string(),
+            firmware: firmware.to_string(),
+            extra: Vec::new(),
+        }
+    }
+
+    fn node(id: &str, name: &str) -> NetBoardInfo {
+        NetBoardInfo {
+            node_id: id.to_string(),
+            node_name: name.to_string(),
+            firmware: "1.05".to_string(),
+            driver_count: Some(8),
+            switch_count: Some(32),
+            extra_fields: vec!["08".to_string(), "20".to_string()],
+        }
+    }
+
+    #[test]
+    fn scan_lists_exp_then_nodes_in_order_then_processors() {
+        let exp = vec![ExpBoardInfo {
+            port: "exp".to_string(),
+            address: "B4".to_string(),
+            board_name: "FP-EXP-0071".to_string(),
+            version: "0.48".to_string(),
+            available_versions: None,
+            is_controller: false,
+            breakouts: Vec::new(),
+        }];
+        let nodes = HashMap::from([
+            (1, node("01", "FP-I/O-0804")),
+            (0, node("00", "FP-I/O-3208")),
+        ]);
+        let seg = ProcessorInfo {
+            port: "seg".to_string(),
+            board_name: "FP-SEG".to_string(),
+            version: "0.10".to_string(),
+        };
+        let inventory = Inventory::from_scan(
+            exp,
+            nodes,
+            [("SEG", Some(seg)), ("AUD", None), ("DSP", None)],
+        );
+        let locations: Vec<&str> = inventory
+            .boards
+            .iter()
+            .map(|b| b.location.as_str())
+            .collect();
+        assert_eq!(locations, ["EXP B4", "NET 00", "NET 01", "SEG"]);
+        assert_eq!(inventory.boards[1].extra, ["08", "20"]);
+    }
+
+    #[test]
+    fn diff_matches_boards_by_location() {
+        let baseline = Inventory {
+            boards: vec![
+                board("EXP B4", "FP-EXP-0071", "0.48"),
+                board("EXP 88", "FP-EXP-0091", "0.46"),
+                board("NET 00", "FP-I/O-3208", "1.05"),
+            ],
+        };
+        let now = Inventory {
+            boards: vec![
+                board("EXP B4", "FP-EXP-0071", "0.50"),
+                board("NET 00", "FP-I/O-3208", "1.05"),
+                board("NET 01", "FP-I/O-0804", "1.05"),
+            ],
+        };
+        let diff = now.diff(&baseline);
+        assert_eq!(diff.added, [board("NET 01", "FP-I/O-0804", "1.05")]);
+        assert_eq!(diff.removed, [board("EXP 88", "FP-EXP-0091", "0.46")]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].before.firmware, "0.48");
+        assert_eq!(diff.changed[0].after.firmware, "0.50");
+        assert!(now.diff(&now).is_empty());
+    }
+
+    #[test]
+    fn saves_and_loads_yaml_and_json() {
+        let inventory = Inventory {
+            boards: vec![board("EXP B4", "FP-EXP-0071", "0.48"), {
+                let mut b = board("NET 00", "FP-I/O-3208", "1.05");
+                b.extra = vec!["08".to_string(), "20".to_string()];
+                b
+            }],
+        };
+        let extensions: &[&str] = if cfg!(feature = "export") {
+            &["yaml", "json"]
+        } else {
+            &["json"]
+        };
+        for extension in extensions {
+            let path = std::env::temp_dir().join(format!(
+                "fast-pinball-test-{}-inventory.{}",
+                std::process::id(),
+                extension
+            ));
+            inventory.save(&path).unwrap();
+            let loaded = Inventory::load(&path);
+            let _ = std::fs::remove_file(&path);
+            assert_eq!(loaded.unwrap(), inventory);
+        }
+    }
+}
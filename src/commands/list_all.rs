@@ -0,0 +1,68 @@
+use crate::commands::exit::Exit;
+use crate::commands::status::{ProcessorStatus, print_csv};
+use crate::commands::utils::print_document;
+use crate::config::OutputFormat;
+use crate::fast_monitor::{FastPinballMonitor, NetBoardInfo};
+use std::collections::BTreeMap;
+
+/// List EXP and NET boards, plus the SEG, AUD and DSP processors when connected; as
+/// JSON, one object with `exp` and `net` arrays and `seg`/`aud`/`dsp` objects (or `null`);
+/// as CSV, one row per board. `NoHardware` when nothing answered at all.
+pub fn run(fpm: &mut FastPinballMonitor, format: OutputFormat) -> Exit {
+    if format == OutputFormat::Csv {
+        let mut rows = crate::commands::list_exp::rows(fpm.list_connected_exp_boards());
+        let net: BTreeMap<usize, NetBoardInfo> =
+            fpm.list_connected_net_boards().into_iter().collect();
+        rows.extend(crate::commands::list_net::rows(
+            &fpm.net.port_name,
+            net.into_values(),
+        ));
+        rows.extend(
+            ProcessorStatus::connected(fpm)
+                .iter()
+                .map(ProcessorStatus::row),
+        );
+        if let Err(e) = print_csv(&rows) {
+            eprintln!("Failed to write CSV: {}", e);
+        }
+        return Exit::found(!rows.is_empty());
+    }
+    if format.is_document() {
+        let exp = fpm.list_connected_exp_boards();
+        let net: BTreeMap<usize, NetBoardInfo> =
+            fpm.list_connected_net_boards().into_iter().collect();
+        let (seg, aud, dsp) = (fpm.seg_info(), fpm.aud_info(), fpm.dsp_info());
+        let any =
+            !exp.is_empty() || !net.is_empty() || seg.is_some() || aud.is_some() || dsp.is_some();
+        let doc = serde_json::json!({
+            "exp": exp,
+            "net": net.into_values().collect::<Vec<_>>(),
+            "seg": seg,
+            "aud": aud,
+            "dsp": dsp,
+        });
+        print_document(&doc, format);
+        return Exit::found(any);
+    }
+    let mut exits = vec![crate::commands::list_exp::run(
+        fpm,
+        &Default::default(),
+        format,
+    )];
+    println!();
+    exits.push(crate::commands::list_net::run(fpm, format));
+    // Optional processors are only mentioned when present
+    if fpm.seg.is_some() {
+        println!();
+        exits.push(crate::commands::list_seg::run(fpm, format));
+    }
+    if fpm.aud.is_some() {
+        println!();
+        exits.push(crate::commands::list_aud::run(fpm, format));
+    }
+    if fpm.dsp.is_some() {
+        println!();
+        exits.push(crate::commands::list_dsp::run(fpm, format));
+    }
+    Exit::found(exits.contains(&Exit::Success))
+}
@@ -0,0 +1,26 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::print_document;
+use crate::config::OutputFormat;
+use crate::fast_monitor::FastPinballMonitor;
+
+/// Show the audio interface's ID and firmware; as JSON, the board info or `null`.
+pub fn run(fpm: &mut FastPinballMonitor, format: OutputFormat) -> Exit {
+    let info = fpm.aud_info();
+    let exit = Exit::found(info.is_some());
+    if format.is_document() {
+        print_document(&info, format);
+        return exit;
+    }
+    match (info, fpm.aud.as_ref()) {
+        (Some(info), _) => {
+            println!("Audio interface:");
+            println!(
+                "  {} -> {} (version {})",
+                info.port, info.board_name, info.version
+            );
+        }
+        (None, Some(aud)) => println!("AUD port {} did not answer ID:.", aud.port_name),
+        (None, None) => println!("No AUD port found."),
+    }
+    exit
+}
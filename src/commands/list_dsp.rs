@@ -0,0 +1,26 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::print_document;
+use crate::config::OutputFormat;
+use crate::fast_monitor::FastPinballMonitor;
+
+/// Show the display controller's ID and firmware; as JSON, the board info or `null`.
+pub fn run(fpm: &mut FastPinballMonitor, format: OutputFormat) -> Exit {
+    let info = fpm.dsp_info();
+    let exit = Exit::found(info.is_some());
+    if format.is_document() {
+        print_document(&info, format);
+        return exit;
+    }
+    match (info, fpm.dsp.as_ref()) {
+        (Some(info), _) => {
+            println!("Display controller:");
+            println!(
+                "  {} -> {} (version {})",
+                info.port, info.board_name, info.version
+            );
+        }
+        (None, Some(dsp)) => println!("DSP port {} did not answer ID:.", dsp.port_name),
+        (None, None) => println!("No DSP port found."),
+    }
+    exit
+}
@@ -1,16 +1,158 @@
+use crate::commands::exit::Exit;
+use crate::commands::status::{BoardRow, ExpStatus, print_csv};
+use crate::commands::table::{Cell, Table, firmware_color};
+use crate::commands::utils::{Target, print_document};
+use crate::config::OutputFormat;
 use crate::fast_monitor::{ExpBoardInfo, FastPinballMonitor};
 
-pub fn run(fpm: &mut FastPinballMonitor) {
+/// Options for `list-exp`.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct ListExpArgs {
+    /// Probe every EXP address (00-FF), not only the known ones, and show what answers
+    /// at unknown addresses
+    #[arg(long)]
+    pub deep_scan: bool,
+}
+
+pub fn run(fpm: &mut FastPinballMonitor, args: &ListExpArgs, format: OutputFormat) -> Exit {
+    if args.deep_scan {
+        return deep_scan(fpm, format);
+    }
     let boards: Vec<ExpBoardInfo> = fpm.list_connected_exp_boards();
+    let exit = Exit::found(!boards.is_empty());
+    match format {
+        OutputFormat::Json | OutputFormat::Yaml => {
+            print_document(&boards, format);
+            return exit;
+        }
+        OutputFormat::Csv => {
+            write_csv(&rows(boards));
+            return exit;
+        }
+        OutputFormat::Text => {}
+    }
     if boards.is_empty() {
         println!("No EXP boards found.");
     } else {
+        print_boards(fpm, &boards);
+    }
+    exit
+}
+
+/// The boards, each followed by its breakouts, as CSV rows.
+pub fn rows(boards: Vec<ExpBoardInfo>) -> Vec<BoardRow> {
+    boards
+        .into_iter()
+        .flat_map(|b| ExpStatus::from_board(b).rows())
+        .collect()
+}
+
+fn write_csv(rows: &[BoardRow]) {
+    if let Err(e) = print_csv(rows) {
+        eprintln!("Failed to write CSV: {}", e);
+    }
+}
+
+// The controller's built-in EXP first, under a heading of its own, then the EXP boards
+fn print_boards(fpm: &FastPinballMonitor, boards: &[ExpBoardInfo]) {
+    let (controller, expansion): (Vec<&ExpBoardInfo>, Vec<&ExpBoardInfo>) =
+        boards.iter().partition(|b| b.is_controller);
+    if !controller.is_empty() {
+        println!("Controller (built-in EXP):");
+        print_board_lines(fpm, &controller);
+    }
+    if !expansion.is_empty() {
         println!("EXP boards:");
-        for b in boards {
-            println!(
-                "  Address {} -> {} (version {})",
-                b.address, b.board_name, b.version
-            );
+        print_board_lines(fpm, &expansion);
+    }
+}
+
+// Breakouts are listed under their EXP board, indented; they are not flashed on their own
+fn print_board_lines(fpm: &FastPinballMonitor, boards: &[&ExpBoardInfo]) {
+    // Only call out the port when boards may sit behind different EXP interfaces
+    let show_port = fpm.exp_ports.len() > 1;
+    let mut headers = vec!["Address", "Board", "Firmware", "Newest"];
+    if show_port {
+        headers.push("Port");
+    }
+    let mut table = Table::new(&headers);
+    for b in boards {
+        let available = b.available_versions.as_deref().unwrap_or_default();
+        let target = Target::for_board(&b.board_name, &format!("EXP {}", b.address), available);
+        let mut row = vec![
+            Cell::from(b.address.as_str()),
+            Cell::from(b.board_name.as_str()),
+            Cell::colored(
+                b.version.as_str(),
+                firmware_color(&b.version, target.as_ref(), available),
+            ),
+            Cell::from(target.map_or_else(|| "-".to_string(), |t| t.to_string())),
+        ];
+        if show_port {
+            row.push(Cell::from(b.port.as_str()));
+        }
+        table.row(row);
+        for breakout in b.breakouts.iter() {
+            table.row(vec![
+                Cell::from(format!("  {}", breakout.address)),
+                Cell::from(breakout.board_name.as_str()),
+                Cell::from(breakout.version.as_str()),
+                Cell::from("-"),
+            ]);
+        }
+    }
+    table.print(2);
+}
+
+fn deep_scan(fpm: &mut FastPinballMonitor, format: OutputFormat) -> Exit {
+    if format == OutputFormat::Text {
+        status!("Probing all 256 EXP addresses; this takes a few seconds...");
+    }
+    let scan = fpm.deep_scan_exp_boards();
+    let exit = Exit::found(!scan.boards.is_empty() || !scan.unknown.is_empty());
+    match format {
+        OutputFormat::Json | OutputFormat::Yaml => {
+            print_document(&scan, format);
+            return exit;
+        }
+        OutputFormat::Csv => {
+            let mut rows = rows(scan.boards);
+            rows.extend(scan.unknown.into_iter().map(|u| BoardRow {
+                kind: "Unknown".to_string(),
+                location: format!("EXP {}", u.address),
+                port: u.port,
+                board: u.response,
+                ..Default::default()
+            }));
+            write_csv(&rows);
+            return exit;
+        }
+        OutputFormat::Text => {}
+    }
+    if scan.boards.is_empty() && scan.unknown.is_empty() {
+        println!("Nothing answered at any EXP address.");
+        return exit;
+    }
+    print_boards(fpm, &scan.boards);
+    if !scan.unknown.is_empty() {
+        println!("Other EXP responders (not in the address map, or not answering with an ID):");
+        let show_port = fpm.exp_ports.len() > 1;
+        let mut table = Table::new(if show_port {
+            &["Address", "Response", "Port"]
+        } else {
+            &["Address", "Response"]
+        });
+        for u in scan.unknown.iter() {
+            table.row(vec![
+                Cell::from(u.address.as_str()),
+                Cell::from(u.response.as_str()),
+                Cell::from(u.port.as_str()),
+            ]);
         }
+        table.print(2);
+        println!(
+            "Add unknown addresses to ~/.fast/boards.toml to list and flash those boards as usual; boards in their bootloader can be flashed with recover."
+        );
     }
+    exit
 }
@@ -1,19 +1,67 @@
+use crate::commands::exit::Exit;
+use crate::commands::status::{BoardRow, NodeStatus, print_csv};
+use crate::commands::table::{Cell, Table, firmware_color};
+use crate::commands::utils::{Target, print_document};
+use crate::config::OutputFormat;
 use crate::fast_monitor::{FastPinballMonitor, NetBoardInfo};
 use std::collections::BTreeMap;
 
-pub fn run(fpm: &mut FastPinballMonitor) {
+pub fn run(fpm: &mut FastPinballMonitor, format: OutputFormat) -> Exit {
     let boards = fpm.list_connected_net_boards();
-    if boards.is_empty() {
-        println!("No NET boards found.");
-    } else {
-        println!("NET nodes:");
-        // Ensure stable ordered output by node id
-        let mut ordered: BTreeMap<usize, NetBoardInfo> = BTreeMap::new();
-        for (k, v) in boards.into_iter() {
-            ordered.insert(k, v);
+    // Ensure stable ordered output by node id
+    let ordered: BTreeMap<usize, NetBoardInfo> = boards.into_iter().collect();
+    let exit = Exit::found(!ordered.is_empty());
+    match format {
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let nodes: Vec<&NetBoardInfo> = ordered.values().collect();
+            print_document(&nodes, format);
+            return exit;
         }
-        for (_k, NetBoardInfo { node_id, node_name, firmware, .. }) in ordered.into_iter() {
-            println!("  Node {} ({}) -> firmware {}", node_id, node_name, firmware);
+        OutputFormat::Csv => {
+            if let Err(e) = print_csv(&rows(&fpm.net.port_name, ordered.into_values())) {
+                eprintln!("Failed to write CSV: {}", e);
+            }
+            return exit;
         }
+        OutputFormat::Text => {}
     }
+    if ordered.is_empty() {
+        println!("No NET nodes found.");
+        return exit;
+    }
+    println!("NET nodes:");
+    let mut table = Table::new(&["Node", "Board", "Firmware", "Newest", "Capabilities"]);
+    for node in ordered.values() {
+        let available = crate::firmware_repo::get().versions(&format!("{}_NET", node.node_name));
+        let capabilities = node.capabilities();
+        let target = Target::for_board(
+            &node.node_name,
+            &format!("NET {}", node.node_id),
+            &available,
+        );
+        table.row(vec![
+            Cell::from(node.node_id.as_str()),
+            Cell::from(node.node_name.as_str()),
+            Cell::colored(
+                node.firmware.as_str(),
+                firmware_color(&node.firmware, target.as_ref(), &available),
+            ),
+            Cell::from(target.map_or_else(|| "-".to_string(), |t| t.to_string())),
+            Cell::from(if capabilities.is_empty() {
+                "-".to_string()
+            } else {
+                capabilities
+            }),
+        ]);
+    }
+    table.print(2);
+    exit
+}
+
+/// The nodes as CSV rows, on the NET port `port`.
+pub fn rows(port: &str, nodes: impl IntoIterator<Item = NetBoardInfo>) -> Vec<BoardRow> {
+    nodes
+        .into_iter()
+        .map(|n| NodeStatus::from_node(n).row(port))
+        .collect()
 }
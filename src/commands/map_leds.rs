@@ -0,0 +1,166 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::{parse_color, parse_hex_address, read_line_trimmed, save_document};
+use crate::fast_monitor::FastPinballMonitor;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Options for `map-leds`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct MapLedsArgs {
+    /// Mapping file to write: .csv for CSV, .yaml/.yml for YAML, JSON otherwise
+    pub file: PathBuf,
+    /// EXP board address to map (hex, repeatable; every connected board by default)
+    #[arg(long, value_parser = parse_hex_address)]
+    pub address: Vec<String>,
+    /// Breakout behind each board to map (repeatable; the board's own LEDs by default)
+    #[arg(long)]
+    pub breakout: Vec<String>,
+    /// Number of LEDs in each chain
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u8).range(1..))]
+    pub count: u8,
+    /// Color of the lit LED (RRGGBB)
+    #[arg(long, value_name = "RRGGBB", value_parser = parse_color, default_value = "FFFFFF")]
+    pub color: (u8, u8, u8),
+}
+
+/// The playfield insert an LED lights.
+#[derive(Serialize, Debug, Clone)]
+pub struct LedMapping {
+    pub insert: String,
+    pub address: String,
+    pub breakout: Option<String>,
+    pub led: u8,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct LedMap {
+    pub leds: Vec<LedMapping>,
+}
+
+impl LedMap {
+    /// Write the map as CSV, YAML or JSON depending on the extension of `path`.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let is_csv = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+        if !is_csv {
+            return save_document(path, self);
+        }
+        self.save_csv(path)
+    }
+
+    #[cfg(feature = "export")]
+    fn save_csv(&self, path: &Path) -> Result<(), String> {
+        let mut writer = csv::Writer::from_path(path)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        for led in self.leds.iter() {
+            writer
+                .serialize(led)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        writer
+            .flush()
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    #[cfg(not(feature = "export"))]
+    fn save_csv(&self, _path: &Path) -> Result<(), String> {
+        Err(crate::commands::utils::NO_EXPORT.to_string())
+    }
+}
+
+// What the user answered for a lit LED
+enum Answer {
+    Insert(String),
+    Nothing,
+    EndOfChain,
+    Quit,
+}
+
+/// Light each LED in turn and ask which insert lit up, saving the answers as they come
+/// so an interrupted session keeps what was mapped.
+pub fn run(fpm: &mut FastPinballMonitor, args: &MapLedsArgs) -> Exit {
+    let boards: Vec<(String, String)> = fpm
+        .list_connected_exp_boards()
+        .into_iter()
+        .filter(|b| args.address.is_empty() || args.address.contains(&b.address))
+        .map(|b| (b.port, b.address))
+        .collect();
+    if let Some(missing) = args
+        .address
+        .iter()
+        .find(|a| !boards.iter().any(|(_, address)| address == *a))
+    {
+        eprintln!("No EXP board found at address {}.", missing);
+        return Exit::NoHardware;
+    }
+    if boards.is_empty() {
+        eprintln!("No EXP boards found.");
+        return Exit::NoHardware;
+    }
+    let breakouts: Vec<Option<String>> = if args.breakout.is_empty() {
+        vec![None]
+    } else {
+        args.breakout.iter().cloned().map(Some).collect()
+    };
+
+    println!("Type the name of the insert that lights up for each LED.");
+    println!("Enter alone: nothing lit   -: end of this chain   q: stop and save");
+    let mut map = LedMap::default();
+    'chains: for (port, address) in boards.iter() {
+        for breakout in breakouts.iter() {
+            let target = format!("{}{}", address, breakout.as_deref().unwrap_or_default());
+            let Some(exp) = fpm.exp_port(port) else {
+                continue;
+            };
+            exp.select_address(&target);
+            exp.set_leds(0, &vec![(0, 0, 0); args.count as usize]);
+            for led in 0..args.count {
+                exp.set_leds(led, &[args.color]);
+                let answer = ask(&format!("EXP {} LED {}", target, led));
+                exp.set_leds(led, &[(0, 0, 0)]);
+                match answer {
+                    Answer::Insert(insert) => {
+                        map.leds.push(LedMapping {
+                            insert,
+                            address: address.clone(),
+                            breakout: breakout.clone(),
+                            led,
+                        });
+                        if let Err(e) = map.save(&args.file) {
+                            eprintln!("{}", e);
+                            return Exit::Error;
+                        }
+                    }
+                    Answer::Nothing => {}
+                    Answer::EndOfChain => break,
+                    Answer::Quit => break 'chains,
+                }
+            }
+        }
+    }
+
+    if let Err(e) = map.save(&args.file) {
+        eprintln!("{}", e);
+        return Exit::Error;
+    }
+    println!(
+        "Saved {} LED(s) to {}.",
+        map.leds.len(),
+        args.file.display()
+    );
+    Exit::Success
+}
+
+fn ask(led: &str) -> Answer {
+    print!("{}: ", led);
+    let _ = io::stdout().flush();
+    match read_line_trimmed().as_str() {
+        "" => Answer::Nothing,
+        "-" => Answer::EndOfChain,
+        "q" | "Q" => Answer::Quit,
+        insert => Answer::Insert(insert.to_string()),
+    }
+}
@@ -0,0 +1,110 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::{load_document, read_line_trimmed, save_document};
+use crate::fast_monitor::FastPinballMonitor;
+use crate::protocol::net_commands::{NEURON_HARDWARE, NetCommand};
+use crate::protocol::responses::NetResponse;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Options for `map-switches`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct MapSwitchesArgs {
+    /// Names file to extend (.yaml/.yml for YAML, JSON otherwise; default
+    /// ~/.fast/switches.yaml)
+    #[arg(long)]
+    pub file: Option<PathBuf>,
+}
+
+/// Friendly names of switches by number, as written by `map-switches`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct SwitchNames(pub BTreeMap<u16, String>);
+
+impl SwitchNames {
+    /// Read a names file; a missing file is an empty map.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        load_document(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        save_document(path, self)
+    }
+
+    pub fn get(&self, switch: u16) -> Option<&str> {
+        self.0.get(&switch).map(String::as_str)
+    }
+}
+
+/// Ask for a name for every switch that closes, saving each answer as it is given.
+pub fn run(fpm: &mut FastPinballMonitor, args: &MapSwitchesArgs) -> Exit {
+    let path = args
+        .file
+        .clone()
+        .unwrap_or_else(crate::config::switch_names_path);
+    let mut names = match SwitchNames::load(&path) {
+        Ok(names) => names,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Exit::Error;
+        }
+    };
+
+    let _ = fpm.net.receive();
+    let events = match fpm.subscribe_net_events() {
+        Ok(events) => events,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Exit::Error;
+        }
+    };
+    let configure = NetCommand::ConfigureHardware {
+        hardware: NEURON_HARDWARE,
+        switch_reporting: 0xFF,
+    };
+    if let Err(e) = fpm.net.send_command(&configure) {
+        eprintln!("Failed to configure switch reporting: {}", e);
+        return Exit::Error;
+    }
+
+    println!(
+        "Activate each switch and type its name ({} named so far in {}).",
+        names.0.len(),
+        path.display()
+    );
+    println!("Enter alone keeps the current name; q stops. Press Ctrl+C to stop at any time.");
+    for response in events.iter() {
+        let NetResponse::SwitchClosed { switch } = response else {
+            continue;
+        };
+        match names.get(switch) {
+            Some(name) => print!("Switch 0x{:02X} ({}) [{}]: ", switch, switch, name),
+            None => print!("Switch 0x{:02X} ({}): ", switch, switch),
+        }
+        let _ = io::stdout().flush();
+        let answer = read_line_trimmed();
+        match answer.as_str() {
+            "q" | "Q" => break,
+            "" => {}
+            name => {
+                names.0.insert(switch, name.to_string());
+                if let Err(e) = names.save(&path) {
+                    eprintln!("{}", e);
+                    return Exit::Error;
+                }
+            }
+        }
+        // Forget what happened while typing (the switch opening, bounces)
+        while events.try_recv().is_ok() {}
+    }
+    println!("{} switch name(s) in {}.", names.0.len(), path.display());
+    Exit::Success
+}
@@ -0,0 +1,100 @@
+use crate::commands::exit::Exit;
+use crate::commands::{self, MonitorSwitchesArgs, TermArgs, TestDriverArgs, TestLedsArgs};
+use crate::config::OutputFormat;
+use crate::fast_monitor::FastPinballMonitor;
+use clap::{Args, FromArgMatches};
+use std::io::{self, Write};
+
+/// Seconds the menu watches switches for.
+const WATCH_SWITCHES_SECS: u64 = 30;
+
+const ITEMS: [&str; 9] = [
+    "List boards",
+    "Update the firmware of an EXP board",
+    "Update every EXP board that is behind",
+    "Update the NET (CPU) firmware",
+    "Test a driver (pulse a coil)",
+    "Test LEDs",
+    "Watch switches (30 seconds)",
+    "Download the latest firmware",
+    "Open a serial terminal",
+];
+
+/// Offer the common tasks as a numbered menu until the user quits, for those who do not
+/// remember the subcommand names. Each task prompts for what it needs.
+pub fn run(fpm: &mut FastPinballMonitor, format: OutputFormat, offline: bool) -> Exit {
+    loop {
+        println!();
+        println!("FAST Pinball utilities");
+        for (i, item) in ITEMS.iter().enumerate() {
+            println!("  {}) {}", i + 1, item);
+        }
+        println!("  0) Quit");
+        print!("Choose (0-{}): ", ITEMS.len());
+        let _ = io::stdout().flush();
+        // End of input quits, like 0
+        let mut line = String::new();
+        if matches!(io::stdin().read_line(&mut line), Ok(0) | Err(_)) {
+            println!();
+            return Exit::Success;
+        }
+        println!();
+        // A Ctrl+C that stopped the last task's flash ends that task, not the menu
+        crate::interrupt::clear_stop();
+        match line.trim() {
+            "0" | "q" | "Q" => return Exit::Success,
+            "1" => {
+                commands::run_list_all(fpm, format);
+            }
+            "2" => {
+                commands::run_update_exp(fpm, &Default::default());
+            }
+            "3" => {
+                commands::run_update_all_exp(fpm, &Default::default());
+            }
+            "4" => {
+                commands::run_update_net(fpm, &Default::default());
+            }
+            "5" => {
+                commands::run_test_driver(fpm, &default_args::<TestDriverArgs>());
+            }
+            "6" => {
+                commands::run_test_leds(fpm, &default_args::<TestLedsArgs>());
+            }
+            "7" => {
+                commands::run_monitor_switches(
+                    fpm,
+                    &MonitorSwitchesArgs {
+                        duration: Some(WATCH_SWITCHES_SECS),
+                        ..Default::default()
+                    },
+                );
+            }
+            "8" => download_firmware(fpm, offline),
+            "9" => {
+                commands::run_term(fpm, &TermArgs::default());
+            }
+            other => println!("Invalid selection: {}", other),
+        }
+    }
+}
+
+fn download_firmware(fpm: &mut FastPinballMonitor, offline: bool) {
+    if offline {
+        println!("Downloads are disabled by --offline.");
+        return;
+    }
+    match commands::check_updates::download_latest_firmware() {
+        Ok(()) => {
+            crate::firmware_repo::reload();
+            commands::check_updates::report_versions(fpm);
+        }
+        Err(e) => eprintln!("Failed to download firmware: {}", e),
+    }
+}
+
+// The options of a command as if it were run without any, so it prompts for what it needs
+fn default_args<T: Args + FromArgMatches>() -> T {
+    let matches = T::augment_args(clap::Command::new("menu")).get_matches_from(["menu"]);
+    T::from_arg_matches(&matches).expect("defaults parse")
+}
@@ -1,13 +1,139 @@
-pub mod utils;
+pub mod benchmark;
+pub mod check_updates;
+pub mod daemon;
+#[cfg(feature = "tui")]
+pub mod dashboard;
+pub mod drivers;
+pub mod emulate;
+#[cfg(feature = "server")]
+pub mod event_stream;
+pub mod exit;
+pub mod firmware;
+pub mod hardware_event;
+pub mod history;
+pub mod import_firmware;
+pub mod inventory;
+pub mod list_all;
+pub mod list_aud;
+pub mod list_dsp;
 pub mod list_exp;
 pub mod list_net;
+pub mod list_seg;
+pub mod map_leds;
+pub mod map_switches;
+pub mod menu;
+pub mod monitor_switches;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod recover;
+pub mod rescan;
+pub mod reset;
+pub mod script;
+pub mod send;
+#[cfg(feature = "server")]
+pub mod serve;
+pub mod set_volume;
+pub mod status;
+pub mod stress;
+pub mod table;
+pub mod term;
+pub mod test_driver;
+pub mod test_gi;
+pub mod test_leds;
+pub mod test_seg;
+pub mod test_servo;
+pub mod update_all_exp;
+pub mod update_dsp;
 pub mod update_exp;
+pub mod update_exp_batch;
 pub mod update_net;
-pub mod check_updates;
+pub mod utils;
+pub mod wait_for_device;
 
 // (optional) re-exports for ergonomics
+pub use benchmark::run as run_benchmark;
+pub use check_updates::run as run_check_updates;
+pub use daemon::run as run_daemon;
+#[cfg(feature = "tui")]
+pub use dashboard::run as run_dashboard;
+pub use drivers::run as run_drivers;
+pub use emulate::run as run_emulate;
+pub use firmware::run as run_firmware;
+pub use history::run as run_history;
+pub use import_firmware::run as run_import_firmware;
+pub use inventory::run as run_inventory;
+pub use list_all::run as run_list_all;
+pub use list_aud::run as run_list_aud;
+pub use list_dsp::run as run_list_dsp;
 pub use list_exp::run as run_list_exp;
 pub use list_net::run as run_list_net;
+pub use list_seg::run as run_list_seg;
+pub use map_leds::run as run_map_leds;
+pub use map_switches::run as run_map_switches;
+pub use menu::run as run_menu;
+pub use monitor_switches::run as run_monitor_switches;
+#[cfg(feature = "mqtt")]
+pub use mqtt::run as run_mqtt;
+pub use recover::run as run_recover;
+pub use reset::run_exp as run_reset_exp;
+pub use reset::run_net as run_reset_net;
+pub use script::run as run_script;
+pub use send::run_exp as run_send_exp;
+pub use send::run_net as run_send_net;
+#[cfg(feature = "server")]
+pub use serve::run as run_serve;
+pub use set_volume::run as run_set_volume;
+pub use status::run as run_status;
+pub use stress::run as run_stress;
+pub use term::run as run_term;
+pub use test_driver::run as run_test_driver;
+pub use test_gi::run as run_test_gi;
+pub use test_leds::run as run_test_leds;
+pub use test_seg::run as run_test_seg;
+pub use test_servo::run as run_test_servo;
+pub use update_all_exp::run as run_update_all_exp;
+pub use update_dsp::run as run_update_dsp;
 pub use update_exp::run as run_update_exp;
+pub use update_exp_batch::run as run_update_exp_batch;
 pub use update_net::run as run_update_net;
-pub use check_updates::run as run_check_updates;
+pub use wait_for_device::run as run_wait_for_device;
+
+pub use exit::Exit;
+
+pub use benchmark::BenchmarkArgs;
+pub use check_updates::CheckUpdatesArgs;
+pub use daemon::DaemonArgs;
+#[cfg(feature = "tui")]
+pub use dashboard::DashboardArgs;
+pub use drivers::DriversArgs;
+pub use emulate::EmulateArgs;
+pub use firmware::FirmwareArgs;
+pub use history::HistoryArgs;
+pub use import_firmware::ImportFirmwareArgs;
+pub use inventory::InventoryArgs;
+pub use list_exp::ListExpArgs;
+pub use map_leds::MapLedsArgs;
+pub use map_switches::MapSwitchesArgs;
+pub use monitor_switches::MonitorSwitchesArgs;
+#[cfg(feature = "mqtt")]
+pub use mqtt::MqttArgs;
+pub use recover::RecoverArgs;
+pub use reset::{ResetExpArgs, ResetNetArgs};
+pub use script::ScriptArgs;
+pub use send::SendArgs;
+#[cfg(feature = "server")]
+pub use serve::ServeArgs;
+pub use set_volume::SetVolumeArgs;
+pub use status::StatusArgs;
+pub use stress::StressArgs;
+pub use term::TermArgs;
+pub use test_driver::TestDriverArgs;
+pub use test_gi::TestGiArgs;
+pub use test_leds::TestLedsArgs;
+pub use test_seg::TestSegArgs;
+pub use test_servo::TestServoArgs;
+pub use update_all_exp::UpdateAllExpArgs;
+pub use update_dsp::UpdateDspArgs;
+pub use update_exp::UpdateExpArgs;
+pub use update_exp_batch::UpdateExpBatchArgs;
+pub use update_net::UpdateNetArgs;
@@ -0,0 +1,90 @@
+use crate::commands::exit::Exit;
+use crate::commands::map_switches::SwitchNames;
+use crate::fast_monitor::FastPinballMonitor;
+use crate::protocol::net_commands::{NEURON_HARDWARE, NetCommand};
+use crate::protocol::responses::NetResponse;
+use std::path::PathBuf;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+/// Options for `monitor-switches`.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct MonitorSwitchesArgs {
+    /// Stop after this many seconds instead of running until interrupted
+    #[arg(long, value_name = "SECS")]
+    pub duration: Option<u64>,
+    /// Switch names file from `map-switches` (default ~/.fast/switches.yaml, if present)
+    #[arg(long, value_name = "FILE")]
+    pub names: Option<PathBuf>,
+}
+
+/// Print switch open/close events reported by the NET processor as they happen.
+///
+/// Subscribes to NET events, enables switch reporting with `CH:2000,FF` and then
+/// prints the `-L:` (closed) and `/L:` (opened) messages, with the switch's name when it
+/// has one.
+pub fn run(fpm: &mut FastPinballMonitor, args: &MonitorSwitchesArgs) -> Exit {
+    let duration = args.duration.map(Duration::from_secs);
+    let names = match &args.names {
+        Some(path) => match SwitchNames::load(path) {
+            Ok(names) => names,
+            Err(e) => {
+                eprintln!("{}", e);
+                return Exit::Error;
+            }
+        },
+        None => SwitchNames::load(&crate::config::switch_names_path()).unwrap_or_else(|e| {
+            log::warn!("{}", e);
+            SwitchNames::default()
+        }),
+    };
+
+    // Drain any pending bytes, then configure the Neuron for switch reporting
+    let _ = fpm.net.receive();
+    let events = match fpm.subscribe_net_events() {
+        Ok(events) => events,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Exit::Error;
+        }
+    };
+    let configure = NetCommand::ConfigureHardware {
+        hardware: NEURON_HARDWARE,
+        switch_reporting: 0xFF,
+    };
+    if let Err(e) = fpm.net.send_command(&configure) {
+        eprintln!("Failed to configure switch reporting: {}", e);
+        return Exit::Error;
+    }
+
+    println!("Monitoring switches. Press Ctrl+C to stop.");
+    let start = Instant::now();
+    loop {
+        let wait = match duration {
+            Some(d) if start.elapsed() >= d => break,
+            Some(d) => d - start.elapsed(),
+            None => Duration::from_secs(1),
+        };
+        let (switch, closed) = match events.recv_timeout(wait) {
+            Ok(NetResponse::SwitchClosed { switch }) => (switch, true),
+            Ok(NetResponse::SwitchOpened { switch }) => (switch, false),
+            Ok(_) | Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let elapsed = start.elapsed();
+        let name = names
+            .get(switch)
+            .map(|n| format!(" {}", n))
+            .unwrap_or_default();
+        println!(
+            "[{:>4}.{:03}s] switch 0x{:02X} ({}){} {}",
+            elapsed.as_secs(),
+            elapsed.subsec_millis(),
+            switch,
+            switch,
+            name,
+            if closed { "closed" } else { "open" }
+        );
+    }
+    Exit::Success
+}
@@ -0,0 +1,111 @@
+use crate::commands::exit::Exit;
+use crate::commands::hardware_event::HardwareEvent;
+use crate::commands::inventory::Inventory;
+use crate::fast_monitor::FastPinballMonitor;
+use crate::protocol::net_commands::{NEURON_HARDWARE, NetCommand};
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+use std::time::Duration;
+
+/// Requests the MQTT client queues while the broker is unreachable.
+const QUEUE_CAPACITY: usize = 64;
+
+/// Options for `mqtt`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct MqttArgs {
+    /// MQTT broker host
+    #[arg(long, default_value = "localhost")]
+    pub host: String,
+    /// MQTT broker port
+    #[arg(long, default_value_t = 1883)]
+    pub port: u16,
+    /// Client ID to connect with
+    #[arg(long, default_value = "fast-pinball-utilities")]
+    pub client_id: String,
+    /// User name to log in with
+    #[arg(long)]
+    pub username: Option<String>,
+    /// Password to log in with
+    #[arg(long, requires = "username")]
+    pub password: Option<String>,
+    /// Topic of the board inventory, published (retained) on every connect
+    #[arg(long, value_name = "TOPIC", default_value = "fast/boards")]
+    pub boards_topic: String,
+    /// Topic of switch events; `{switch}` is replaced with the switch number
+    #[arg(long, value_name = "TOPIC", default_value = "fast/switches/{switch}")]
+    pub switch_topic: String,
+}
+
+/// Publish the board inventory and then every switch change until interrupted.
+pub fn run(fpm: &mut FastPinballMonitor, args: &MqttArgs) -> Exit {
+    let events = match fpm.subscribe_net_events() {
+        Ok(events) => events,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Exit::Error;
+        }
+    };
+    let configure = NetCommand::ConfigureHardware {
+        hardware: NEURON_HARDWARE,
+        switch_reporting: 0xFF,
+    };
+    if let Err(e) = fpm.net.send_command(&configure) {
+        eprintln!("Failed to configure switch reporting: {}", e);
+        return Exit::Error;
+    }
+    let _ = fpm.net.receive();
+    let inventory = Inventory::capture(fpm);
+    let boards = serde_json::to_vec(&inventory).unwrap_or_default();
+
+    let mut options = MqttOptions::new(&args.client_id, &args.host, args.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(username) = &args.username {
+        options.set_credentials(username, args.password.clone().unwrap_or_default());
+    }
+    let (client, mut connection) = Client::new(options, QUEUE_CAPACITY);
+
+    // The connection does the network I/O and reconnects; (re)publish the inventory on
+    // each connect so the broker always holds the current one
+    let publisher = client.clone();
+    let boards_topic = args.boards_topic.clone();
+    let broker = format!("{}:{}", args.host, args.port);
+    std::thread::spawn(move || {
+        for notification in connection.iter() {
+            match notification {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    log::info!("Connected to MQTT broker {}", broker);
+                    if let Err(e) =
+                        publisher.try_publish(&boards_topic, QoS::AtLeastOnce, true, boards.clone())
+                    {
+                        log::warn!("Failed to publish the board inventory: {}", e);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    log::warn!("MQTT broker {}: {}", broker, e);
+                    std::thread::sleep(Duration::from_secs(1));
+                }
+            }
+        }
+    });
+
+    println!(
+        "Publishing {} boards to {} and switch events to {} on {}:{}.",
+        inventory.boards.len(),
+        args.boards_topic,
+        args.switch_topic,
+        args.host,
+        args.port
+    );
+    println!("Press Ctrl+C to stop.");
+    for response in events {
+        let Some(HardwareEvent::Switch { switch, state }) = HardwareEvent::from_net(&response)
+        else {
+            continue;
+        };
+        let topic = args.switch_topic.replace("{switch}", &switch.to_string());
+        if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, false, state.as_str()) {
+            log::warn!("Dropped switch {} event: {}", switch, e);
+        }
+    }
+    Exit::Success
+}
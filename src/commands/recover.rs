@@ -0,0 +1,145 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::{
+    expected_version_for_file, newest_version, parse_hex_address, parse_version_arg,
+    read_line_trimmed,
+};
+use crate::fast_monitor::{BootloaderBoard, FastPinballMonitor};
+use crate::protocol::{FlashOutcome, FlashSummary};
+use std::io::{self, Write};
+
+/// Options for `recover`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct RecoverArgs {
+    /// Only recover the board at this address (hex, e.g. B4)
+    #[arg(long, value_parser = parse_hex_address)]
+    pub address: Option<String>,
+    /// Firmware version to flash (default: the newest in the firmware directory)
+    #[arg(long, value_parser = parse_version_arg, conflicts_with = "file")]
+    pub version: Option<String>,
+    /// Flash this local firmware file instead of one from the firmware directory
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<String>,
+    /// Version to verify after flashing --file (defaults to the one in the file name)
+    #[arg(long, value_parser = parse_version_arg, requires = "file")]
+    pub expect_version: Option<String>,
+    /// Flash without asking for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+    /// Flash even firmware made for another board than the one answering at the address
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// Find EXP boards that only answer from their bootloader and flash firmware to them.
+pub fn run(fpm: &mut FastPinballMonitor, args: &RecoverArgs) -> Exit {
+    fpm.allow_exp_board_mismatch(args.force);
+    if let Some(file) = &args.file
+        && !std::path::Path::new(file).is_file()
+    {
+        eprintln!("Firmware file not found: {}", file);
+        return Exit::Error;
+    }
+    status!("Looking for EXP boards waiting in their bootloader ...");
+    let boards: Vec<BootloaderBoard> = fpm
+        .list_bootloader_exp_boards()
+        .into_iter()
+        .filter(|b| args.address.as_ref().is_none_or(|a| *a == b.address))
+        .collect();
+    if boards.is_empty() {
+        return match &args.address {
+            Some(address) => {
+                eprintln!(
+                    "The board at address {} is not waiting in its bootloader.",
+                    address
+                );
+                Exit::NoHardware
+            }
+            None => {
+                println!("No EXP boards are waiting in their bootloader.");
+                Exit::Success
+            }
+        };
+    }
+
+    let mut exit = Exit::Success;
+    let mut flashed = 0usize;
+    for board in boards.iter() {
+        if crate::interrupt::stop_requested() {
+            status!("Stopped; the remaining boards were not recovered.");
+            break;
+        }
+        println!(
+            "EXP board at address {} ({}) on {} answers from its bootloader: {}",
+            board.address, board.board_type, board.port, board.banner
+        );
+        let summary = match &args.file {
+            Some(file) => {
+                let expected = expected_version_for_file(file, args.expect_version.as_deref());
+                if !confirm(board, file, args.yes) {
+                    continue;
+                }
+                fpm.exp_port(&board.port).map(|exp| {
+                    let outcome = exp.flash_file(&board.address, file, expected.as_deref());
+                    FlashSummary::single(outcome, exp.last_transfer)
+                })
+            }
+            None => {
+                let key = format!("{}_EXP", board.board_type);
+                let available = crate::firmware_repo::get().versions(&key);
+                let version = args.version.clone().or_else(|| newest_version(&available));
+                let Some(version) = version.filter(|v| available.contains(v)) else {
+                    eprintln!(
+                        "No {} firmware{} in {}; download it with get-latest-firmware or pass --file.",
+                        board.board_type,
+                        args.version
+                            .as_ref()
+                            .map(|v| format!(" {}", v))
+                            .unwrap_or_default(),
+                        crate::config::firmware_dir().display()
+                    );
+                    exit = Exit::Error;
+                    continue;
+                };
+                if !confirm(board, &format!("firmware {}", version), args.yes) {
+                    continue;
+                }
+                fpm.exp_port(&board.port).map(|exp| {
+                    let outcome = exp.update_firmware(&board.address, &version);
+                    FlashSummary::single(outcome, exp.last_transfer)
+                })
+            }
+        };
+        let summary = summary.unwrap_or(FlashSummary::single(FlashOutcome::Failed, None));
+        status!("Flash summary: {}", summary);
+        let outcome = summary.outcome;
+        println!(
+            "{} at address {}: {}",
+            board.board_type, board.address, outcome
+        );
+        flashed += 1;
+        if exit == Exit::Success {
+            exit = outcome.into();
+        }
+    }
+    if flashed == 0 && exit == Exit::Success {
+        return Exit::Canceled;
+    }
+    exit
+}
+
+fn confirm(board: &BootloaderBoard, firmware: &str, yes: bool) -> bool {
+    if yes {
+        return true;
+    }
+    print!(
+        "Flash {} at address {} with {}? [y/N]: ",
+        board.board_type, board.address, firmware
+    );
+    let _ = io::stdout().flush();
+    if matches!(read_line_trimmed().as_str(), "y" | "Y" | "yes" | "YES") {
+        true
+    } else {
+        println!("Skipped.");
+        false
+    }
+}
@@ -0,0 +1,59 @@
+use crate::fast_monitor::{ExpBoardInfo, FastPinballMonitor};
+use std::collections::BTreeMap;
+
+/// Board name and firmware version keyed by location (e.g. `EXP B4`, `NET 00`).
+pub type VersionSnapshot = BTreeMap<String, (String, String)>;
+
+pub fn exp_versions(boards: &[ExpBoardInfo]) -> VersionSnapshot {
+    boards
+        .iter()
+        .map(|b| {
+            (
+                format!("EXP {}", b.address),
+                (b.board_name.clone(), b.version.clone()),
+            )
+        })
+        .collect()
+}
+
+pub fn net_versions(fpm: &mut FastPinballMonitor) -> VersionSnapshot {
+    fpm.list_connected_net_boards()
+        .into_values()
+        .map(|n| (format!("NET {}", n.node_id), (n.node_name, n.firmware)))
+        .collect()
+}
+
+/// Print every board seen before or after a flash with both versions, marking the
+/// ones that changed or disappeared.
+pub fn print_before_after(before: &VersionSnapshot, after: &VersionSnapshot) {
+    let mut locations: Vec<&String> = before.keys().chain(after.keys()).collect();
+    locations.sort();
+    locations.dedup();
+
+    status!();
+    status!("Versions after rescan:");
+    status!(
+        "  {:<10} {:<14} {:<10} After",
+        "Location",
+        "Board",
+        "Before"
+    );
+    for location in locations {
+        let old = before.get(location);
+        let new = after.get(location);
+        let board = new.or(old).map(|(b, _)| b.as_str()).unwrap_or("?");
+        let old_version = old.map(|(_, v)| v.as_str()).unwrap_or("-");
+        let new_version = new.map(|(_, v)| v.as_str()).unwrap_or("missing");
+        let note = match (old, new) {
+            (_, None) => "not found after update",
+            (None, Some(_)) => "new",
+            (Some((_, o)), Some((_, n))) if o != n => "updated",
+            _ => "",
+        };
+        let row = format!(
+            "  {:<10} {:<14} {:<10} {:<10} {}",
+            location, board, old_version, new_version, note
+        );
+        status!("{}", row.trim_end());
+    }
+}
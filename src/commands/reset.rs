@@ -0,0 +1,74 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::parse_hex_address;
+use crate::fast_monitor::FastPinballMonitor;
+use std::time::{Duration, Instant};
+
+/// Options for `reset-net`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ResetNetArgs {
+    /// Seconds to wait for the NET processor to answer again
+    #[arg(long, value_name = "SECS", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: u64,
+}
+
+/// Options for `reset-exp`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ResetExpArgs {
+    /// EXP board address (hex, e.g. B4)
+    #[arg(value_parser = parse_hex_address)]
+    pub address: String,
+    /// Seconds to wait for the board to answer again
+    #[arg(long, value_name = "SECS", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: u64,
+}
+
+/// Reboot the NET processor and wait until it identifies itself again.
+pub fn run_net(fpm: &mut FastPinballMonitor, args: &ResetNetArgs) -> Exit {
+    status!("Rebooting the NET processor on {} ...", fpm.net.port_name);
+    let start = Instant::now();
+    match fpm.net.reboot(Duration::from_secs(args.timeout)) {
+        Some(id) => {
+            println!(
+                "NET processor is back after {:.1}s: {}",
+                start.elapsed().as_secs_f64(),
+                id
+            );
+            Exit::Success
+        }
+        None => {
+            eprintln!(
+                "The NET processor did not answer within {}s of the reboot; power-cycle the machine.",
+                args.timeout
+            );
+            Exit::Error
+        }
+    }
+}
+
+/// Reset one EXP board and wait until it identifies itself again.
+pub fn run_exp(fpm: &mut FastPinballMonitor, args: &ResetExpArgs) -> Exit {
+    let Some(exp) = fpm.exp_port_for_address(&args.address) else {
+        eprintln!("No EXP board answered at address {}.", args.address);
+        return Exit::NoHardware;
+    };
+    status!("Resetting the EXP board at address {} ...", args.address);
+    let start = Instant::now();
+    match exp.reset_board(&args.address, Duration::from_secs(args.timeout)) {
+        Some(id) => {
+            println!(
+                "EXP board at address {} is back after {:.1}s: {}",
+                args.address,
+                start.elapsed().as_secs_f64(),
+                id
+            );
+            Exit::Success
+        }
+        None => {
+            eprintln!(
+                "The EXP board at address {} did not answer within {}s of the reset.",
+                args.address, args.timeout
+            );
+            Exit::Error
+        }
+    }
+}
@@ -0,0 +1,380 @@
+// Playfield exercise scripts: a list of steps run against the connected hardware, each
+// reported as passed or failed, for repeatable end-of-line tests. Scripts are YAML
+// (.yaml/.yml), TOML (.toml) or JSON:
+//
+//     name: Left ramp
+//     steps:
+//       - pulse: { driver: 5, ms: 20 }           # ms default 10, power default 100 (%)
+//       - wait_ms: 500
+//       - led: { address: B4, index: 12, color: FF0000 }
+//       - expect_switch: { switch: 33, state: closed, within_ms: 2000 }
+//
+// Switch changes are collected from the start of the step before an `expect_switch`, so
+// a switch that a pulse closes right away is still seen.
+
+use crate::commands::exit::Exit;
+use crate::commands::test_driver::MAX_PULSE_MS;
+use crate::commands::utils::{load_document, parse_color, parse_hex_address, print_document};
+use crate::config::OutputFormat;
+use crate::fast_monitor::FastPinballMonitor;
+use crate::protocol::net_commands::{NEURON_HARDWARE, NetCommand};
+use crate::protocol::responses::NetResponse;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Options for `script`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ScriptArgs {
+    #[command(subcommand)]
+    pub command: ScriptCommand,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum ScriptCommand {
+    /// Run the steps in a script (.yaml/.yml, .toml or JSON) and report each one
+    Run {
+        file: PathBuf,
+        /// Stop at the first step that fails
+        #[arg(long)]
+        fail_fast: bool,
+    },
+}
+
+/// A test script.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    #[serde(default)]
+    pub name: Option<String>,
+    // `- pulse: {...}` rather than YAML's `- !pulse {...}`
+    #[cfg_attr(
+        feature = "export",
+        serde(with = "serde_yaml::with::singleton_map_recursive")
+    )]
+    pub steps: Vec<Step>,
+}
+
+/// One step of a script.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    /// Fire a driver once, like `test-driver`.
+    Pulse {
+        driver: u8,
+        #[serde(default = "default_pulse_ms")]
+        ms: u8,
+        /// In percent.
+        #[serde(default = "default_power")]
+        power: u8,
+    },
+    /// Do nothing for a while.
+    WaitMs(u64),
+    /// Set one LED on an EXP board, or on a breakout behind it.
+    Led {
+        #[serde(deserialize_with = "address")]
+        address: String,
+        #[serde(default)]
+        breakout: Option<u8>,
+        index: u8,
+        #[serde(deserialize_with = "color")]
+        color: (u8, u8, u8),
+    },
+    /// Wait for a switch to close (or open).
+    ExpectSwitch {
+        switch: u16,
+        #[serde(default)]
+        state: SwitchState,
+        #[serde(default = "default_within_ms")]
+        within_ms: u64,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SwitchState {
+    #[default]
+    Closed,
+    Open,
+}
+
+/// How one step went.
+#[derive(Serialize, Debug, Clone)]
+pub struct StepResult {
+    /// Position in the script, from 1.
+    pub step: usize,
+    /// The step, e.g. `pulse driver 5 for 20 ms at 100%`.
+    pub action: String,
+    pub passed: bool,
+    /// Why the step failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+fn default_pulse_ms() -> u8 {
+    10
+}
+
+fn default_power() -> u8 {
+    100
+}
+
+fn default_within_ms() -> u64 {
+    1000
+}
+
+fn address<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_hex_address(&text).map_err(serde::de::Error::custom)
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<(u8, u8, u8), D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_color(&text).map_err(serde::de::Error::custom)
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Pulse { driver, ms, power } => {
+                write!(f, "pulse driver {} for {} ms at {}%", driver, ms, power)
+            }
+            Step::WaitMs(ms) => write!(f, "wait {} ms", ms),
+            Step::Led {
+                address,
+                breakout,
+                index,
+                color: (r, g, b),
+            } => {
+                let target = match breakout {
+                    Some(b) => format!("{}{}", address, b),
+                    None => address.clone(),
+                };
+                write!(
+                    f,
+                    "set LED {} on {} to {:02X}{:02X}{:02X}",
+                    index, target, r, g, b
+                )
+            }
+            Step::ExpectSwitch {
+                switch,
+                state,
+                within_ms,
+            } => write!(
+                f,
+                "expect switch {} to {} within {} ms",
+                switch,
+                match state {
+                    SwitchState::Closed => "close",
+                    SwitchState::Open => "open",
+                },
+                within_ms
+            ),
+        }
+    }
+}
+
+impl Script {
+    /// Read and check a script: YAML for `.yaml`/`.yml`, TOML for `.toml`, JSON otherwise.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let is_toml = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("toml"));
+        let script: Script = if is_toml {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            toml::from_str(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))?
+        } else {
+            load_document(path)?
+        };
+        for (i, step) in script.steps.iter().enumerate() {
+            if let Step::Pulse { ms, power, .. } = step {
+                if *ms == 0 || *ms > MAX_PULSE_MS {
+                    return Err(format!(
+                        "{}: step {}: pulse ms must be 1-{}",
+                        path.display(),
+                        i + 1,
+                        MAX_PULSE_MS
+                    ));
+                }
+                if *power == 0 || *power > 100 {
+                    return Err(format!(
+                        "{}: step {}: power must be 1-100",
+                        path.display(),
+                        i + 1
+                    ));
+                }
+            }
+        }
+        Ok(script)
+    }
+}
+
+pub fn run(fpm: &mut FastPinballMonitor, args: &ScriptArgs, format: OutputFormat) -> Exit {
+    match &args.command {
+        ScriptCommand::Run { file, fail_fast } => run_script(fpm, file, *fail_fast, format),
+    }
+}
+
+fn run_script(
+    fpm: &mut FastPinballMonitor,
+    file: &Path,
+    fail_fast: bool,
+    format: OutputFormat,
+) -> Exit {
+    let script = match Script::load(file) {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Exit::Error;
+        }
+    };
+
+    // Switch changes only come in once reporting is on
+    let _ = fpm.net.receive();
+    let mut events = None;
+    if script
+        .steps
+        .iter()
+        .any(|s| matches!(s, Step::ExpectSwitch { .. }))
+    {
+        match fpm.subscribe_net_events() {
+            Ok(rx) => events = Some(rx),
+            Err(e) => {
+                eprintln!("{}", e);
+                return Exit::Error;
+            }
+        }
+        let configure = NetCommand::ConfigureHardware {
+            hardware: NEURON_HARDWARE,
+            switch_reporting: 0xFF,
+        };
+        if let Err(e) = fpm.net.send_command(&configure) {
+            eprintln!("Failed to configure switch reporting: {}", e);
+            return Exit::Error;
+        }
+    }
+
+    let text = !format.is_document();
+    if text {
+        let name = script
+            .name
+            .clone()
+            .unwrap_or_else(|| file.display().to_string());
+        println!("Running {} ({} steps)", name, script.steps.len());
+    }
+    let mut results: Vec<StepResult> = Vec::new();
+    for (i, step) in script.steps.iter().enumerate() {
+        // A switch change from before the previous step is not what an expectation means
+        if let Some(rx) = &events
+            && !matches!(step, Step::ExpectSwitch { .. })
+        {
+            while rx.try_recv().is_ok() {}
+        }
+        let start = Instant::now();
+        let outcome = run_step(fpm, step, events.as_ref());
+        let result = StepResult {
+            step: i + 1,
+            action: step.to_string(),
+            passed: outcome.is_ok(),
+            error: outcome.err(),
+            elapsed_ms: start.elapsed().as_millis() as u64,
+        };
+        if text {
+            println!(
+                "  [{}/{}] {}  {}{}",
+                result.step,
+                script.steps.len(),
+                if result.passed { "PASS" } else { "FAIL" },
+                result.action,
+                result
+                    .error
+                    .as_ref()
+                    .map(|e| format!(": {}", e))
+                    .unwrap_or_default()
+            );
+        }
+        let failed = !result.passed;
+        results.push(result);
+        if failed && fail_fast {
+            break;
+        }
+    }
+
+    let passed = results.iter().filter(|r| r.passed).count();
+    if text {
+        println!("{} of {} steps passed.", passed, script.steps.len());
+    } else {
+        print_document(&results, format);
+    }
+    if passed == script.steps.len() {
+        Exit::Success
+    } else {
+        Exit::Error
+    }
+}
+
+fn run_step(
+    fpm: &mut FastPinballMonitor,
+    step: &Step,
+    events: Option<&Receiver<NetResponse>>,
+) -> Result<(), String> {
+    match step {
+        Step::Pulse { driver, ms, power } => {
+            let power = (*power as u32 * 0xFF / 100) as u8;
+            fpm.net
+                .pulse_driver(*driver, *ms, power)
+                .map_err(|e| e.to_string())
+        }
+        Step::WaitMs(ms) => {
+            std::thread::sleep(Duration::from_millis(*ms));
+            Ok(())
+        }
+        Step::Led {
+            address,
+            breakout,
+            index,
+            color,
+        } => {
+            let Some(exp) = fpm.exp_port_for_address(address) else {
+                return Err(format!("no EXP board answered at address {}", address));
+            };
+            match breakout {
+                Some(b) => exp.select_address(&format!("{}{}", address, b)),
+                None => exp.select_address(address),
+            }
+            exp.set_leds(*index, &[*color]);
+            Ok(())
+        }
+        Step::ExpectSwitch {
+            switch,
+            state,
+            within_ms,
+        } => {
+            let Some(events) = events else {
+                return Err("switch reporting is off".to_string());
+            };
+            let deadline = Instant::now() + Duration::from_millis(*within_ms);
+            loop {
+                let wait = deadline.saturating_duration_since(Instant::now());
+                let seen = match events.recv_timeout(wait) {
+                    Ok(NetResponse::SwitchClosed { switch: s }) => (s, SwitchState::Closed),
+                    Ok(NetResponse::SwitchOpened { switch: s }) => (s, SwitchState::Open),
+                    Ok(_) => continue,
+                    Err(RecvTimeoutError::Timeout) => {
+                        return Err(format!("no change within {} ms", within_ms));
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        return Err("the NET port stopped reporting".to_string());
+                    }
+                };
+                if seen == (*switch, *state) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
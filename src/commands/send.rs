@@ -0,0 +1,53 @@
+use crate::commands::exit::Exit;
+use crate::fast_monitor::FastPinballMonitor;
+use std::time::{Duration, Instant};
+
+/// Options for `send-net` and `send-exp`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct SendArgs {
+    /// Command to send, e.g. `ID:` or `ID@B4:` (a trailing `\r` is added)
+    pub command: String,
+    /// Milliseconds to keep printing responses after sending
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    pub wait: u64,
+}
+
+/// Send a raw command on the NET port and print the response lines.
+pub fn run_net(fpm: &mut FastPinballMonitor, args: &SendArgs) -> Exit {
+    let net = &mut fpm.net;
+    let _ = net.receive();
+    if let Err(e) = net.send(format!("{}\r", args.command.trim_end()).as_bytes()) {
+        eprintln!("Failed to send to NET port {}: {}", net.port_name, e);
+        return Exit::Error;
+    }
+    print_responses(args.wait, || net.receive_lines());
+    Exit::Success
+}
+
+/// Send a raw command on the (first) EXP port and print the response lines.
+pub fn run_exp(fpm: &mut FastPinballMonitor, args: &SendArgs) -> Exit {
+    let Some(exp) = fpm.exp_ports.first_mut() else {
+        println!("No EXP port available.");
+        return Exit::NoHardware;
+    };
+    let _ = exp.receive();
+    exp.send(format!("{}\r", args.command.trim_end()).into_bytes());
+    print_responses(args.wait, || exp.receive_lines());
+    Exit::Success
+}
+
+/// Print lines as they arrive until `wait_ms` have passed since sending.
+fn print_responses(wait_ms: u64, mut receive: impl FnMut() -> Vec<String>) {
+    let start = Instant::now();
+    let mut any = false;
+    while start.elapsed() < Duration::from_millis(wait_ms) {
+        for line in receive() {
+            println!("{}", line);
+            any = true;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    if !any {
+        println!("(no response)");
+    }
+}
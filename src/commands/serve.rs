@@ -0,0 +1,401 @@
+use crate::commands::event_stream::EventStream;
+use crate::commands::exit::Exit;
+use crate::commands::utils::{expected_version_for_file, parse_hex_address, parse_version_arg};
+use crate::fast_monitor::{FastPinballMonitor, NetBoardInfo};
+use crate::protocol::FlashOutcome;
+use crate::shared_monitor::SharedMonitor;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::net::ToSocketAddrs;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Options for `serve`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ServeArgs {
+    /// Address to serve the REST API on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:7780")]
+    pub listen: String,
+    /// Seconds between board rescans while WebSocket clients are connected
+    #[arg(long, value_name = "SECS", default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    pub refresh: u64,
+}
+
+/// How often the server checks whether Ctrl+C stopped a flash while no request comes in.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Body of `POST /update/exp`: the board's address and either a version from the
+/// firmware directory or a file in it (with an optional version to verify). The
+/// controller's built-in EXP is only flashed with `"controller": true`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateExpRequest {
+    pub address: String,
+    pub version: Option<String>,
+    pub file: Option<String>,
+    pub expect_version: Option<String>,
+    #[serde(default)]
+    pub controller: bool,
+}
+
+/// Serve the REST API until interrupted. Each request is handled on a thread of its own
+/// over a `SharedMonitor`, so status queries are answered while a flash runs; the flash
+/// holds only the EXP interface it goes over. Ctrl+C during a flash stops the flash, and
+/// the server shuts down once the requests in progress are answered, exiting with
+/// `FlashFailed` if a flash was cut short and `Interrupted` otherwise.
+pub fn run(fpm: FastPinballMonitor, args: &ServeArgs) -> Exit {
+    let fpm = SharedMonitor::new(fpm);
+    let server = match Server::http(&args.listen) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Cannot listen on {}: {}", args.listen, e);
+            return Exit::Error;
+        }
+    };
+    let exposed = args
+        .listen
+        .to_socket_addrs()
+        .map(|mut addrs| addrs.any(|a| !a.ip().is_loopback()))
+        .unwrap_or(true);
+    if exposed {
+        eprintln!(
+            "Warning: {} is reachable from other machines, and the API flashes boards without authentication.",
+            args.listen
+        );
+    }
+    println!("Serving the REST API on http://{}", args.listen);
+    println!("Press Ctrl+C to stop.");
+    let refresh = Duration::from_secs(args.refresh);
+    let mut events = EventStream::default();
+    let mut last_scan = Instant::now();
+    let mut handlers: Vec<JoinHandle<Option<FlashOutcome>>> = Vec::new();
+    let mut flash_stopped = false;
+    loop {
+        let (finished, running) = handlers.into_iter().partition(|h| h.is_finished());
+        handlers = running;
+        flash_stopped |= stopped_flash(finished);
+        if crate::interrupt::stop_requested() {
+            status!("Stopped; shutting down after the requests in progress.");
+            flash_stopped |= stopped_flash(handlers);
+            return if flash_stopped {
+                Exit::FlashFailed
+            } else {
+                Exit::Interrupted
+            };
+        }
+        let wait = refresh
+            .saturating_sub(last_scan.elapsed())
+            .min(STOP_POLL_INTERVAL);
+        let request = match server.recv_timeout(wait) {
+            Ok(request) => request,
+            Err(e) => {
+                eprintln!("Failed to receive request: {}", e);
+                return Exit::Error;
+            }
+        };
+        if last_scan.elapsed() >= refresh {
+            events.rescan(&fpm);
+            last_scan = Instant::now();
+        }
+        let Some(request) = request else {
+            continue;
+        };
+        let path = request.url().split('?').next().unwrap_or_default();
+        if path == "/events" && *request.method() == Method::Get {
+            events.accept(&fpm, request);
+            continue;
+        }
+        if path == "/metrics" && *request.method() == Method::Get {
+            let header = Header::from_bytes("Content-Type", "text/plain; version=0.0.4")
+                .expect("static header is valid");
+            let response = Response::from_string(crate::metrics::render()).with_header(header);
+            if let Err(e) = request.respond(response) {
+                log::warn!("Failed to send response: {}", e);
+            }
+            continue;
+        }
+        let fpm = fpm.clone();
+        handlers.push(std::thread::spawn(move || respond(&fpm, request)));
+    }
+}
+
+/// Whether any of the request `handlers` stopped a flash part way, once they are done.
+fn stopped_flash(handlers: Vec<JoinHandle<Option<FlashOutcome>>>) -> bool {
+    handlers
+        .into_iter()
+        .map(|h| h.join())
+        .any(|outcome| matches!(outcome, Ok(Some(FlashOutcome::Cancelled))))
+}
+
+// Answer `request`, returning the outcome of the flash it asked for, if any
+fn respond(fpm: &SharedMonitor, mut request: Request) -> Option<FlashOutcome> {
+    let (status, body) = handle(fpm, &mut request);
+    let outcome = serde_json::from_value(body["outcome"].clone()).ok();
+    log::debug!("{} {} -> {}", request.method(), request.url(), status);
+    let header =
+        Header::from_bytes("Content-Type", "application/json").expect("static header is valid");
+    let response = Response::from_string(serde_json::to_string_pretty(&body).unwrap_or_default())
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        log::warn!("Failed to send response: {}", e);
+    }
+    outcome
+}
+
+fn handle(fpm: &SharedMonitor, request: &mut Request) -> (u16, Value) {
+    let path = request
+        .url()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    match (request.method(), path.as_str()) {
+        (Method::Get, "/health") => health(fpm),
+        (Method::Get, "/boards/exp") => (200, json!(fpm.list_connected_exp_boards())),
+        (Method::Get, "/boards/net") => {
+            let nodes: BTreeMap<usize, NetBoardInfo> =
+                fpm.list_connected_net_boards().into_iter().collect();
+            (200, json!(nodes.into_values().collect::<Vec<_>>()))
+        }
+        (Method::Post, "/update/exp") => {
+            let mut body = String::new();
+            if let Err(e) = request.as_reader().read_to_string(&mut body) {
+                return error(400, format!("cannot read request body: {}", e));
+            }
+            match serde_json::from_str::<UpdateExpRequest>(&body) {
+                Ok(update) => update_exp(fpm, &update),
+                Err(e) => error(400, format!("invalid request body: {}", e)),
+            }
+        }
+        (_, "/health" | "/boards/exp" | "/boards/net" | "/update/exp" | "/events" | "/metrics") => {
+            error(
+                405,
+                format!("{} is not supported on {}", request.method(), path),
+            )
+        }
+        _ => error(404, format!("no such endpoint: {}", path)),
+    }
+}
+
+/// Whether the NET processor still answers `ID:`.
+fn health(fpm: &SharedMonitor) -> (u16, Value) {
+    let mut net = fpm.net();
+    match net.id() {
+        Some(id) => (
+            200,
+            json!({
+                "status": "ok",
+                "net": { "port": net.port_name, "board": id.board, "version": id.version },
+                "exp_ports": fpm.exp_port_names(),
+            }),
+        ),
+        None => (
+            503,
+            json!({
+                "status": "unresponsive",
+                "error": format!("no ID response on {}", net.port_name),
+            }),
+        ),
+    }
+}
+
+fn update_exp(fpm: &SharedMonitor, update: &UpdateExpRequest) -> (u16, Value) {
+    let address = match parse_hex_address(&update.address) {
+        Ok(address) => address,
+        Err(e) => return error(400, e),
+    };
+    let boards = fpm.list_connected_exp_boards();
+    let Some(board) = boards.iter().find(|b| b.address == address) else {
+        return error(404, format!("no EXP board found at address {}", address));
+    };
+    if board.is_controller && !update.controller {
+        return error(
+            400,
+            format!(
+                "address {} is the controller's built-in EXP ({}); send \"controller\": true to flash it",
+                address, board.board_name
+            ),
+        );
+    }
+
+    let (outcome, transfer) = match (&update.version, &update.file) {
+        (Some(version), None) => {
+            let version = match parse_version_arg(version) {
+                Ok(version) => version,
+                Err(e) => return error(400, e),
+            };
+            let available = board.available_versions.clone().unwrap_or_default();
+            if !available.contains(&version) {
+                return error(
+                    400,
+                    format!(
+                        "version {} is not available for {}",
+                        version, board.board_name
+                    ),
+                );
+            }
+            status!(
+                "Flashing {} at address {} to {} ...",
+                board.board_name,
+                address,
+                version
+            );
+            match fpm.exp_port(&board.port) {
+                Some(mut exp) => (exp.update_firmware(&address, &version), exp.last_transfer),
+                None => (FlashOutcome::Failed, None),
+            }
+        }
+        (None, Some(file)) => {
+            let file = match firmware_dir_file(file) {
+                Ok(file) => file,
+                Err(e) => return e,
+            };
+            let file = file.as_str();
+            let expected = match update.expect_version.as_deref().map(parse_version_arg) {
+                Some(Err(e)) => return error(400, e),
+                Some(Ok(v)) => Some(v),
+                None => expected_version_for_file(file, None),
+            };
+            status!(
+                "Flashing {} at address {} with {} ...",
+                board.board_name,
+                address,
+                file
+            );
+            match fpm.exp_port(&board.port) {
+                Some(mut exp) => (
+                    exp.flash_file(&address, file, expected.as_deref()),
+                    exp.last_transfer,
+                ),
+                None => (FlashOutcome::Failed, None),
+            }
+        }
+        _ => return error(400, "give either \"version\" or \"file\"".to_string()),
+    };
+    status!("{} at address {}: {}", board.board_name, address, outcome);
+    crate::metrics::record_flash(outcome);
+
+    let now = fpm
+        .list_connected_exp_boards()
+        .into_iter()
+        .find(|b| b.address == address)
+        .map(|b| b.version);
+    let status = match outcome {
+        FlashOutcome::Verified | FlashOutcome::Unverified => 200,
+        FlashOutcome::Mismatch | FlashOutcome::Failed => 500,
+        // Stopped by Ctrl+C; the server shuts down after this answer
+        FlashOutcome::Cancelled => 503,
+    };
+    (
+        status,
+        json!({
+            "address": address,
+            "board": board.board_name,
+            "previous_version": board.version,
+            "version": now,
+            "outcome": outcome,
+            "transfer": transfer,
+        }),
+    )
+}
+
+/// Resolve the `file` of an update: a path relative to the firmware directory, or an
+/// absolute one inside it. Clients must not get any other file on the server streamed
+/// into a board.
+fn firmware_dir_file(file: &str) -> Result<String, (u16, Value)> {
+    let dir = crate::firmware_repo::get().dir().to_path_buf();
+    let not_found = || error(400, format!("firmware file not found: {}", file));
+    let (Ok(dir), Ok(path)) = (dir.canonicalize(), dir.join(file).canonicalize()) else {
+        return Err(not_found());
+    };
+    if !path.starts_with(&dir) {
+        return Err(error(
+            403,
+            format!(
+                "{} is not in the firmware directory {}",
+                file,
+                dir.display()
+            ),
+        ));
+    }
+    if !path.is_file() {
+        return Err(not_found());
+    }
+    Ok(path.to_string_lossy().into_owned())
+}
+
+fn error(status: u16, message: String) -> (u16, Value) {
+    (status, json!({ "error": message }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::mock::{self, MockTransport, firmware_file, firmware_repo};
+
+    fn monitor(exp: &MockTransport) -> SharedMonitor {
+        SharedMonitor::new(mock::monitor(Box::new(exp.clone())))
+    }
+
+    fn update(version: &str, controller: bool) -> UpdateExpRequest {
+        UpdateExpRequest {
+            address: "48".to_string(),
+            version: Some(version.to_string()),
+            file: None,
+            expect_version: None,
+            controller,
+        }
+    }
+
+    #[test]
+    fn file_outside_the_firmware_directory_is_refused() {
+        firmware_repo();
+        let mock = MockTransport::new().respond("ID@B4:", "ID:EXP FP-EXP-0071 0.48\r");
+        let outside = firmware_file("serve-outside");
+        let (status, _) = update_exp(
+            &monitor(&mock),
+            &UpdateExpRequest {
+                address: "B4".to_string(),
+                version: None,
+                file: Some(outside.clone()),
+                expect_version: None,
+                controller: false,
+            },
+        );
+        let _ = std::fs::remove_file(&outside);
+        assert_eq!(status, 403);
+        assert!(!mock.commands().iter().any(|c| c.starts_with("ea:")));
+    }
+
+    #[test]
+    fn controller_is_refused_without_opt_in() {
+        firmware_repo();
+        let mock = MockTransport::new().respond("ID@48:", "ID:EXP FP-CPU-2000 0.48\r");
+        let (status, body) = update_exp(&monitor(&mock), &update("9.99", false));
+        assert_eq!(status, 400);
+        assert!(
+            body["error"]
+                .as_str()
+                .unwrap()
+                .contains("\"controller\": true")
+        );
+        assert!(!mock.commands().iter().any(|c| c.starts_with("ea:")));
+    }
+
+    #[test]
+    fn controller_opt_in_passes_the_guard() {
+        firmware_repo();
+        let mock = MockTransport::new()
+            .respond("ID@48:", "ID:EXP FP-CPU-2000 0.48\r")
+            .respond(":00000001FF", "!BL2040:02\r")
+            .respond("ID@48:", "ID:EXP FP-CPU-2000 9.99\r")
+            .respond("ID@48:", "ID:EXP FP-CPU-2000 9.99\r");
+        let (status, body) = update_exp(&monitor(&mock), &update("9.99", true));
+        assert_eq!(status, 200);
+        assert_eq!(body["outcome"], json!(FlashOutcome::Verified));
+        assert!(mock.commands().iter().any(|c| c == "ea:48"));
+    }
+}
@@ -0,0 +1,36 @@
+use crate::commands::exit::Exit;
+use crate::fast_monitor::FastPinballMonitor;
+use crate::protocol::aud_protocol::AUD_MAX_VOLUME;
+
+/// Options for `set-volume`. At least one level must be given.
+#[derive(clap::Args, Debug, Clone)]
+#[group(required = true, multiple = true)]
+pub struct SetVolumeArgs {
+    /// Main (speaker) volume, 0-63
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=AUD_MAX_VOLUME as i64))]
+    pub main: Option<u8>,
+    /// Subwoofer volume, 0-63
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=AUD_MAX_VOLUME as i64))]
+    pub sub: Option<u8>,
+}
+
+/// Set the audio interface's main and/or subwoofer volume.
+pub fn run(fpm: &mut FastPinballMonitor, args: &SetVolumeArgs) -> Exit {
+    let Some(aud) = fpm.aud.as_mut() else {
+        println!("No AUD port found. Connect the audio interface or pass --aud-port.");
+        return Exit::NoHardware;
+    };
+    if let Some(level) = args.main {
+        let resp = aud.set_main_volume(level);
+        println!("Main volume set to {} ({}).", level, reply_or_none(&resp));
+    }
+    if let Some(level) = args.sub {
+        let resp = aud.set_sub_volume(level);
+        println!("Sub volume set to {} ({}).", level, reply_or_none(&resp));
+    }
+    Exit::Success
+}
+
+fn reply_or_none(resp: &str) -> &str {
+    if resp.is_empty() { "no reply" } else { resp }
+}
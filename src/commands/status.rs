@@ -0,0 +1,982 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::{
+    Target, load_document, newest_version, print_document, save_document, version_key,
+};
+use crate::config::OutputFormat;
+use crate::fast_monitor::{ExpBoardInfo, ExpBreakoutInfo, FastPinballMonitor, NetBoardInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Options for `status`.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct StatusArgs {
+    /// Save the report to a file (.yaml/.yml for YAML, JSON otherwise) to compare
+    /// against later
+    #[arg(long, value_name = "FILE", conflicts_with = "compare")]
+    pub save: Option<PathBuf>,
+    /// Compare the machine against a report saved with --save
+    #[arg(long, value_name = "FILE")]
+    pub compare: Option<PathBuf>,
+}
+
+/// Everything `status` reports about a machine.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MachineStatus {
+    pub ports: PortAssignments,
+    /// The NET processor; `None` when it did not answer `ID:`.
+    pub controller: Option<FirmwareStatus>,
+    pub net: Vec<NodeStatus>,
+    pub exp: Vec<ExpStatus>,
+    /// SEG, AUD and DSP, when connected.
+    pub processors: Vec<ProcessorStatus>,
+}
+
+/// Which serial port serves what.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortAssignments {
+    pub net: String,
+    pub exp: Vec<String>,
+    pub seg: Option<String>,
+    pub aud: Option<String>,
+    pub dsp: Option<String>,
+}
+
+/// A board's firmware next to the newest version in the firmware directory, or the
+/// version pinned for it in the config file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareStatus {
+    pub board: String,
+    pub firmware: String,
+    pub newest: Option<String>,
+    /// The version pinned for the board; `state` is then against it instead of `newest`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<String>,
+    pub state: FirmwareState,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FirmwareState {
+    UpToDate,
+    UpdateAvailable,
+    /// The firmware directory has nothing for the board.
+    NoFirmware,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NodeStatus {
+    pub node_id: String,
+    #[serde(flatten)]
+    pub firmware: FirmwareStatus,
+    pub switches: Option<u16>,
+    pub drivers: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExpStatus {
+    pub port: String,
+    pub address: String,
+    #[serde(flatten)]
+    pub firmware: FirmwareStatus,
+    /// The controller's built-in EXP.
+    pub is_controller: bool,
+    pub breakouts: Vec<ExpBreakoutInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProcessorStatus {
+    /// `SEG`, `AUD` or `DSP`.
+    pub kind: String,
+    pub port: String,
+    #[serde(flatten)]
+    pub firmware: FirmwareStatus,
+}
+
+/// A board and where it sits, as compared by `status --compare`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BoardAt {
+    /// `Controller`, `NET`, `EXP`, `Breakout`, `SEG`, `AUD` or `DSP`.
+    pub kind: String,
+    /// E.g. `EXP B4`, `EXP B41`, `NET 00` or `SEG`.
+    pub location: String,
+    pub board: String,
+    pub firmware: String,
+}
+
+/// One board in `--format csv` output. `list`, `list-exp`, `list-net` and `status` all
+/// write these columns in this order, so rows from several commands and machines can go
+/// into one spreadsheet; fields that do not apply are left empty.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct BoardRow {
+    /// `Controller`, `NET`, `EXP`, `Breakout`, `SEG`, `AUD`, `DSP` or, from a deep scan,
+    /// `Unknown`.
+    pub kind: String,
+    /// E.g. `EXP B4`, `EXP B41`, `NET 00` or `SEG`.
+    pub location: String,
+    pub port: String,
+    /// The board type, or an unknown responder's raw reply.
+    pub board: String,
+    pub firmware: String,
+    /// Newest version in the firmware directory.
+    pub newest: Option<String>,
+    /// Version pinned in the config file, which `state` is against.
+    pub pinned: Option<String>,
+    pub state: Option<FirmwareState>,
+    pub switches: Option<u16>,
+    pub drivers: Option<u16>,
+}
+
+/// One difference between a saved report and the machine.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum StatusChange {
+    /// In the saved report, but not found.
+    Missing(BoardAt),
+    /// Found, but not in the saved report.
+    Added(BoardAt),
+    /// The same board type now answers at another location.
+    Moved {
+        board: String,
+        from: String,
+        to: String,
+        before: String,
+        after: String,
+    },
+    FirmwareChanged {
+        location: String,
+        board: String,
+        before: String,
+        after: String,
+    },
+    /// Another board type answers at the location.
+    BoardChanged {
+        location: String,
+        before: BoardAt,
+        after: BoardAt,
+    },
+}
+
+/// The change as a sentence, e.g. `FP-EXP-0071 moved from EXP B4 to EXP B5`.
+impl fmt::Display for StatusChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatusChange::Missing(b) => {
+                write!(
+                    f,
+                    "{} {} (firmware {}) is missing",
+                    b.location, b.board, b.firmware
+                )
+            }
+            StatusChange::Added(b) => {
+                write!(
+                    f,
+                    "{} {} (firmware {}) was added",
+                    b.location, b.board, b.firmware
+                )
+            }
+            StatusChange::Moved {
+                board,
+                from,
+                to,
+                before,
+                after,
+            } => {
+                write!(f, "{} moved from {} to {}", board, from, to)?;
+                if before != after {
+                    write!(f, ", firmware {} -> {}", before, after)?;
+                }
+                Ok(())
+            }
+            StatusChange::FirmwareChanged {
+                location,
+                board,
+                before,
+                after,
+            } => {
+                write!(
+                    f,
+                    "{} {} firmware changed {} -> {}",
+                    location, board, before, after
+                )
+            }
+            StatusChange::BoardChanged {
+                location,
+                before,
+                after,
+            } => write!(
+                f,
+                "{} is now {} (firmware {}), was {} (firmware {})",
+                location, after.board, after.firmware, before.board, before.firmware
+            ),
+        }
+    }
+}
+
+impl fmt::Display for FirmwareState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FirmwareState::UpToDate => "up to date",
+            FirmwareState::UpdateAvailable => "UPDATE AVAILABLE",
+            FirmwareState::NoFirmware => "no firmware available",
+        })
+    }
+}
+
+impl FirmwareStatus {
+    /// `board` at `location` running `firmware`, compared against its pinned version or
+    /// the firmware directory's versions for `{board}_{protocol}` (e.g. `FP-EXP-0071_EXP`).
+    pub fn check(board: &str, location: &str, firmware: &str, protocol: &str) -> Self {
+        let versions = crate::firmware_repo::get().versions(&format!("{}_{}", board, protocol));
+        let target = Target::for_board(board, location, &versions);
+        let state = match &target {
+            None => FirmwareState::NoFirmware,
+            Some(t) if !t.needs_update(firmware) => FirmwareState::UpToDate,
+            // A pinned version may not be in the firmware directory
+            Some(t)
+                if !versions
+                    .iter()
+                    .any(|v| version_key(v) == version_key(&t.version)) =>
+            {
+                FirmwareState::NoFirmware
+            }
+            Some(_) => FirmwareState::UpdateAvailable,
+        };
+        Self {
+            board: board.to_string(),
+            firmware: firmware.to_string(),
+            newest: newest_version(&versions),
+            pinned: target.filter(|t| t.pinned).map(|t| t.version),
+            state,
+        }
+    }
+}
+
+impl NodeStatus {
+    pub fn from_node(n: NetBoardInfo) -> Self {
+        Self {
+            firmware: FirmwareStatus::check(
+                &n.node_name,
+                &format!("NET {}", n.node_id),
+                &n.firmware,
+                "NET",
+            ),
+            node_id: n.node_id,
+            switches: n.switch_count,
+            drivers: n.driver_count,
+        }
+    }
+
+    /// The node as a CSV row; the controller's own entry (node `NC`) as `Controller`.
+    pub fn row(&self, port: &str) -> BoardRow {
+        let mut row = if self.node_id == "NC" {
+            BoardRow::new("Controller", "Controller", port, &self.firmware)
+        } else {
+            BoardRow::new(
+                "NET",
+                &format!("NET {}", self.node_id),
+                port,
+                &self.firmware,
+            )
+        };
+        row.switches = self.switches;
+        row.drivers = self.drivers;
+        row
+    }
+}
+
+impl ExpStatus {
+    pub fn from_board(b: ExpBoardInfo) -> Self {
+        Self {
+            firmware: FirmwareStatus::check(
+                &b.board_name,
+                &format!("EXP {}", b.address),
+                &b.version,
+                "EXP",
+            ),
+            port: b.port,
+            address: b.address,
+            is_controller: b.is_controller,
+            breakouts: b.breakouts,
+        }
+    }
+
+    /// The board and then its breakouts as CSV rows.
+    pub fn rows(&self) -> Vec<BoardRow> {
+        let mut rows = vec![BoardRow::new(
+            "EXP",
+            &format!("EXP {}", self.address),
+            &self.port,
+            &self.firmware,
+        )];
+        rows.extend(self.breakouts.iter().map(|breakout| BoardRow {
+            kind: "Breakout".to_string(),
+            location: format!("EXP {}", breakout.address),
+            port: self.port.clone(),
+            board: breakout.board_name.clone(),
+            firmware: breakout.version.clone(),
+            ..Default::default()
+        }));
+        rows
+    }
+}
+
+impl ProcessorStatus {
+    /// The SEG, AUD and DSP processors connected to `fpm` that answered.
+    pub fn connected(fpm: &mut FastPinballMonitor) -> Vec<Self> {
+        [
+            ("SEG", fpm.seg_info()),
+            ("AUD", fpm.aud_info()),
+            ("DSP", fpm.dsp_info()),
+        ]
+        .into_iter()
+        .filter_map(|(kind, info)| {
+            info.map(|p| ProcessorStatus {
+                kind: kind.to_string(),
+                firmware: FirmwareStatus::check(&p.board_name, kind, &p.version, kind),
+                port: p.port,
+            })
+        })
+        .collect()
+    }
+
+    pub fn row(&self) -> BoardRow {
+        BoardRow::new(&self.kind, &self.kind, &self.port, &self.firmware)
+    }
+}
+
+impl BoardRow {
+    /// Column names, in order. Written even when there are no rows.
+    pub const HEADERS: [&'static str; 10] = [
+        "kind", "location", "port", "board", "firmware", "newest", "pinned", "state", "switches",
+        "drivers",
+    ];
+
+    pub fn new(kind: &str, location: &str, port: &str, firmware: &FirmwareStatus) -> Self {
+        Self {
+            kind: kind.to_string(),
+            location: location.to_string(),
+            port: port.to_string(),
+            board: firmware.board.clone(),
+            firmware: firmware.firmware.clone(),
+            newest: firmware.newest.clone(),
+            pinned: firmware.pinned.clone(),
+            state: Some(firmware.state),
+            ..Default::default()
+        }
+    }
+}
+
+/// Print `rows` as CSV with a header line.
+#[cfg(feature = "export")]
+pub fn print_csv(rows: &[BoardRow]) -> Result<(), String> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(std::io::stdout());
+    writer
+        .write_record(BoardRow::HEADERS)
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        writer.serialize(row).map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())
+}
+
+/// Print `rows` as CSV with a header line.
+#[cfg(not(feature = "export"))]
+pub fn print_csv(_rows: &[BoardRow]) -> Result<(), String> {
+    Err(crate::commands::utils::NO_EXPORT.to_string())
+}
+
+impl MachineStatus {
+    /// Query everything connected to `fpm`.
+    pub fn capture(fpm: &mut FastPinballMonitor) -> Self {
+        let ports = PortAssignments {
+            net: fpm.net.port_name.clone(),
+            exp: fpm.exp_ports.iter().map(|e| e.port_name.clone()).collect(),
+            seg: fpm.seg.as_ref().map(|p| p.port_name.clone()),
+            aud: fpm.aud.as_ref().map(|p| p.port_name.clone()),
+            dsp: fpm.dsp.as_ref().map(|p| p.port_name.clone()),
+        };
+        let nodes: BTreeMap<usize, NetBoardInfo> =
+            fpm.list_connected_net_boards().into_iter().collect();
+        // The listing ends with the controller itself (node `NC`), which gets its own line
+        let (controller, nodes): (Vec<NetBoardInfo>, Vec<NetBoardInfo>) =
+            nodes.into_values().partition(|n| n.node_id == "NC");
+        let controller = controller
+            .first()
+            .map(|c| FirmwareStatus::check(&c.node_name, "NET NC", &c.firmware, "NET"));
+        let net = nodes.into_iter().map(NodeStatus::from_node).collect();
+        let exp = fpm
+            .list_connected_exp_boards()
+            .into_iter()
+            .map(ExpStatus::from_board)
+            .collect();
+        let processors = ProcessorStatus::connected(fpm);
+        Self {
+            ports,
+            controller,
+            net,
+            exp,
+            processors,
+        }
+    }
+
+    /// Read a report written by `save`, YAML or JSON depending on its extension.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        load_document(path)
+    }
+
+    /// Write the report, as YAML or JSON depending on the extension of `path`.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        save_document(path, self)
+    }
+
+    /// Every board in the report as a CSV row, in the order of the text report.
+    pub fn rows(&self) -> Vec<BoardRow> {
+        let mut rows = Vec::new();
+        if let Some(c) = &self.controller {
+            rows.push(BoardRow::new(
+                "Controller",
+                "Controller",
+                &self.ports.net,
+                c,
+            ));
+        }
+        rows.extend(self.net.iter().map(|n| n.row(&self.ports.net)));
+        for b in self.exp.iter() {
+            rows.extend(b.rows());
+        }
+        rows.extend(self.processors.iter().map(ProcessorStatus::row));
+        rows
+    }
+
+    /// Every board in the report, breakouts included.
+    pub fn boards(&self) -> Vec<BoardAt> {
+        let mut boards = Vec::new();
+        let mut add = |kind: &str, location: String, board: &str, firmware: &str| {
+            boards.push(BoardAt {
+                kind: kind.to_string(),
+                location,
+                board: board.to_string(),
+                firmware: firmware.to_string(),
+            })
+        };
+        if let Some(c) = &self.controller {
+            add(
+                "Controller",
+                "Controller".to_string(),
+                &c.board,
+                &c.firmware,
+            );
+        }
+        for n in self.net.iter() {
+            add(
+                "NET",
+                format!("NET {}", n.node_id),
+                &n.firmware.board,
+                &n.firmware.firmware,
+            );
+        }
+        for b in self.exp.iter() {
+            add(
+                "EXP",
+                format!("EXP {}", b.address),
+                &b.firmware.board,
+                &b.firmware.firmware,
+            );
+            for breakout in b.breakouts.iter() {
+                add(
+                    "Breakout",
+                    format!("EXP {}", breakout.address),
+                    &breakout.board_name,
+                    &breakout.version,
+                );
+            }
+        }
+        for p in self.processors.iter() {
+            add(
+                &p.kind,
+                p.kind.clone(),
+                &p.firmware.board,
+                &p.firmware.firmware,
+            );
+        }
+        boards
+    }
+
+    /// What changed going from `baseline` to `self`. Boards are matched by location; a
+    /// board missing from one location that turns up at another of the same kind has
+    /// moved.
+    pub fn compare(&self, baseline: &MachineStatus) -> Vec<StatusChange> {
+        let before = baseline.boards();
+        let after = self.boards();
+        let at = |boards: &[BoardAt], location: &str| -> Option<usize> {
+            boards.iter().position(|b| b.location == location)
+        };
+        let mut changes = Vec::new();
+        // Boards gone from their location, and boards new at theirs
+        let mut gone: Vec<&BoardAt> = Vec::new();
+        let mut new: Vec<&BoardAt> = Vec::new();
+        for old in before.iter() {
+            match at(&after, &old.location).map(|i| &after[i]) {
+                Some(now) if now.board == old.board => {
+                    if now.firmware != old.firmware {
+                        changes.push(StatusChange::FirmwareChanged {
+                            location: now.location.clone(),
+                            board: now.board.clone(),
+                            before: old.firmware.clone(),
+                            after: now.firmware.clone(),
+                        });
+                    }
+                }
+                Some(now) => {
+                    gone.push(old);
+                    new.push(now);
+                }
+                None => gone.push(old),
+            }
+        }
+        new.extend(after.iter().filter(|b| at(&before, &b.location).is_none()));
+
+        let mut moved_to: Vec<bool> = vec![false; new.len()];
+        let mut unmatched = Vec::new();
+        for old in gone {
+            let found = new.iter().enumerate().position(|(i, now)| {
+                !moved_to[i] && now.kind == old.kind && now.board == old.board
+            });
+            match found {
+                Some(i) => {
+                    moved_to[i] = true;
+                    changes.push(StatusChange::Moved {
+                        board: old.board.clone(),
+                        from: old.location.clone(),
+                        to: new[i].location.clone(),
+                        before: old.firmware.clone(),
+                        after: new[i].firmware.clone(),
+                    });
+                }
+                None => unmatched.push(old),
+            }
+        }
+        let mut new: Vec<&BoardAt> = new
+            .into_iter()
+            .zip(moved_to)
+            .filter(|(_, moved)| !moved)
+            .map(|(b, _)| b)
+            .collect();
+        for old in unmatched {
+            match new.iter().position(|b| b.location == old.location) {
+                Some(i) => {
+                    let now = new.remove(i);
+                    changes.push(StatusChange::BoardChanged {
+                        location: old.location.clone(),
+                        before: old.clone(),
+                        after: now.clone(),
+                    });
+                }
+                None => changes.push(StatusChange::Missing(old.clone())),
+            }
+        }
+        changes.extend(new.into_iter().map(|b| StatusChange::Added(b.clone())));
+        changes
+    }
+
+    /// Boards whose firmware is behind the newest in the firmware directory, or other than
+    /// their pinned version.
+    pub fn updates_available(&self) -> usize {
+        self.controller
+            .iter()
+            .chain(self.net.iter().map(|n| &n.firmware))
+            .chain(self.exp.iter().map(|b| &b.firmware))
+            .chain(self.processors.iter().map(|p| &p.firmware))
+            .filter(|f| f.state == FirmwareState::UpdateAvailable)
+            .count()
+    }
+}
+
+/// Print one report of the whole machine: ports, controller, NET nodes, EXP boards with
+/// their breakouts and other processors, each with its firmware against the newest
+/// available. With `--save` or `--compare`, save it or compare against a saved one instead.
+pub fn run(fpm: &mut FastPinballMonitor, args: &StatusArgs, format: OutputFormat) -> Exit {
+    let status = MachineStatus::capture(fpm);
+    if let Some(file) = &args.save {
+        return save(&status, file);
+    }
+    if let Some(file) = &args.compare {
+        return compare(&status, file, format);
+    }
+    match format {
+        OutputFormat::Json | OutputFormat::Yaml => {
+            print_document(&status, format);
+        }
+        OutputFormat::Csv => {
+            if let Err(e) = print_csv(&status.rows()) {
+                eprintln!("Failed to write CSV: {}", e);
+                return Exit::Error;
+            }
+        }
+        OutputFormat::Text => print_status(&status),
+    }
+    Exit::Success
+}
+
+fn save(status: &MachineStatus, file: &Path) -> Exit {
+    if let Err(e) = status.save(file) {
+        eprintln!("{}", e);
+        return Exit::Error;
+    }
+    println!(
+        "Saved the status of {} boards to {}.",
+        status.boards().len(),
+        file.display()
+    );
+    Exit::Success
+}
+
+fn compare(status: &MachineStatus, file: &Path, format: OutputFormat) -> Exit {
+    let baseline = match MachineStatus::load(file) {
+        Ok(baseline) => baseline,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Exit::Error;
+        }
+    };
+    let changes = status.compare(&baseline);
+    let exit = if changes.is_empty() {
+        Exit::Success
+    } else {
+        Exit::Differs
+    };
+
+    if format.is_document() {
+        print_document(&changes, format);
+        return exit;
+    }
+    if changes.is_empty() {
+        println!("The machine matches {}.", file.display());
+        return exit;
+    }
+    println!("Differences from {}:", file.display());
+    for change in changes.iter() {
+        match change {
+            StatusChange::Missing(b) => {
+                println!(
+                    "  - {:<10} {:<14} {:<10} missing",
+                    b.location, b.board, b.firmware
+                )
+            }
+            StatusChange::Added(b) => {
+                println!(
+                    "  + {:<10} {:<14} {:<10} added",
+                    b.location, b.board, b.firmware
+                )
+            }
+            StatusChange::Moved {
+                board,
+                from,
+                to,
+                before,
+                after,
+            } => {
+                let firmware = if before == after {
+                    String::new()
+                } else {
+                    format!(", firmware was {}", before)
+                };
+                println!(
+                    "  > {:<10} {:<14} {:<10} moved from {}{}",
+                    to, board, after, from, firmware
+                )
+            }
+            StatusChange::FirmwareChanged {
+                location,
+                board,
+                before,
+                after,
+            } => {
+                println!(
+                    "  ~ {:<10} {:<14} {:<10} firmware was {}",
+                    location, board, after, before
+                )
+            }
+            StatusChange::BoardChanged {
+                location,
+                before,
+                after,
+            } => println!(
+                "  ~ {:<10} {:<14} {:<10} was {} {}",
+                location, after.board, after.firmware, before.board, before.firmware
+            ),
+        }
+    }
+    exit
+}
+
+fn print_status(status: &MachineStatus) {
+    let ports = &status.ports;
+    println!("Ports:");
+    println!("  NET  {}", ports.net);
+    for port in ports.exp.iter() {
+        println!("  EXP  {}", port);
+    }
+    for (kind, port) in [
+        ("SEG", &ports.seg),
+        ("AUD", &ports.aud),
+        ("DSP", &ports.dsp),
+    ] {
+        if let Some(port) = port {
+            println!("  {}  {}", kind, port);
+        }
+    }
+    println!();
+    match &status.controller {
+        Some(c) => println!("Controller: {} {}", c.board, describe(c)),
+        None => println!("Controller: did not answer ID:"),
+    }
+
+    println!();
+    if status.net.is_empty() {
+        println!("No NET nodes found.");
+    } else {
+        println!("NET nodes:");
+        println!(
+            "  {:<4} {:<14} {:<9} {:<7} {:<24} Status",
+            "Node", "Board", "Firmware", "Newest", "Capabilities"
+        );
+        for n in status.net.iter() {
+            let capabilities = [(n.switches, "switches"), (n.drivers, "drivers")]
+                .into_iter()
+                .filter_map(|(count, what)| count.map(|c| format!("{} {}", c, what)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!(
+                "  {:<4} {:<14} {:<9} {:<7} {:<24} {}",
+                n.node_id,
+                n.firmware.board,
+                n.firmware.firmware,
+                n.firmware.newest.as_deref().unwrap_or("-"),
+                if capabilities.is_empty() {
+                    "-"
+                } else {
+                    &capabilities
+                },
+                state_of(&n.firmware)
+            );
+        }
+    }
+
+    println!();
+    if status.exp.is_empty() {
+        println!("No EXP boards found.");
+    } else {
+        println!("EXP boards:");
+        let show_port = ports.exp.len() > 1;
+        println!(
+            "  {:<7} {:<14} {:<9} {:<7} Status",
+            "Address", "Board", "Firmware", "Newest"
+        );
+        for b in status.exp.iter() {
+            let mut notes = Vec::new();
+            if b.is_controller {
+                notes.push("controller".to_string());
+            }
+            if show_port {
+                notes.push(format!("on {}", b.port));
+            }
+            let notes = if notes.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", notes.join(", "))
+            };
+            println!(
+                "  {:<7} {:<14} {:<9} {:<7} {}{}",
+                b.address,
+                b.firmware.board,
+                b.firmware.firmware,
+                b.firmware.newest.as_deref().unwrap_or("-"),
+                state_of(&b.firmware),
+                notes
+            );
+            for breakout in b.breakouts.iter() {
+                println!(
+                    "    {:<5} {:<14} {:<9} breakout {}",
+                    breakout.address, breakout.board_name, breakout.version, breakout.index
+                );
+            }
+        }
+    }
+
+    for p in status.processors.iter() {
+        println!();
+        println!(
+            "{}: {} on {} {}",
+            p.kind,
+            p.firmware.board,
+            p.port,
+            describe(&p.firmware)
+        );
+    }
+
+    let behind = status.updates_available();
+    if behind > 0 {
+        println!();
+        println!(
+            "{} board(s) can be updated (update-all-exp / update-net).",
+            behind
+        );
+    }
+}
+
+// `UPDATE AVAILABLE`, or `UPDATE AVAILABLE (pinned 2.27)`
+fn state_of(firmware: &FirmwareStatus) -> String {
+    match &firmware.pinned {
+        Some(pinned) => format!("{} (pinned {})", firmware.state, pinned),
+        None => firmware.state.to_string(),
+    }
+}
+
+// `firmware 2.28 (newest 2.30, UPDATE AVAILABLE)`, or `(pinned 2.27, ...)`
+fn describe(firmware: &FirmwareStatus) -> String {
+    if let Some(pinned) = &firmware.pinned {
+        return format!(
+            "firmware {} (pinned {}, {})",
+            firmware.firmware, pinned, firmware.state
+        );
+    }
+    match &firmware.newest {
+        Some(newest) => format!(
+            "firmware {} (newest {}, {})",
+            firmware.firmware, newest, firmware.state
+        ),
+        None => format!("firmware {} ({})", firmware.firmware, firmware.state),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn firmware(board: &str, version: &str) -> FirmwareStatus {
+        FirmwareStatus {
+            board: board.to_string(),
+            firmware: version.to_string(),
+            newest: None,
+            pinned: None,
+            state: FirmwareState::NoFirmware,
+        }
+    }
+
+    fn exp(address: &str, board: &str, version: &str) -> ExpStatus {
+        ExpStatus {
+            port: "exp".to_string(),
+            address: address.to_string(),
+            firmware: firmware(board, version),
+            is_controller: false,
+            breakouts: Vec::new(),
+        }
+    }
+
+    fn machine(exp: Vec<ExpStatus>) -> MachineStatus {
+        MachineStatus {
+            controller: Some(firmware("FP-CPU-2000", "2.28")),
+            exp,
+            ..MachineStatus::default()
+        }
+    }
+
+    fn at(kind: &str, location: &str, board: &str, firmware: &str) -> BoardAt {
+        BoardAt {
+            kind: kind.to_string(),
+            location: location.to_string(),
+            board: board.to_string(),
+            firmware: firmware.to_string(),
+        }
+    }
+
+    #[test]
+    fn unchanged_machine_has_no_changes() {
+        let status = machine(vec![exp("B4", "FP-EXP-0071", "0.48")]);
+        assert!(status.compare(&status).is_empty());
+    }
+
+    #[test]
+    fn firmware_change() {
+        let before = machine(vec![exp("B4", "FP-EXP-0071", "0.48")]);
+        let after = machine(vec![exp("B4", "FP-EXP-0071", "0.50")]);
+        assert_eq!(
+            after.compare(&before),
+            [StatusChange::FirmwareChanged {
+                location: "EXP B4".to_string(),
+                board: "FP-EXP-0071".to_string(),
+                before: "0.48".to_string(),
+                after: "0.50".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn board_at_another_address_has_moved() {
+        let before = machine(vec![exp("B4", "FP-EXP-0071", "0.48")]);
+        let after = machine(vec![exp("B5", "FP-EXP-0071", "0.48")]);
+        assert_eq!(
+            after.compare(&before),
+            [StatusChange::Moved {
+                board: "FP-EXP-0071".to_string(),
+                from: "EXP B4".to_string(),
+                to: "EXP B5".to_string(),
+                before: "0.48".to_string(),
+                after: "0.48".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn other_board_type_at_a_location() {
+        let before = machine(vec![exp("B4", "FP-EXP-0071", "0.48")]);
+        let after = machine(vec![exp("B4", "FP-EXP-0081", "0.48")]);
+        assert_eq!(
+            after.compare(&before),
+            [StatusChange::BoardChanged {
+                location: "EXP B4".to_string(),
+                before: at("EXP", "EXP B4", "FP-EXP-0071", "0.48"),
+                after: at("EXP", "EXP B4", "FP-EXP-0081", "0.48"),
+            }]
+        );
+    }
+
+    #[test]
+    fn missing_and_added_boards() {
+        let before = machine(vec![exp("B4", "FP-EXP-0071", "0.48")]);
+        let after = machine(vec![exp("88", "FP-EXP-0091", "0.46")]);
+        assert_eq!(
+            after.compare(&before),
+            [
+                StatusChange::Missing(at("EXP", "EXP B4", "FP-EXP-0071", "0.48")),
+                StatusChange::Added(at("EXP", "EXP 88", "FP-EXP-0091", "0.46")),
+            ]
+        );
+    }
+
+    #[test]
+    fn breakouts_are_compared_too() {
+        let mut before = machine(vec![exp("B4", "FP-EXP-0071", "0.48")]);
+        before.exp[0].breakouts.push(ExpBreakoutInfo {
+            address: "B41".to_string(),
+            index: 1,
+            board_name: "FP-BRK-0001".to_string(),
+            version: "0.10".to_string(),
+        });
+        let after = machine(vec![exp("B4", "FP-EXP-0071", "0.48")]);
+        assert_eq!(
+            after.compare(&before),
+            [StatusChange::Missing(at(
+                "Breakout",
+                "EXP B41",
+                "FP-BRK-0001",
+                "0.10"
+            ))]
+        );
+    }
+}
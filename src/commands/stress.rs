@@ -0,0 +1,214 @@
+// Load both links for a while and count what goes wrong: the EXP link carries LED updates
+// as fast as it takes them, the NET link `ID:` queries back to back. Between LED updates
+// the EXP board is asked for its ID, so both links are checked the same way: a reply
+// that does not come back counts as dropped, one that differs from the board's ID as
+// malformed.
+
+use crate::commands::exit::Exit;
+use crate::commands::utils::{parse_hex_address, print_document};
+use crate::config::OutputFormat;
+use crate::fast_monitor::FastPinballMonitor;
+use crate::protocol::exp_protocol::ExpProtocol;
+use crate::protocol::net_commands::NetCommand;
+use crate::protocol::net_protocol::NetProtocol;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// How long to wait for a reply before counting it as dropped.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+/// LED updates sent between two ID checks on the EXP link.
+const LED_UPDATES_PER_CHECK: u64 = 10;
+
+/// Options for `stress`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct StressArgs {
+    /// Seconds to keep both links busy
+    #[arg(long, value_name = "SECS", default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    pub duration: u64,
+    /// EXP board to send the LED traffic to (hex, e.g. B4; default: the first
+    /// expansion board)
+    #[arg(long, value_parser = parse_hex_address)]
+    pub address: Option<String>,
+    /// LEDs set by each update
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u8).range(1..))]
+    pub leds: u8,
+}
+
+/// What one link went through.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct LinkStress {
+    /// `NET` or `EXP`.
+    pub link: String,
+    pub port: String,
+    /// Commands sent, LED updates and ID queries together.
+    pub commands: u64,
+    /// ID queries whose reply was checked.
+    pub checks: u64,
+    /// Replies that did not come back.
+    pub dropped: u64,
+    /// Replies other than the ID the board gave at the start.
+    pub malformed: u64,
+    /// Failed checks per check, 0 to 1.
+    pub error_rate: f64,
+}
+
+impl LinkStress {
+    // Count the reply to one ID query
+    fn check(&mut self, reply: &str, expected: &str) {
+        self.checks += 1;
+        if reply.is_empty() {
+            self.dropped += 1;
+        } else if reply != expected {
+            log::debug!(
+                "{} {}: expected {:?}, got {:?}",
+                self.link,
+                self.port,
+                expected,
+                reply
+            );
+            self.malformed += 1;
+        }
+        self.error_rate = (self.dropped + self.malformed) as f64 / self.checks as f64;
+    }
+}
+
+/// Keep the NET and EXP links busy for `--duration` seconds and report the error rate of
+/// each.
+pub fn run(fpm: &mut FastPinballMonitor, args: &StressArgs, format: OutputFormat) -> Exit {
+    let address = match &args.address {
+        Some(address) => address.clone(),
+        None => {
+            let mut boards = fpm.list_connected_exp_boards();
+            boards.sort_by_key(|b| b.is_controller);
+            match boards.into_iter().next() {
+                Some(b) => b.address,
+                None => {
+                    println!("No EXP boards found.");
+                    return Exit::NoHardware;
+                }
+            }
+        }
+    };
+    let Some(exp_port) = fpm
+        .exp_port_for_address(&address)
+        .map(|e| e.port_name.clone())
+    else {
+        println!("No EXP board answered at address {}.", address);
+        return Exit::NoHardware;
+    };
+
+    let duration = Duration::from_secs(args.duration);
+    if !format.is_document() {
+        status!(
+            "Sending LED updates to the EXP board at {} and ID queries to NET for {}s...",
+            address,
+            args.duration
+        );
+    }
+    let net = &mut fpm.net;
+    let Some(exp) = fpm.exp_ports.iter_mut().find(|e| e.port_name == exp_port) else {
+        return Exit::NoHardware;
+    };
+    let (net_result, exp_result) = std::thread::scope(|scope| {
+        let net_thread = scope.spawn(|| stress_net(net, duration));
+        let exp_result = stress_exp(exp, &address, args.leds, duration);
+        (net_thread.join().ok().flatten(), exp_result)
+    });
+    let results: Vec<LinkStress> = net_result.into_iter().chain(exp_result).collect();
+
+    if format.is_document() {
+        print_document(&results, format);
+    } else {
+        for r in results.iter() {
+            println!(
+                "{} {}: {} commands, {} checks, {} dropped, {} malformed, error rate {:.3}%",
+                r.link,
+                r.port,
+                r.commands,
+                r.checks,
+                r.dropped,
+                r.malformed,
+                r.error_rate * 100.0
+            );
+        }
+    }
+    if results.len() < 2 {
+        eprintln!("A link did not answer before the test; it was not stressed.");
+        return Exit::NoHardware;
+    }
+    if results.iter().any(|r| r.dropped + r.malformed > 0) {
+        return Exit::Error;
+    }
+    Exit::Success
+}
+
+// `None` when the NET processor does not answer to begin with
+fn stress_net(net: &mut NetProtocol, duration: Duration) -> Option<LinkStress> {
+    let _ = net.receive();
+    net.send_command(&NetCommand::Id).ok()?;
+    let expected = net.receive_within(REPLY_TIMEOUT);
+    if expected.is_empty() {
+        return None;
+    }
+    let mut result = LinkStress {
+        link: "NET".to_string(),
+        port: net.port_name.clone(),
+        ..Default::default()
+    };
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        let _ = net.send_command(&NetCommand::Id);
+        result.commands += 1;
+        let reply = net.receive_within(REPLY_TIMEOUT);
+        result.check(&reply, &expected);
+        if reply.is_empty() {
+            // A late reply must not be taken for the next one
+            let _ = net.receive_within(REPLY_TIMEOUT);
+        }
+    }
+    Some(result)
+}
+
+// `None` when the board does not answer to begin with
+fn stress_exp(
+    exp: &mut ExpProtocol,
+    address: &str,
+    leds: u8,
+    duration: Duration,
+) -> Option<LinkStress> {
+    let query = format!("ID@{}:\r", address).into_bytes();
+    let _ = exp.receive();
+    exp.send(query.clone());
+    let expected = exp.receive_within(REPLY_TIMEOUT);
+    if expected.is_empty() {
+        return None;
+    }
+    let mut result = LinkStress {
+        link: "EXP".to_string(),
+        port: exp.port_name.clone(),
+        ..Default::default()
+    };
+    exp.select_address(address);
+    let batches = (leds as u64).div_ceil(16);
+    let start = Instant::now();
+    let mut frame: u64 = 0;
+    while start.elapsed() < duration {
+        // A color that changes every frame, so every update really changes the LEDs
+        let level = (frame % 256) as u8;
+        let colors = vec![(level, 255 - level, level / 2); leds as usize];
+        exp.set_leds(0, &colors);
+        result.commands += batches;
+        frame += 1;
+        if frame.is_multiple_of(LED_UPDATES_PER_CHECK) {
+            exp.send(query.clone());
+            result.commands += 1;
+            let reply = exp.receive_within(REPLY_TIMEOUT);
+            result.check(&reply, &expected);
+            if reply.is_empty() {
+                let _ = exp.receive_within(REPLY_TIMEOUT);
+            }
+        }
+    }
+    exp.set_leds(0, &vec![(0, 0, 0); leds as usize]);
+    Some(result)
+}
@@ -0,0 +1,157 @@
+// Aligned tables for text listings. Cells that need attention, like outdated firmware, can
+// be colored; color is only used when stdout is a terminal, and never with `--no-color`
+// (`no_color = true` in the config file) or a non-empty `NO_COLOR` environment variable.
+
+use crate::commands::utils::{Target, version_key};
+use std::io::IsTerminal;
+
+/// Colors a cell can be highlighted with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Yellow,
+    Red,
+}
+
+impl Color {
+    fn code(self) -> &'static str {
+        match self {
+            Color::Yellow => "\x1b[33m",
+            Color::Red => "\x1b[31m",
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Default)]
+pub struct Cell {
+    pub text: String,
+    pub color: Option<Color>,
+}
+
+impl Cell {
+    pub fn colored(text: impl Into<String>, color: Option<Color>) -> Self {
+        Self {
+            text: text.into(),
+            color,
+        }
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Self::colored(text, None)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Self::colored(text, None)
+    }
+}
+
+/// Rows under a header line, each column as wide as its widest cell.
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Self {
+        Self {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Add a row; missing cells at the end are left blank.
+    pub fn row(&mut self, cells: Vec<Cell>) {
+        self.rows.push(cells);
+    }
+
+    /// The table as lines, each starting with `indent` spaces, colored when `color`.
+    pub fn render(&self, indent: usize, color: bool) -> Vec<String> {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in self.rows.iter() {
+            for (i, cell) in row.iter().enumerate() {
+                let width = cell.text.chars().count();
+                match widths.get_mut(i) {
+                    Some(w) => *w = (*w).max(width),
+                    None => widths.push(width),
+                }
+            }
+        }
+        let header: Vec<Cell> = self
+            .headers
+            .iter()
+            .map(|h| Cell::from(h.as_str()))
+            .collect();
+        std::iter::once(&header)
+            .chain(self.rows.iter())
+            .map(|row| {
+                let mut line = " ".repeat(indent);
+                for (i, width) in widths.iter().enumerate() {
+                    let cell = row.get(i).cloned().unwrap_or_default();
+                    // Pad before coloring, so escape codes do not count towards the width
+                    let text = if i + 1 < widths.len() {
+                        format!("{:<width$}  ", cell.text, width = width)
+                    } else {
+                        cell.text
+                    };
+                    match cell.color.filter(|_| color) {
+                        Some(c) => {
+                            let padded = text.trim_end();
+                            line.push_str(c.code());
+                            line.push_str(padded);
+                            line.push_str(RESET);
+                            line.push_str(&text[padded.len()..]);
+                        }
+                        None => line.push_str(&text),
+                    }
+                }
+                line.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    /// Print the table to stdout, indented by `indent` spaces.
+    pub fn print(&self, indent: usize) {
+        for line in self.render(indent, use_color()) {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Whether stdout gets colored output.
+pub fn use_color() -> bool {
+    !crate::config::get().no_color
+        && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        && std::io::stdout().is_terminal()
+}
+
+/// How to highlight firmware `current` given the `available` versions: yellow one version
+/// behind the newest, red further behind, nothing when up to date or unknown. A board
+/// with a pinned `target` is yellow when it runs any other version.
+pub fn firmware_color(
+    current: &str,
+    target: Option<&Target>,
+    available: &[String],
+) -> Option<Color> {
+    if let Some(target) = target.filter(|t| t.pinned) {
+        return target.needs_update(current).then_some(Color::Yellow);
+    }
+    let current = version_key(current)?;
+    let mut newer: Vec<_> = available
+        .iter()
+        .filter_map(|v| version_key(v))
+        .filter(|v| *v > current)
+        .collect();
+    newer.sort();
+    newer.dedup();
+    match newer.len() {
+        0 => None,
+        1 => Some(Color::Yellow),
+        _ => Some(Color::Red),
+    }
+}
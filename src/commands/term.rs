@@ -0,0 +1,88 @@
+use crate::commands::exit::Exit;
+use crate::fast_monitor::FastPinballMonitor;
+use std::io::{self, BufRead, Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TermTarget {
+    #[default]
+    Net,
+    Exp,
+}
+
+/// Options for `term`.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct TermArgs {
+    /// Port to open the console on
+    #[arg(value_enum, default_value_t = TermTarget::Net)]
+    pub target: TermTarget,
+}
+
+/// Interactive console on the NET (default) or EXP port.
+///
+/// Each typed line is sent terminated with `\r`; everything the board sends is echoed
+/// as it arrives. Type `exit` or `quit` (or close stdin) to leave.
+pub fn run(fpm: &mut FastPinballMonitor, args: &TermArgs) -> Exit {
+    let (label, port) = match args.target {
+        TermTarget::Net => ("NET", &mut fpm.net.serial_port),
+        TermTarget::Exp => match fpm.exp_ports.first_mut() {
+            Some(exp) => ("EXP", &mut exp.serial_port),
+            None => {
+                println!("No EXP port available.");
+                return Exit::NoHardware;
+            }
+        },
+    };
+
+    let mut reader = match port.try_clone() {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("Failed to open {} port for reading: {}", label, e);
+            return Exit::Error;
+        }
+    };
+
+    println!(
+        "Connected to {} port. Commands are sent with \\r; type 'exit' to quit.",
+        label
+    );
+
+    // Echo everything the board sends until we are told to stop
+    let stop = Arc::new(AtomicBool::new(false));
+    let reader_stop = Arc::clone(&stop);
+    let reader_thread = std::thread::spawn(move || {
+        let mut buf = [0u8; 256];
+        while !reader_stop.load(Ordering::Relaxed) {
+            if let Ok(n) = reader.read(&mut buf)
+                && n > 0
+            {
+                let text = String::from_utf8_lossy(&buf[..n]).replace('\r', "\n");
+                print!("{}", text);
+                let _ = io::stdout().flush();
+            }
+        }
+    });
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let cmd = line.trim();
+        if cmd.eq_ignore_ascii_case("exit") || cmd.eq_ignore_ascii_case("quit") {
+            break;
+        }
+        if cmd.is_empty() {
+            continue;
+        }
+        if let Err(e) = port.write_all(format!("{}\r", cmd).as_bytes()) {
+            eprintln!("Write failed: {}", e);
+            break;
+        }
+        let _ = port.flush();
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    let _ = reader_thread.join();
+    Exit::Success
+}
@@ -0,0 +1,114 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::read_line_trimmed;
+use crate::fast_monitor::FastPinballMonitor;
+use std::io::{self, Write};
+
+/// Longest pulse the test command will fire, to avoid cooking a coil.
+pub const MAX_PULSE_MS: u8 = 100;
+
+/// Options for `test-driver`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct TestDriverArgs {
+    /// Driver number to pulse (prompted for if missing)
+    #[arg(long)]
+    pub driver: Option<u8>,
+    /// Pulse time in milliseconds
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u8).range(1..=MAX_PULSE_MS as i64))]
+    pub ms: u8,
+    /// Pulse power in percent
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub power: u8,
+    /// Pulse without asking for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+/// Pulse a single driver to verify coil wiring. Driver numbers beyond the drivers the NET
+/// nodes report are refused before anything is asked.
+pub fn run(fpm: &mut FastPinballMonitor, args: &TestDriverArgs) -> Exit {
+    let driver = match args.driver {
+        Some(d) => d,
+        None => {
+            print!("Enter driver number: ");
+            let _ = io::stdout().flush();
+            let input = read_line_trimmed();
+            let Ok(d) = input.parse::<u8>() else {
+                println!("Invalid driver number: {}", input);
+                return Exit::Error;
+            };
+            d
+        }
+    };
+    // Drivers are numbered on from one node to the next
+    let count: u16 = fpm
+        .net
+        .list_nodes()
+        .iter()
+        .filter_map(|n| n.driver_count)
+        .sum();
+    if count == 0 {
+        eprintln!("No drivers found on the NET loop.");
+        return Exit::NoHardware;
+    }
+    if u16::from(driver) >= count {
+        eprintln!(
+            "There is no driver {}: the NET loop has {} driver(s), 0 to {}.",
+            driver,
+            count,
+            count - 1
+        );
+        return Exit::Error;
+    }
+    let pulse_ms = args.ms;
+    let power_pct = args.power;
+    let power = (power_pct as u32 * 0xFF / 100) as u8;
+
+    println!(
+        "About to pulse driver {} for {} ms at {}% power.",
+        driver, pulse_ms, power_pct
+    );
+    if !args.yes {
+        print!("Proceed? [y/N]: ");
+        let _ = io::stdout().flush();
+        let confirm = read_line_trimmed();
+        if !matches!(confirm.as_str(), "y" | "Y" | "yes" | "YES") {
+            println!("Canceled.");
+            return Exit::Canceled;
+        }
+    }
+
+    match fpm.net.pulse_driver(driver, pulse_ms, power) {
+        Ok(()) => {
+            println!("Pulsed driver {}.", driver);
+            Exit::Success
+        }
+        Err(e) => {
+            eprintln!("Failed to pulse driver {}: {}", driver, e);
+            Exit::Error
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::mock::MockTransport;
+    use crate::protocol::net_protocol::NetProtocol;
+
+    #[test]
+    fn driver_beyond_the_nodes_is_refused_before_asking() {
+        let mock = MockTransport::new()
+            .respond("NN:00", "NN:00,FP-I/O-3208,1.05,08,20\r")
+            .respond("NN:01", "!Node Not Found!\r");
+        let net = NetProtocol::with_transport("net".into(), Box::new(mock.clone()));
+        let mut fpm = FastPinballMonitor::with_protocols(net, Vec::new(), None, None, None);
+        let args = TestDriverArgs {
+            driver: Some(8),
+            ms: 10,
+            power: 100,
+            yes: false,
+        };
+        assert_eq!(run(&mut fpm, &args), Exit::Error);
+        assert_eq!(mock.commands(), ["NN:00", "NN:01"]);
+    }
+}
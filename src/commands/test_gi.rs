@@ -0,0 +1,142 @@
+// Walk the playfield with the glass off: each GI string is held on and each flasher
+// flashed for `--on-ms`, one channel after another. After each one the tech says
+// whether it lit (or has it repeated); the channels that did not are listed at the end.
+// With `--auto` the channels are stepped through without asking.
+
+use crate::commands::exit::Exit;
+use crate::commands::test_driver::MAX_PULSE_MS;
+use crate::commands::utils::read_line_trimmed;
+use crate::fast_monitor::FastPinballMonitor;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Time between two flashes of a flasher.
+const FLASH_INTERVAL: Duration = Duration::from_millis(250);
+/// Pause between two channels with `--auto`.
+const AUTO_PAUSE: Duration = Duration::from_millis(500);
+
+/// Options for `test-gi`. At least one `--gi` or `--flasher` driver is needed.
+#[derive(clap::Args, Debug, Clone)]
+#[command(group = clap::ArgGroup::new("channels").required(true).multiple(true))]
+pub struct TestGiArgs {
+    /// Driver of a GI string, held on (repeatable)
+    #[arg(long, value_name = "DRIVER", group = "channels")]
+    pub gi: Vec<u8>,
+    /// Driver of a flasher, flashed (repeatable)
+    #[arg(long, value_name = "DRIVER", group = "channels")]
+    pub flasher: Vec<u8>,
+    /// How long each channel stays on, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 2000, value_parser = clap::value_parser!(u64).range(100..=30000))]
+    pub on_ms: u64,
+    /// GI power in percent
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub power: u8,
+    /// Length of each flash in milliseconds
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u8).range(1..=MAX_PULSE_MS as i64))]
+    pub flash_ms: u8,
+    /// Step through the channels without asking whether each one lit
+    #[arg(long)]
+    pub auto: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Channel {
+    Gi(u8),
+    Flasher(u8),
+}
+
+impl std::fmt::Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Channel::Gi(driver) => write!(f, "GI driver {}", driver),
+            Channel::Flasher(driver) => write!(f, "flasher driver {}", driver),
+        }
+    }
+}
+
+/// Step through the GI strings, then the flashers.
+pub fn run(fpm: &mut FastPinballMonitor, args: &TestGiArgs) -> Exit {
+    let channels: Vec<Channel> = args
+        .gi
+        .iter()
+        .map(|&d| Channel::Gi(d))
+        .chain(args.flasher.iter().map(|&d| Channel::Flasher(d)))
+        .collect();
+    let on_time = Duration::from_millis(args.on_ms);
+    let gi_power = (args.power as u32 * 0xFF / 100) as u8;
+    if !args.auto {
+        println!(
+            "After each channel press Enter if it lit, 'x' if it did not, 'r' to repeat it or 'q' to stop."
+        );
+    }
+
+    let mut dark: Vec<Channel> = Vec::new();
+    let mut tested = 0;
+    let mut i = 0;
+    while i < channels.len() {
+        let channel = channels[i];
+        println!(
+            "[{}/{}] {}: on for {} ms",
+            i + 1,
+            channels.len(),
+            channel,
+            args.on_ms
+        );
+        let result = match channel {
+            Channel::Gi(driver) => fpm.net.hold_driver(driver, gi_power, on_time),
+            Channel::Flasher(driver) => flash(fpm, driver, args.flash_ms, on_time),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to drive {}: {}", channel, e);
+            return Exit::Error;
+        }
+        if args.auto {
+            tested += 1;
+            i += 1;
+            std::thread::sleep(AUTO_PAUSE);
+            continue;
+        }
+        print!("Lit? [Enter/x/r/q]: ");
+        let _ = io::stdout().flush();
+        match read_line_trimmed().to_lowercase().as_str() {
+            "r" => continue,
+            "q" => {
+                println!("Stopped after {} of {} channels.", tested, channels.len());
+                return report(&dark, Exit::Canceled);
+            }
+            "x" | "n" | "no" => dark.push(channel),
+            _ => {}
+        }
+        tested += 1;
+        i += 1;
+    }
+    println!("Tested {} channels.", tested);
+    report(&dark, Exit::Success)
+}
+
+// Flash a flasher every `FLASH_INTERVAL` for `on_time`
+fn flash(
+    fpm: &mut FastPinballMonitor,
+    driver: u8,
+    flash_ms: u8,
+    on_time: Duration,
+) -> io::Result<()> {
+    let start = Instant::now();
+    while start.elapsed() < on_time {
+        let flash_start = Instant::now();
+        fpm.net.pulse_driver(driver, flash_ms, 0xFF)?;
+        std::thread::sleep(FLASH_INTERVAL.saturating_sub(flash_start.elapsed()));
+    }
+    Ok(())
+}
+
+fn report(dark: &[Channel], exit: Exit) -> Exit {
+    if dark.is_empty() {
+        return exit;
+    }
+    println!("Did not light:");
+    for channel in dark {
+        println!("  {}", channel);
+    }
+    Exit::Error
+}
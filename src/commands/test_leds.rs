@@ -0,0 +1,83 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::{parse_color, parse_hex_address, read_line_trimmed};
+use crate::fast_monitor::FastPinballMonitor;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Options for `test-leds`. Without `--chase` or `--blink` every LED is set to `--all`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct TestLedsArgs {
+    /// EXP board address (hex, e.g. B4; prompted for if missing)
+    #[arg(long, value_parser = parse_hex_address)]
+    pub address: Option<String>,
+    /// Breakout behind the board to target (0-3)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=3))]
+    pub breakout: Option<u8>,
+    /// Number of LEDs in the chain
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u8).range(1..))]
+    pub count: u8,
+    /// Set every LED to this color (RRGGBB)
+    #[arg(long, value_name = "RRGGBB", value_parser = parse_color, default_value = "FFFFFF", conflicts_with_all = ["chase", "blink"])]
+    pub all: (u8, u8, u8),
+    /// Walk a single lit LED along the chain, optionally in this color (RRGGBB)
+    #[arg(long, value_name = "RRGGBB", value_parser = parse_color, num_args = 0..=1, default_missing_value = "FFFFFF", conflicts_with = "blink")]
+    pub chase: Option<(u8, u8, u8)>,
+    /// Blink the LED at this index
+    #[arg(long, value_name = "INDEX")]
+    pub blink: Option<u8>,
+}
+
+/// Drive RGB LEDs on an EXP board or breakout to verify LED chains.
+pub fn run(fpm: &mut FastPinballMonitor, args: &TestLedsArgs) -> Exit {
+    let address = match args.address.clone() {
+        Some(a) => a,
+        None => {
+            print!("Enter EXP board address (e.g., B4): ");
+            let _ = io::stdout().flush();
+            match parse_hex_address(&read_line_trimmed()) {
+                Ok(a) => a,
+                Err(e) => {
+                    println!("Invalid EXP board address: {}", e);
+                    return Exit::Error;
+                }
+            }
+        }
+    };
+    let target = match args.breakout {
+        Some(b) => format!("{}{:X}", address, b),
+        None => address.clone(),
+    };
+    let count = args.count;
+
+    let Some(exp) = fpm.exp_port_for_address(&address) else {
+        println!("No EXP board answered at address {}.", address);
+        return Exit::NoHardware;
+    };
+    exp.select_address(&target);
+
+    if let Some(index) = args.blink {
+        println!("Blinking LED {} on {}...", index, target);
+        for _ in 0..10 {
+            exp.set_leds(index, &[(0xFF, 0xFF, 0xFF)]);
+            std::thread::sleep(Duration::from_millis(250));
+            exp.set_leds(index, &[(0, 0, 0)]);
+            std::thread::sleep(Duration::from_millis(250));
+        }
+    } else if let Some(color) = args.chase {
+        println!("Walking {} LEDs on {}...", count, target);
+        exp.set_leds(0, &vec![(0, 0, 0); count as usize]);
+        for index in 0..count {
+            exp.set_leds(index, &[color]);
+            std::thread::sleep(Duration::from_millis(100));
+            exp.set_leds(index, &[(0, 0, 0)]);
+        }
+    } else {
+        let (r, g, b) = args.all;
+        println!(
+            "Setting {} LEDs on {} to {:02X}{:02X}{:02X}.",
+            count, target, r, g, b
+        );
+        exp.set_leds(0, &vec![args.all; count as usize]);
+    }
+    Exit::Success
+}
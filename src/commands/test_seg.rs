@@ -0,0 +1,80 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::parse_color;
+use crate::fast_monitor::FastPinballMonitor;
+use crate::protocol::seg_protocol::SEG_DIGITS;
+use std::time::Duration;
+
+/// Test patterns for `test-seg`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegPattern {
+    /// Step every character through 0-9, then show the display number
+    Count,
+    /// Light every segment
+    AllOn,
+    /// Blank the display
+    Off,
+}
+
+/// Options for `test-seg`. Without `--text` the `--pattern` is shown.
+#[derive(clap::Args, Debug, Clone)]
+pub struct TestSegArgs {
+    /// Display to drive (repeatable)
+    #[arg(long, value_name = "INDEX", default_values_t = [0u8])]
+    pub display: Vec<u8>,
+    /// Show this text (cut to the display width)
+    #[arg(long, conflicts_with = "pattern")]
+    pub text: Option<String>,
+    /// Test pattern to show
+    #[arg(long, value_enum, default_value_t = SegPattern::Count)]
+    pub pattern: SegPattern,
+    /// Set the characters to this color first (RRGGBB)
+    #[arg(long, value_name = "RRGGBB", value_parser = parse_color)]
+    pub color: Option<(u8, u8, u8)>,
+}
+
+/// Write test text or patterns to segment displays to verify wiring.
+pub fn run(fpm: &mut FastPinballMonitor, args: &TestSegArgs) -> Exit {
+    let Some(seg) = fpm.seg.as_mut() else {
+        println!("No SEG port found. Connect the segment display processor or pass --seg-port.");
+        return Exit::NoHardware;
+    };
+    if let Some(color) = args.color {
+        for &display in &args.display {
+            seg.set_color(display, color);
+        }
+    }
+    if let Some(text) = &args.text {
+        println!("Showing {:?} on display(s) {:?}.", text, args.display);
+        for &display in &args.display {
+            seg.write_text(display, text);
+        }
+        return Exit::Success;
+    }
+    match args.pattern {
+        SegPattern::Count => {
+            println!("Counting through 0-9 on display(s) {:?}...", args.display);
+            for digit in '0'..='9' {
+                for &display in &args.display {
+                    seg.write_text(display, &digit.to_string().repeat(SEG_DIGITS));
+                }
+                std::thread::sleep(Duration::from_millis(500));
+            }
+            for &display in &args.display {
+                seg.write_text(display, &format!("DISP {}", display));
+            }
+        }
+        SegPattern::AllOn => {
+            println!("Lighting every segment on display(s) {:?}.", args.display);
+            for &display in &args.display {
+                seg.write_text(display, &"8".repeat(SEG_DIGITS));
+            }
+        }
+        SegPattern::Off => {
+            println!("Blanking display(s) {:?}.", args.display);
+            for &display in &args.display {
+                seg.write_text(display, "");
+            }
+        }
+    }
+    Exit::Success
+}
@@ -0,0 +1,88 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::{parse_hex_address, read_line_trimmed};
+use crate::fast_monitor::FastPinballMonitor;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Largest angle accepted by `--angle`; the servo's full travel.
+pub const MAX_SERVO_ANGLE: u8 = 180;
+/// Degrees moved per step of a sweep.
+const SWEEP_STEP: u8 = 5;
+
+/// Options for `test-servo`. Without `--angle` the servo sweeps its full travel.
+#[derive(clap::Args, Debug, Clone)]
+pub struct TestServoArgs {
+    /// EXP board address (hex, e.g. B4; prompted for if missing)
+    #[arg(long, value_parser = parse_hex_address)]
+    pub address: Option<String>,
+    /// Servo output on the board
+    #[arg(long, default_value_t = 0)]
+    pub servo: u8,
+    /// Move to this angle (0-180) and stay there
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=MAX_SERVO_ANGLE as i64))]
+    pub angle: Option<u8>,
+    /// Milliseconds between sweep steps
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 50,
+        conflicts_with = "angle"
+    )]
+    pub step_ms: u64,
+}
+
+/// Drive a servo output on an EXP board to verify servo wiring.
+pub fn run(fpm: &mut FastPinballMonitor, args: &TestServoArgs) -> Exit {
+    let address = match args.address.clone() {
+        Some(a) => a,
+        None => {
+            print!("Enter EXP board address (e.g., B4): ");
+            let _ = io::stdout().flush();
+            match parse_hex_address(&read_line_trimmed()) {
+                Ok(a) => a,
+                Err(e) => {
+                    println!("Invalid EXP board address: {}", e);
+                    return Exit::Error;
+                }
+            }
+        }
+    };
+    let Some(board_type) = crate::board_map::get().exp_board_type(&address) else {
+        println!("{} is not a known EXP board address.", address);
+        return Exit::Error;
+    };
+
+    let Some(exp) = fpm.exp_port_for_address(&address) else {
+        println!("No EXP board answered at address {}.", address);
+        return Exit::NoHardware;
+    };
+    exp.select_address(&address);
+
+    let servo = args.servo;
+    match args.angle {
+        Some(angle) => {
+            println!(
+                "Moving servo {} on {} ({}) to {} degrees.",
+                servo, board_type, address, angle
+            );
+            exp.set_servo(servo, angle_to_position(angle));
+        }
+        None => {
+            println!(
+                "Sweeping servo {} on {} ({}) from 0 to {} degrees and back...",
+                servo, board_type, address, MAX_SERVO_ANGLE
+            );
+            let up = (0..=MAX_SERVO_ANGLE).step_by(SWEEP_STEP as usize);
+            for angle in up.clone().chain(up.rev()) {
+                exp.set_servo(servo, angle_to_position(angle));
+                std::thread::sleep(Duration::from_millis(args.step_ms));
+            }
+        }
+    }
+    Exit::Success
+}
+
+/// Map 0-180 degrees onto the 0x00-0xFF position range.
+fn angle_to_position(angle: u8) -> u8 {
+    (angle.min(MAX_SERVO_ANGLE) as u32 * 0xFF / MAX_SERVO_ANGLE as u32) as u8
+}
@@ -0,0 +1,266 @@
+use crate::commands::exit::Exit;
+use crate::commands::rescan::exp_versions;
+use crate::commands::utils::{
+    Target, confirm_downgrade, is_available, is_downgrade, read_line_trimmed, skip_controller,
+};
+use crate::fast_monitor::{ExpBoardInfo, FastPinballMonitor};
+use crate::protocol::{FlashOutcome, flash_with_retries};
+use std::io::{self, Write};
+
+/// Options for `update-all-exp`.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct UpdateAllExpArgs {
+    /// Flash without asking for confirmation, also of downgrades
+    #[arg(short, long)]
+    pub yes: bool,
+    /// Flash a board again up to this many times while it reports another version than
+    /// expected
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub retries: u32,
+    /// Flash even firmware made for another board than the one answering at the address
+    #[arg(long)]
+    pub force: bool,
+    /// Also flash the controller's built-in EXP (the Neuron's, at address 48)
+    #[arg(long)]
+    pub controller: bool,
+}
+
+/// Flash every connected EXP board whose reported version is behind the newest
+/// available firmware, or other than the version pinned for it, then print a summary
+/// table.
+///
+/// The controller's built-in EXP is left alone unless `--controller` is given. Going
+/// back to an older (pinned) version is confirmed per board unless `--yes` is given.
+///
+/// Exits with the worst outcome: a failed or stopped flash wins over a version mismatch.
+pub fn run(fpm: &mut FastPinballMonitor, args: &UpdateAllExpArgs) -> Exit {
+    fpm.allow_exp_board_mismatch(args.force);
+    let assume_yes = args.yes;
+
+    let boards: Vec<ExpBoardInfo> = fpm.list_connected_exp_boards();
+    if boards.is_empty() {
+        println!("No EXP boards found. Connect a board and try again.");
+        return Exit::NoHardware;
+    }
+
+    // Determine which boards are behind the newest firmware available for their type, or
+    // not on the version pinned for them
+    let mut stale: Vec<(ExpBoardInfo, String)> = Vec::new();
+    let mut declined = false;
+    for b in boards.iter() {
+        if skip_controller(b, args.controller) {
+            continue;
+        }
+        let available = b.available_versions.as_deref().unwrap_or_default();
+        let Some(target) =
+            Target::for_board(&b.board_name, &format!("EXP {}", b.address), available)
+        else {
+            status!(
+                "  Address {} -> {}: no firmware files available, skipping",
+                b.address,
+                b.board_name
+            );
+            continue;
+        };
+        if !target.needs_update(&b.version) {
+            continue;
+        }
+        if !is_available(available, &target.version) {
+            status!(
+                "  Address {} -> {}: pinned version {} is not in the firmware directory, skipping",
+                b.address,
+                b.board_name,
+                target.version
+            );
+            continue;
+        }
+        if target.pinned {
+            status!(
+                "  Address {} -> {}: pinned to {}",
+                b.address,
+                b.board_name,
+                target.version
+            );
+        }
+        if is_downgrade(&b.version, &target.version)
+            && !confirm_downgrade(&b.version, &target.version, !assume_yes)
+        {
+            declined = true;
+            continue;
+        }
+        stale.push((b.clone(), target.version));
+    }
+
+    if stale.is_empty() && declined {
+        return Exit::Canceled;
+    }
+    if stale.is_empty() {
+        println!("All {} EXP boards are up to date.", boards.len());
+        return Exit::Success;
+    }
+
+    status!("EXP boards to update:");
+    for (b, latest) in stale.iter() {
+        status!(
+            "  Address {} -> {} ({} -> {})",
+            b.address,
+            b.board_name,
+            b.version,
+            latest
+        );
+    }
+    if !assume_yes {
+        print!("Flash {} board(s)? [y/N]: ", stale.len());
+        let _ = io::stdout().flush();
+        let confirm = read_line_trimmed();
+        if !matches!(confirm.as_str(), "y" | "Y" | "yes" | "YES") {
+            println!("Canceled.");
+            return Exit::Canceled;
+        }
+    }
+
+    let mut results: Vec<(ExpBoardInfo, String, FlashOutcome)> = Vec::new();
+    for (b, latest) in stale.into_iter() {
+        if crate::interrupt::stop_requested() {
+            status!("Stopped; the remaining boards were not flashed.");
+            break;
+        }
+        status!(
+            "Flashing {} at address {} to version {}...",
+            b.board_name,
+            b.address,
+            latest
+        );
+        let result = flash_with_retries(
+            args.retries,
+            || false,
+            || match fpm.exp_port(&b.port) {
+                Some(exp) => (exp.update_firmware(&b.address, &latest), exp.last_transfer),
+                None => (FlashOutcome::Failed, None),
+            },
+        );
+        let (Ok(summary) | Err(summary)) = result;
+        status!("  {}", summary);
+        results.push((b, latest, summary.outcome));
+    }
+
+    // Rescan so the summary shows what the boards report now, not what we asked for
+    let after = exp_versions(&fpm.list_connected_exp_boards());
+
+    status!();
+    status!("Summary:");
+    status!(
+        "  {:<8} {:<14} {:<10} {:<10} {:<10} Result",
+        "Address",
+        "Board",
+        "Before",
+        "Target",
+        "After"
+    );
+    for (b, latest, outcome) in results.iter() {
+        status!(
+            "  {:<8} {:<14} {:<10} {:<10} {:<10} {}",
+            b.address,
+            b.board_name,
+            b.version,
+            latest,
+            after
+                .get(&format!("EXP {}", b.address))
+                .map(|(_, v)| v.as_str())
+                .unwrap_or("missing"),
+            if outcome.is_verified() {
+                "OK"
+            } else {
+                "FAILED"
+            }
+        );
+    }
+
+    let failed = results.iter().filter(|(_, _, o)| !o.is_verified()).count();
+    println!(
+        "Updated {} of {} EXP board(s); {} failed.",
+        results.len() - failed,
+        results.len(),
+        failed
+    );
+    Exit::for_flashes(results.iter().map(|(_, _, o)| *o))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::CancelToken;
+    use crate::protocol::mock::{MockTransport, firmware_repo, monitor};
+    use crate::protocol::transport::Transport;
+    use std::io::Read;
+
+    // Cancels `cancel` once a flash addresses its board, like Ctrl+C during the stream
+    struct CancelOnFlash {
+        inner: MockTransport,
+        cancel: CancelToken,
+    }
+
+    impl Read for CancelOnFlash {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for CancelOnFlash {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.starts_with(b"ea:") {
+                self.cancel.cancel();
+            }
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl Transport for CancelOnFlash {
+        fn bytes_to_read(&self) -> io::Result<u32> {
+            self.inner.bytes_to_read()
+        }
+
+        fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+            self.inner.try_clone()
+        }
+    }
+
+    #[test]
+    fn cancelled_flash_exits_as_failed() {
+        firmware_repo();
+        let mock = MockTransport::new()
+            .respond("ID@B4:", "ID:EXP FP-EXP-0071 0.48\r")
+            .respond("ID@B4:", "ID:EXP FP-EXP-0071 0.48\r");
+        let cancel = CancelToken::new();
+        let mut fpm = monitor(Box::new(CancelOnFlash {
+            inner: mock.clone(),
+            cancel: cancel.clone(),
+        }));
+        fpm.set_cancel_token(cancel);
+        let args = UpdateAllExpArgs {
+            yes: true,
+            ..UpdateAllExpArgs::default()
+        };
+        assert_eq!(run(&mut fpm, &args), Exit::FlashFailed);
+        assert!(!mock.commands().iter().any(|c| c.starts_with(':')));
+    }
+
+    #[test]
+    fn controller_is_skipped_without_opt_in() {
+        firmware_repo();
+        let mock = MockTransport::new().respond("ID@48:", "ID:EXP FP-CPU-2000 0.48\r");
+        let args = UpdateAllExpArgs {
+            yes: true,
+            ..UpdateAllExpArgs::default()
+        };
+        assert_eq!(
+            run(&mut monitor(Box::new(mock.clone())), &args),
+            Exit::Success
+        );
+        assert!(!mock.commands().iter().any(|c| c.starts_with("ea:")));
+    }
+}
@@ -0,0 +1,78 @@
+use crate::commands::exit::Exit;
+use crate::commands::utils::{expected_version_for_file, parse_version_arg};
+use crate::fast_monitor::FastPinballMonitor;
+use crate::protocol::FlashSummary;
+
+/// Options for `update-dsp`. Either a version or a file is required; there are no prompts.
+#[derive(clap::Args, Debug, Clone, Default)]
+#[group(skip)]
+#[command(group(clap::ArgGroup::new("source").required(true)))]
+pub struct UpdateDspArgs {
+    /// Firmware version to flash from the firmware directory (e.g. 0.12)
+    #[arg(long, value_parser = parse_version_arg, group = "source")]
+    pub version: Option<String>,
+    /// Flash this local firmware file instead
+    #[arg(long, value_name = "PATH", group = "source")]
+    pub file: Option<String>,
+    /// Version to verify after flashing --file (defaults to the one in the file name)
+    #[arg(long, value_parser = parse_version_arg, requires = "file")]
+    pub expect_version: Option<String>,
+}
+
+/// Flash the display controller firmware.
+pub fn run(fpm: &mut FastPinballMonitor, args: &UpdateDspArgs) -> Exit {
+    let Some(before) = fpm.dsp_info() else {
+        println!("No display controller found. Connect it or pass --dsp-port.");
+        return Exit::NoHardware;
+    };
+    let Some(dsp) = fpm.dsp.as_mut() else {
+        return Exit::NoHardware;
+    };
+
+    let (target, outcome) = match (&args.file, &args.version) {
+        (Some(file), _) => {
+            if !std::path::Path::new(file).is_file() {
+                println!("Firmware file not found: {}", file);
+                return Exit::Error;
+            }
+            let expected = expected_version_for_file(file, args.expect_version.as_deref());
+            status!(
+                "Flashing {} with {} (expecting version {}). This may take a few minutes.",
+                before.board_name,
+                file,
+                expected.as_deref().unwrap_or("unknown")
+            );
+            (
+                format!("with {}", file),
+                dsp.flash_file(file, expected.as_deref()),
+            )
+        }
+        (None, Some(version)) => {
+            status!(
+                "Flashing {} from {} to {}. This may take a few minutes.",
+                before.board_name,
+                before.version,
+                version
+            );
+            (format!("to {}", version), dsp.update_firmware(version))
+        }
+        (None, None) => {
+            println!("Pass --version or --file.");
+            return Exit::Error;
+        }
+    };
+
+    status!(
+        "Flash summary: {}",
+        FlashSummary::single(outcome, dsp.last_transfer)
+    );
+    let after = fpm
+        .dsp_info()
+        .map(|i| i.version)
+        .unwrap_or_else(|| "missing".to_string());
+    println!(
+        "{} flashed {}: {} (version {} -> {})",
+        before.board_name, target, outcome, before.version, after
+    );
+    outcome.into()
+}
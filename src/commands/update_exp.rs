@@ -1,62 +1,285 @@
-use std::io::{self, Write};
+use crate::commands::exit::Exit;
+use crate::commands::rescan::{exp_versions, print_before_after};
+use crate::commands::utils::{
+    confirm_downgrade, expected_version_for_file, is_downgrade, parse_hex_address,
+    parse_version_arg, read_line_trimmed,
+};
 use crate::fast_monitor::{ExpBoardInfo, FastPinballMonitor};
-use crate::commands::utils::read_line_trimmed;
+use crate::protocol::{FlashOutcome, FlashSummary, flash_with_retries};
+use std::io::{self, Write};
+
+/// Options for `update-exp`. Any value that is not supplied is prompted for; when the
+/// address and a version (or file) are both supplied the flash runs without prompts.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct UpdateExpArgs {
+    /// EXP board address to flash (hex, e.g. B4)
+    #[arg(long, value_parser = parse_hex_address)]
+    pub address: Option<String>,
+    /// Firmware version to flash (e.g. 0.48)
+    #[arg(long, value_parser = parse_version_arg, conflicts_with = "file")]
+    pub version: Option<String>,
+    /// Flash this local firmware file instead of one from the firmware directory
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<String>,
+    /// Version to verify after flashing --file (defaults to the one in the file name)
+    #[arg(long, value_parser = parse_version_arg, requires = "file")]
+    pub expect_version: Option<String>,
+    /// Flash again up to this many times while the board reports another version than
+    /// expected (afterwards, or with 0, you are asked unless running without prompts)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub retries: u32,
+    /// Flash even firmware made for another board than the one answering at the address
+    #[arg(long)]
+    pub force: bool,
+    /// Allow flashing the controller's built-in EXP (the Neuron's, at address 48)
+    #[arg(long)]
+    pub controller: bool,
+}
+
+/// Flash an EXP board.
+pub fn run(fpm: &mut FastPinballMonitor, args: &UpdateExpArgs) -> Exit {
+    fpm.allow_exp_board_mismatch(args.force);
+    let address_flag = args.address.clone();
+    let version_flag = args.version.clone();
+    let file_flag = args.file.clone();
+    let non_interactive = address_flag.is_some() && (version_flag.is_some() || file_flag.is_some());
 
-pub fn run(fpm: &mut FastPinballMonitor) {
     // List EXP boards and let the user choose one
     let boards: Vec<ExpBoardInfo> = fpm.list_connected_exp_boards();
     if boards.is_empty() {
         println!("No EXP boards found. Connect a board and try again.");
-        return;
+        return Exit::NoHardware;
+    }
+    let idx = match address_flag {
+        Some(addr) => {
+            let Some(found) = boards
+                .iter()
+                .position(|b| b.address.eq_ignore_ascii_case(&addr))
+            else {
+                println!("No EXP board found at address {}.", addr);
+                return Exit::NoHardware;
+            };
+            found
+        }
+        None => match select_board(&boards) {
+            Some(i) => i,
+            None => return Exit::Canceled,
+        },
+    };
+
+    // Extract chosen board info (owned strings)
+    let chosen = &boards[idx];
+    if chosen.is_controller && !args.controller {
+        println!(
+            "Address {} is the controller's built-in EXP ({}); flashing it updates the controller itself. Pass --controller to flash it anyway.",
+            chosen.address, chosen.board_name
+        );
+        return Exit::Error;
+    }
+    let port = chosen.port.clone();
+    let address = chosen.address.clone();
+    let board_name = chosen.board_name.clone();
+    let current_version = chosen.version.clone();
+
+    if let Some(file) = file_flag {
+        if !std::path::Path::new(&file).is_file() {
+            println!("Firmware file not found: {}", file);
+            return Exit::Error;
+        }
+        let expected = expected_version_for_file(&file, args.expect_version.as_deref());
+        status!(
+            "About to flash {} at address {} with {} (expecting version {}).",
+            board_name,
+            address,
+            file,
+            expected.as_deref().unwrap_or("unknown")
+        );
+        if !non_interactive {
+            let what = format!(
+                "{} {}",
+                board_name,
+                expected.as_deref().unwrap_or("firmware")
+            );
+            crate::release_notes::show(&what, &file);
+        }
+        if !non_interactive && !confirm() {
+            return Exit::Canceled;
+        }
+        if let Some(expected) = expected.as_deref()
+            && is_downgrade(&current_version, expected)
+            && !confirm_downgrade(&current_version, expected, !non_interactive)
+        {
+            return Exit::Canceled;
+        }
+        status!("Starting firmware update... This may take a few minutes.");
+        let result = flash_with_retries(
+            args.retries,
+            || !non_interactive && confirm_retry(),
+            || match fpm.exp_port(&port) {
+                Some(exp) => (
+                    exp.flash_file(&address, &file, expected.as_deref()),
+                    exp.last_transfer,
+                ),
+                None => (FlashOutcome::Failed, None),
+            },
+        );
+        print_before_after(
+            &exp_versions(&boards),
+            &exp_versions(&fpm.list_connected_exp_boards()),
+        );
+        return report(
+            &format!(
+                "{} at address {} flashed with {}",
+                board_name, address, file
+            ),
+            result,
+        );
     }
+
+    let mut versions: Vec<String> = chosen.available_versions.clone().unwrap_or_default();
+
+    if versions.is_empty() {
+        println!(
+            "No firmware files available for {} in {}.",
+            board_name,
+            crate::firmware_repo::get().dir().display()
+        );
+        return Exit::Error;
+    }
+    crate::version::sort_newest_first(&mut versions);
+
+    let version = match version_flag {
+        Some(v) => {
+            if !versions.contains(&v) {
+                println!(
+                    "Version {} is not available for {}. Available: {}",
+                    v,
+                    board_name,
+                    versions.join(", ")
+                );
+                return Exit::Error;
+            }
+            v
+        }
+        None => match select_version(&board_name, &current_version, &versions) {
+            Some(v) => v,
+            None => return Exit::Canceled,
+        },
+    };
+
+    status!(
+        "About to flash {} at address {} to version {}.",
+        board_name,
+        address,
+        version
+    );
+    if !non_interactive
+        && let Some(file) =
+            crate::firmware_repo::get().path(&format!("{}_EXP", board_name), &version)
+    {
+        crate::release_notes::show(&format!("{} {}", board_name, version), file);
+    }
+    if !non_interactive && !confirm() {
+        return Exit::Canceled;
+    }
+    if is_downgrade(&current_version, &version)
+        && !confirm_downgrade(&current_version, &version, !non_interactive)
+    {
+        return Exit::Canceled;
+    }
+
+    // Perform update
+    status!("Starting firmware update... This may take a few minutes.");
+    let result = flash_with_retries(
+        args.retries,
+        || !non_interactive && confirm_retry(),
+        || match fpm.exp_port(&port) {
+            Some(exp) => (exp.update_firmware(&address, &version), exp.last_transfer),
+            None => (FlashOutcome::Failed, None),
+        },
+    );
+    print_before_after(
+        &exp_versions(&boards),
+        &exp_versions(&fpm.list_connected_exp_boards()),
+    );
+    report(
+        &format!(
+            "{} at address {} flashed to {}",
+            board_name, address, version
+        ),
+        result,
+    )
+}
+
+// Print the flash summary, then the final outcome: on stdout when the firmware went
+// through, on stderr otherwise
+fn report(what: &str, result: Result<FlashSummary, FlashSummary>) -> Exit {
+    let (Ok(summary) | Err(summary)) = result;
+    status!("Flash summary: {}", summary);
+    match result {
+        Ok(summary) => {
+            println!("{}: {}", what, summary.outcome);
+            Exit::Success
+        }
+        Err(summary) => {
+            eprintln!("{}: {}", what, summary.outcome);
+            summary.outcome.into()
+        }
+    }
+}
+
+fn confirm_retry() -> bool {
+    print!("The board does not report the expected version. Flash again? [y/N]: ");
+    let _ = io::stdout().flush();
+    matches!(read_line_trimmed().as_str(), "y" | "Y" | "yes" | "YES")
+}
+
+fn confirm() -> bool {
+    print!("Proceed? [y/N]: ");
+    let _ = io::stdout().flush();
+    let confirm = read_line_trimmed();
+    if !matches!(confirm.as_str(), "y" | "Y" | "yes" | "YES") {
+        println!("Canceled.");
+        return false;
+    }
+    true
+}
+
+fn select_board(boards: &[ExpBoardInfo]) -> Option<usize> {
     println!("Select an EXP board to flash:");
     for (i, b) in boards.iter().enumerate() {
         println!(
-            "  {}) Address {} -> {} (current {})",
+            "  {}) Address {} -> {} (current {}){}",
             i + 1,
             b.address,
             b.board_name,
-            b.version
+            b.version,
+            if b.is_controller {
+                "  (controller, needs --controller)"
+            } else {
+                ""
+            }
         );
     }
     print!("Enter number (1-{}), or 0 to cancel: ", boards.len());
     let _ = io::stdout().flush();
     let sel = read_line_trimmed();
-    let Ok(mut idx) = sel.parse::<usize>() else {
+    let Ok(idx) = sel.parse::<usize>() else {
         println!("Invalid selection.");
-        return;
+        return None;
     };
     if idx == 0 {
         println!("Canceled.");
-        return;
+        return None;
     }
     if idx < 1 || idx > boards.len() {
         println!("Out of range.");
-        return;
+        return None;
     }
-    idx -= 1;
-
-    // Extract chosen board info (owned strings)
-    let chosen = &boards[idx];
-    let address = chosen.address.clone();
-    let board_name = chosen.board_name.clone();
-    let current_version = chosen.version.clone();
-    let mut versions: Vec<String> = chosen
-        .available_versions
-        .clone()
-        .unwrap_or_else(|| Vec::new());
-
-    if versions.is_empty() {
-        println!(
-            "No firmware files available for {}. Place firmware files in src\\firmware and try again.",
-            board_name
-        );
-        return;
-    }
-    // Sort descending so newest (highest) appears first
-    versions.sort();
-    versions.reverse();
+    Some(idx - 1)
+}
 
+fn select_version(board_name: &str, current_version: &str, versions: &[String]) -> Option<String> {
     println!(
         "Available versions for {} (current {}):",
         board_name, current_version
@@ -66,7 +289,7 @@ pub fn run(fpm: &mut FastPinballMonitor) {
             "  {}) {}{}",
             i + 1,
             v,
-            if *v == current_version {
+            if v == current_version {
                 "  (installed)"
             } else {
                 ""
@@ -79,34 +302,17 @@ pub fn run(fpm: &mut FastPinballMonitor) {
     );
     let _ = io::stdout().flush();
     let vsel = read_line_trimmed();
-    let Ok(mut vidx) = vsel.parse::<usize>() else {
+    let Ok(vidx) = vsel.parse::<usize>() else {
         println!("Invalid selection.");
-        return;
+        return None;
     };
     if vidx == 0 {
         println!("Canceled.");
-        return;
+        return None;
     }
     if vidx < 1 || vidx > versions.len() {
         println!("Out of range.");
-        return;
-    }
-    vidx -= 1;
-    let version = versions[vidx].clone();
-
-    println!(
-        "About to flash {} at address {} to version {}.",
-        board_name, address, version
-    );
-    print!("Proceed? [y/N]: ");
-    let _ = io::stdout().flush();
-    let confirm = read_line_trimmed();
-    if !matches!(confirm.as_str(), "y" | "Y" | "yes" | "YES") {
-        println!("Canceled.");
-        return;
+        return None;
     }
-
-    // Perform update
-    println!("Starting firmware update... This may take a few minutes.");
-    fpm.exp.update_firmware(&address, &version);
+    Some(versions[vidx - 1].clone())
 }
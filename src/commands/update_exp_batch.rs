@@ -0,0 +1,321 @@
+use crate::commands::exit::Exit;
+use crate::commands::rescan::exp_versions;
+use crate::commands::utils::{
+    Target, confirm_downgrade, is_available, is_downgrade, parse_hex_address, parse_version_arg,
+    read_line_trimmed, skip_controller,
+};
+use crate::fast_monitor::{ExpBoardInfo, FastPinballMonitor};
+use crate::protocol::{FlashOutcome, FlashSummary, flash_with_retries, with_batch_bar};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::io::{self, Write};
+
+/// Options for `update-exp-batch`.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct UpdateExpBatchArgs {
+    /// EXP board address to flash (hex, repeatable; chosen from a list when omitted)
+    #[arg(long, value_parser = parse_hex_address)]
+    pub address: Vec<String>,
+    /// Firmware version to flash to every board (default: the pinned or newest for each
+    /// board)
+    #[arg(long, value_parser = parse_version_arg)]
+    pub version: Option<String>,
+    /// Flash without asking for confirmation, also of downgrades
+    #[arg(short, long)]
+    pub yes: bool,
+    /// Flash a board again up to this many times while it reports another version than
+    /// expected
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub retries: u32,
+    /// Flash even firmware made for another board than the one answering at the address
+    #[arg(long)]
+    pub force: bool,
+    /// Also flash the controller's built-in EXP (the Neuron's, at address 48)
+    #[arg(long)]
+    pub controller: bool,
+}
+
+/// Flash several EXP boards back to back, with a progress bar per board, then print a
+/// pass/fail table.
+///
+/// The controller's built-in EXP is skipped unless `--controller` is given. Going back
+/// to an older version is confirmed per board unless `--yes` is given.
+///
+/// Exits with the worst outcome: a failed or stopped flash wins over a version mismatch.
+pub fn run(fpm: &mut FastPinballMonitor, args: &UpdateExpBatchArgs) -> Exit {
+    fpm.allow_exp_board_mismatch(args.force);
+    let boards: Vec<ExpBoardInfo> = fpm.list_connected_exp_boards();
+    if boards.is_empty() {
+        println!("No EXP boards found. Connect a board and try again.");
+        return Exit::NoHardware;
+    }
+    let chosen: Vec<&ExpBoardInfo> = if args.address.is_empty() {
+        match select_boards(&boards) {
+            Some(chosen) => chosen,
+            None => return Exit::Canceled,
+        }
+    } else {
+        let mut chosen = Vec::new();
+        for address in args.address.iter() {
+            let Some(board) = boards.iter().find(|b| b.address == *address) else {
+                eprintln!("No EXP board found at address {}.", address);
+                return Exit::NoHardware;
+            };
+            if !chosen
+                .iter()
+                .any(|b: &&ExpBoardInfo| b.address == board.address)
+            {
+                chosen.push(board);
+            }
+        }
+        chosen
+    };
+    let chosen: Vec<&ExpBoardInfo> = chosen
+        .into_iter()
+        .filter(|b| !skip_controller(b, args.controller))
+        .collect();
+    if chosen.is_empty() {
+        println!("No EXP boards left to flash.");
+        return Exit::Error;
+    }
+
+    // Pick the version for each board before flashing any of them
+    let mut queue: Vec<(&ExpBoardInfo, String)> = Vec::new();
+    for b in chosen {
+        let available = b.available_versions.as_deref().unwrap_or_default();
+        let version = match &args.version {
+            Some(v) if available.contains(v) => v.clone(),
+            Some(v) => {
+                eprintln!(
+                    "Version {} is not available for {} at address {}. Available: {}",
+                    v,
+                    b.board_name,
+                    b.address,
+                    available.join(", ")
+                );
+                return Exit::Error;
+            }
+            None => {
+                match Target::for_board(&b.board_name, &format!("EXP {}", b.address), available) {
+                    Some(t) if t.pinned && !is_available(available, &t.version) => {
+                        eprintln!(
+                            "Version {} is pinned for {} at address {} but not available. Available: {}",
+                            t.version,
+                            b.board_name,
+                            b.address,
+                            available.join(", ")
+                        );
+                        return Exit::Error;
+                    }
+                    Some(t) => t.version,
+                    None => {
+                        eprintln!(
+                            "No firmware files available for {} at address {} in {}.",
+                            b.board_name,
+                            b.address,
+                            crate::firmware_repo::get().dir().display()
+                        );
+                        return Exit::Error;
+                    }
+                }
+            }
+        };
+        if is_downgrade(&b.version, &version) && !confirm_downgrade(&b.version, &version, !args.yes)
+        {
+            continue;
+        }
+        queue.push((b, version));
+    }
+    if queue.is_empty() {
+        return Exit::Canceled;
+    }
+
+    status!("EXP boards to flash:");
+    for (b, version) in queue.iter() {
+        status!(
+            "  Address {} -> {} ({} -> {})",
+            b.address,
+            b.board_name,
+            b.version,
+            version
+        );
+    }
+    if !args.yes {
+        print!("Flash {} board(s)? [y/N]: ", queue.len());
+        let _ = io::stdout().flush();
+        let confirm = read_line_trimmed();
+        if !matches!(confirm.as_str(), "y" | "Y" | "yes" | "YES") {
+            println!("Canceled.");
+            return Exit::Canceled;
+        }
+    }
+
+    // One bar per board; boards waiting their turn show as queued
+    let config = crate::config::get();
+    let multi = MultiProgress::new();
+    let waiting = ProgressStyle::with_template("{prefix:<24} {msg}").unwrap();
+    let bars: Vec<ProgressBar> = queue
+        .iter()
+        .map(|(b, version)| {
+            let bar = if config.quiet || !config.progress.use_bar() {
+                ProgressBar::hidden()
+            } else {
+                multi.add(ProgressBar::new(0))
+            };
+            bar.set_style(waiting.clone());
+            bar.set_prefix(format!("{} {}", b.address, b.board_name));
+            bar.set_message(format!("queued ({} -> {})", b.version, version));
+            bar
+        })
+        .collect();
+
+    let mut results: Vec<FlashSummary> = Vec::new();
+    for (i, ((b, version), bar)) in queue.iter().zip(bars.iter()).enumerate() {
+        if crate::interrupt::stop_requested() {
+            for bar in bars[i..].iter() {
+                bar.finish_with_message("not flashed (stopped)");
+            }
+            status!("Stopped; the remaining boards were not flashed.");
+            break;
+        }
+        if bar.is_hidden() {
+            status!(
+                "Flashing {} at address {} to version {}...",
+                b.board_name,
+                b.address,
+                version
+            );
+        }
+        let result = with_batch_bar(&multi, bar, || {
+            flash_with_retries(
+                args.retries,
+                || false,
+                || match fpm.exp_port(&b.port) {
+                    Some(exp) => (exp.update_firmware(&b.address, version), exp.last_transfer),
+                    None => (FlashOutcome::Failed, None),
+                },
+            )
+        });
+        let (Ok(summary) | Err(summary)) = result;
+        bar.set_style(waiting.clone());
+        bar.finish_with_message(summary.to_string());
+        if bar.is_hidden() {
+            status!("  {}", summary);
+        }
+        results.push(summary);
+    }
+    // Rescan so the table shows what the boards report now, not what we asked for
+    let after = exp_versions(&fpm.list_connected_exp_boards());
+
+    status!();
+    status!("Summary:");
+    status!(
+        "  {:<8} {:<14} {:<10} {:<10} {:<10} {:<8} {:<8} Result",
+        "Address",
+        "Board",
+        "Before",
+        "Target",
+        "After",
+        "Attempts",
+        "Time"
+    );
+    for ((b, version), summary) in queue.iter().zip(results.iter()) {
+        status!(
+            "  {:<8} {:<14} {:<10} {:<10} {:<10} {:<8} {:<8} {}",
+            b.address,
+            b.board_name,
+            b.version,
+            version,
+            after
+                .get(&format!("EXP {}", b.address))
+                .map(|(_, v)| v.as_str())
+                .unwrap_or("missing"),
+            summary.attempts,
+            summary
+                .transfer
+                .map(|t| format!("{:.1}s", t.elapsed.as_secs_f64()))
+                .unwrap_or_else(|| "-".to_string()),
+            if summary.outcome.is_verified() {
+                "PASS"
+            } else {
+                "FAIL"
+            }
+        );
+    }
+
+    let failed = results.iter().filter(|s| !s.outcome.is_verified()).count();
+    println!(
+        "Flashed {} of {} EXP board(s); {} failed.",
+        results.len() - failed,
+        results.len(),
+        failed
+    );
+    Exit::for_flashes(results.iter().map(|s| s.outcome))
+}
+
+// Ask which boards to flash: numbers separated by spaces or commas, or "all"
+fn select_boards(boards: &[ExpBoardInfo]) -> Option<Vec<&ExpBoardInfo>> {
+    println!("Select the EXP boards to flash:");
+    for (i, b) in boards.iter().enumerate() {
+        println!(
+            "  {}) Address {} -> {} (current {})",
+            i + 1,
+            b.address,
+            b.board_name,
+            b.version
+        );
+    }
+    print!(
+        "Enter numbers (1-{}) separated by spaces, \"all\", or 0 to cancel: ",
+        boards.len()
+    );
+    let _ = io::stdout().flush();
+    let sel = read_line_trimmed();
+    if sel.eq_ignore_ascii_case("all") {
+        return Some(boards.iter().collect());
+    }
+    let mut chosen: Vec<&ExpBoardInfo> = Vec::new();
+    for part in sel.split([' ', ',']).filter(|p| !p.is_empty()) {
+        let Ok(idx) = part.parse::<usize>() else {
+            println!("Invalid selection: {}", part);
+            return None;
+        };
+        if idx == 0 {
+            println!("Canceled.");
+            return None;
+        }
+        let Some(board) = boards.get(idx - 1) else {
+            println!("Out of range: {}", idx);
+            return None;
+        };
+        if !chosen.iter().any(|b| b.address == board.address) {
+            chosen.push(board);
+        }
+    }
+    if chosen.is_empty() {
+        println!("Canceled.");
+        return None;
+    }
+    Some(chosen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::mock::{MockTransport, firmware_repo, monitor};
+
+    #[test]
+    fn controller_is_skipped_without_opt_in() {
+        firmware_repo();
+        let mock = MockTransport::new().respond("ID@48:", "ID:EXP FP-CPU-2000 0.48\r");
+        let args = UpdateExpBatchArgs {
+            address: vec!["48".to_string()],
+            yes: true,
+            ..UpdateExpBatchArgs::default()
+        };
+        assert_eq!(
+            run(&mut monitor(Box::new(mock.clone())), &args),
+            Exit::Error
+        );
+        assert!(!mock.commands().iter().any(|c| c.starts_with("ea:")));
+    }
+}
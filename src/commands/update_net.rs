@@ -1,26 +1,71 @@
-use std::io::{self, Write};
-use crate::constants::AVAILABLE_FIRMWARE_VERSIONS;
+use crate::commands::exit::Exit;
+use crate::commands::rescan::{VersionSnapshot, net_versions, print_before_after};
+use crate::commands::utils::{
+    confirm_downgrade, expected_version_for_file, is_downgrade, parse_version_arg,
+    read_line_trimmed,
+};
 use crate::fast_monitor::FastPinballMonitor;
-use crate::commands::utils::read_line_trimmed;
+use crate::protocol::{FlashOutcome, FlashSummary};
+use std::io::{self, Write};
+
+/// Options for `update-net`.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct UpdateNetArgs {
+    /// Flash this local firmware file without prompting
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<String>,
+    /// Version to verify after flashing --file (defaults to the one in the file name)
+    #[arg(long, value_parser = parse_version_arg, requires = "file")]
+    pub expect_version: Option<String>,
+}
+
+/// Flash the NET (CPU) firmware.
+///
+/// Prompts for a version from the firmware directory, or with `--file` flashes a
+/// local firmware file without prompting.
+pub fn run(fpm: &mut FastPinballMonitor, args: &UpdateNetArgs) -> Exit {
+    if let Some(file) = args.file.clone() {
+        if !std::path::Path::new(&file).is_file() {
+            println!("Firmware file not found: {}", file);
+            return Exit::Error;
+        }
+        let expected = expected_version_for_file(&file, args.expect_version.as_deref());
+        status!(
+            "Flashing NET (CPU) with {} (expecting version {}). This may take a few minutes.",
+            file,
+            expected.as_deref().unwrap_or("unknown")
+        );
+        if let Some(expected) = expected.as_deref()
+            && let Some(current) = fpm.net.id().map(|id| id.version)
+            && is_downgrade(&current, expected)
+        {
+            confirm_downgrade(&current, expected, false);
+        }
+        let before = net_versions(fpm);
+        let outcome = fpm.net.flash_file(&file, expected.as_deref());
+        rescan(fpm, &before);
+        return report(fpm, &format!("with {}", file), outcome);
+    }
 
-pub fn run(fpm: &mut FastPinballMonitor) {
     let key = "FP-CPU-2000_NET";
-    let maybe = AVAILABLE_FIRMWARE_VERSIONS.get(key);
-    let mut versions: Vec<String> = match maybe {
-        Some(map) => map.keys().cloned().collect(),
-        None => Vec::new(),
-    };
+    let mut versions = crate::firmware_repo::get().versions(key);
     if versions.is_empty() {
         println!(
-            "No NET firmware files found. Place files under src\\firmware\\FP-CPU-2000 and try again."
+            "No NET firmware files found in {}.",
+            crate::firmware_repo::get().dir().display()
         );
-        return;
+        return Exit::Error;
     }
-    versions.sort();
-    versions.reverse();
+    crate::version::sort_newest_first(&mut versions);
+    let pinned = crate::config::get().pinned_version("FP-CPU-2000", "NET NC");
     println!("Available NET firmware versions (newest first):");
     for (i, v) in versions.iter().enumerate() {
-        println!("  {}) {}", i + 1, v);
+        let note = if pinned.as_ref() == Some(v) {
+            " (pinned)"
+        } else {
+            ""
+        };
+        println!("  {}) {}{}", i + 1, v, note);
     }
     print!(
         "Enter version number (1-{}), or 0 to cancel: ",
@@ -30,28 +75,55 @@ pub fn run(fpm: &mut FastPinballMonitor) {
     let sel = read_line_trimmed();
     let Ok(mut idx) = sel.parse::<usize>() else {
         println!("Invalid selection.");
-        return;
+        return Exit::Canceled;
     };
     if idx == 0 {
         println!("Canceled.");
-        return;
+        return Exit::Canceled;
     }
     if idx < 1 || idx > versions.len() {
         println!("Out of range.");
-        return;
+        return Exit::Canceled;
     }
     idx -= 1;
     let version = versions[idx].clone();
 
     println!("About to flash NET (CPU) to version {}.", version);
+    if let Some(file) = crate::firmware_repo::get().path(key, &version) {
+        crate::release_notes::show(&format!("FP-CPU-2000 {}", version), file);
+    }
     print!("Proceed? [y/N]: ");
     let _ = io::stdout().flush();
     let confirm = read_line_trimmed();
     if !matches!(confirm.as_str(), "y" | "Y" | "yes" | "YES") {
         println!("Canceled.");
-        return;
+        return Exit::Canceled;
+    }
+    if let Some(current) = fpm.net.id().map(|id| id.version)
+        && is_downgrade(&current, &version)
+        && !confirm_downgrade(&current, &version, true)
+    {
+        return Exit::Canceled;
     }
 
-    println!("Starting NET firmware update... This may take a few minutes.");
-    fpm.net.update_firmware(&version);
+    status!("Starting NET firmware update... This may take a few minutes.");
+    let before = net_versions(fpm);
+    let outcome = fpm.net.update_firmware(&version);
+    rescan(fpm, &before);
+    report(fpm, &format!("to {}", version), outcome)
+}
+
+/// Print the flash summary and the final result line.
+fn report(fpm: &FastPinballMonitor, target: &str, outcome: FlashOutcome) -> Exit {
+    status!(
+        "Flash summary: {}",
+        FlashSummary::single(outcome, fpm.net.last_transfer)
+    );
+    println!("NET (CPU) flashed {}: {}", target, outcome);
+    outcome.into()
+}
+
+/// Re-list the NET nodes after a flash and show which versions changed.
+fn rescan(fpm: &mut FastPinballMonitor, before: &VersionSnapshot) {
+    print_before_after(before, &net_versions(fpm));
 }
@@ -1,7 +1,305 @@
-use std::io::{self};
+use crate::config::OutputFormat;
+use crate::version::Version;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::io::{self, Write};
+use std::path::Path;
 
 pub fn read_line_trimmed() -> String {
     let mut s = String::new();
     let _ = io::stdin().read_line(&mut s);
     s.trim().to_string()
 }
+
+/// Clap value parser for EXP board addresses: one or more hex digits, returned uppercase.
+pub fn parse_hex_address(s: &str) -> Result<String, String> {
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("'{}' is not a hex address (e.g., B4)", s));
+    }
+    Ok(s.to_ascii_uppercase())
+}
+
+/// Clap value parser for RRGGBB colors (a leading `#` is allowed).
+pub fn parse_color(s: &str) -> Result<(u8, u8, u8), String> {
+    let hex = s.trim().trim_start_matches('#');
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|c| u8::from_str_radix(c, 16).ok())
+    };
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok((r, g, b)),
+        _ => Err(format!("'{}' is not an RRGGBB hex color, e.g. FF0000", s)),
+    }
+}
+
+/// Clap value parser for firmware versions, returned normalized (e.g., 1.5 -> 1.05).
+pub fn parse_version_arg(s: &str) -> Result<String, String> {
+    s.parse::<Version>().map(|v| v.to_string())
+}
+
+/// Normalize a user supplied version to the stored `major.minor` format (e.g., 1.5 ->
+/// 1.05). Anything that is not a version is returned trimmed but otherwise unchanged.
+pub fn normalize_version(version: &str) -> String {
+    version_key(version)
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| version.trim().to_string())
+}
+
+/// Parse a version such as `0.48`, `v0.48` or `1.5` for numeric comparison (see `Version`).
+pub fn version_key(version: &str) -> Option<Version> {
+    version.parse().ok()
+}
+
+/// Whether `version` is one of `available`, however either is spelled (`1.5` is `1.05`).
+pub fn is_available(available: &[String], version: &str) -> bool {
+    let key = version_key(version);
+    key.is_some() && available.iter().any(|v| version_key(v) == key)
+}
+
+/// The highest of `versions` by numeric order.
+pub fn newest_version(versions: &[String]) -> Option<String> {
+    versions
+        .iter()
+        .filter_map(|v| version_key(v).map(|k| (k, v)))
+        .max_by_key(|(k, _)| *k)
+        .map(|(_, v)| v.clone())
+}
+
+/// The firmware version a board should run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub version: String,
+    /// Pinned in the config file, rather than the newest available.
+    pub pinned: bool,
+}
+
+impl Target {
+    /// The version pinned for `board` at `location` (e.g. `EXP B4`), or else the newest
+    /// of `available`.
+    pub fn for_board(board: &str, location: &str, available: &[String]) -> Option<Self> {
+        match crate::config::get().pinned_version(board, location) {
+            Some(version) => Some(Self {
+                version,
+                pinned: true,
+            }),
+            None => newest_version(available).map(|version| Self {
+                version,
+                pinned: false,
+            }),
+        }
+    }
+
+    /// Whether a board running `current` needs flashing: when it is behind the newest
+    /// version, or on any other version than its pinned one.
+    pub fn needs_update(&self, current: &str) -> bool {
+        match (version_key(current), version_key(&self.version)) {
+            (Some(current), Some(target)) if self.pinned => current != target,
+            (Some(current), Some(target)) => current < target,
+            _ => true,
+        }
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.pinned {
+            write!(f, "{} (pinned)", self.version)
+        } else {
+            f.write_str(&self.version)
+        }
+    }
+}
+
+/// Whether a flash of several boards leaves `board` alone because it is the controller's
+/// built-in EXP and `controller` (the `--controller` opt-in) was not given. Says so when
+/// skipping.
+pub fn skip_controller(board: &crate::fast_monitor::ExpBoardInfo, controller: bool) -> bool {
+    if !board.is_controller || controller {
+        return false;
+    }
+    status!(
+        "  Address {} -> {}: the controller's built-in EXP, skipping (pass --controller to flash it)",
+        board.address,
+        board.board_name
+    );
+    true
+}
+
+/// Whether flashing `target` onto a board running `current` goes back to an older version.
+pub fn is_downgrade(current: &str, target: &str) -> bool {
+    matches!((version_key(current), version_key(target)), (Some(c), Some(t)) if t < c)
+}
+
+/// Warn that flashing goes back from `from` to `to` and, when `ask`, ask to go ahead
+/// anyway. Returns whether to flash.
+pub fn confirm_downgrade(from: &str, to: &str, ask: bool) -> bool {
+    eprintln!(
+        "Warning: you are downgrading from {} to {}. Downgrades can reset the board's configuration.",
+        from, to
+    );
+    if !ask {
+        return true;
+    }
+    print!("Downgrade anyway? [y/N]: ");
+    let _ = io::stdout().flush();
+    if !matches!(read_line_trimmed().as_str(), "y" | "Y" | "yes" | "YES") {
+        println!("Canceled.");
+        return false;
+    }
+    true
+}
+
+/// Resolve the version to verify after flashing a local file: `--expect-version` wins,
+/// otherwise it is parsed from a `..._firmware_v_{major}_{minor}.txt` (or `.hex`, `.bin`)
+/// file name.
+pub fn expected_version_for_file(file: &str, expect_version: Option<&str>) -> Option<String> {
+    expect_version.map(normalize_version).or_else(|| {
+        crate::firmware_repo::parse_firmware_filename(std::path::Path::new(file))
+            .map(|(_, _, version)| version.to_string())
+    })
+}
+
+/// Read a document written by `save_document`: YAML for `.yaml`/`.yml`, JSON otherwise.
+pub fn load_document<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let parsed = if is_yaml(path) {
+        from_yaml(&text)
+    } else {
+        serde_json::from_str(&text).map_err(|e| e.to_string())
+    };
+    parsed.map_err(|e| format!("Invalid {}: {}", path.display(), e))
+}
+
+/// Print `value` as pretty JSON, or as YAML with `--format yaml`.
+pub fn print_document<T: Serialize + ?Sized>(value: &T, format: OutputFormat) {
+    if format == OutputFormat::Yaml {
+        match to_yaml(value) {
+            Ok(text) => print!("{}", text),
+            Err(e) => eprintln!("{}", e),
+        }
+    } else {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(value).unwrap_or_default()
+        );
+    }
+}
+
+/// Write `value` as YAML when `path` ends in `.yaml`/`.yml`, as JSON otherwise.
+pub fn save_document<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let text = if is_yaml(path) {
+        to_yaml(value)?
+    } else {
+        serde_json::to_string_pretty(value).map_err(|e| e.to_string())? + "\n"
+    };
+    std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("yaml") || e.eq_ignore_ascii_case("yml"))
+}
+
+/// Why YAML and CSV fail in builds without the `export` feature.
+#[cfg(not(feature = "export"))]
+pub const NO_EXPORT: &str =
+    "YAML and CSV are not available in this build (it lacks the `export` feature)";
+
+#[cfg(feature = "export")]
+fn from_yaml<T: DeserializeOwned>(text: &str) -> Result<T, String> {
+    serde_yaml::from_str(text).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "export"))]
+fn from_yaml<T: DeserializeOwned>(_text: &str) -> Result<T, String> {
+    Err(NO_EXPORT.to_string())
+}
+
+#[cfg(feature = "export")]
+fn to_yaml<T: Serialize + ?Sized>(value: &T) -> Result<String, String> {
+    serde_yaml::to_string(value).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "export"))]
+fn to_yaml<T: Serialize + ?Sized>(_value: &T) -> Result<String, String> {
+    Err(NO_EXPORT.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn newest(version: &str) -> Target {
+        Target {
+            version: version.to_string(),
+            pinned: false,
+        }
+    }
+
+    fn pinned(version: &str) -> Target {
+        Target {
+            version: version.to_string(),
+            pinned: true,
+        }
+    }
+
+    #[test]
+    fn normalizes_versions() {
+        assert_eq!(normalize_version("1.5"), "1.05");
+        assert_eq!(normalize_version("v0.48"), "0.48");
+        assert_eq!(normalize_version("02.28"), "2.28");
+        assert_eq!(normalize_version(" latest "), "latest");
+    }
+
+    #[test]
+    fn available_whatever_the_spelling() {
+        let available = ["0.5", "0.48"].map(String::from);
+        assert!(is_available(&available, "0.05"));
+        assert!(is_available(&available, "v0.48"));
+        assert!(!is_available(&available, "0.50"));
+        assert!(!is_available(&available, "latest"));
+    }
+
+    #[test]
+    fn newest_version_by_number() {
+        let versions = ["0.9", "0.48", "junk"].map(String::from);
+        assert_eq!(newest_version(&versions).as_deref(), Some("0.48"));
+        assert_eq!(newest_version(&["junk".to_string()]), None);
+    }
+
+    #[test]
+    fn update_to_newest_only_when_behind() {
+        assert!(newest("0.50").needs_update("0.48"));
+        assert!(!newest("0.50").needs_update("0.50"));
+        assert!(!newest("0.48").needs_update("0.50"));
+        // `0.5` is 0.05, behind 0.48
+        assert!(newest("0.48").needs_update("0.5"));
+        assert!(!newest("2.28").needs_update("02.28"));
+        assert!(!newest("0.48").needs_update("v0.48"));
+    }
+
+    #[test]
+    fn update_to_pinned_whenever_off_it() {
+        assert!(pinned("0.48").needs_update("0.50"));
+        assert!(pinned("0.50").needs_update("0.48"));
+        assert!(!pinned("0.48").needs_update("v0.48"));
+    }
+
+    #[test]
+    fn malformed_versions_need_an_update() {
+        assert!(newest("0.50").needs_update(""));
+        assert!(newest("0.50").needs_update("unknown"));
+        assert!(newest("junk").needs_update("0.50"));
+    }
+
+    #[test]
+    fn downgrades() {
+        assert!(is_downgrade("0.50", "0.48"));
+        assert!(is_downgrade("0.48", "0.5"));
+        assert!(!is_downgrade("0.48", "0.50"));
+        assert!(!is_downgrade("2.28", "02.28"));
+        assert!(!is_downgrade("junk", "0.48"));
+    }
+}
@@ -0,0 +1,35 @@
+use crate::commands::exit::Exit;
+use crate::fast_monitor::{FastPinballMonitor, PinnedPorts};
+use std::time::Duration;
+
+/// Connect to the FAST hardware, waiting for its ports to appear when they are not
+/// there yet (until `timeout`, if given).
+pub fn connect(pins: PinnedPorts, timeout: Option<Duration>) -> Result<FastPinballMonitor, String> {
+    FastPinballMonitor::connect_with_ports(pins.clone()).or_else(|e| {
+        log::debug!("{}", e);
+        match timeout {
+            Some(t) => status!("Waiting up to {}s for FAST hardware ...", t.as_secs()),
+            None => status!("Waiting for FAST hardware ..."),
+        }
+        FastPinballMonitor::connect_waiting(pins, timeout)
+    })
+}
+
+/// Wait until the NET and EXP ports appear and answer, then print them.
+pub fn run(pins: PinnedPorts, timeout: Option<Duration>) -> Exit {
+    match connect(pins, timeout) {
+        Ok(fpm) => {
+            let exp: Vec<&str> = fpm.exp_ports.iter().map(|e| e.port_name.as_str()).collect();
+            println!(
+                "FAST hardware found: NET on {}, EXP on {}.",
+                fpm.net.port_name,
+                exp.join(", ")
+            );
+            Exit::Success
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            Exit::NoHardware
+        }
+    }
+}
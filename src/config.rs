@@ -0,0 +1,497 @@
+// User configuration from ~/.fast/config.toml. Every setting is optional; command line
+// flags override whatever the file says. Loaded once at startup by the CLI; library users
+// that never call `init` get the defaults.
+//
+// Example:
+//
+//     net_port = "/dev/ttyACM0"
+//     exp_ports = ["/dev/ttyACM1"]
+//     seg_port = "/dev/ttyACM2"
+//     aud_port = "/dev/ttyACM3"
+//     dsp_port = "/dev/ttyACM4"
+//     firmware_dir = "~/fast-firmware"
+//     format = "json"
+//     quiet = true
+//     progress = "plain"
+//     no_color = true
+//     offline = true
+//     proxy = "http://proxy.example.com:3128"
+//     ca_bundle = "~/corporate-ca.pem"
+//     github_token = "ghp_..."
+//     profile = "bench-left"
+//
+//     [timeouts]
+//     net_read_ms = 200
+//     exp_read_ms = 5
+//     receive_ms = 500
+//     bootloader_ms = 30000
+//     verify_ms = 5000
+//
+//     [flash]
+//     exp_line_delay_ms = 200
+//     net_line_delay_ms = 400
+//
+//     [hooks]
+//     pre_flash = "systemctl --user stop mpf"
+//     post_flash = "systemctl --user start mpf"
+//
+//     [pinned_firmware]
+//     "FP-EXP-0071" = "0.31"
+//     "NET 01" = "2.28"
+//
+//     [profiles.bench-left]
+//     usb_serials = ["FAST1234"]
+//     inventory = "~/machines/bench-left.yaml"
+//
+//     [profiles.bench-left.pinned_firmware]
+//     "FP-CPU-2000_NET" = "2.28"
+//
+//     [profiles.bench-right]
+//     net_port = "/dev/ttyACM5"
+//     exp_ports = ["/dev/ttyACM6"]
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Environment variable the CLI reads the firmware directory from when `--firmware-dir`
+/// is not given; it takes precedence over `firmware_dir` in the config file.
+pub const FIRMWARE_DIR_ENV: &str = "FAST_FIRMWARE_DIR";
+
+/// How commands print their results.
+#[derive(clap::ValueEnum, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    /// One row per board, for `list`, `list-exp`, `list-net` and `status`; other
+    /// commands print text.
+    Csv,
+    /// Like JSON, as YAML.
+    Yaml,
+}
+
+impl OutputFormat {
+    /// Whether results are printed as a JSON or YAML document rather than for reading.
+    pub fn is_document(self) -> bool {
+        matches!(self, OutputFormat::Json | OutputFormat::Yaml)
+    }
+}
+
+/// How firmware streams show their progress.
+#[derive(clap::ValueEnum, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgressMode {
+    /// A bar on a terminal; plain lines under pipes and CI.
+    #[default]
+    Auto,
+    /// Always draw a progress bar.
+    Bar,
+    /// Print a plain-text line per 10% sent.
+    Plain,
+}
+
+impl ProgressMode {
+    /// Whether to draw a bar. The bar goes to stderr, so `Auto` draws one when stderr
+    /// is a terminal and the `CI` environment variable is not set.
+    pub fn use_bar(self) -> bool {
+        use std::io::IsTerminal;
+        match self {
+            ProgressMode::Bar => true,
+            ProgressMode::Plain => false,
+            ProgressMode::Auto => {
+                std::io::stderr().is_terminal() && std::env::var_os("CI").is_none()
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// NET port to use instead of discovery.
+    pub net_port: Option<String>,
+    /// EXP ports to use instead of discovery.
+    pub exp_ports: Vec<String>,
+    /// Segment display port to use instead of discovery.
+    pub seg_port: Option<String>,
+    /// Audio interface port to use instead of discovery.
+    pub aud_port: Option<String>,
+    /// Display controller port to use instead of discovery.
+    pub dsp_port: Option<String>,
+    /// Where firmware files are stored (default ~/.fast/firmware).
+    pub firmware_dir: Option<PathBuf>,
+    pub format: OutputFormat,
+    /// Suppress progress output and print only the final result line.
+    pub quiet: bool,
+    pub progress: ProgressMode,
+    /// Never color output (as does a non-empty `NO_COLOR` environment variable).
+    pub no_color: bool,
+    /// Never go online: no firmware download offers and no release notes lookups.
+    pub offline: bool,
+    /// Proxy for downloads, e.g. `http://proxy:3128` (default: `HTTPS_PROXY` and friends).
+    pub proxy: Option<String>,
+    /// PEM file of extra CA certificates to trust for downloads.
+    pub ca_bundle: Option<PathBuf>,
+    /// Token for GitHub downloads and lookups (`GITHUB_TOKEN` or `GH_TOKEN` win over it).
+    pub github_token: Option<String>,
+    /// Only discover ports of USB devices with these serial numbers.
+    pub usb_serials: Vec<String>,
+    /// Inventory file the machine should match, for `inventory save` and `inventory diff`.
+    pub inventory: Option<PathBuf>,
+    /// Versions boards must run, by board (`FP-EXP-0071`), firmware (`FP-CPU-2000_NET`) or
+    /// location (`EXP B4`, `NET 01`); updates and staleness checks target these instead
+    /// of the newest.
+    pub pinned_firmware: BTreeMap<String, String>,
+    /// Profile to use when `--profile` is not given.
+    pub profile: Option<String>,
+    /// Settings per machine, selected with `--profile <name>`.
+    pub profiles: BTreeMap<String, Profile>,
+    pub timeouts: Timeouts,
+    pub flash: FlashSettings,
+    pub hooks: Hooks,
+}
+
+/// Settings of one machine, for benches with several machines connected at once. Those
+/// given override the ones at the top of the file.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub net_port: Option<String>,
+    pub exp_ports: Vec<String>,
+    pub seg_port: Option<String>,
+    pub aud_port: Option<String>,
+    pub dsp_port: Option<String>,
+    /// USB serial numbers of the machine's FAST devices.
+    pub usb_serials: Vec<String>,
+    pub inventory: Option<PathBuf>,
+    /// Added to the pins at the top of the file, replacing those for the same board.
+    pub pinned_firmware: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Apply the settings of profile `name` over the top-level ones.
+    pub fn select_profile(&mut self, name: &str) -> Result<(), String> {
+        let Some(profile) = self.profiles.get(name).cloned() else {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            return Err(if known.is_empty() {
+                format!(
+                    "unknown profile {}: the config file defines no [profiles]",
+                    name
+                )
+            } else {
+                format!("unknown profile {} (known: {})", name, known.join(", "))
+            });
+        };
+        let overlay = |slot: &mut Option<String>, value: Option<String>| {
+            if value.is_some() {
+                *slot = value;
+            }
+        };
+        overlay(&mut self.net_port, profile.net_port);
+        overlay(&mut self.seg_port, profile.seg_port);
+        overlay(&mut self.aud_port, profile.aud_port);
+        overlay(&mut self.dsp_port, profile.dsp_port);
+        if !profile.exp_ports.is_empty() {
+            self.exp_ports = profile.exp_ports;
+        }
+        if !profile.usb_serials.is_empty() {
+            self.usb_serials = profile.usb_serials;
+        }
+        if profile.inventory.is_some() {
+            self.inventory = profile.inventory;
+        }
+        self.pinned_firmware.extend(profile.pinned_firmware);
+        self.profile = Some(name.to_string());
+        Ok(())
+    }
+
+    /// The version pinned for `board` at `location` (e.g. `EXP B4`). A pin for the
+    /// location wins over one for the board's firmware (`FP-CPU-2000_NET`, the protocol
+    /// being the location's first word), which wins over one for the board type. Keys
+    /// match regardless of case.
+    pub fn pinned_version(&self, board: &str, location: &str) -> Option<String> {
+        let find = |key: &str| {
+            self.pinned_firmware
+                .iter()
+                .find(|(k, _)| k.trim().eq_ignore_ascii_case(key))
+                .map(|(_, v)| crate::commands::utils::normalize_version(v))
+        };
+        let protocol = location.split_whitespace().next().unwrap_or_default();
+        find(location)
+            .or_else(|| find(&format!("{}_{}", board, protocol)))
+            .or_else(|| find(board))
+    }
+}
+
+/// Serial timeouts, in milliseconds.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// Read timeout of the NET port.
+    pub net_read_ms: u64,
+    /// Read timeout of EXP, SEG, AUD and DSP ports (and of discovery probes).
+    pub exp_read_ms: u64,
+    /// Longest a single receive keeps reading while a response is still arriving.
+    pub receive_ms: u64,
+    /// How long to wait for the bootloader to confirm a flash.
+    pub bootloader_ms: u64,
+    /// How long to wait for the `ID:` response that verifies a flash.
+    pub verify_ms: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            net_read_ms: 200,
+            exp_read_ms: 5,
+            receive_ms: crate::protocol::RECEIVE_DEADLINE.as_millis() as u64,
+            bootloader_ms: 30_000,
+            verify_ms: 5_000,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn net_read(&self) -> Duration {
+        Duration::from_millis(self.net_read_ms)
+    }
+
+    pub fn exp_read(&self) -> Duration {
+        Duration::from_millis(self.exp_read_ms)
+    }
+
+    pub fn receive(&self) -> Duration {
+        Duration::from_millis(self.receive_ms)
+    }
+
+    pub fn bootloader(&self) -> Duration {
+        Duration::from_millis(self.bootloader_ms)
+    }
+
+    pub fn verify(&self) -> Duration {
+        Duration::from_millis(self.verify_ms)
+    }
+}
+
+/// Pacing of firmware streams, in milliseconds between lines.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlashSettings {
+    pub exp_line_delay_ms: u64,
+    pub net_line_delay_ms: u64,
+}
+
+impl Default for FlashSettings {
+    fn default() -> Self {
+        Self {
+            exp_line_delay_ms: 200,
+            net_line_delay_ms: 400,
+        }
+    }
+}
+
+impl FlashSettings {
+    pub fn exp_line_delay(&self) -> Duration {
+        Duration::from_millis(self.exp_line_delay_ms)
+    }
+
+    pub fn net_line_delay(&self) -> Duration {
+        Duration::from_millis(self.net_line_delay_ms)
+    }
+}
+
+/// Shell commands run around the flashing commands (see `hooks`).
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    /// Run before the ports are opened; a failure stops the command.
+    pub pre_flash: Option<String>,
+    /// Run after the command, with its exit code in `FAST_EXIT_CODE`.
+    pub post_flash: Option<String>,
+}
+
+/// ~/.fast/config.toml, if the home directory can be determined.
+pub fn default_path() -> Option<PathBuf> {
+    directories::UserDirs::new().map(|u| u.home_dir().join(".fast").join("config.toml"))
+}
+
+/// Read a config file. A missing file is not an error and yields the defaults.
+pub fn load(path: &Path) -> Result<Config, String> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(format!("read {} failed: {}", path.display(), e)),
+    };
+    toml::from_str(&text).map_err(|e| format!("invalid config {}: {}", path.display(), e))
+}
+
+/// Make `config` the one returned by `get`. Only the first call has any effect.
+pub fn init(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+/// The active configuration (defaults when `init` was never called).
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+/// Directory holding the firmware files.
+pub fn firmware_dir() -> PathBuf {
+    match get().firmware_dir.as_deref() {
+        Some(dir) => expand_home(dir),
+        None => directories::UserDirs::new()
+            .map(|u| u.home_dir().join(".fast").join("firmware"))
+            .unwrap_or_default(),
+    }
+}
+
+/// Directory holding downloaded firmware archives (~/.fast/cache).
+pub fn cache_dir() -> PathBuf {
+    directories::UserDirs::new()
+        .map(|u| u.home_dir().join(".fast").join("cache"))
+        .unwrap_or_default()
+}
+
+/// Directory of the per-port locks held while flashing (~/.fast/locks).
+pub fn lock_dir() -> PathBuf {
+    directories::UserDirs::new()
+        .map(|u| u.home_dir().join(".fast").join("locks"))
+        .unwrap_or_default()
+}
+
+/// File extending or overriding the EXP address map (~/.fast/boards.toml).
+pub fn boards_path() -> PathBuf {
+    directories::UserDirs::new()
+        .map(|u| u.home_dir().join(".fast").join("boards.toml"))
+        .unwrap_or_default()
+}
+
+/// Journal of flash attempts (~/.fast/history.jsonl).
+pub fn history_path() -> PathBuf {
+    directories::UserDirs::new()
+        .map(|u| u.home_dir().join(".fast").join("history.jsonl"))
+        .unwrap_or_default()
+}
+
+/// File of switch names written by `map-switches` (~/.fast/switches.yaml).
+pub fn switch_names_path() -> PathBuf {
+    directories::UserDirs::new()
+        .map(|u| u.home_dir().join(".fast").join("switches.yaml"))
+        .unwrap_or_default()
+}
+
+/// Expand a leading `~` so paths in the config file can be written like in a shell.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), directories::UserDirs::new()) {
+        (Ok(rest), Some(u)) => u.home_dir().join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Config, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn example_at_the_top_of_this_file_parses() {
+        let example: String = include_str!("config.rs")
+            .lines()
+            .skip_while(|l| *l != "// Example:")
+            .skip(1)
+            .take_while(|l| l.starts_with("//"))
+            .map(|l| l.trim_start_matches("//").trim_start())
+            .map(|l| format!("{}\n", l))
+            .collect();
+        let config = parse(&example).unwrap();
+        assert_eq!(config.net_port.as_deref(), Some("/dev/ttyACM0"));
+        assert_eq!(config.format, OutputFormat::Json);
+        assert_eq!(config.progress, ProgressMode::Plain);
+        assert_eq!(config.timeouts.exp_read(), Duration::from_millis(5));
+        assert_eq!(config.flash.net_line_delay(), Duration::from_millis(400));
+        assert_eq!(
+            config.hooks.pre_flash.as_deref(),
+            Some("systemctl --user stop mpf")
+        );
+        assert_eq!(config.profiles.len(), 2);
+    }
+
+    #[test]
+    fn missing_file_gives_the_defaults() {
+        let path = std::env::temp_dir().join(format!(
+            "fast-pinball-test-{}-no-config.toml",
+            std::process::id()
+        ));
+        let config = load(&path).unwrap();
+        assert_eq!(config.format, OutputFormat::Text);
+        assert_eq!(config.timeouts.bootloader(), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        assert!(parse("net_prot = \"/dev/ttyACM0\"").is_err());
+        assert!(parse("[timeouts]\nverify = 5000").is_err());
+    }
+
+    #[test]
+    fn profile_overrides_the_top_level_settings() {
+        let mut config = parse(
+            r#"
+            net_port = "/dev/ttyACM0"
+            exp_ports = ["/dev/ttyACM1"]
+            [pinned_firmware]
+            "FP-EXP-0071" = "0.31"
+            "FP-EXP-0081" = "0.40"
+            [profiles.bench]
+            net_port = "/dev/ttyACM5"
+            [profiles.bench.pinned_firmware]
+            "FP-EXP-0071" = "0.48"
+            "#,
+        )
+        .unwrap();
+        config.select_profile("bench").unwrap();
+        assert_eq!(config.net_port.as_deref(), Some("/dev/ttyACM5"));
+        assert_eq!(config.exp_ports, ["/dev/ttyACM1"]);
+        assert_eq!(config.pinned_firmware["FP-EXP-0071"], "0.48");
+        assert_eq!(config.pinned_firmware["FP-EXP-0081"], "0.40");
+        assert_eq!(config.profile.as_deref(), Some("bench"));
+
+        let err = config.select_profile("other").unwrap_err();
+        assert!(err.contains("known: bench"), "{}", err);
+    }
+
+    #[test]
+    fn location_pin_wins_over_firmware_and_board_pins() {
+        let config = parse(
+            r#"
+            [pinned_firmware]
+            "fp-exp-0071" = "0.31"
+            "FP-EXP-0071_EXP" = "0.40"
+            "EXP B4" = "0.50"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.pinned_version("FP-EXP-0071", "EXP B4").as_deref(),
+            Some("0.50")
+        );
+        assert_eq!(
+            config.pinned_version("FP-EXP-0071", "EXP B5").as_deref(),
+            Some("0.40")
+        );
+        assert_eq!(
+            config.pinned_version("FP-EXP-0071", "NET 01").as_deref(),
+            Some("0.31")
+        );
+        assert_eq!(config.pinned_version("FP-EXP-0081", "EXP 88"), None);
+    }
+}
@@ -0,0 +1,149 @@
+// The serial ports discovery last found, kept in ~/.fast/devices.json so startup can try
+// them instead of probing every port.
+//
+// Ports are matched by USB identity (vendor/product ID, serial number and interface),
+// so the cache survives ports being renumbered, e.g. /dev/ttyACM0 coming back as
+// /dev/ttyACM2. Whenever the ports present no longer line up with the cache, or a
+// cached port does not answer as expected, the caller falls back to full discovery and
+// saves the new result.
+
+use crate::fast_monitor::Protocol;
+use serde::{Deserialize, Serialize};
+use serialport::{SerialPortInfo, SerialPortType};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceCache {
+    /// Every port discovery considered, whether it answered or not.
+    pub ports: Vec<CachedPort>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CachedPort {
+    pub port: String,
+    /// What the port answered to `ID:`, or `None` if it did not answer.
+    pub protocol: Option<Protocol>,
+    /// USB identity of the port; `None` for non-USB ports.
+    pub usb: Option<UsbId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UsbId {
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_number: Option<String>,
+    pub interface: Option<u8>,
+}
+
+impl UsbId {
+    fn of(port: &SerialPortInfo) -> Option<Self> {
+        match &port.port_type {
+            SerialPortType::UsbPort(info) => Some(Self {
+                vid: info.vid,
+                pid: info.pid,
+                serial_number: info.serial_number.clone(),
+                interface: info.interface,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl DeviceCache {
+    /// Record the outcome of discovering `candidates`.
+    pub fn new(candidates: &[SerialPortInfo], ids: &HashMap<String, Protocol>) -> Self {
+        let ports = candidates
+            .iter()
+            .map(|p| CachedPort {
+                port: p.port_name.clone(),
+                protocol: ids.get(&p.port_name).copied(),
+                usb: UsbId::of(p),
+            })
+            .collect();
+        Self { ports }
+    }
+
+    /// Map the cached protocols onto today's port names, or `None` when `candidates`
+    /// are not the ports that were cached.
+    ///
+    /// A cached port keeps its name when a candidate with that name has the same USB
+    /// identity; otherwise it must match exactly one of the remaining candidates by USB
+    /// identity, which needs a serial number to tell devices of the same model apart.
+    pub fn resolve(&self, candidates: &[SerialPortInfo]) -> Option<HashMap<String, Protocol>> {
+        if candidates.len() != self.ports.len() {
+            return None;
+        }
+        let current: Vec<(&str, Option<UsbId>)> = candidates
+            .iter()
+            .map(|p| (p.port_name.as_str(), UsbId::of(p)))
+            .collect();
+
+        let mut taken = vec![false; current.len()];
+        let mut moved: Vec<&CachedPort> = Vec::new();
+        let mut names: Vec<(&CachedPort, &str)> = Vec::new();
+        for cached in self.ports.iter() {
+            match current
+                .iter()
+                .position(|(name, usb)| *name == cached.port && *usb == cached.usb)
+            {
+                Some(i) => {
+                    taken[i] = true;
+                    names.push((cached, current[i].0));
+                }
+                None => moved.push(cached),
+            }
+        }
+        for cached in moved {
+            let usb = cached.usb.as_ref()?;
+            usb.serial_number.as_ref()?;
+            let mut matches =
+                (0..current.len()).filter(|&i| !taken[i] && current[i].1.as_ref() == Some(usb));
+            let i = matches.next()?;
+            if matches.next().is_some() {
+                return None;
+            }
+            taken[i] = true;
+            log::debug!("Cached port {} is now {}", cached.port, current[i].0);
+            names.push((cached, current[i].0));
+        }
+
+        Some(
+            names
+                .into_iter()
+                .filter_map(|(cached, name)| Some((name.to_string(), cached.protocol?)))
+                .collect(),
+        )
+    }
+}
+
+/// ~/.fast/devices.json, if the home directory can be determined.
+pub fn path() -> Option<PathBuf> {
+    directories::UserDirs::new().map(|u| u.home_dir().join(".fast").join("devices.json"))
+}
+
+/// The cached ports, if the cache exists and can be read.
+pub fn load() -> Option<DeviceCache> {
+    let path = path()?;
+    let text = std::fs::read_to_string(&path).ok()?;
+    serde_json::from_str(&text)
+        .inspect_err(|e| log::debug!("Ignoring invalid {}: {}", path.display(), e))
+        .ok()
+}
+
+/// Write the cache. Failing to is not fatal; the next start just discovers again.
+pub fn save(cache: &DeviceCache) {
+    let Some(path) = path() else {
+        return;
+    };
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| {
+            let json = serde_json::to_string_pretty(cache).unwrap_or_default();
+            std::fs::write(&path, json)
+        });
+    if let Err(e) = result {
+        log::warn!("Failed to write {}: {}", path.display(), e);
+    }
+}
@@ -0,0 +1,449 @@
+// Software stand-in for a FAST Neuron: a NET endpoint with its I/O node boards and an EXP
+// endpoint with a set of EXP boards and their breakouts. It answers `ID:`, `NN:`, `DL:`,
+// `ID@xx:`, `ID@xxN:` and the bootloader handshake at the end of a firmware stream, and
+// goes quiet for a moment after `BR:` or `BR@xx:`, so the list and update flows can run
+// without hardware. Serve it over TCP and point the CLI at it with `--net-port tcp://...`
+// and `--exp-port tcp://...`, or hand `net_port()`/`exp_port()` straight to the
+// protocols.
+
+use crate::protocol::transport::Transport;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// How long the firmware stream has to pause before a flash counts as finished.
+const FLASH_IDLE: Duration = Duration::from_millis(1_000);
+
+/// How long the Neuron or an EXP board ignores commands after a reset.
+const RESET_TIME: Duration = Duration::from_millis(1_000);
+
+/// An I/O board on the NET loop, reported by `NN:`.
+#[derive(Debug, Clone)]
+pub struct EmulatedNode {
+    pub name: String,
+    pub firmware: String,
+    pub drivers: u8,
+    pub switches: u8,
+}
+
+/// An EXP board, reported by `ID@{address}:`.
+#[derive(Debug, Clone)]
+pub struct EmulatedExpBoard {
+    pub address: String,
+    pub board_name: String,
+    pub version: String,
+    /// Stuck in its bootloader: answers `ID@{address}:` with a `!BL2040:00` banner until
+    /// it is flashed.
+    pub in_bootloader: bool,
+    pub breakouts: Vec<EmulatedBreakout>,
+}
+
+/// A breakout board behind an EXP board, reported by `ID@{address}{index}:`.
+#[derive(Debug, Clone)]
+pub struct EmulatedBreakout {
+    /// 1-3; index 0 is the EXP board itself.
+    pub index: u8,
+    pub board_name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct EmulatorConfig {
+    /// Firmware version the Neuron reports on the NET port.
+    pub neuron_version: String,
+    pub nodes: Vec<EmulatedNode>,
+    pub exp_boards: Vec<EmulatedExpBoard>,
+    /// Version a board reports once it has been flashed. The firmware stream carries no
+    /// version the emulator could read, so without this a flashed board keeps its version.
+    pub flashed_version: Option<String>,
+}
+
+impl Default for EmulatorConfig {
+    fn default() -> Self {
+        let node = |name: &str, drivers, switches| EmulatedNode {
+            name: name.to_string(),
+            firmware: "1.05".to_string(),
+            drivers,
+            switches,
+        };
+        let exp = |address: &str, board_name: &str, version: &str| EmulatedExpBoard {
+            address: address.to_string(),
+            board_name: board_name.to_string(),
+            version: version.to_string(),
+            in_bootloader: false,
+            breakouts: Vec::new(),
+        };
+        Self {
+            neuron_version: "2.28".to_string(),
+            nodes: vec![node("FP-I/O-3208", 8, 32), node("FP-I/O-0804", 4, 8)],
+            exp_boards: vec![
+                exp("48", "FP-CPU-2000", "0.48"),
+                EmulatedExpBoard {
+                    breakouts: vec![EmulatedBreakout {
+                        index: 1,
+                        board_name: "FP-BRK-0001".to_string(),
+                        version: "0.10".to_string(),
+                    }],
+                    ..exp("B4", "FP-EXP-0071", "0.48")
+                },
+                exp("88", "FP-EXP-0091", "0.46"),
+            ],
+            flashed_version: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endpoint {
+    Net,
+    Exp,
+}
+
+#[derive(Default)]
+struct Link {
+    /// Received bytes not yet terminated with `\r`.
+    partial: Vec<u8>,
+    /// Bytes waiting to be read by the host.
+    pending: VecDeque<u8>,
+    /// When the last firmware line arrived, while a flash is in progress.
+    flashing_since: Option<Instant>,
+}
+
+struct State {
+    config: EmulatorConfig,
+    net: Link,
+    exp: Link,
+    // EXP address selected with `EA:`, the target of a firmware stream
+    selected: Option<String>,
+    // Driver configurations set with `DL:`; unset drivers read back as all zeros
+    drivers: HashMap<u8, [u8; 8]>,
+    // When the Neuron (`None`) or the EXP board at an address was last reset
+    resets: HashMap<Option<String>, Instant>,
+}
+
+pub struct Emulator {
+    state: Arc<Mutex<State>>,
+}
+
+impl Emulator {
+    pub fn new(config: EmulatorConfig) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                config,
+                net: Link::default(),
+                exp: Link::default(),
+                selected: None,
+                drivers: HashMap::new(),
+                resets: HashMap::new(),
+            })),
+        }
+    }
+
+    /// In-process transport for the NET endpoint.
+    pub fn net_port(&self) -> EmulatedPort {
+        EmulatedPort {
+            endpoint: Endpoint::Net,
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    /// In-process transport for the EXP endpoint.
+    pub fn exp_port(&self) -> EmulatedPort {
+        EmulatedPort {
+            endpoint: Endpoint::Exp,
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    /// Serve the NET and EXP endpoints on the given TCP addresses. Blocks while serving;
+    /// only returns early when a listener cannot be bound.
+    pub fn serve_tcp(&self, net_addr: &str, exp_addr: &str) -> io::Result<()> {
+        let net = TcpListener::bind(net_addr)?;
+        let exp = TcpListener::bind(exp_addr)?;
+        std::thread::scope(|scope| {
+            scope.spawn(|| serve_listener(net, self.net_port()));
+            serve_listener(exp, self.exp_port());
+        });
+        Ok(())
+    }
+}
+
+/// One end of the emulator, usable anywhere a serial port is.
+#[derive(Clone)]
+pub struct EmulatedPort {
+    endpoint: Endpoint,
+    state: Arc<Mutex<State>>,
+}
+
+impl EmulatedPort {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.poll(self.endpoint);
+        state
+    }
+}
+
+impl Read for EmulatedPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let endpoint = self.endpoint;
+        let mut state = self.lock();
+        let link = state.link(endpoint);
+        if link.pending.is_empty() {
+            return Err(io::Error::new(ErrorKind::TimedOut, "nothing to read"));
+        }
+        let n = buf.len().min(link.pending.len());
+        for (slot, byte) in buf.iter_mut().zip(link.pending.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for EmulatedPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let endpoint = self.endpoint;
+        let mut state = self.lock();
+        for &byte in buf {
+            if byte == b'\r' || byte == b'\n' {
+                let line = std::mem::take(&mut state.link(endpoint).partial);
+                state.handle_line(endpoint, &String::from_utf8_lossy(&line));
+            } else {
+                state.link(endpoint).partial.push(byte);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for EmulatedPort {
+    fn bytes_to_read(&self) -> io::Result<u32> {
+        let endpoint = self.endpoint;
+        Ok(self.lock().link(endpoint).pending.len() as u32)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+impl State {
+    fn link(&mut self, endpoint: Endpoint) -> &mut Link {
+        match endpoint {
+            Endpoint::Net => &mut self.net,
+            Endpoint::Exp => &mut self.exp,
+        }
+    }
+
+    fn handle_line(&mut self, endpoint: Endpoint, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        // Anything that is not a command is part of a firmware stream
+        let Some((command, address, args)) = parse_command(line) else {
+            self.link(endpoint).flashing_since = Some(Instant::now());
+            return;
+        };
+        self.resets.retain(|_, at| at.elapsed() < RESET_TIME);
+        if self.resets.contains_key(&None) {
+            return;
+        }
+        let reply = match endpoint {
+            Endpoint::Net => self.net_command(&command, args),
+            Endpoint::Exp => self.exp_command(&command, address, args),
+        };
+        if let Some(reply) = reply {
+            let link = self.link(endpoint);
+            link.pending.extend(reply.bytes());
+            link.pending.push_back(b'\r');
+        }
+    }
+
+    fn net_command(&mut self, command: &str, args: &str) -> Option<String> {
+        match command {
+            "ID" => Some(format!("ID:NET FP-CPU-2000 {}", self.config.neuron_version)),
+            "NN" => {
+                let node = args
+                    .trim()
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|i| self.config.nodes.get(i).map(|n| (i, n)));
+                Some(match node {
+                    Some((i, n)) => format!(
+                        "NN:{:02},{},{},{:02X},{:02X}",
+                        i, n.name, n.firmware, n.drivers, n.switches
+                    ),
+                    None => "!Node Not Found!".to_string(),
+                })
+            }
+            // Every node acknowledges the bulk node update; their firmware stays as it is
+            "BN" => {
+                let mut reply = "BN:P".to_string();
+                for _ in &self.config.nodes {
+                    reply.push_str("\r!B:02");
+                }
+                Some(reply)
+            }
+            "DL" => {
+                let fields: Option<Vec<u8>> = args
+                    .split(',')
+                    .map(|f| u8::from_str_radix(f.trim(), 16).ok())
+                    .collect();
+                let total: usize = self.config.nodes.iter().map(|n| n.drivers as usize).sum();
+                Some(match fields.as_deref() {
+                    Some(&[driver]) if (driver as usize) < total => {
+                        let config = self.drivers.get(&driver).copied().unwrap_or_default();
+                        let hex: Vec<String> =
+                            config.iter().map(|b| format!("{:02X}", b)).collect();
+                        format!("DL:{:02X},{}", driver, hex.join(","))
+                    }
+                    Some(&[driver, ref config @ ..])
+                        if config.len() == 8 && (driver as usize) < total =>
+                    {
+                        let mut stored = [0; 8];
+                        stored.copy_from_slice(config);
+                        self.drivers.insert(driver, stored);
+                        "DL:P".to_string()
+                    }
+                    _ => "DL:F".to_string(),
+                })
+            }
+            "BR" => {
+                self.resets.insert(None, Instant::now());
+                None
+            }
+            other => Some(format!("{}:P", other)),
+        }
+    }
+
+    fn exp_command(&mut self, command: &str, address: Option<&str>, args: &str) -> Option<String> {
+        match (command, address) {
+            ("ID", Some(address)) if self.resets.contains_key(&Some(board_address(address))) => {
+                None
+            }
+            ("ID", Some(address)) => {
+                let breakout = address
+                    .get(2..)
+                    .and_then(|i| i.parse::<u8>().ok())
+                    .unwrap_or(0);
+                let board = self.exp_board(address)?;
+                if board.in_bootloader {
+                    return Some("!BL2040:00".to_string());
+                }
+                if breakout == 0 {
+                    return Some(format!("ID:EXP {} {}", board.board_name, board.version));
+                }
+                board
+                    .breakouts
+                    .iter()
+                    .find(|b| b.index == breakout)
+                    .map(|b| format!("ID:EXP {} {}", b.board_name, b.version))
+            }
+            ("BR", Some(address)) => {
+                self.resets
+                    .insert(Some(board_address(address)), Instant::now());
+                None
+            }
+            ("ID", None) => Some(format!("ID:EXP FP-CPU-2000 {}", self.config.neuron_version)),
+            ("EA", _) => {
+                self.selected = Some(args.trim().to_ascii_uppercase());
+                None
+            }
+            // LED and other EXP commands are not acknowledged
+            _ => None,
+        }
+    }
+
+    /// The board at `address`, ignoring a trailing breakout digit.
+    fn exp_board(&mut self, address: &str) -> Option<&mut EmulatedExpBoard> {
+        let address = board_address(address);
+        self.config
+            .exp_boards
+            .iter_mut()
+            .find(|b| b.address.eq_ignore_ascii_case(&address))
+    }
+
+    /// Finish a flash once its firmware stream has gone quiet.
+    fn poll(&mut self, endpoint: Endpoint) {
+        let done = self
+            .link(endpoint)
+            .flashing_since
+            .is_some_and(|t| t.elapsed() >= FLASH_IDLE);
+        if !done {
+            return;
+        }
+        self.link(endpoint).flashing_since = None;
+        let flashed = self.config.flashed_version.clone();
+        let token = match endpoint {
+            Endpoint::Net => {
+                if let Some(v) = flashed {
+                    self.config.neuron_version = v;
+                }
+                "!B:02\r"
+            }
+            Endpoint::Exp => {
+                let selected = self.selected.clone().unwrap_or_default();
+                if let Some(board) = self.exp_board(&selected) {
+                    board.in_bootloader = false;
+                    if let Some(v) = flashed {
+                        board.version = v;
+                    }
+                }
+                "!BL2040:02\r"
+            }
+        };
+        self.link(endpoint).pending.extend(token.bytes());
+    }
+}
+
+// The board part of an EXP address (without a breakout digit), uppercase
+fn board_address(address: &str) -> String {
+    address.get(..2).unwrap_or(address).to_ascii_uppercase()
+}
+
+/// Split `XX:args` or `XX@addr:args` into (command, address, args).
+fn parse_command(line: &str) -> Option<(String, Option<&str>, &str)> {
+    let (head, args) = line.split_once(':')?;
+    let (command, address) = match head.split_once('@') {
+        Some((c, a)) => (c, Some(a)),
+        None => (head, None),
+    };
+    if command.len() != 2 || !command.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    Some((command.to_ascii_uppercase(), address, args))
+}
+
+fn serve_listener(listener: TcpListener, port: EmulatedPort) {
+    for stream in listener.incoming().flatten() {
+        let port = port.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = serve_connection(stream, port) {
+                log::debug!("Emulator connection closed: {}", e);
+            }
+        });
+    }
+}
+
+fn serve_connection(mut stream: TcpStream, mut port: EmulatedPort) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_millis(20)))?;
+    stream.set_nodelay(true)?;
+    let mut buf = [0u8; 1024];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => port.write_all(&buf[..n])?,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) => return Err(e),
+        }
+        while let Ok(n) = port.read(&mut buf) {
+            stream.write_all(&buf[..n])?;
+        }
+    }
+}
@@ -1,227 +1,979 @@
+use crate::cancel::{CancelToken, Cancelled};
+use crate::port_owner;
+use crate::protocol::aud_protocol::AudProtocol;
+use crate::protocol::dsp_protocol::DspProtocol;
 use crate::protocol::exp_protocol::ExpProtocol;
+use crate::protocol::net_events::Subscriber;
 use crate::protocol::net_protocol::NetProtocol;
-use serialport::{DataBits, FlowControl, Parity, StopBits, available_ports};
+use crate::protocol::responses::{ExpResponse, IdResponse, NetResponse, Response};
+use crate::protocol::seg_protocol::SegProtocol;
+use serialport::{
+    DataBits, FlowControl, Parity, SerialPortInfo, SerialPortType, StopBits, UsbPortInfo,
+    available_ports,
+};
 use std::collections::HashMap;
 use std::io::{Read, Write};
-use std::time::Duration;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum Protocol {
     NET,
     EXP,
+    SEG,
+    AUD,
+    DSP,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
 pub struct ExpBoardInfo {
+    // Serial port of the EXP interface the board was found behind
+    pub port: String,
     pub address: String,
     pub board_name: String,
     pub version: String,
     pub available_versions: Option<Vec<String>>,
+    /// The controller's own EXP interface (the Neuron's at address 48) rather than an
+    /// expansion board. Flashing it updates the controller.
+    pub is_controller: bool,
+    /// Breakout boards attached to it.
+    pub breakouts: Vec<ExpBreakoutInfo>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// A breakout board behind an EXP board, which answers `ID@{address}{index}:`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ExpBreakoutInfo {
+    /// Full address, e.g. `B41`.
+    pub address: String,
+    /// Breakout index (0-3) behind the EXP board.
+    pub index: u8,
+    pub board_name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
 pub struct NetBoardInfo {
     pub node_id: String,
     pub node_name: String,
     pub firmware: String,
+    /// Driver outputs on the node (first field after the firmware, hex).
+    pub driver_count: Option<u16>,
+    /// Switch inputs on the node (second field after the firmware, hex).
+    pub switch_count: Option<u16>,
     // All additional numeric/config fields returned after the firmware version, in order
     pub extra_fields: Vec<String>,
 }
 
+impl NetBoardInfo {
+    /// Capabilities for display, e.g. `32 switches, 8 drivers`; empty when unknown.
+    pub fn capabilities(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(n) = self.switch_count {
+            parts.push(format!("{} switches", n));
+        }
+        if let Some(n) = self.driver_count {
+            parts.push(format!("{} drivers", n));
+        }
+        parts.join(", ")
+    }
+}
+
+/// An EXP board that answers with a bootloader banner instead of its ID.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct BootloaderBoard {
+    // Serial port of the EXP interface the board was found behind
+    pub port: String,
+    pub address: String,
+    /// Board type expected at the address (from the EXP address map, see `board_map`).
+    pub board_type: String,
+    /// The banner it answered with, e.g. `!BL2040:00`.
+    pub banner: String,
+}
+
+/// Something answering a deep scan that is not a known board: an address outside the
+/// address map, or a reply that is not an ID (e.g. a bootloader banner).
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct UnknownExpResponder {
+    // Serial port of the EXP interface it answered behind
+    pub port: String,
+    pub address: String,
+    /// The reply as received, e.g. `ID:EXP FP-EXP-0072 0.10`.
+    pub response: String,
+}
+
+/// What `deep_scan_exp_boards` found.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ExpDeepScan {
+    /// Boards at addresses in the address map, as `list_connected_exp_boards` lists them.
+    pub boards: Vec<ExpBoardInfo>,
+    pub unknown: Vec<UnknownExpResponder>,
+}
+
+/// ID of a single-processor port such as SEG, AUD or DSP.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ProcessorInfo {
+    pub port: String,
+    pub board_name: String,
+    pub version: String,
+}
+
+/// How often `connect_waiting` looks for the ports again.
+pub const CONNECT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Serial ports to use instead of discovery; anything left unset is discovered.
+#[derive(Debug, Clone, Default)]
+pub struct PinnedPorts {
+    pub net: Option<String>,
+    pub exp: Vec<String>,
+    pub seg: Option<String>,
+    pub aud: Option<String>,
+    pub dsp: Option<String>,
+}
+
 pub struct FastPinballMonitor {
     pub net: NetProtocol,
-    pub exp: ExpProtocol,
+    // Every EXP-capable interface; the Neuron can expose more than one
+    pub exp_ports: Vec<ExpProtocol>,
+    // Segment display processor, only present on builds with segment displays
+    pub seg: Option<SegProtocol>,
+    // Audio interface, only present on builds with the FAST audio board
+    pub aud: Option<AudProtocol>,
+    // Display controller (RGB DMD), only present on builds with a FAST display board
+    pub dsp: Option<DspProtocol>,
+    // Given to every port by `set_cancel_token`
+    pub(crate) cancel: CancelToken,
 }
 impl FastPinballMonitor {
     pub fn connect() -> Option<Self> {
-        let ids = Self::discover_protocol_ports();
-
-        let mut net_opt: Option<NetProtocol> = None;
-        let mut exp_opt: Option<ExpProtocol> = None;
-        for (port, proto) in ids.iter() {
-            match proto {
-                Protocol::NET => {
-                    if net_opt.is_none() {
-                        net_opt = Some(NetProtocol::new(port.clone()));
-                    }
-                }
-                Protocol::EXP => {
-                    if exp_opt.is_none() {
-                        exp_opt = Some(ExpProtocol::new(port.clone()));
-                    }
+        Self::connect_with_ports(PinnedPorts::default()).ok()
+    }
+
+    /// Connect using explicitly pinned ports where given.
+    ///
+    /// Ports that are not pinned are located via discovery, which skips any pinned
+    /// port. When NET and EXP are both pinned no discovery probing happens at all, so
+    /// the optional SEG, AUD and DSP ports are then only opened when pinned too. When
+    /// nothing is pinned, the ports found last time (see `device_cache`) are tried first.
+    pub fn connect_with_ports(pins: PinnedPorts) -> Result<Self, String> {
+        Self::connect_with_ports_cancellable(pins, &CancelToken::new())
+    }
+
+    // `connect_with_ports`, with discovery stopping early once `cancel` is cancelled
+    fn connect_with_ports_cancellable(
+        pins: PinnedPorts,
+        cancel: &CancelToken,
+    ) -> Result<Self, String> {
+        let PinnedPorts {
+            net: net_port,
+            exp: exp_ports,
+            seg: seg_port,
+            aud: aud_port,
+            dsp: dsp_port,
+        } = pins;
+        let ids = if net_port.is_some() && !exp_ports.is_empty() {
+            HashMap::new()
+        } else {
+            let pinned: Vec<String> = net_port
+                .iter()
+                .chain(exp_ports.iter())
+                .chain(seg_port.iter())
+                .chain(aud_port.iter())
+                .chain(dsp_port.iter())
+                .cloned()
+                .collect();
+            // The cache remembers every FAST device, not those of one machine
+            if pinned.is_empty() && crate::config::get().usb_serials.is_empty() {
+                Self::discover_cached_ports(cancel)
+            } else {
+                Self::discover_protocol_ports(&pinned, cancel)
+            }
+        };
+        cancel.check().map_err(|e| e.to_string())?;
+
+        let net_port = net_port.or_else(|| {
+            ids.iter()
+                .find(|(_, proto)| **proto == Protocol::NET)
+                .map(|(port, _)| port.clone())
+        });
+        let exp_ports = if exp_ports.is_empty() {
+            let mut found: Vec<String> = ids
+                .iter()
+                .filter(|(_, proto)| **proto == Protocol::EXP)
+                .map(|(port, _)| port.clone())
+                .collect();
+            // Keep a stable port order between runs
+            found.sort();
+            found
+        } else {
+            exp_ports
+        };
+
+        let Some(net_port) = net_port.filter(|_| !exp_ports.is_empty()) else {
+            let mut message = "Could not find FAST NET/EXP serial ports. Ensure devices are connected and accessible.".to_string();
+            // Discovery leaves ports other programs hold alone; they may be the FAST ones
+            let candidates: Vec<String> = available_ports()
+                .map(|ports| candidate_ports(ports, &[]))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|p| p.port_name)
+                .filter(|p| !ids.contains_key(p))
+                .collect();
+            if let Some(hint) = port_owner::busy_ports_hint(&candidates) {
+                message = format!("{}\n{}", message, hint);
+            }
+            if let Some(hint) = usb_serials_hint() {
+                message = format!("{}\n{}", message, hint);
+            }
+            return Err(message);
+        };
+        port_owner::check_free("NET", &net_port)?;
+        let net = NetProtocol::try_new(net_port.clone())
+            .map_err(|e| port_owner::describe_open_error("NET", &net_port, &e))?;
+        let mut exp = Vec::new();
+        for port in exp_ports {
+            port_owner::check_free("EXP", &port)?;
+            exp.push(
+                ExpProtocol::try_new(port.clone())
+                    .map_err(|e| port_owner::describe_open_error("EXP", &port, &e))?,
+            );
+        }
+        let seg = open_optional(seg_port, &ids, Protocol::SEG, SegProtocol::try_new)?;
+        let aud = open_optional(aud_port, &ids, Protocol::AUD, AudProtocol::try_new)?;
+        let dsp = open_optional(dsp_port, &ids, Protocol::DSP, DspProtocol::try_new)?;
+        Ok(FastPinballMonitor {
+            net,
+            exp_ports: exp,
+            seg,
+            aud,
+            dsp,
+            cancel: CancelToken::new(),
+        })
+    }
+
+    /// Use ports that are already open, e.g. over `MockTransport`s.
+    pub fn with_protocols(
+        net: NetProtocol,
+        exp_ports: Vec<ExpProtocol>,
+        seg: Option<SegProtocol>,
+        aud: Option<AudProtocol>,
+        dsp: Option<DspProtocol>,
+    ) -> Self {
+        FastPinballMonitor {
+            net,
+            exp_ports,
+            seg,
+            aud,
+            dsp,
+            cancel: CancelToken::new(),
+        }
+    }
+
+    /// Like `connect_with_ports`, but keep retrying every `CONNECT_POLL_INTERVAL` until
+    /// the ports appear, e.g. while the machine is still powering up. Gives up after
+    /// `timeout`, if given, with the last connect error.
+    pub fn connect_waiting(pins: PinnedPorts, timeout: Option<Duration>) -> Result<Self, String> {
+        Self::connect_cancellable(pins, timeout, CancelToken::new())
+    }
+
+    /// Like `connect_waiting`, but give up as soon as `cancel` is cancelled, with the
+    /// `Cancelled` message as the error. A `timeout` of zero tries once. The monitor keeps
+    /// `cancel` for its scans and flashes (see `set_cancel_token`).
+    pub fn connect_cancellable(
+        pins: PinnedPorts,
+        timeout: Option<Duration>,
+        cancel: CancelToken,
+    ) -> Result<Self, String> {
+        let start = Instant::now();
+        loop {
+            cancel.check().map_err(|e| e.to_string())?;
+            match Self::connect_with_ports_cancellable(pins.clone(), &cancel) {
+                Ok(mut fpm) => {
+                    fpm.set_cancel_token(cancel);
+                    return Ok(fpm);
                 }
+                Err(e) if cancel.is_cancelled() => return Err(e),
+                Err(e) if timeout.is_some_and(|t| start.elapsed() >= t) => return Err(e),
+                Err(e) => log::debug!("Not connected yet: {}", e),
             }
+            std::thread::sleep(CONNECT_POLL_INTERVAL);
         }
+    }
 
-        match (net_opt, exp_opt) {
-            (Some(net), Some(exp)) => Some(FastPinballMonitor { net, exp }),
-            _ => None,
+    /// Stop this monitor's scans and flashes once `cancel` is cancelled: every port gets
+    /// a clone of it (see `cancel`).
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.net.cancel = cancel.clone();
+        for exp in self.exp_ports.iter_mut() {
+            exp.cancel = cancel.clone();
+        }
+        if let Some(dsp) = self.dsp.as_mut() {
+            dsp.cancel = cancel.clone();
         }
+        self.cancel = cancel;
+    }
+
+    /// Receive every NET message from now on, parsed, including those the controller
+    /// sends unprompted such as switch changes. The first subscription starts a
+    /// background reader of the NET port (see `net_events`); commands keep working as
+    /// before.
+    pub fn subscribe_net_events(&mut self) -> Result<mpsc::Receiver<NetResponse>, String> {
+        let (tx, rx) = mpsc::channel();
+        self.net.subscribe(Subscriber::Channel(tx))?;
+        Ok(rx)
+    }
+
+    /// Call `callback` for every NET message from now on, like `subscribe_net_events`.
+    /// It runs on the reader thread, so it should return quickly.
+    pub fn on_net_event(
+        &mut self,
+        callback: impl FnMut(&NetResponse) + Send + 'static,
+    ) -> Result<(), String> {
+        self.net.subscribe(Subscriber::Callback(Box::new(callback)))
+    }
+
+    /// ID and firmware of the segment display processor, if one is connected.
+    pub fn seg_info(&mut self) -> Option<ProcessorInfo> {
+        let seg = self.seg.as_mut()?;
+        let (board_name, version) = seg.id()?;
+        Some(ProcessorInfo {
+            port: seg.port_name.clone(),
+            board_name,
+            version,
+        })
     }
 
+    /// ID and firmware of the display controller, if one is connected.
+    pub fn dsp_info(&mut self) -> Option<ProcessorInfo> {
+        let dsp = self.dsp.as_mut()?;
+        let (board_name, version) = dsp.id()?;
+        Some(ProcessorInfo {
+            port: dsp.port_name.clone(),
+            board_name,
+            version,
+        })
+    }
+
+    /// ID and firmware of the audio interface, if one is connected.
+    pub fn aud_info(&mut self) -> Option<ProcessorInfo> {
+        let aud = self.aud.as_mut()?;
+        let (board_name, version) = aud.id()?;
+        Some(ProcessorInfo {
+            port: aud.port_name.clone(),
+            board_name,
+            version,
+        })
+    }
+
+    /// The EXP interface opened on `port_name`, if any.
+    pub fn exp_port(&mut self, port_name: &str) -> Option<&mut ExpProtocol> {
+        self.exp_ports.iter_mut().find(|e| e.port_name == port_name)
+    }
+
+    /// Let flashes over every EXP interface send firmware made for another board than
+    /// the one answering (see `ExpProtocol::allow_board_mismatch`).
+    pub fn allow_exp_board_mismatch(&mut self, allow: bool) {
+        for exp in self.exp_ports.iter_mut() {
+            exp.allow_board_mismatch = allow;
+        }
+    }
+
+    /// The first EXP interface that answers an `ID@{address}:` query.
+    pub fn exp_port_for_address(&mut self, address_hex: &str) -> Option<&mut ExpProtocol> {
+        let idx = self
+            .exp_ports
+            .iter_mut()
+            .position(|exp| answers_at(exp, address_hex))?;
+        self.exp_ports.get_mut(idx)
+    }
+
+    /// The boards answering at the addresses of the EXP address map. A cancelled scan
+    /// (see `set_cancel_token`) lists what it found so far; `try_list_connected_exp_boards`
+    /// tells it apart.
     pub fn list_connected_exp_boards(&mut self) -> Vec<ExpBoardInfo> {
-        let mut results: Vec<ExpBoardInfo> = Vec::new();
-
-        // Small helper to drain any pending bytes before we start
-        let _ = self.exp.receive();
-
-        // Use the centralized EXP address mapping constant and the static firmware map
-        use crate::constants::{AVAILABLE_FIRMWARE_VERSIONS, EXP_ADDRESS_MAP};
-
-        // Iterate addresses, send ID@{Address}: and collect parsed responses
-        for &(addr, board_type) in EXP_ADDRESS_MAP.iter() {
-            let cmd = format!("ID@{}:\r", addr);
-
-            self.exp.send(cmd.into_bytes());
-            std::thread::sleep(Duration::from_millis(10));
-
-            let resp = self.exp.receive();
-
-            if let Some((proto, board, version)) = parse_id_response(&resp) {
-                let board_name = if board.is_empty() {
-                    board_type.to_string()
-                } else {
-                    board
-                };
-                let key = format!("{}_{}", board_name, proto);
-                let fallback_key = format!("{}_{}", board_type, proto);
-                // Translate the available firmware map (version -> path) into a list of versions
-                let versions_from_map = |m: &HashMap<String, HashMap<String, String>>,
-                                         k: &str|
-                 -> Option<Vec<String>> {
-                    m.get(k).map(|inner| {
-                        let mut v: Vec<String> = inner.keys().cloned().collect();
-                        v.sort();
-                        v
-                    })
-                };
-                let available_versions = versions_from_map(&AVAILABLE_FIRMWARE_VERSIONS, &key)
-                    .or_else(|| versions_from_map(&AVAILABLE_FIRMWARE_VERSIONS, &fallback_key));
-                results.push(ExpBoardInfo {
-                    address: addr.to_string(),
-                    board_name,
-                    version,
-                    available_versions,
-                });
+        // Each EXP interface has its own bus, so they are scanned side by side
+        std::thread::scope(|scope| {
+            let scans: Vec<_> = self
+                .exp_ports
+                .iter_mut()
+                .map(|exp| scope.spawn(move || scan_exp_port(exp)))
+                .collect();
+            let boards: Vec<ExpBoardInfo> = scans
+                .into_iter()
+                .flat_map(|scan| scan.join().unwrap_or_default())
+                .collect();
+            crate::metrics::set_board_count("exp", boards.len());
+            boards
+        })
+    }
+
+    /// `list_connected_exp_boards`, or `Cancelled` when the scan was cancelled.
+    pub fn try_list_connected_exp_boards(&mut self) -> Result<Vec<ExpBoardInfo>, Cancelled> {
+        let boards = self.list_connected_exp_boards();
+        self.cancel.check()?;
+        Ok(boards)
+    }
+
+    /// Query every possible EXP address (`00`-`FF`) rather than only those in the address
+    /// map, so boards with unexpected DIP settings or of types this tool does not know
+    /// show up too. Each address gets the full reply wait, so this takes a few seconds.
+    pub fn deep_scan_exp_boards(&mut self) -> ExpDeepScan {
+        std::thread::scope(|scope| {
+            let scans: Vec<_> = self
+                .exp_ports
+                .iter_mut()
+                .map(|exp| scope.spawn(move || deep_scan_exp_port(exp)))
+                .collect();
+            let mut found = ExpDeepScan::default();
+            for scan in scans {
+                let scan = scan.join().unwrap_or_default();
+                found.boards.extend(scan.boards);
+                found.unknown.extend(scan.unknown);
             }
+            found
+        })
+    }
 
-            // Small delay between polls to be gentle on the bus
-            std::thread::sleep(Duration::from_millis(5));
+    /// EXP boards stuck in their bootloader, e.g. after a failed flash: they answer
+    /// `ID@{address}:` with a `!BL...` banner, so `list_connected_exp_boards` skips them.
+    pub fn list_bootloader_exp_boards(&mut self) -> Vec<BootloaderBoard> {
+        let mut found = Vec::new();
+        for exp in self.exp_ports.iter_mut() {
+            let _ = exp.receive();
+            for (addr, board_type) in crate::board_map::get().exp_boards() {
+                if exp.cancel.is_cancelled() {
+                    break;
+                }
+                let (addr, board_type) = (addr.as_str(), board_type.as_str());
+                let resp = query_exp_id(exp, addr, SCAN_REPLY_TIMEOUT);
+                let banner = resp
+                    .lines()
+                    .find_map(|line| match ExpResponse::parse(line) {
+                        ExpResponse::Bootloader(banner) => Some(banner),
+                        _ => None,
+                    });
+                if let Some(banner) = banner {
+                    found.push(BootloaderBoard {
+                        port: exp.port_name.clone(),
+                        address: addr.to_string(),
+                        board_type: board_type.to_string(),
+                        banner,
+                    });
+                }
+            }
         }
-
-        results
+        found
     }
 
+    /// The node boards on the NET loop, by position, with the controller itself last. A
+    /// cancelled scan lists what it found so far, like `list_connected_exp_boards`.
     pub fn list_connected_net_boards(&mut self) -> HashMap<usize, NetBoardInfo> {
-        let mut results: HashMap<usize, NetBoardInfo> = HashMap::new();
-
-        // Drain any pending bytes from NET before starting
-        let _ = self.net.receive();
-
-        // Also query the Neuron controller directly via ID:\r to get its own info
-        let controller_info: Option<(String, String)> = {
-            let _ = self.net.send(b"ID:\r");
-            std::thread::sleep(Duration::from_millis(10));
-            let resp = self.net.receive();
-            if let Some((_proto, board, version)) = parse_id_response(&resp) {
-                Some((board, version))
-            } else {
-                None
-            }
+        list_net_boards(&mut self.net)
+    }
+
+    /// `list_connected_net_boards`, or `Cancelled` when the scan was cancelled.
+    pub fn try_list_connected_net_boards(
+        &mut self,
+    ) -> Result<HashMap<usize, NetBoardInfo>, Cancelled> {
+        let boards = self.list_connected_net_boards();
+        self.cancel.check()?;
+        Ok(boards)
+    }
+
+    fn discover_protocol_ports(skip: &[String], cancel: &CancelToken) -> HashMap<String, Protocol> {
+        let Ok(ports) = available_ports() else {
+            return HashMap::new();
         };
+        probe_ports(&candidate_ports(ports, skip), cancel)
+    }
 
-        let mut index: usize = 0;
-        loop {
-            let node_id_str = format!("{:02}", index);
-            let cmd = format!("NN:{}\r", node_id_str);
-            let _ = self.net.send(cmd.as_bytes());
-            std::thread::sleep(Duration::from_millis(10));
-
-            let resp = self.net.receive();
-            if resp.is_empty() || resp.contains("!Node Not Found!") {
-                // No response or node not found: stop scanning
-                break;
-            }
+    /// Discover every port, trying the ports cached in ~/.fast/devices.json first.
+    ///
+    /// The cached ports are used when the same ports are present and each cached FAST
+    /// port still answers with its protocol; only those ports are probed. Otherwise all
+    /// candidates are probed and the cache is rewritten.
+    fn discover_cached_ports(cancel: &CancelToken) -> HashMap<String, Protocol> {
+        use crate::device_cache::{self, DeviceCache};
 
-            if let Some(info) = parse_nn_response(&resp) {
-                results.insert(index, info);
+        let Ok(ports) = available_ports() else {
+            return HashMap::new();
+        };
+        let candidates = candidate_ports(ports, &[]);
+        if let Some(cached) = device_cache::load().and_then(|c| c.resolve(&candidates)) {
+            let names: Vec<SerialPortInfo> = candidates
+                .iter()
+                .filter(|p| cached.contains_key(&p.port_name))
+                .cloned()
+                .collect();
+            if probe_ports(&names, cancel) == cached {
+                log::debug!("Using cached ports {:?}", cached);
+                return cached;
             }
+            log::debug!("Cached ports did not answer as before; discovering again");
+        }
 
-            index += 1;
-            // Be gentle on the bus
-            std::thread::sleep(Duration::from_millis(5));
+        let ids = probe_ports(&candidates, cancel);
+        let found = |protocol| ids.values().any(|p| *p == protocol);
+        // A cancelled discovery may have skipped some of the ports
+        if found(Protocol::NET) && found(Protocol::EXP) && !cancel.is_cancelled() {
+            device_cache::save(&DeviceCache::new(&candidates, &ids));
         }
+        ids
+    }
+}
+
+/// The node boards on the NET loop and the controller itself (see
+/// `FastPinballMonitor::list_connected_net_boards`).
+pub(crate) fn list_net_boards(net: &mut NetProtocol) -> HashMap<usize, NetBoardInfo> {
+    let mut results: HashMap<usize, NetBoardInfo> = HashMap::new();
+
+    // Also query the Neuron controller directly via ID:\r to get its own info
+    let controller_info = net.id().map(|id| (id.board, id.version));
+
+    for (i, info) in net.list_nodes().into_iter().enumerate() {
+        results.insert(i, info);
+    }
+    let index = results.len();
+
+    // Add the Neuron controller (from ID:) as its own entry, without overriding NN data
+    if let Some((board, version)) = controller_info {
+        let neuron_info = NetBoardInfo {
+            node_id: "NC".to_string(),
+            node_name: board,
+            firmware: version,
+            driver_count: None,
+            switch_count: None,
+            extra_fields: Vec::new(),
+        };
+        // Use the next available index so we don't collide with NN-reported nodes
+        results.insert(index, neuron_info);
+    }
+
+    crate::metrics::set_board_count("net", results.len());
+    results
+}
+
+/// Whether a board answers `ID@{address}:` on `exp`.
+pub(crate) fn answers_at(exp: &mut ExpProtocol, address_hex: &str) -> bool {
+    let _ = exp.receive();
+    exp.send(format!("ID@{}:\r", address_hex).into_bytes());
+    std::thread::sleep(Duration::from_millis(10));
+    IdResponse::find(&exp.receive()).is_some()
+}
+
+/// Probe every port at once; each probe is dominated by waiting on the device. Ports not
+/// probed yet are skipped once `cancel` is cancelled.
+fn probe_ports(ports: &[SerialPortInfo], cancel: &CancelToken) -> HashMap<String, Protocol> {
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = ports
+            .iter()
+            .map(|p| &p.port_name)
+            .map(|name| {
+                scope.spawn(move || {
+                    cancel.check().ok()?;
+                    probe_port(name).map(|p| (name.clone(), p))
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .filter_map(|h| h.join().ok().flatten())
+            .collect()
+    })
+}
+
+/// The first discovered port (in name order) speaking `protocol`.
+fn discovered(ids: &HashMap<String, Protocol>, protocol: Protocol) -> Option<String> {
+    ids.iter()
+        .filter(|(_, proto)| **proto == protocol)
+        .map(|(port, _)| port.clone())
+        .min()
+}
 
-        // Add the Neuron controller (from ID:) as its own entry, without overriding NN data
-        if let Some((board, version)) = controller_info.clone() {
-            let neuron_info = NetBoardInfo {
-                node_id: "NC".to_string(),
-                node_name: board,
-                firmware: version,
-                extra_fields: Vec::new(),
-            };
-            // Use the next available index so we don't collide with NN-reported nodes
-            results.insert(index, neuron_info);
+/// Open an optional port such as SEG: the pinned one, or else the discovered one. A
+/// discovered port that will not open must not keep the NET/EXP tools from working, so
+/// that only logs a warning.
+fn open_optional<T>(
+    pinned: Option<String>,
+    ids: &HashMap<String, Protocol>,
+    protocol: Protocol,
+    open: impl Fn(String) -> serialport::Result<T>,
+) -> Result<Option<T>, String> {
+    match pinned {
+        Some(port) => {
+            let label = format!("{:?}", protocol);
+            port_owner::check_free(&label, &port)?;
+            open(port.clone())
+                .map(Some)
+                .map_err(|e| port_owner::describe_open_error(&label, &port, &e))
         }
+        None => Ok(discovered(ids, protocol).and_then(|port| {
+            open(port.clone())
+                .inspect_err(|e| log::warn!("Failed to open {:?} port {}: {}", protocol, port, e))
+                .ok()
+        })),
+    }
+}
+
+/// Pick the ports worth probing, skipping pinned ones.
+///
+/// With `usb_serials` configured (usually by a profile), only ports of USB devices with
+/// those serial numbers. Otherwise prefers USB ports that look like FAST hardware (known
+/// vendor ID, or "FAST" in the USB strings); falls back to every USB port, and then to
+/// every port, when nothing matches.
+fn candidate_ports(ports: Vec<SerialPortInfo>, skip: &[String]) -> Vec<SerialPortInfo> {
+    let ports: Vec<SerialPortInfo> = ports
+        .into_iter()
+        .filter(|p| {
+            let pinned = skip.contains(&p.port_name);
+            if pinned {
+                log::debug!("Skipping pinned port {}", p.port_name);
+            }
+            !pinned
+        })
+        .collect();
+
+    let serials = &crate::config::get().usb_serials;
+    if !serials.is_empty() {
+        return ports
+            .into_iter()
+            .filter(|p| {
+                usb_serial(p).is_some_and(|s| serials.iter().any(|w| w.eq_ignore_ascii_case(s)))
+            })
+            .collect();
+    }
+
+    let usb: Vec<(&SerialPortInfo, &UsbPortInfo)> = ports
+        .iter()
+        .filter_map(|p| match &p.port_type {
+            SerialPortType::UsbPort(info) => Some((p, info)),
+            _ => None,
+        })
+        .collect();
+    let fast: Vec<SerialPortInfo> = usb
+        .iter()
+        .filter(|(_, info)| looks_fast(info))
+        .map(|(p, _)| (*p).clone())
+        .collect();
+    if !fast.is_empty() {
+        return fast;
+    }
+    if !usb.is_empty() {
+        log::debug!("No ports matched FAST USB IDs; probing all USB serial ports");
+        return usb.iter().map(|(p, _)| (*p).clone()).collect();
+    }
+    log::debug!("No USB serial ports found; probing every serial port");
+    ports
+}
+
+/// Whether a USB device looks like FAST hardware: a known vendor ID, or "FAST" in its
+/// USB strings.
+fn looks_fast(info: &UsbPortInfo) -> bool {
+    use crate::constants::FAST_USB_VENDOR_IDS;
 
-        results
-    }
-
-    fn discover_protocol_ports() -> HashMap<String, Protocol> {
-        let mut results: HashMap<String, Protocol> = HashMap::new();
-        match available_ports() {
-            Ok(ports) => {
-                for port in ports {
-                    if let Ok(mut serial_port) = serialport::new(port.port_name.clone(), 921_600)
-                        .data_bits(DataBits::Eight)
-                        .parity(Parity::None)
-                        .stop_bits(StopBits::One)
-                        .dtr_on_open(true)
-                        .flow_control(FlowControl::None)
-                        .timeout(Duration::from_millis(5))
-                        .open()
-                    {
-                        // Try to identify the device by sending the ID command
-                        let _ = serial_port.write_all(b"ID:\r");
-                        // Give the device a moment to respond
-                        std::thread::sleep(Duration::from_millis(5));
-
-                        let mut buf_bytes = [0u8; 256];
-                        let mut collected = Vec::new();
-                        loop {
-                            match serial_port.read(&mut buf_bytes) {
-                                Ok(0) => break,
-                                Ok(n) => {
-                                    collected.extend_from_slice(&buf_bytes[..n]);
-                                    if collected.len() >= 256 {
-                                        break;
-                                    }
-                                }
-                                Err(e) => {
-                                    let kind = e.kind();
-                                    if kind == std::io::ErrorKind::WouldBlock
-                                        || kind == std::io::ErrorKind::TimedOut
-                                    {
-                                        break;
-                                    } else {
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                        if !collected.is_empty() {
-                            let s = String::from_utf8_lossy(&collected).trim().to_string();
-                            if let Some(proto) = parse_protocol(&s) {
-                                results.insert(port.port_name.clone(), proto);
-                            }
-                        }
-                    }
+    let mentions_fast = |s: &Option<String>| {
+        s.as_deref()
+            .map(|s| s.to_ascii_uppercase().contains("FAST"))
+            .unwrap_or(false)
+    };
+    FAST_USB_VENDOR_IDS.contains(&info.vid)
+        || mentions_fast(&info.manufacturer)
+        || mentions_fast(&info.product)
+}
+
+fn usb_serial(port: &SerialPortInfo) -> Option<&str> {
+    match &port.port_type {
+        SerialPortType::UsbPort(info) => info.serial_number.as_deref(),
+        _ => None,
+    }
+}
+
+/// When discovery is limited to `usb_serials`, which serial numbers the connected FAST
+/// devices have, so a profile can be corrected.
+fn usb_serials_hint() -> Option<String> {
+    let config = crate::config::get();
+    if config.usb_serials.is_empty() {
+        return None;
+    }
+    let mut connected: Vec<String> = available_ports()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|p| matches!(&p.port_type, SerialPortType::UsbPort(info) if looks_fast(info)))
+        .filter_map(|p| usb_serial(&p).map(|s| format!("{} ({})", s, p.port_name)))
+        .collect();
+    connected.sort();
+    let wanted = match config.profile.as_deref() {
+        Some(profile) => format!("profile {}", profile),
+        None => "usb_serials".to_string(),
+    };
+    Some(if connected.is_empty() {
+        format!(
+            "Only looked for USB serial numbers {} ({}); no FAST USB devices are connected.",
+            config.usb_serials.join(", "),
+            wanted
+        )
+    } else {
+        format!(
+            "Only looked for USB serial numbers {} ({}); connected FAST devices: {}.",
+            config.usb_serials.join(", "),
+            wanted,
+            connected.join(", ")
+        )
+    })
+}
+
+/// Send `ID:` to a port and classify the reply by protocol (NET, EXP, SEG, AUD or DSP).
+fn probe_port(port_name: &str) -> Option<Protocol> {
+    // Probing a port another program uses would answer its requests in its place
+    let owners = port_owner::owners(port_name);
+    if !owners.is_empty() {
+        log::debug!("Skipping {}: in use by {:?}", port_name, owners);
+        return None;
+    }
+    let serial_port = serialport::new(port_name, 921_600)
+        .data_bits(DataBits::Eight)
+        .parity(Parity::None)
+        .stop_bits(StopBits::One)
+        .dtr_on_open(true)
+        .flow_control(FlowControl::None)
+        .timeout(crate::config::get().timeouts.exp_read())
+        .open()
+        .ok()?;
+    let mut serial_port = crate::capture::wrap(port_name, Box::new(serial_port));
+    // Try to identify the device by sending the ID command
+    log::debug!("Probing {} with ID:", port_name);
+    let _ = serial_port.write_all(b"ID:\r");
+    // Give the device a moment to respond
+    std::thread::sleep(Duration::from_millis(5));
+
+    let mut buf_bytes = [0u8; 256];
+    let mut collected = Vec::new();
+    loop {
+        match serial_port.read(&mut buf_bytes) {
+            Ok(0) => break,
+            Ok(n) => {
+                collected.extend_from_slice(&buf_bytes[..n]);
+                if collected.len() >= 256 {
+                    break;
                 }
             }
-            Err(_) => {}
+            // WouldBlock/TimedOut mean the device has nothing more to say
+            Err(_) => break,
         }
-        results
     }
+    if collected.is_empty() {
+        return None;
+    }
+    let s = String::from_utf8_lossy(&collected).trim().to_string();
+    log::debug!("Probe {} RX {:?}", port_name, s);
+    parse_protocol(&s)
+}
+
+/// Longest wait for a board's `ID@xx:` reply, used until a board has answered.
+const SCAN_REPLY_TIMEOUT: Duration = Duration::from_millis(20);
+/// Board names of controllers; their built-in EXP answers with the controller's name.
+const CONTROLLER_BOARD_PREFIX: &str = "FP-CPU-";
+/// Breakout sub-addresses behind each EXP board.
+const BREAKOUTS_PER_BOARD: u8 = 4;
+/// Shortest wait per address, however quickly boards answer.
+const SCAN_MIN_WAIT: Duration = Duration::from_millis(3);
+
+/// Query every address in the EXP address map (see `board_map`) with `ID@xx:`.
+///
+/// Replies do not name the address they answer, so queries go out one at a time. The
+/// wait for each reply adapts to how quickly boards answer (a few times the slowest
+/// reply seen), which keeps the absent addresses cheap. An unanswered query may still
+/// get a late reply while a later one is waiting, so a reply whose board type matches
+/// such a query is not trusted: the scan lets stragglers arrive and asks each of the
+/// candidates again with the full wait.
+pub(crate) fn scan_exp_port(exp: &mut ExpProtocol) -> Vec<ExpBoardInfo> {
+    let map = crate::board_map::get();
+
+    // Drain any pending bytes before we start
+    let _ = exp.receive();
+
+    let mut results: Vec<ExpBoardInfo> = Vec::new();
+    let mut wait = SCAN_REPLY_TIMEOUT;
+    let mut slowest = Duration::ZERO;
+    // Unanswered queries a slow board could still reply to: (address, board type, sent)
+    let mut outstanding: Vec<(&str, &str, Instant)> = Vec::new();
+    for (addr, board_type) in map.exp_boards() {
+        if exp.cancel.is_cancelled() {
+            break;
+        }
+        let (addr, board_type) = (addr.as_str(), board_type.as_str());
+        outstanding.retain(|(_, _, sent)| sent.elapsed() < SCAN_REPLY_TIMEOUT);
+        let sent = Instant::now();
+        let resp = query_exp_id(exp, addr, wait);
+        let Some(IdResponse { board, .. }) = IdResponse::find(&resp) else {
+            outstanding.push((addr, board_type, sent));
+            continue;
+        };
+        let mut candidates: Vec<(&str, &str)> = outstanding
+            .iter()
+            .filter(|(_, t, _)| board.is_empty() || board == *t)
+            .map(|&(a, t, _)| (a, t))
+            .collect();
+        if candidates.is_empty() {
+            slowest = slowest.max(sent.elapsed());
+            wait = (slowest * 4).clamp(SCAN_MIN_WAIT, SCAN_REPLY_TIMEOUT);
+            results.extend(exp_board_info(&exp.port_name, addr, board_type, &resp));
+            continue;
+        }
+        outstanding.retain(|(a, _, _)| !candidates.iter().any(|(c, _)| c == a));
+        candidates.push((addr, board_type));
+        if recheck_exp_ids(exp, &candidates, &mut results) {
+            wait = SCAN_REPLY_TIMEOUT;
+            slowest = SCAN_REPLY_TIMEOUT;
+        }
+    }
+    // Give the queries still outstanding at the end their full time
+    if let Some(&(_, _, sent)) = outstanding.last() {
+        let resp = exp.receive_within(SCAN_REPLY_TIMEOUT.saturating_sub(sent.elapsed()));
+        if let Some(IdResponse { board, .. }) = IdResponse::find(&resp) {
+            let candidates: Vec<(&str, &str)> = outstanding
+                .iter()
+                .filter(|(_, t, _)| board.is_empty() || board == *t)
+                .map(|&(a, t, _)| (a, t))
+                .collect();
+            recheck_exp_ids(exp, &candidates, &mut results);
+        }
+    }
+
+    // Rechecks can add boards out of order
+    results.sort_by_key(|b| map.exp_position(&b.address));
+    for board in results.iter_mut() {
+        board.breakouts = scan_breakouts(exp, board, wait);
+    }
+    results
+}
+
+/// Query all 256 addresses of one EXP interface (see `deep_scan_exp_boards`).
+fn deep_scan_exp_port(exp: &mut ExpProtocol) -> ExpDeepScan {
+    let map = crate::board_map::get();
+    let _ = exp.receive();
+
+    let mut found = ExpDeepScan::default();
+    for address in 0..=u8::MAX {
+        if exp.cancel.is_cancelled() {
+            break;
+        }
+        let addr = format!("{:02X}", address);
+        let resp = query_exp_id(exp, &addr, SCAN_REPLY_TIMEOUT);
+        let response = resp
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if response.is_empty() {
+            continue;
+        }
+        // A mapped address that does not answer with an ID (e.g. a bootloader banner)
+        // is reported as it answered
+        match map
+            .exp_board_type(&addr)
+            .and_then(|board_type| exp_board_info(&exp.port_name, &addr, board_type, &resp))
+        {
+            Some(mut board) => {
+                board.breakouts = scan_breakouts(exp, &board, SCAN_REPLY_TIMEOUT);
+                found.boards.push(board);
+            }
+            None => found.unknown.push(UnknownExpResponder {
+                port: exp.port_name.clone(),
+                address: addr,
+                response,
+            }),
+        }
+    }
+    found
+}
+
+/// Send `ID@{addr}:` and wait up to `wait` for the reply.
+fn query_exp_id(exp: &mut ExpProtocol, addr: &str, wait: Duration) -> String {
+    exp.send(format!("ID@{}:\r", addr).into_bytes());
+    exp.receive_within(wait)
+}
+
+/// Let late replies arrive, then query each of `candidates` again with the full wait.
+/// Returns true if a late reply did turn up, i.e. some board answers slowly.
+fn recheck_exp_ids(
+    exp: &mut ExpProtocol,
+    candidates: &[(&str, &str)],
+    results: &mut Vec<ExpBoardInfo>,
+) -> bool {
+    std::thread::sleep(SCAN_REPLY_TIMEOUT);
+    let late = !exp.receive().is_empty();
+    if late {
+        log::debug!(
+            "EXP {} late ID reply; rechecking {:?}",
+            exp.port_name,
+            candidates
+        );
+    }
+    for &(addr, board_type) in candidates {
+        let resp = query_exp_id(exp, addr, SCAN_REPLY_TIMEOUT);
+        results.extend(exp_board_info(&exp.port_name, addr, board_type, &resp));
+    }
+    late
+}
+
+/// Board info from an `ID@{addr}:` reply, with the firmware versions installed for it.
+fn exp_board_info(port: &str, addr: &str, board_type: &str, resp: &str) -> Option<ExpBoardInfo> {
+    let IdResponse {
+        protocol: proto,
+        board,
+        version,
+    } = IdResponse::find(resp)?;
+    let board_name = if board.is_empty() {
+        board_type.to_string()
+    } else {
+        board
+    };
+    // Translate the available firmware (version -> path) into a list of versions
+    let repo = crate::firmware_repo::get();
+    let versions_from_repo =
+        |k: &str| -> Option<Vec<String>> { Some(repo.versions(k)).filter(|v| !v.is_empty()) };
+    let available_versions = versions_from_repo(&format!("{}_{}", board_name, proto))
+        .or_else(|| versions_from_repo(&format!("{}_{}", board_type, proto)));
+    Some(ExpBoardInfo {
+        port: port.to_string(),
+        address: addr.to_string(),
+        is_controller: board_name.starts_with(CONTROLLER_BOARD_PREFIX),
+        board_name,
+        version,
+        available_versions,
+        breakouts: Vec::new(),
+    })
+}
+
+/// Query the breakout sub-addresses (`ID@{address}0:` to `ID@{address}3:`) of `board`,
+/// waiting up to `wait` for each reply. Breakout 0 answering with the board's own ID is
+/// the board itself and is left out.
+fn scan_breakouts(
+    exp: &mut ExpProtocol,
+    board: &ExpBoardInfo,
+    wait: Duration,
+) -> Vec<ExpBreakoutInfo> {
+    let mut breakouts = Vec::new();
+    for index in 0..BREAKOUTS_PER_BOARD {
+        if exp.cancel.is_cancelled() {
+            break;
+        }
+        let address = format!("{}{}", board.address, index);
+        let Some(id) = IdResponse::find(&query_exp_id(exp, &address, wait)) else {
+            continue;
+        };
+        if index == 0 && id.board == board.board_name && id.version == board.version {
+            continue;
+        }
+        breakouts.push(ExpBreakoutInfo {
+            address,
+            index,
+            board_name: id.board,
+            version: id.version,
+        });
+    }
+    breakouts
 }
 
 fn parse_protocol(resp: &str) -> Option<Protocol> {
@@ -236,51 +988,9 @@ fn parse_protocol(resp: &str) -> Option<Protocol> {
     match token.as_str() {
         "NET" => Some(Protocol::NET),
         "EXP" => Some(Protocol::EXP),
+        "SEG" => Some(Protocol::SEG),
+        "AUD" => Some(Protocol::AUD),
+        "DSP" => Some(Protocol::DSP),
         _ => None,
     }
 }
-
-fn parse_id_response(resp: &str) -> Option<(String, String, String)> {
-    // Expected formats:
-    // "ID:{Protocol} {BoardName} {Version}"
-    // Be tolerant of commas after the protocol token (e.g., "ID:EXP, FP-EXP-0091 v0.48")
-    let after = resp.split_once("ID:")?.1;
-    // Normalize commas to spaces and trim
-    let normalized = after.replace(',', " ");
-    let mut parts = normalized.split_whitespace();
-    let protocol = parts.next()?.to_string();
-    let board = parts.next()?.to_string();
-    let version = parts.next()?.to_string();
-    Some((protocol, board, version))
-}
-
-fn parse_nn_response(resp: &str) -> Option<NetBoardInfo> {
-    // Find the last occurrence of an NN: response within the buffer
-    let idx = resp.rfind("NN:")?;
-    let after = &resp[idx + 3..];
-
-    // Take until end of line or whole remainder
-    let line = after.lines().next().unwrap_or(after).trim();
-
-    // Split by commas into fields
-    let parts: Vec<&str> = line.split(',').map(|s| s.trim()).collect();
-    if parts.len() < 3 {
-        return None;
-    }
-
-    let node_id = parts[0].to_string();
-    let node_name = parts[1].to_string();
-    let firmware = parts[2].to_string();
-    let extra_fields = if parts.len() > 3 {
-        parts[3..].iter().map(|s| s.to_string()).collect()
-    } else {
-        Vec::new()
-    };
-
-    Some(NetBoardInfo {
-        node_id,
-        node_name,
-        firmware,
-        extra_fields,
-    })
-}
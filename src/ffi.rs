@@ -0,0 +1,513 @@
+// A C ABI over the library (the `ffi` feature), so C, C++ and C# tools can discover boards
+// and flash firmware without reimplementing the protocol. include/fast_pinball.h declares
+// everything here.
+//
+// Strings are UTF-8 and NUL-terminated. Functions that fail return NULL or a negative code
+// and leave a message for `fast_last_error`. Board lists are allocated here and must be
+// released with the matching `fast_free_*` function; a monitor with `fast_disconnect`.
+
+use crate::commands::utils::{expected_version_for_file, parse_hex_address};
+use crate::{FastPinballMonitor, FlashEvent, FlashOutcome, PinnedPorts};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::ptr;
+
+/// Returned by listings that succeeded.
+pub const FAST_OK: c_int = 0;
+/// Returned when a call fails before anything was sent; see `fast_last_error`.
+pub const FAST_ERROR: c_int = -1;
+
+/// How a flash ended, as returned by `fast_flash_exp` and `fast_flash_net`.
+pub const FAST_FLASH_VERIFIED: c_int = 0;
+pub const FAST_FLASH_UNVERIFIED: c_int = 1;
+pub const FAST_FLASH_MISMATCH: c_int = 2;
+pub const FAST_FLASH_FAILED: c_int = 3;
+pub const FAST_FLASH_CANCELLED: c_int = 4;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An EXP board, as listed by `fast_list_exp_boards`.
+#[repr(C)]
+pub struct FastExpBoard {
+    pub port: *mut c_char,
+    pub address: *mut c_char,
+    pub board_name: *mut c_char,
+    pub version: *mut c_char,
+    pub is_controller: bool,
+}
+
+/// A NET node, as listed by `fast_list_net_boards`.
+#[repr(C)]
+pub struct FastNetBoard {
+    pub node_id: *mut c_char,
+    pub node_name: *mut c_char,
+    pub firmware: *mut c_char,
+    /// -1 when the node does not report it.
+    pub switch_count: c_int,
+    /// -1 when the node does not report it.
+    pub driver_count: c_int,
+}
+
+/// The message of the last failed call on this thread, or NULL. Valid until the next
+/// call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn fast_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Connect to the FAST hardware. `net_port` and `exp_port` pin the ports (e.g. `COM3`);
+/// either may be NULL to discover it. Returns NULL when the hardware is not found.
+///
+/// # Safety
+///
+/// `net_port` and `exp_port` must each be NULL or a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fast_connect(
+    net_port: *const c_char,
+    exp_port: *const c_char,
+) -> *mut FastPinballMonitor {
+    // SAFETY: the caller passes NULL or NUL-terminated strings
+    let (net, exp) = match unsafe { (opt_str(net_port), opt_str(exp_port)) } {
+        (Ok(net), Ok(exp)) => (net, exp),
+        (Err(e), _) | (_, Err(e)) => return fail(e, ptr::null_mut()),
+    };
+    let pins = PinnedPorts {
+        net,
+        exp: exp.into_iter().collect(),
+        ..Default::default()
+    };
+    guard(
+        ptr::null_mut(),
+        || match FastPinballMonitor::connect_with_ports(pins) {
+            Ok(fpm) => Box::into_raw(Box::new(fpm)),
+            Err(e) => fail(e, ptr::null_mut()),
+        },
+    )
+}
+
+/// Close the ports of a monitor from `fast_connect` and free it. NULL is ignored.
+///
+/// # Safety
+///
+/// `fpm` must be NULL or a monitor from `fast_connect` that was not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fast_disconnect(fpm: *mut FastPinballMonitor) {
+    if !fpm.is_null() {
+        // SAFETY: the caller passes a monitor from `fast_connect`, once
+        drop(unsafe { Box::from_raw(fpm) });
+    }
+}
+
+/// List the EXP boards into `*boards` (`*count` of them). Returns `FAST_OK`, or
+/// `FAST_ERROR` leaving both untouched. Free the list with `fast_free_exp_boards`.
+///
+/// # Safety
+///
+/// `fpm` must be a monitor from `fast_connect`; `boards` and `count` must be valid for
+/// writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fast_list_exp_boards(
+    fpm: *mut FastPinballMonitor,
+    boards: *mut *mut FastExpBoard,
+    count: *mut usize,
+) -> c_int {
+    // SAFETY: the caller passes a live monitor
+    let Some(fpm) = (unsafe { fpm.as_mut() }) else {
+        return fail("monitor is NULL", FAST_ERROR);
+    };
+    if boards.is_null() || count.is_null() {
+        return fail("output pointer is NULL", FAST_ERROR);
+    }
+    let list = guard(None, || Some(fpm.list_connected_exp_boards()));
+    let Some(list) = list else {
+        return FAST_ERROR;
+    };
+    let list: Vec<FastExpBoard> = list
+        .into_iter()
+        .map(|b| FastExpBoard {
+            port: c_string(&b.port),
+            address: c_string(&b.address),
+            board_name: c_string(&b.board_name),
+            version: c_string(&b.version),
+            is_controller: b.is_controller,
+        })
+        .collect();
+    // SAFETY: checked for NULL above
+    unsafe {
+        *count = list.len();
+        *boards = Box::into_raw(list.into_boxed_slice()) as *mut FastExpBoard;
+    }
+    FAST_OK
+}
+
+/// Free a list from `fast_list_exp_boards`. NULL is ignored.
+///
+/// # Safety
+///
+/// `boards` and `count` must be exactly as returned by `fast_list_exp_boards`, and the
+/// list not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fast_free_exp_boards(boards: *mut FastExpBoard, count: usize) {
+    // SAFETY: the caller passes a list from `fast_list_exp_boards`, once
+    for b in unsafe { take_list(boards, count) } {
+        // SAFETY: every field came from `c_string`
+        unsafe { free_strings(&[b.port, b.address, b.board_name, b.version]) };
+    }
+}
+
+/// List the NET nodes, ordered by node, into `*boards` (`*count` of them). Returns
+/// `FAST_OK`, or `FAST_ERROR` leaving both untouched. Free the list with
+/// `fast_free_net_boards`.
+///
+/// # Safety
+///
+/// `fpm` must be a monitor from `fast_connect`; `boards` and `count` must be valid for
+/// writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fast_list_net_boards(
+    fpm: *mut FastPinballMonitor,
+    boards: *mut *mut FastNetBoard,
+    count: *mut usize,
+) -> c_int {
+    // SAFETY: the caller passes a live monitor
+    let Some(fpm) = (unsafe { fpm.as_mut() }) else {
+        return fail("monitor is NULL", FAST_ERROR);
+    };
+    if boards.is_null() || count.is_null() {
+        return fail("output pointer is NULL", FAST_ERROR);
+    }
+    let nodes = guard(None, || Some(fpm.list_connected_net_boards()));
+    let Some(nodes) = nodes else {
+        return FAST_ERROR;
+    };
+    let mut nodes: Vec<_> = nodes.into_iter().collect();
+    nodes.sort_by_key(|(index, _)| *index);
+    let count_or_none = |c: Option<u16>| c.map_or(-1, c_int::from);
+    let list: Vec<FastNetBoard> = nodes
+        .into_iter()
+        .map(|(_, n)| FastNetBoard {
+            node_id: c_string(&n.node_id),
+            node_name: c_string(&n.node_name),
+            firmware: c_string(&n.firmware),
+            switch_count: count_or_none(n.switch_count),
+            driver_count: count_or_none(n.driver_count),
+        })
+        .collect();
+    // SAFETY: checked for NULL above
+    unsafe {
+        *count = list.len();
+        *boards = Box::into_raw(list.into_boxed_slice()) as *mut FastNetBoard;
+    }
+    FAST_OK
+}
+
+/// Free a list from `fast_list_net_boards`. NULL is ignored.
+///
+/// # Safety
+///
+/// `boards` and `count` must be exactly as returned by `fast_list_net_boards`, and the
+/// list not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fast_free_net_boards(boards: *mut FastNetBoard, count: usize) {
+    // SAFETY: the caller passes a list from `fast_list_net_boards`, once
+    for b in unsafe { take_list(boards, count) } {
+        // SAFETY: every field came from `c_string`
+        unsafe { free_strings(&[b.node_id, b.node_name, b.firmware]) };
+    }
+}
+
+/// Flash the firmware file `file` into the EXP board at `address` (hex, e.g. `B4`).
+/// The board must then report `expected_version`; when that is NULL, the version in
+/// the file name. Returns a `FAST_FLASH_*` outcome, or `FAST_ERROR`.
+///
+/// # Safety
+///
+/// `fpm` must be a monitor from `fast_connect`; `address` and `file` NUL-terminated
+/// strings; `expected_version` NULL or a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fast_flash_exp(
+    fpm: *mut FastPinballMonitor,
+    address: *const c_char,
+    file: *const c_char,
+    expected_version: *const c_char,
+) -> c_int {
+    // SAFETY: the caller passes a live monitor and NULL or NUL-terminated strings
+    let (fpm, address, file, expected) = unsafe {
+        (
+            fpm.as_mut(),
+            opt_str(address),
+            opt_str(file),
+            opt_str(expected_version),
+        )
+    };
+    let Some(fpm) = fpm else {
+        return fail("monitor is NULL", FAST_ERROR);
+    };
+    let (address, file, expected) = match (address, file, expected) {
+        (Ok(Some(address)), Ok(Some(file)), Ok(expected)) => (address, file, expected),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => return fail(e, FAST_ERROR),
+        _ => return fail("address and file are required", FAST_ERROR),
+    };
+    let address = match parse_hex_address(&address) {
+        Ok(address) => address,
+        Err(e) => return fail(e, FAST_ERROR),
+    };
+    guard(FAST_ERROR, || {
+        let expected = expected_version_for_file(&file, expected.as_deref());
+        match fpm.exp_port_for_address(&address) {
+            Some(exp) => {
+                outcome_code(exp.flash_file_with(&address, &file, expected.as_deref(), &mut silent))
+            }
+            None => fail(
+                format!("no EXP board answers at address {}", address),
+                FAST_ERROR,
+            ),
+        }
+    })
+}
+
+/// Flash the firmware file `file` into the NET processor, like `fast_flash_exp`.
+///
+/// # Safety
+///
+/// `fpm` must be a monitor from `fast_connect`; `file` a NUL-terminated string;
+/// `expected_version` NULL or a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fast_flash_net(
+    fpm: *mut FastPinballMonitor,
+    file: *const c_char,
+    expected_version: *const c_char,
+) -> c_int {
+    // SAFETY: the caller passes a live monitor and NULL or NUL-terminated strings
+    let (fpm, file, expected) = unsafe { (fpm.as_mut(), opt_str(file), opt_str(expected_version)) };
+    let Some(fpm) = fpm else {
+        return fail("monitor is NULL", FAST_ERROR);
+    };
+    let (file, expected) = match (file, expected) {
+        (Ok(Some(file)), Ok(expected)) => (file, expected),
+        (Err(e), _) | (_, Err(e)) => return fail(e, FAST_ERROR),
+        _ => return fail("file is required", FAST_ERROR),
+    };
+    guard(FAST_ERROR, || {
+        let expected = expected_version_for_file(&file, expected.as_deref());
+        outcome_code(
+            fpm.net
+                .flash_file_with(&file, expected.as_deref(), &mut silent),
+        )
+    })
+}
+
+// Flashes draw nothing: the CLI's progress bar would write into the host's terminal
+fn silent(_: &FlashEvent) {}
+
+fn outcome_code(outcome: FlashOutcome) -> c_int {
+    match outcome {
+        FlashOutcome::Verified => FAST_FLASH_VERIFIED,
+        FlashOutcome::Unverified => FAST_FLASH_UNVERIFIED,
+        FlashOutcome::Mismatch => FAST_FLASH_MISMATCH,
+        FlashOutcome::Failed => FAST_FLASH_FAILED,
+        FlashOutcome::Cancelled => FAST_FLASH_CANCELLED,
+    }
+}
+
+// Remember `message` for `fast_last_error` and return `value`
+fn fail<T>(message: impl Into<String>, value: T) -> T {
+    let message = message.into().replace('\0', " ");
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(message).ok());
+    value
+}
+
+// Run `f`, turning a panic into a failure instead of unwinding into C
+fn guard<T>(fallback: T, f: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        fail(format!("internal error: {}", message), fallback)
+    })
+}
+
+// SAFETY: `s` must be NULL or a NUL-terminated string
+unsafe fn opt_str(s: *const c_char) -> Result<Option<String>, String> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    // SAFETY: non-NULL and NUL-terminated, per the caller
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map(|s| Some(s.to_string()))
+        .map_err(|_| "string argument is not UTF-8".to_string())
+}
+
+fn c_string(s: &str) -> *mut c_char {
+    CString::new(s.replace('\0', ""))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+// SAFETY: `list` must be NULL, or a boxed slice of `count` items leaked by a listing
+unsafe fn take_list<T>(list: *mut T, count: usize) -> Vec<T> {
+    if list.is_null() {
+        return Vec::new();
+    }
+    // SAFETY: per the caller
+    unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(list, count)) }.into_vec()
+}
+
+// SAFETY: every pointer must come from `c_string` and not be freed yet
+unsafe fn free_strings(strings: &[*mut c_char]) {
+    for s in strings.iter().filter(|s| !s.is_null()) {
+        // SAFETY: per the caller
+        drop(unsafe { CString::from_raw(*s) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::exp_protocol::ExpProtocol;
+    use crate::protocol::mock::MockTransport;
+    use crate::protocol::net_protocol::NetProtocol;
+
+    fn monitor(net: &MockTransport, exp: &MockTransport) -> *mut FastPinballMonitor {
+        let net = NetProtocol::with_transport("net".into(), Box::new(net.clone()));
+        let exp = ExpProtocol::with_transport("exp".into(), Box::new(exp.clone()));
+        Box::into_raw(Box::new(FastPinballMonitor::with_protocols(
+            net,
+            vec![exp],
+            None,
+            None,
+            None,
+        )))
+    }
+
+    fn last_error() -> String {
+        let e = fast_last_error();
+        assert!(!e.is_null());
+        // SAFETY: a message from `fail`, valid until the next call on this thread
+        unsafe { CStr::from_ptr(e) }.to_string_lossy().into_owned()
+    }
+
+    // SAFETY: `s` must be a string from `c_string`
+    unsafe fn string(s: *mut c_char) -> String {
+        // SAFETY: per the caller
+        unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn exp_boards_are_listed_and_freed() {
+        let exp = MockTransport::new().respond("ID@B4:", "ID:EXP FP-EXP-0071 0.48\r");
+        let fpm = monitor(&MockTransport::new(), &exp);
+        let mut boards = ptr::null_mut();
+        let mut count = 0;
+        // SAFETY: a live monitor and valid output pointers
+        let code = unsafe { fast_list_exp_boards(fpm, &mut boards, &mut count) };
+        assert_eq!(code, FAST_OK);
+        assert_eq!(count, 1);
+        // SAFETY: `boards` holds `count` boards from the listing
+        unsafe {
+            let board = &*boards;
+            assert_eq!(string(board.port), "exp");
+            assert_eq!(string(board.address), "B4");
+            assert_eq!(string(board.board_name), "FP-EXP-0071");
+            assert_eq!(string(board.version), "0.48");
+            assert!(!board.is_controller);
+            fast_free_exp_boards(boards, count);
+            fast_disconnect(fpm);
+        }
+    }
+
+    #[test]
+    fn net_boards_are_listed_and_freed() {
+        let net = MockTransport::new()
+            .respond("NN:00", "NN:00,FP-I/O-3208,1.05,08,20\r")
+            .respond("NN:01", "!Node Not Found!\r");
+        let fpm = monitor(&net, &MockTransport::new());
+        let mut boards = ptr::null_mut();
+        let mut count = 0;
+        // SAFETY: a live monitor and valid output pointers
+        let code = unsafe { fast_list_net_boards(fpm, &mut boards, &mut count) };
+        assert_eq!(code, FAST_OK);
+        assert_eq!(count, 1);
+        // SAFETY: `boards` holds `count` nodes from the listing
+        unsafe {
+            let node = &*boards;
+            assert_eq!(string(node.node_id), "00");
+            assert_eq!(string(node.node_name), "FP-I/O-3208");
+            assert_eq!(string(node.firmware), "1.05");
+            assert_eq!(node.driver_count, 8);
+            assert_eq!(node.switch_count, 32);
+            fast_free_net_boards(boards, count);
+            fast_disconnect(fpm);
+        }
+    }
+
+    #[test]
+    fn null_arguments_fail_without_touching_the_hardware() {
+        let exp = MockTransport::new();
+        let fpm = monitor(&MockTransport::new(), &exp);
+        let mut boards: *mut FastExpBoard = ptr::null_mut();
+        let mut count = 7;
+        // SAFETY: NULL where the API allows callers to pass it by mistake
+        unsafe {
+            assert_eq!(
+                fast_list_exp_boards(ptr::null_mut(), &mut boards, &mut count),
+                FAST_ERROR
+            );
+            assert_eq!(last_error(), "monitor is NULL");
+            assert_eq!(
+                fast_list_net_boards(fpm, ptr::null_mut(), &mut count),
+                FAST_ERROR
+            );
+            assert_eq!(last_error(), "output pointer is NULL");
+            assert_eq!(
+                fast_flash_exp(fpm, ptr::null(), c"fw.txt".as_ptr(), ptr::null()),
+                FAST_ERROR
+            );
+            assert_eq!(last_error(), "address and file are required");
+            assert_eq!(fast_flash_net(fpm, ptr::null(), ptr::null()), FAST_ERROR);
+            assert_eq!(last_error(), "file is required");
+            fast_free_exp_boards(ptr::null_mut(), 0);
+            fast_free_net_boards(ptr::null_mut(), 0);
+            fast_disconnect(ptr::null_mut());
+            fast_disconnect(fpm);
+        }
+        assert!(boards.is_null());
+        assert_eq!(count, 7);
+        assert!(exp.commands().is_empty());
+    }
+
+    #[test]
+    fn last_error_is_per_thread() {
+        std::thread::spawn(|| {
+            assert!(fast_last_error().is_null());
+            // SAFETY: a NUL-terminated address, a NULL file
+            let code = unsafe {
+                fast_flash_exp(ptr::null_mut(), c"B4".as_ptr(), ptr::null(), ptr::null())
+            };
+            assert_eq!(code, FAST_ERROR);
+            assert_eq!(last_error(), "monitor is NULL");
+        })
+        .join()
+        .unwrap();
+        std::thread::spawn(|| assert!(fast_last_error().is_null()))
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn bad_address_is_reported() {
+        let fpm = monitor(&MockTransport::new(), &MockTransport::new());
+        // SAFETY: a live monitor and NUL-terminated strings
+        let code = unsafe { fast_flash_exp(fpm, c"XYZ".as_ptr(), c"fw.txt".as_ptr(), ptr::null()) };
+        assert_eq!(code, FAST_ERROR);
+        assert!(!last_error().is_empty());
+        // SAFETY: the monitor from above, freed once
+        unsafe { fast_disconnect(fpm) };
+    }
+}
@@ -0,0 +1,292 @@
+// The firmware files installed in the firmware directory (~/.fast/firmware unless
+// configured otherwise), as a `FirmwareCatalog` of board, protocol and version, with each
+// file's size, modification time and, for downloaded or imported files, its source.
+//
+// Loading only scans the directory; it never downloads. The CLI loads the repo up front
+// and offers to download firmware when the directory is empty. Library users can call
+// `init` with a repo of their own, otherwise `get` scans the firmware directory on
+// first use.
+
+use crate::protocol::firmware_file::FirmwareFormat;
+use crate::version::Version;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+// Shared so a repo from `get` stays valid while `reload` swaps in a new one
+static REPO: RwLock<Option<Arc<FirmwareRepo>>> = RwLock::new(None);
+
+/// Where each installed file came from: its path relative to the firmware directory
+/// (`EXP/...`) -> the source's description, written by downloads and imports.
+pub const SOURCES_FILE: &str = "sources.json";
+
+#[derive(Debug, Clone, Default)]
+pub struct FirmwareRepo {
+    dir: PathBuf,
+    catalog: FirmwareCatalog,
+}
+
+/// One firmware file of the firmware directory.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareEntry {
+    pub board: String,
+    pub protocol: String,
+    /// `major.minor`, two-digit minor.
+    pub version: String,
+    pub path: String,
+    pub format: FirmwareFormat,
+    /// Size in bytes (0 if the file could not be read).
+    pub size: u64,
+    /// Last modified, RFC 3339 (UTC).
+    pub modified: Option<String>,
+    /// Where the file was downloaded or imported from; `None` for files put there by hand.
+    pub source: Option<String>,
+}
+
+impl FirmwareEntry {
+    /// `{BoardType}_{Protocol}`, e.g. `FP-EXP-0071_EXP`.
+    pub fn key(&self) -> String {
+        format!("{}_{}", self.board, self.protocol)
+    }
+}
+
+/// Every firmware file of the firmware directory, ordered by board, protocol and then
+/// version (oldest first).
+#[derive(Debug, Clone, Default)]
+pub struct FirmwareCatalog {
+    entries: Vec<FirmwareEntry>,
+}
+
+impl FirmwareCatalog {
+    /// A catalog of `entries`, in any order.
+    pub fn new(mut entries: Vec<FirmwareEntry>) -> Self {
+        entries.sort_by(|a, b| {
+            (&a.board, &a.protocol, a.version.parse::<Version>().ok()).cmp(&(
+                &b.board,
+                &b.protocol,
+                b.version.parse::<Version>().ok(),
+            ))
+        });
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &[FirmwareEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every firmware for `board` (e.g. `FP-EXP-0071`), whatever the protocol.
+    pub fn versions_for(&self, board: &str) -> Vec<&FirmwareEntry> {
+        self.entries.iter().filter(|e| e.board == board).collect()
+    }
+
+    /// Every firmware for `board` on `protocol` (`EXP`, `NET`, ...), oldest first.
+    pub fn entries_for(&self, board: &str, protocol: &str) -> Vec<&FirmwareEntry> {
+        self.entries
+            .iter()
+            .filter(|e| e.board == board && e.protocol == protocol)
+            .collect()
+    }
+
+    /// The newest firmware for `board` on `protocol`.
+    pub fn latest_for(&self, board: &str, protocol: &str) -> Option<&FirmwareEntry> {
+        self.entries_for(board, protocol).pop()
+    }
+
+    /// One firmware version (any spelling `Version` parses, e.g. `0.5` for `0.05`).
+    pub fn get(&self, board: &str, protocol: &str, version: &str) -> Option<&FirmwareEntry> {
+        let version = version.parse::<Version>().ok()?.to_string();
+        self.entries_for(board, protocol)
+            .into_iter()
+            .find(|e| e.version == version)
+    }
+}
+
+impl FirmwareRepo {
+    /// Scan the configured firmware directory.
+    pub fn load() -> Self {
+        Self::load_from(&crate::config::firmware_dir())
+    }
+
+    /// Scan `dir`, which holds one folder per protocol (`EXP/`, `NET/`, ...) of firmware
+    /// files. A missing directory yields an empty repo.
+    pub fn load_from(dir: &Path) -> Self {
+        let sources = load_sources(dir);
+        let mut entries: Vec<FirmwareEntry> = Vec::new();
+        let folders = std::fs::read_dir(dir).into_iter().flatten().flatten();
+        for folder in folders {
+            let path = folder.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let Ok(files) = std::fs::read_dir(&path) else {
+                continue;
+            };
+            for file in files.flatten() {
+                let fpath = file.path();
+                let Some(format) = FirmwareFormat::from_path(&fpath) else {
+                    continue;
+                };
+                let Some((board, protocol, version)) = parse_firmware_filename(&fpath) else {
+                    continue;
+                };
+                // Versions are stored in their two-digit form. With several files of a
+                // version, `.txt` wins over `.hex` over `.bin`
+                let version = version.to_string();
+                if let Some(i) = entries.iter().position(|e| {
+                    e.board == board && e.protocol == protocol && e.version == version
+                }) {
+                    if entries[i].format <= format {
+                        continue;
+                    }
+                    entries.remove(i);
+                }
+                let metadata = std::fs::metadata(&fpath).ok();
+                let modified = metadata
+                    .as_ref()
+                    .and_then(|m| m.modified().ok())
+                    .and_then(|t| {
+                        let t = OffsetDateTime::from(t);
+                        t.replace_nanosecond(0).unwrap_or(t).format(&Rfc3339).ok()
+                    });
+                let relative = fpath
+                    .strip_prefix(dir)
+                    .map(|r| r.to_string_lossy().replace('\\', "/"))
+                    .unwrap_or_default();
+                entries.push(FirmwareEntry {
+                    board,
+                    protocol,
+                    version,
+                    path: fpath.to_string_lossy().to_string(),
+                    format,
+                    size: metadata.map(|m| m.len()).unwrap_or(0),
+                    modified,
+                    source: sources.get(&relative).cloned(),
+                });
+            }
+        }
+        Self {
+            dir: dir.to_path_buf(),
+            catalog: FirmwareCatalog::new(entries),
+        }
+    }
+
+    /// The directory this repo was loaded from.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// True when no firmware file was found.
+    pub fn is_empty(&self) -> bool {
+        self.catalog.is_empty()
+    }
+
+    /// The firmware files found.
+    pub fn catalog(&self) -> &FirmwareCatalog {
+        &self.catalog
+    }
+
+    /// Every version available for `key` (`{BoardType}_{Protocol}`), oldest first.
+    pub fn versions(&self, key: &str) -> Vec<String> {
+        split_key(key)
+            .map(|(board, protocol)| self.catalog.entries_for(board, protocol))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|e| e.version.clone())
+            .collect()
+    }
+
+    /// File path of one firmware version, e.g. `path("FP-CPU-2000_NET", "2.28")`.
+    pub fn path(&self, key: &str, version: &str) -> Option<&str> {
+        let (board, protocol) = split_key(key)?;
+        self.catalog
+            .get(board, protocol, version)
+            .map(|e| e.path.as_str())
+    }
+}
+
+// `FP-EXP-0071_EXP` -> (`FP-EXP-0071`, `EXP`)
+fn split_key(key: &str) -> Option<(&str, &str)> {
+    key.rsplit_once('_')
+}
+
+// The sources file of `dir`; missing or unreadable means no sources are known
+fn load_sources(dir: &Path) -> BTreeMap<String, String> {
+    std::fs::read_to_string(dir.join(SOURCES_FILE))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Note in the sources file of `dir` that the files at `relative_paths` came from `source`.
+pub(crate) fn record_sources(
+    dir: &Path,
+    relative_paths: &[String],
+    source: &str,
+) -> Result<(), String> {
+    if relative_paths.is_empty() {
+        return Ok(());
+    }
+    let mut sources = load_sources(dir);
+    for path in relative_paths {
+        sources.insert(path.clone(), source.to_string());
+    }
+    let text = serde_json::to_string_pretty(&sources).map_err(|e| e.to_string())? + "\n";
+    let path = dir.join(SOURCES_FILE);
+    std::fs::write(&path, text).map_err(|e| format!("write {} failed: {}", path.display(), e))
+}
+
+/// Make `repo` the one returned by `get`. Only the first call has any effect.
+pub fn init(repo: FirmwareRepo) {
+    let mut active = REPO.write().unwrap_or_else(|e| e.into_inner());
+    if active.is_none() {
+        *active = Some(Arc::new(repo));
+    }
+}
+
+/// The active firmware repo (a scan of the firmware directory when `init` was never
+/// called).
+pub fn get() -> Arc<FirmwareRepo> {
+    if let Some(repo) = REPO.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        return Arc::clone(repo);
+    }
+    let mut active = REPO.write().unwrap_or_else(|e| e.into_inner());
+    Arc::clone(active.get_or_insert_with(|| Arc::new(FirmwareRepo::load())))
+}
+
+/// Scan the firmware directory again, e.g. after downloading firmware in a long-running
+/// session. Repos returned by earlier `get` calls stay valid but no longer change, and
+/// are freed once the last of them is dropped.
+pub fn reload() {
+    let repo = Arc::new(FirmwareRepo::load());
+    *REPO.write().unwrap_or_else(|e| e.into_inner()) = Some(repo);
+}
+
+/// Make `repo` the one returned by `get` from now on, whatever was active before.
+#[cfg(test)]
+pub(crate) fn replace(repo: Arc<FirmwareRepo>) {
+    *REPO.write().unwrap_or_else(|e| e.into_inner()) = Some(repo);
+}
+
+/// Parse a firmware file name of the form
+/// `{BoardType}_{Protocol}_firmware_v_{major}_{minor}.txt` (or `.hex`, `.bin`) into
+/// (board type, protocol, version).
+pub fn parse_firmware_filename(path: &Path) -> Option<(String, String, Version)> {
+    let stem = path.file_stem()?.to_str()?;
+    let (prefix, ver_part_full) = stem.split_once("_firmware_v_")?;
+    let (board_type, protocol) = prefix.rsplit_once('_')?;
+    let mut it = ver_part_full.split('_');
+    let maj = it.next()?.parse::<u32>().ok()?;
+    let min = it.next()?.parse::<u32>().ok()?;
+    Some((
+        board_type.to_string(),
+        protocol.to_string(),
+        Version::new(maj, min),
+    ))
+}
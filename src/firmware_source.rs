@@ -0,0 +1,845 @@
+// Where firmware files come from. `check_updates` installs from any `FirmwareSource`;
+// the default is the main branch of the official fastpinball/fast-firmware repository.
+// Downloaded archives are kept under ~/.fast/cache together with the ETag/Last-Modified
+// the server sent, so an unchanged archive is not downloaded again. With a GitHub token
+// (see `http::github_token`) GitHub archives come from the REST API instead of github.com,
+// so they count against the token's rate limit rather than the address's.
+//
+// Every archive is checked whole (`verify_zip`) before anything is taken out of it, so a
+// truncated or damaged download fails before the firmware directory is touched.
+
+use crate::protocol::firmware_file::FirmwareFormat;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const DEFAULT_GITHUB_REPO: &str = "fastpinball/fast-firmware";
+pub const DEFAULT_GITHUB_BRANCH: &str = "main";
+
+const DOWNLOAD_ATTEMPTS: u32 = 4;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// A firmware file taken from a source, with its path relative to the source root.
+pub struct FirmwareFile {
+    pub rel_path: PathBuf,
+    pub contents: Vec<u8>,
+}
+
+pub trait FirmwareSource {
+    /// Human readable location, used in progress output.
+    fn describe(&self) -> String;
+
+    /// Fetch every firmware file (`.txt`, `.hex` or `.bin`) the source provides, and the
+    /// `.md` release notes next to them.
+    fn fetch(&self) -> Result<Vec<FirmwareFile>, String>;
+
+    /// Expected SHA-256 per relative path, if the source publishes a manifest.
+    fn checksums(&self) -> Result<Option<HashMap<String, String>>, String> {
+        Ok(None)
+    }
+}
+
+/// A branch of a GitHub repository, fetched as the branch ZIP.
+pub struct GitHubBranch {
+    pub repo: String,
+    pub branch: String,
+    /// Download the archive even when the cached copy is still current.
+    pub force: bool,
+}
+
+impl Default for GitHubBranch {
+    fn default() -> Self {
+        Self {
+            repo: DEFAULT_GITHUB_REPO.to_string(),
+            branch: DEFAULT_GITHUB_BRANCH.to_string(),
+            force: false,
+        }
+    }
+}
+
+impl FirmwareSource for GitHubBranch {
+    fn describe(&self) -> String {
+        self.archive_url()
+    }
+
+    fn fetch(&self) -> Result<Vec<FirmwareFile>, String> {
+        extract_zip(download_archive(&self.archive_url(), self.force)?)
+    }
+
+    fn checksums(&self) -> Result<Option<HashMap<String, String>>, String> {
+        fetch_checksums(&format!(
+            "https://raw.githubusercontent.com/{}/{}/SHA256SUMS",
+            self.repo, self.branch
+        ))
+    }
+}
+
+impl GitHubBranch {
+    fn archive_url(&self) -> String {
+        github_archive_url(
+            &self.repo,
+            &format!("refs/heads/{}", self.branch),
+            &self.branch,
+        )
+    }
+}
+
+/// A release tag of a GitHub repository, fetched as the tag ZIP.
+pub struct GitHubTag {
+    pub repo: String,
+    pub tag: String,
+    /// Download the archive even when the cached copy is still current.
+    pub force: bool,
+}
+
+impl FirmwareSource for GitHubTag {
+    fn describe(&self) -> String {
+        self.archive_url()
+    }
+
+    fn fetch(&self) -> Result<Vec<FirmwareFile>, String> {
+        extract_zip(download_archive(&self.archive_url(), self.force)?)
+    }
+
+    fn checksums(&self) -> Result<Option<HashMap<String, String>>, String> {
+        fetch_checksums(&format!(
+            "https://raw.githubusercontent.com/{}/{}/SHA256SUMS",
+            self.repo, self.tag
+        ))
+    }
+}
+
+impl GitHubTag {
+    fn archive_url(&self) -> String {
+        github_archive_url(&self.repo, &format!("refs/tags/{}", self.tag), &self.tag)
+    }
+}
+
+/// Any git ref of a GitHub repository (branch, tag or commit SHA), fetched as its ZIP.
+pub struct GitHubRef {
+    pub repo: String,
+    pub reference: String,
+    /// Download the archive even when the cached copy is still current.
+    pub force: bool,
+}
+
+impl FirmwareSource for GitHubRef {
+    fn describe(&self) -> String {
+        self.archive_url()
+    }
+
+    fn fetch(&self) -> Result<Vec<FirmwareFile>, String> {
+        extract_zip(download_archive(&self.archive_url(), self.force)?)
+    }
+
+    fn checksums(&self) -> Result<Option<HashMap<String, String>>, String> {
+        fetch_checksums(&format!(
+            "https://raw.githubusercontent.com/{}/{}/SHA256SUMS",
+            self.repo, self.reference
+        ))
+    }
+}
+
+impl GitHubRef {
+    fn archive_url(&self) -> String {
+        github_archive_url(&self.repo, &self.reference, &self.reference)
+    }
+}
+
+/// The ZIP of `reference` in `repo`: from the REST API when a GitHub token is set,
+/// otherwise from github.com, where `web_path` names it (e.g. `refs/heads/main`).
+fn github_archive_url(repo: &str, web_path: &str, reference: &str) -> String {
+    if crate::http::github_token().is_some() {
+        format!(
+            "https://api.github.com/repos/{}/zipball/{}",
+            repo, reference
+        )
+    } else {
+        format!("https://github.com/{}/archive/{}.zip", repo, web_path)
+    }
+}
+
+/// A ZIP archive at an arbitrary URL, e.g. an internal mirror.
+///
+/// A `SHA256SUMS` manifest is looked for next to the archive (same URL with the file
+/// name replaced).
+pub struct HttpArchive {
+    pub url: String,
+    /// Download the archive even when the cached copy is still current.
+    pub force: bool,
+}
+
+impl FirmwareSource for HttpArchive {
+    fn describe(&self) -> String {
+        self.url.clone()
+    }
+
+    fn fetch(&self) -> Result<Vec<FirmwareFile>, String> {
+        extract_zip(download_archive(&self.url, self.force)?)
+    }
+
+    fn checksums(&self) -> Result<Option<HashMap<String, String>>, String> {
+        match self.url.rsplit_once('/') {
+            Some((base, _)) => fetch_checksums(&format!("{}/SHA256SUMS", base)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A local directory (e.g., a network share or unpacked archive) holding firmware files.
+pub struct LocalDirectory {
+    pub path: PathBuf,
+}
+
+impl FirmwareSource for LocalDirectory {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn fetch(&self) -> Result<Vec<FirmwareFile>, String> {
+        let mut files = Vec::new();
+        collect_dir(&self.path, &self.path, &mut files)?;
+        Ok(files)
+    }
+
+    fn checksums(&self) -> Result<Option<HashMap<String, String>>, String> {
+        match std::fs::read_to_string(self.path.join("SHA256SUMS")) {
+            Ok(text) => Ok(Some(parse_checksums(&text))),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+/// A ZIP archive on disk, e.g. the GitHub archive carried over on a USB stick.
+///
+/// A `SHA256SUMS` manifest is looked for inside the archive, next to the firmware folders.
+pub struct LocalArchive {
+    pub path: PathBuf,
+}
+
+impl FirmwareSource for LocalArchive {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn fetch(&self) -> Result<Vec<FirmwareFile>, String> {
+        extract_zip(self.read()?)
+    }
+
+    fn checksums(&self) -> Result<Option<HashMap<String, String>>, String> {
+        let reader = std::io::Cursor::new(self.read()?);
+        let mut zip = zip::ZipArchive::new(reader).map_err(|e| format!("invalid zip: {}", e))?;
+        let root = zip_root(&zip);
+        let name = format!("{}SHA256SUMS", root);
+        let Ok(mut file) = zip.by_name(&name) else {
+            return Ok(None);
+        };
+        let mut text = String::new();
+        file.read_to_string(&mut text)
+            .map_err(|e| format!("zip read {} failed: {}", name, e))?;
+        Ok(Some(parse_checksums(&text)))
+    }
+}
+
+impl LocalArchive {
+    fn read(&self) -> Result<Vec<u8>, String> {
+        std::fs::read(&self.path).map_err(|e| format!("read {} failed: {}", self.path.display(), e))
+    }
+}
+
+/// Parse a `--source` value: `github[:owner/repo[@branch]]`, `tag:<tag>[@owner/repo]`,
+/// `ref:<branch or sha>[@owner/repo]`, `url:<zip url>` or `dir:<path>`. `force` bypasses
+/// the archive cache of downloaded sources.
+pub fn parse_source(spec: &str, force: bool) -> Result<Box<dyn FirmwareSource>, String> {
+    let (kind, value) = spec.split_once(':').unwrap_or((spec, ""));
+    match kind.to_ascii_lowercase().as_str() {
+        "github" => {
+            let mut source = GitHubBranch {
+                force,
+                ..GitHubBranch::default()
+            };
+            if !value.is_empty() {
+                let (repo, branch) = value
+                    .split_once('@')
+                    .unwrap_or((value, DEFAULT_GITHUB_BRANCH));
+                source.repo = repo.to_string();
+                source.branch = branch.to_string();
+            }
+            Ok(Box::new(source))
+        }
+        "tag" if !value.is_empty() => {
+            let (tag, repo) = value
+                .split_once('@')
+                .unwrap_or((value, DEFAULT_GITHUB_REPO));
+            Ok(Box::new(GitHubTag {
+                repo: repo.to_string(),
+                tag: tag.to_string(),
+                force,
+            }))
+        }
+        "ref" if !value.is_empty() => {
+            let (reference, repo) = value
+                .split_once('@')
+                .unwrap_or((value, DEFAULT_GITHUB_REPO));
+            Ok(Box::new(GitHubRef {
+                repo: repo.to_string(),
+                reference: reference.to_string(),
+                force,
+            }))
+        }
+        "url" if !value.is_empty() => Ok(Box::new(HttpArchive {
+            url: value.to_string(),
+            force,
+        })),
+        "dir" if !value.is_empty() => Ok(Box::new(LocalDirectory {
+            path: PathBuf::from(value),
+        })),
+        _ => Err(format!(
+            "unknown firmware source '{}'; expected github[:owner/repo[@branch]], tag:<tag>, ref:<ref>, url:<url> or dir:<path>",
+            spec
+        )),
+    }
+}
+
+// Firmware files (`.txt`, `.hex` or `.bin`) and the release notes that go with them
+fn is_wanted(path: &Path) -> bool {
+    FirmwareFormat::from_path(path).is_some() || crate::release_notes::is_notes_file(path)
+}
+
+/// Validators a server sent with an archive, stored next to the cached copy.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct CacheEntry {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl CacheEntry {
+    fn from_headers(headers: &reqwest::header::HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            etag: get(reqwest::header::ETAG),
+            last_modified: get(reqwest::header::LAST_MODIFIED),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Result of a (possibly conditional) download.
+enum Download {
+    /// The server confirmed the cached copy is current (HTTP 304).
+    NotModified,
+    Fetched(Vec<u8>, CacheEntry),
+}
+
+/// Paths of the cached archive of one URL and its validators.
+struct CachedArchive {
+    archive: PathBuf,
+    entry: PathBuf,
+}
+
+impl CachedArchive {
+    fn for_url(url: &str) -> Option<Self> {
+        let dir = crate::config::cache_dir();
+        if dir.as_os_str().is_empty() {
+            return None;
+        }
+        // One file per URL, named after it so the cache is easy to inspect
+        let name: String = url
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        Some(Self {
+            archive: dir.join(format!("{}.zip", name)),
+            entry: dir.join(format!("{}.json", name)),
+        })
+    }
+
+    /// The cached archive and its validators, if both are present and usable.
+    fn load(&self) -> Option<(Vec<u8>, CacheEntry)> {
+        let entry: CacheEntry =
+            serde_json::from_str(&std::fs::read_to_string(&self.entry).ok()?).ok()?;
+        if entry.is_empty() {
+            return None;
+        }
+        Some((std::fs::read(&self.archive).ok()?, entry))
+    }
+
+    fn remove(&self) {
+        let _ = std::fs::remove_file(&self.entry);
+        let _ = std::fs::remove_file(&self.archive);
+    }
+
+    fn store(&self, body: &[u8], entry: &CacheEntry) -> Result<(), String> {
+        // Drop the old validators first so a half-written archive is never trusted
+        let _ = std::fs::remove_file(&self.entry);
+        if entry.is_empty() {
+            return Ok(());
+        }
+        if let Some(dir) = self.archive.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("create {} failed: {}", dir.display(), e))?;
+        }
+        std::fs::write(&self.archive, body)
+            .map_err(|e| format!("write {} failed: {}", self.archive.display(), e))?;
+        let json = serde_json::to_string_pretty(entry).unwrap_or_default();
+        std::fs::write(&self.entry, json)
+            .map_err(|e| format!("write {} failed: {}", self.entry.display(), e))
+    }
+}
+
+/// Download the archive at `url`, reusing the cached copy when the server reports it
+/// unchanged. `force` skips the conditional request and always downloads.
+fn download_archive(url: &str, force: bool) -> Result<Vec<u8>, String> {
+    let cache = CachedArchive::for_url(url);
+    let cached = match (&cache, force) {
+        (Some(cache), false) => cache.load(),
+        _ => None,
+    };
+    match download(url, cached.as_ref().map(|(_, entry)| entry))? {
+        Download::NotModified => {
+            let body = cached.map(|(body, _)| body).unwrap_or_default();
+            if let Err(e) = verify_zip(&body) {
+                eprintln!(
+                    "The cached archive is damaged ({}); downloading it again.",
+                    e
+                );
+                if let Some(cache) = &cache {
+                    cache.remove();
+                }
+                return download_archive(url, true);
+            }
+            status!("Archive unchanged since the last download; using the cached copy.");
+            Ok(body)
+        }
+        Download::Fetched(body, entry) => {
+            // A damaged archive must not be cached and trusted next time
+            verify_zip(&body).map_err(|e| format!("downloaded archive is damaged: {}", e))?;
+            if let Some(cache) = cache
+                && let Err(e) = cache.store(&body, &entry)
+            {
+                log::warn!("Could not cache the firmware archive: {}", e);
+            }
+            Ok(body)
+        }
+    }
+}
+
+/// Download `url`, retrying with exponential backoff. Retries resume from the bytes
+/// already received when the server honours range requests. With `known` validators the
+/// request is conditional and may come back as `Download::NotModified`.
+fn download(url: &str, known: Option<&CacheEntry>) -> Result<Download, String> {
+    let client = crate::http::client()?;
+    let mut body = Vec::new();
+    let mut entry = CacheEntry::default();
+    let mut last_error = String::new();
+    for attempt in 0..DOWNLOAD_ATTEMPTS {
+        if attempt > 0 {
+            let delay = RETRY_BASE_DELAY * 2u32.pow(attempt - 1);
+            if body.is_empty() {
+                eprintln!("{}; retrying in {}s...", last_error, delay.as_secs());
+            } else {
+                eprintln!(
+                    "{}; resuming after {} bytes in {}s...",
+                    last_error,
+                    body.len(),
+                    delay.as_secs()
+                );
+            }
+            std::thread::sleep(delay);
+        }
+        match fetch_into(&client, url, known, &mut body, &mut entry) {
+            Ok(false) => return Ok(Download::Fetched(body, entry)),
+            Ok(true) => return Ok(Download::NotModified),
+            Err((true, e)) => last_error = e,
+            Err((false, e)) => return Err(e),
+        }
+    }
+    Err(format!(
+        "download failed after {} attempts: {}",
+        DOWNLOAD_ATTEMPTS, last_error
+    ))
+}
+
+/// Fetch `url` into `body`, continuing after the bytes it already holds, and record the
+/// response's validators in `entry`. Returns `true` when the server answered that the
+/// `known` copy is still current. Errors carry whether another attempt could succeed.
+fn fetch_into(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    known: Option<&CacheEntry>,
+    body: &mut Vec<u8>,
+    entry: &mut CacheEntry,
+) -> Result<bool, (bool, String)> {
+    let mut request = crate::http::get(client, url);
+    if !body.is_empty() {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", body.len()));
+    } else if let Some(known) = known {
+        if let Some(etag) = &known.etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(modified) = &known.last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, modified);
+        }
+    }
+    let mut resp = request.send().map_err(|e| {
+        (
+            true,
+            format!("download failed: {}", crate::http::describe_error(&e)),
+        )
+    })?;
+    let status = resp.status();
+    if let Some(e) = crate::http::rate_limit_error(&resp) {
+        return Err((false, e));
+    }
+    if status == reqwest::StatusCode::NOT_MODIFIED && body.is_empty() && known.is_some() {
+        return Ok(true);
+    }
+    if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        body.clear();
+        return Err((true, "server rejected resume".to_string()));
+    }
+    if !status.is_success() {
+        let retryable = status.is_server_error()
+            || status == reqwest::StatusCode::REQUEST_TIMEOUT
+            || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+        return Err((retryable, format!("HTTP error: {}", status)));
+    }
+    if status != reqwest::StatusCode::PARTIAL_CONTENT {
+        // The server ignored the range and sent everything again
+        body.clear();
+    }
+    *entry = CacheEntry::from_headers(resp.headers());
+    let expected = resp.content_length().map(|n| n + body.len() as u64);
+    let mut chunk = [0u8; 64 * 1024];
+    loop {
+        match resp.read(&mut chunk) {
+            Ok(0) => match expected {
+                Some(expected) if (body.len() as u64) < expected => {
+                    return Err((
+                        true,
+                        format!(
+                            "connection closed after {} of {} bytes",
+                            body.len(),
+                            expected
+                        ),
+                    ));
+                }
+                _ => return Ok(false),
+            },
+            Ok(n) => body.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err((true, format!("read body failed: {}", e))),
+        }
+    }
+}
+
+/// The folder every entry of the archive sits in, with a trailing `/` (e.g.
+/// `fast-firmware-main/` in a GitHub zip), or an empty string when there is none.
+fn zip_root<R: std::io::Read + std::io::Seek>(zip: &zip::ZipArchive<R>) -> String {
+    let mut names = zip.file_names();
+    let Some((top, _)) = names.next().and_then(|n| n.split_once('/')) else {
+        return String::new();
+    };
+    let root = format!("{}/", top);
+    if zip.file_names().all(|n| n.starts_with(&root)) {
+        root
+    } else {
+        String::new()
+    }
+}
+
+/// Check that `bytes` is a whole ZIP archive: its central directory can be read and every
+/// entry decompresses to the size and CRC-32 it records. Returns the number of entries.
+pub fn verify_zip(bytes: &[u8]) -> Result<usize, String> {
+    let reader = std::io::Cursor::new(bytes);
+    let mut zip = zip::ZipArchive::new(reader)
+        .map_err(|e| format!("invalid zip ({}); it may be truncated", e))?;
+    for i in 0..zip.len() {
+        let mut file = zip
+            .by_index(i)
+            .map_err(|e| format!("zip entry {} unreadable: {}", i, e))?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        // The reader checks the CRC-32 once the whole entry has been read
+        let size = std::io::copy(&mut file, &mut std::io::sink())
+            .map_err(|e| format!("{} is damaged: {}", name, e))?;
+        if size != file.size() {
+            return Err(format!(
+                "{} is damaged: {} of {} bytes",
+                name,
+                size,
+                file.size()
+            ));
+        }
+    }
+    Ok(zip.len())
+}
+
+fn extract_zip(bytes: Vec<u8>) -> Result<Vec<FirmwareFile>, String> {
+    verify_zip(&bytes)?;
+    let reader = std::io::Cursor::new(bytes);
+    let mut zip = zip::ZipArchive::new(reader).map_err(|e| format!("invalid zip: {}", e))?;
+    let root = zip_root(&zip);
+
+    let mut files = Vec::new();
+    for i in 0..zip.len() {
+        let mut file = zip
+            .by_index(i)
+            .map_err(|e| format!("zip read failed: {}", e))?;
+        if file.is_dir() {
+            continue;
+        }
+        let name_in_zip = file.name().to_string();
+        // Absolute names and names that climb out with `..` would land outside the
+        // firmware directory
+        let Some(enclosed) = file.enclosed_name() else {
+            log::warn!("Skipping zip entry {} outside the archive", name_in_zip);
+            continue;
+        };
+        // Skip the top-level folder of a GitHub zip (e.g., fast-firmware-main/)
+        let rel_path = enclosed
+            .strip_prefix(root.trim_end_matches('/'))
+            .unwrap_or(&enclosed)
+            .to_path_buf();
+        if rel_path.as_os_str().is_empty() || !is_wanted(&rel_path) {
+            continue;
+        }
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)
+            .map_err(|e| format!("zip read {} failed: {}", name_in_zip, e))?;
+        files.push(FirmwareFile { rel_path, contents });
+    }
+    Ok(files)
+}
+
+fn collect_dir(root: &Path, dir: &Path, files: &mut Vec<FirmwareFile>) -> Result<(), String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("read dir {} failed: {}", dir.display(), e))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_dir(root, &path, files)?;
+        } else if is_wanted(&path) {
+            let contents = std::fs::read(&path)
+                .map_err(|e| format!("read {} failed: {}", path.display(), e))?;
+            let rel_path = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            files.push(FirmwareFile { rel_path, contents });
+        }
+    }
+    Ok(())
+}
+
+/// Fetch a checksum manifest, mapping relative path -> lowercase hex SHA-256.
+///
+/// Returns `Ok(None)` when no manifest is published (HTTP 404).
+fn fetch_checksums(url: &str) -> Result<Option<HashMap<String, String>>, String> {
+    let resp = crate::http::get(&crate::http::client()?, url)
+        .send()
+        .map_err(|e| {
+            format!(
+                "checksum download failed: {}",
+                crate::http::describe_error(&e)
+            )
+        })?;
+    if let Some(e) = crate::http::rate_limit_error(&resp) {
+        return Err(e);
+    }
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !resp.status().is_success() {
+        return Err(format!("HTTP error fetching checksums: {}", resp.status()));
+    }
+    let text = resp
+        .text()
+        .map_err(|e| format!("read checksums failed: {}", e))?;
+    Ok(Some(parse_checksums(&text)))
+}
+
+// sha256sum-style manifest: "<hex digest>  <path relative to repo root>"
+fn parse_checksums(text: &str) -> HashMap<String, String> {
+    let mut sums = HashMap::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((digest, path)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        // sha256sum marks binary mode with a leading '*' on the path
+        let path = path.trim().trim_start_matches('*').trim_start_matches("./");
+        sums.insert(path.to_string(), digest.to_ascii_lowercase());
+    }
+    sums
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const DIGEST: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    // A ZIP holding `entries` (name, contents), as GitHub serves them
+    fn zip_of(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, contents) in entries {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("fast-pinball-test-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn checksums_in_sha256sum_format() {
+        let text = format!(
+            "# firmware manifest\n\n{}  EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt\n{} *./NET/FP-CPU-2000_NET_firmware_v_2_28.txt\nnot-a-line\n",
+            DIGEST.to_ascii_uppercase(),
+            DIGEST
+        );
+        let sums = parse_checksums(&text);
+        assert_eq!(sums.len(), 2);
+        assert_eq!(
+            sums["EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt"], DIGEST,
+            "digests are lowercased"
+        );
+        assert_eq!(sums["NET/FP-CPU-2000_NET_firmware_v_2_28.txt"], DIGEST);
+    }
+
+    #[test]
+    fn directory_manifest_next_to_the_firmware() {
+        let dir = temp_path("source-dir");
+        std::fs::create_dir_all(dir.join("EXP")).unwrap();
+        let source = LocalDirectory { path: dir.clone() };
+        assert!(source.checksums().unwrap().is_none());
+
+        std::fs::write(
+            dir.join("SHA256SUMS"),
+            format!("{}  EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt\n", DIGEST),
+        )
+        .unwrap();
+        std::fs::write(dir.join("EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt"), ":00\r").unwrap();
+        std::fs::write(dir.join("EXP/readme.pdf"), "not firmware").unwrap();
+        let sums = source.checksums().unwrap().unwrap();
+        let files = source.fetch().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(sums["EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt"], DIGEST);
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].rel_path,
+            Path::new("EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt")
+        );
+    }
+
+    #[test]
+    fn archive_manifest_and_files_below_the_root_folder() {
+        let path = temp_path("source.zip");
+        let sums = format!("{}  EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt\n", DIGEST);
+        std::fs::write(
+            &path,
+            zip_of(&[
+                ("fast-firmware-main/SHA256SUMS", &sums),
+                (
+                    "fast-firmware-main/EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt",
+                    ":00\r",
+                ),
+                ("fast-firmware-main/README.txt.orig", "skipped"),
+            ]),
+        )
+        .unwrap();
+        let source = LocalArchive { path: path.clone() };
+        let checksums = source.checksums();
+        let files = source.fetch();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            checksums.unwrap().unwrap()["EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt"],
+            DIGEST
+        );
+        let files = files.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].rel_path,
+            Path::new("EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt")
+        );
+        assert_eq!(files[0].contents, b":00\r");
+    }
+
+    #[test]
+    fn archive_without_manifest() {
+        let path = temp_path("source-plain.zip");
+        std::fs::write(
+            &path,
+            zip_of(&[("EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt", ":00\r")]),
+        )
+        .unwrap();
+        let checksums = LocalArchive { path: path.clone() }.checksums();
+        let _ = std::fs::remove_file(&path);
+        assert!(checksums.unwrap().is_none());
+    }
+
+    #[test]
+    fn archive_entries_outside_the_archive_are_skipped() {
+        let files = extract_zip(zip_of(&[
+            ("../FP-EXP-0071_EXP_firmware_v_0_50.txt", ":00\r"),
+            ("/tmp/FP-EXP-0071_EXP_firmware_v_0_51.txt", ":00\r"),
+            ("EXP/FP-EXP-0071_EXP_firmware_v_0_52.txt", ":00\r"),
+        ]))
+        .unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].rel_path,
+            Path::new("EXP/FP-EXP-0071_EXP_firmware_v_0_52.txt")
+        );
+    }
+
+    #[test]
+    fn truncated_archive_is_rejected() {
+        let bytes = zip_of(&[("EXP/FP-EXP-0071_EXP_firmware_v_0_50.txt", ":00\r")]);
+        assert_eq!(verify_zip(&bytes), Ok(1));
+        assert!(verify_zip(&bytes[..bytes.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn source_specs() {
+        let describe = |spec: &str| parse_source(spec, false).map(|s| s.describe());
+        assert_eq!(
+            describe("dir:/mnt/firmware"),
+            Ok("/mnt/firmware".to_string())
+        );
+        assert_eq!(
+            describe("url:https://example.com/fw.zip"),
+            Ok("https://example.com/fw.zip".to_string())
+        );
+        assert!(describe("github").is_ok());
+        assert!(describe("tag:").is_err());
+        assert!(describe("ftp:somewhere").is_err());
+    }
+}
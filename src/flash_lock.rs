@@ -0,0 +1,78 @@
+// Advisory locks that keep two invocations from streaming firmware over the same port at
+// once, which would interleave their records and leave the boards half flashed. There is
+// one lock file per port in ~/.fast/locks; the OS releases the lock when its holder exits,
+// however it exits, so a crash never leaves a stale lock behind.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+
+/// Held while firmware is being flashed over a port; dropping it releases the port.
+#[derive(Debug)]
+pub struct FlashLock {
+    // Locked for as long as it is open
+    _file: File,
+}
+
+impl FlashLock {
+    /// Lock `port` for flashing, or explain which process is flashing over it already.
+    pub fn acquire(port: &str) -> Result<Self, String> {
+        let path = lock_path(port);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        // Never truncated before locking: the holder's pid is in there
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| format!("Failed to open lock file {}: {}", path.display(), e))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut holder = String::new();
+                let _ = file.read_to_string(&mut holder);
+                let holder = match holder.trim() {
+                    "" => String::new(),
+                    pid => format!(" (pid {})", pid),
+                };
+                return Err(format!(
+                    "A firmware update is already in progress on {}{}. Wait for it to finish and try again.",
+                    port, holder
+                ));
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(format!("Failed to lock {}: {}", path.display(), e));
+            }
+        }
+        // Best effort: the pid only makes the message above more helpful
+        let _ = file
+            .set_len(0)
+            .and_then(|_| file.rewind())
+            .and_then(|_| write!(file, "{}", std::process::id()));
+        Ok(Self { _file: file })
+    }
+}
+
+/// Lock file of `port`. Symlinks such as /dev/serial/by-id/... resolve to the device, so
+/// every name of a port shares its lock.
+fn lock_path(port: &str) -> PathBuf {
+    let device = std::fs::canonicalize(port)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| port.to_string());
+    let name: String = device
+        .trim_start_matches('/')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    crate::config::lock_dir().join(format!("{}.lock", name))
+}
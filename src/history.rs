@@ -0,0 +1,108 @@
+// The flash journal: one JSON line per flash attempt in ~/.fast/history.jsonl, appended by
+// the protocols' `flash_file` whoever calls it, so operators have an audit trail of what
+// was flashed on a machine and when. `history` shows it.
+
+use crate::protocol::FlashOutcome;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+/// One flash attempt.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FlashRecord {
+    /// When the flash ended (RFC 3339, UTC).
+    pub timestamp: String,
+    /// `EXP`, `NET` or `DSP`.
+    pub target: String,
+    pub port: String,
+    /// EXP board address.
+    pub address: Option<String>,
+    pub board: Option<String>,
+    /// Version the board reported before the flash, if it answered.
+    pub old_version: Option<String>,
+    /// Version flashed, when known.
+    pub new_version: Option<String>,
+    pub file: String,
+    pub result: FlashOutcome,
+    pub duration_secs: f64,
+    /// Bytes of firmware sent; `None` when the firmware was not sent in full.
+    pub bytes_sent: Option<u64>,
+}
+
+impl FlashRecord {
+    /// A record of a flash of `file` over `port` that ended just now after `duration`;
+    /// the optional fields are left empty.
+    pub fn new(
+        target: &str,
+        port: &str,
+        file: &str,
+        result: FlashOutcome,
+        duration: Duration,
+    ) -> Self {
+        let now = OffsetDateTime::now_utc();
+        Self {
+            timestamp: now
+                .replace_nanosecond(0)
+                .unwrap_or(now)
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            target: target.to_string(),
+            port: port.to_string(),
+            address: None,
+            board: None,
+            old_version: None,
+            new_version: None,
+            file: file.to_string(),
+            result,
+            duration_secs: duration.as_secs_f64(),
+            bytes_sent: None,
+        }
+    }
+}
+
+/// Append `record` to the journal. A journal that cannot be written must not fail the
+/// flash, so that is only logged.
+pub fn record(record: &FlashRecord) {
+    if let Err(e) = append(&crate::config::history_path(), record) {
+        log::warn!("{}", e);
+    }
+}
+
+/// Append `record` to the journal at `path`, creating it (and its directory) if needed.
+pub fn append(path: &Path, record: &FlashRecord) -> Result<(), String> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let line = serde_json::to_string(record)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", line))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Read the journal at `path`, oldest first. A missing journal is empty; lines that do
+/// not parse (e.g. one cut short by a crash) are skipped.
+pub fn load(path: &Path) -> Result<Vec<FlashRecord>, String> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    Ok(text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            serde_json::from_str(line)
+                .inspect_err(|e| log::warn!("Skipping history line {:?}: {}", line, e))
+                .ok()
+        })
+        .collect())
+}
@@ -0,0 +1,68 @@
+// Shell commands from the config file that run around the flashing commands, so
+// integrators can stop MPF (or whatever else holds the serial ports) before a flash and
+// start it again afterwards, or tell a dashboard about it:
+//
+//     [hooks]
+//     pre_flash = "systemctl --user stop mpf"
+//     post_flash = "systemctl --user start mpf"
+//
+// `pre_flash` runs before the ports are opened and a failing one stops the command;
+// `post_flash` runs once the command is done, whether it flashed anything or not.
+
+use std::process::Command;
+
+/// The points around a flash where a hook can run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    PreFlash,
+    PostFlash,
+}
+
+impl Hook {
+    /// The hook's key in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PreFlash => "pre_flash",
+            Hook::PostFlash => "post_flash",
+        }
+    }
+
+    /// The configured shell command, if any.
+    pub fn command(self) -> Option<&'static str> {
+        let hooks = &crate::config::get().hooks;
+        match self {
+            Hook::PreFlash => hooks.pre_flash.as_deref(),
+            Hook::PostFlash => hooks.post_flash.as_deref(),
+        }
+        .filter(|c| !c.trim().is_empty())
+    }
+}
+
+/// Run `hook` through the shell (`sh -c`, or `cmd /C` on Windows) with `env` added to its
+/// environment, along with `FAST_HOOK` set to its name. Succeeds when no hook is
+/// configured; fails when it cannot be started or exits with a non-zero status.
+pub fn run(hook: Hook, env: &[(&str, String)]) -> Result<(), String> {
+    let Some(command) = hook.command() else {
+        return Ok(());
+    };
+    status!("Running {} hook: {}", hook.name(), command);
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let status = Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .env("FAST_HOOK", hook.name())
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .status()
+        .map_err(|e| format!("{} hook failed to start: {}", hook.name(), e))?;
+    if status.success() {
+        return Ok(());
+    }
+    Err(match status.code() {
+        Some(code) => format!("{} hook exited with status {}", hook.name(), code),
+        None => format!("{} hook was killed by a signal", hook.name()),
+    })
+}
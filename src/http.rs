@@ -0,0 +1,149 @@
+// The HTTP client behind every download: firmware archives, checksum manifests and
+// release notes. Without a proxy configured, the `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY`
+// and `NO_PROXY` environment variables are honoured; `--proxy` (or `proxy` in the config
+// file) replaces them. A CA bundle (`--ca-bundle`, `ca_bundle`) is trusted in addition to
+// the system's certificates, for networks whose proxy re-signs HTTPS traffic.
+//
+// Requests to GitHub's API and raw file hosts carry a token when one is set
+// (`GITHUB_TOKEN`, `GH_TOKEN` or `github_token`), raising the rate limit from 60 requests
+// an hour per address to 5000 per token, which CI provisioning many machines needs.
+
+use reqwest::blocking::{Client, ClientBuilder, RequestBuilder, Response};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// `User-Agent` sent with every request; GitHub's API refuses requests without one.
+pub const USER_AGENT: &str = concat!("fast-pinball-utilities/", env!("CARGO_PKG_VERSION"));
+
+/// Environment variables a GitHub token is read from, in this order; either one takes
+/// precedence over `github_token` in the config file.
+pub const GITHUB_TOKEN_ENV: [&str; 2] = ["GITHUB_TOKEN", "GH_TOKEN"];
+
+/// Hosts that are sent the GitHub token.
+const GITHUB_HOSTS: [&str; 2] = ["api.github.com", "raw.githubusercontent.com"];
+
+/// A client builder with the configured proxy and CA bundle, for callers that set more
+/// options (e.g. a timeout) before building it.
+pub fn builder() -> Result<ClientBuilder, String> {
+    let config = crate::config::get();
+    let mut builder = Client::builder().user_agent(USER_AGENT);
+    if let Some(url) = config.proxy.as_deref() {
+        builder = builder.proxy(parse_proxy(url)?);
+    }
+    if let Some(path) = config.ca_bundle.as_deref() {
+        for certificate in load_ca_bundle(&crate::config::expand_home(path))? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    Ok(builder)
+}
+
+/// A client with the configured proxy and CA bundle.
+pub fn client() -> Result<Client, String> {
+    builder()?
+        .build()
+        .map_err(|e| format!("HTTP client setup failed: {}", e))
+}
+
+/// The GitHub token from the environment or the config file, if any.
+pub fn github_token() -> Option<String> {
+    GITHUB_TOKEN_ENV
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .chain(crate::config::get().github_token.clone())
+        .map(|token| token.trim().to_string())
+        .find(|token| !token.is_empty())
+}
+
+/// A GET request for `url`, carrying the GitHub token when it goes to GitHub.
+pub fn get(client: &Client, url: &str) -> RequestBuilder {
+    let request = client.get(url);
+    let to_github = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| GITHUB_HOSTS.contains(&h)))
+        .unwrap_or(false);
+    match github_token() {
+        Some(token) if to_github => request.bearer_auth(token),
+        _ => request,
+    }
+}
+
+/// Why GitHub refused `resp`, when it was for its rate limit: when the limit resets and,
+/// without a token, how to raise it.
+pub fn rate_limit_error(resp: &Response) -> Option<String> {
+    let status = resp.status();
+    if status != reqwest::StatusCode::FORBIDDEN && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+    {
+        return None;
+    }
+    let header = |name: &str| resp.headers().get(name).and_then(|v| v.to_str().ok());
+    let wait = if header("x-ratelimit-remaining") == Some("0") {
+        let reset = header("x-ratelimit-reset")?.parse::<u64>().ok()?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Duration::from_secs(reset).saturating_sub(now)
+    } else {
+        // Secondary rate limits only say when to come back
+        Duration::from_secs(header("retry-after")?.parse().ok()?)
+    };
+    let mut text = format!(
+        "GitHub rate limit reached; it resets in {} min",
+        wait.as_secs().div_ceil(60)
+    );
+    if github_token().is_none() {
+        text.push_str(&format!(
+            ". Set {} (or github_token in the config file) to a GitHub token for a higher limit",
+            GITHUB_TOKEN_ENV[0]
+        ));
+    }
+    Some(text)
+}
+
+/// `e` with its underlying cause, e.g. `error sending request for url (...): Connection
+/// refused`, which says more than reqwest's own message when a proxy or CA is at fault.
+pub fn describe_error(e: &reqwest::Error) -> String {
+    let mut cause: &dyn std::error::Error = e;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    let mut text = e.to_string();
+    if !std::ptr::addr_eq(cause, e) {
+        text = format!("{}: {}", text, cause);
+    }
+    if e.is_connect()
+        && let Some(proxy) = crate::config::get().proxy.as_deref()
+    {
+        text = format!("{} (through proxy {})", text, proxy);
+    }
+    text
+}
+
+// An `http://` or `https://` proxy URL; a bare `host:port` is taken as `http://`
+fn parse_proxy(url: &str) -> Result<reqwest::Proxy, String> {
+    let full = if url.contains("://") {
+        url.to_string()
+    } else {
+        format!("http://{}", url)
+    };
+    let parsed = reqwest::Url::parse(&full).map_err(|e| format!("invalid proxy {}: {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(format!("invalid proxy {}: expected http://host:port", url));
+    }
+    reqwest::Proxy::all(parsed).map_err(|e| format!("invalid proxy {}: {}", url, e))
+}
+
+// The certificates of a PEM file, one or more `BEGIN CERTIFICATE` blocks
+fn load_ca_bundle(path: &Path) -> Result<Vec<reqwest::Certificate>, String> {
+    let pem = std::fs::read(path)
+        .map_err(|e| format!("Failed to read CA bundle {}: {}", path.display(), e))?;
+    let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+        .map_err(|e| format!("Invalid CA bundle {}: {}", path.display(), e))?;
+    if certificates.is_empty() {
+        return Err(format!(
+            "CA bundle {} holds no PEM certificates",
+            path.display()
+        ));
+    }
+    Ok(certificates)
+}
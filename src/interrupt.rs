@@ -0,0 +1,82 @@
+// Ctrl+C handling. Killing the process in the middle of a firmware stream cuts a record
+// in half and leaves the progress bar drawn, so while firmware streams Ctrl+C asks the
+// stream to stop after the line being sent; the flash then fails with recovery steps.
+// A second Ctrl+C quits right away. At any other time Ctrl+C quits as usual.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Exit code after Ctrl+C, as a shell reports a process killed by SIGINT.
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+// Firmware streams running; `serve` can flash boards on several EXP ports at once
+static STREAMING: AtomicUsize = AtomicUsize::new(0);
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Install the Ctrl+C handler described above. The CLI does this at startup; library
+/// users handle signals themselves and call `request_stop`.
+pub fn install() -> Result<(), String> {
+    ctrlc::set_handler(|| {
+        let streaming = streaming();
+        if streaming && !STOP_REQUESTED.swap(true, Ordering::SeqCst) {
+            eprintln!("\nStopping after the current line; press Ctrl+C again to quit immediately.");
+            return;
+        }
+        if streaming {
+            eprintln!("\nQuitting in the middle of a firmware stream; the board is left in its bootloader.");
+        }
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    })
+    .map_err(|e| format!("Failed to install the Ctrl+C handler: {}", e))
+}
+
+/// Ask the firmware stream in progress to stop after the line being sent, and the
+/// commands flashing several boards not to start on the next one.
+pub fn request_stop() {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Whether a stop was requested (by Ctrl+C or `request_stop`).
+pub fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Forget a stop once the command it stopped has finished, so that the next one, e.g.
+/// the menu's next task, flashes again.
+pub fn clear_stop() {
+    STOP_REQUESTED.store(false, Ordering::SeqCst);
+}
+
+// Whether any firmware stream is running
+fn streaming() -> bool {
+    STREAMING.load(Ordering::SeqCst) > 0
+}
+
+/// Marks a firmware stream as running for as long as it is held.
+pub(crate) struct Streaming;
+
+impl Streaming {
+    pub(crate) fn start() -> Self {
+        STREAMING.fetch_add(1, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for Streaming {
+    fn drop(&mut self) {
+        STREAMING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streaming_until_the_last_stream_ends() {
+        let first = Streaming::start();
+        let second = Streaming::start();
+        drop(first);
+        assert!(streaming());
+        drop(second);
+    }
+}
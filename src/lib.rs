@@ -0,0 +1,68 @@
+//! Library interface for FAST Pinball hardware: board discovery, listing and
+//! firmware flashing over the NET (CPU) and EXP (I/O) serial ports.
+//!
+//! The `fast-pinball-utilities` binary is a thin CLI over this crate.
+
+/// `println!` for progress messages, which `--quiet` (or `quiet = true` in the config
+/// file) suppresses so only a command's final result line is printed.
+#[macro_export]
+macro_rules! status {
+    () => {
+        $crate::status!("")
+    };
+    ($($arg:tt)*) => {
+        if !$crate::config::get().quiet {
+            $crate::protocol::print_status(format_args!($($arg)*));
+        }
+    };
+}
+
+pub mod board_map;
+pub mod cancel;
+pub mod capture;
+pub mod commands;
+pub mod config;
+pub mod constants;
+pub mod device_cache;
+pub mod emulator;
+pub mod fast_monitor;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod firmware_repo;
+pub mod firmware_source;
+pub mod flash_lock;
+pub mod history;
+pub mod hooks;
+pub mod http;
+pub mod interrupt;
+pub mod metrics;
+pub mod port_owner;
+pub mod protocol;
+pub mod release_notes;
+pub mod replay;
+pub mod shared_monitor;
+pub mod version;
+
+pub use cancel::{CancelToken, Cancelled};
+pub use emulator::{Emulator, EmulatorConfig};
+pub use fast_monitor::{
+    ExpBoardInfo, ExpBreakoutInfo, FastPinballMonitor, NetBoardInfo, PinnedPorts, ProcessorInfo,
+    Protocol,
+};
+pub use protocol::aud_protocol::AudProtocol;
+pub use protocol::dsp_protocol::DspProtocol;
+pub use protocol::exp_protocol::ExpProtocol;
+pub use protocol::mock::MockTransport;
+pub use protocol::net_commands::NetCommand;
+pub use protocol::net_protocol::NetProtocol;
+pub use protocol::seg_protocol::SegProtocol;
+pub use protocol::transport::Transport;
+pub use protocol::{FlashEvent, FlashObserver, FlashOutcome, ProgressDisplay};
+pub use replay::Recording;
+pub use shared_monitor::SharedMonitor;
+pub use version::Version;
+
+// Firmware repository helpers
+pub use commands::check_updates::{download_latest_firmware, install_from};
+pub use firmware_repo::{FirmwareCatalog, FirmwareEntry, FirmwareRepo};
+pub use firmware_source::FirmwareSource;
@@ -1,101 +1,586 @@
-use std::env;
-use crate::fast_monitor::FastPinballMonitor;
-
-pub mod constants;
-pub mod fast_monitor;
-pub mod protocol;
-pub mod commands;
-
-fn print_help(program: &str) {
-    println!("{} - FAST Pinball utility", program);
-    println!("Usage:");
-    println!(
-        "  {} list-exp       List connected EXP boards and their versions",
-        program
-    );
-    println!(
-        "  {} list-net       List connected NET boards and their versions",
-        program
-    );
-    println!(
-        "  {} list           List both EXP and NET boards (default)",
-        program
-    );
-    println!(
-        "  {} update-exp     Interactive mode to select an EXP board and flash a chosen version",
-        program
-    );
-    println!(
-        "  {} update-net     Interactive mode to flash the NET (CPU) firmware",
-        program
-    );
-    println!(
-        "  {} get-latest-firmware  Download latest firmware files into ~/.fast/firmware",
-        program
-    );
-    println!("  {} help           Show this help", program);
+use clap::{Parser, Subcommand};
+use fast_pinball_utilities::commands::{self, Exit};
+use fast_pinball_utilities::config::{self, FIRMWARE_DIR_ENV, OutputFormat, ProgressMode};
+use fast_pinball_utilities::hooks::{self, Hook};
+use fast_pinball_utilities::{FastPinballMonitor, PinnedPorts};
+use fast_pinball_utilities::{board_map, firmware_repo, replay, status};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// FAST Pinball utility
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Cli {
+    /// Log every serial command and response
+    #[arg(short, long, visible_alias = "debug", global = true)]
+    verbose: bool,
+    /// Hide progress output and print only the final result line
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    /// Record all serial traffic with timestamps into a file
+    #[arg(long, value_name = "PATH", global = true)]
+    capture: Option<String>,
+    /// Use the ports and settings of this machine profile from the config file
+    #[arg(long, value_name = "NAME", global = true)]
+    profile: Option<String>,
+    /// Use this NET serial port instead of auto-discovery
+    #[arg(long, value_name = "PATH", global = true)]
+    net_port: Option<String>,
+    /// Use this EXP serial port instead of auto-discovery (repeatable)
+    #[arg(long, value_name = "PATH", global = true)]
+    exp_port: Vec<String>,
+    /// Use this segment display serial port instead of auto-discovery
+    #[arg(long, value_name = "PATH", global = true)]
+    seg_port: Option<String>,
+    /// Use this audio interface serial port instead of auto-discovery
+    #[arg(long, value_name = "PATH", global = true)]
+    aud_port: Option<String>,
+    /// Use this display controller serial port instead of auto-discovery
+    #[arg(long, value_name = "PATH", global = true)]
+    dsp_port: Option<String>,
+    /// Seconds to wait for the bootloader to confirm a flash (default 30)
+    #[arg(long, value_name = "SECS", global = true)]
+    bootloader_timeout: Option<u64>,
+    /// Seconds to wait for the ID response that verifies a flash (default 5)
+    #[arg(long, value_name = "SECS", global = true)]
+    verify_timeout: Option<u64>,
+    /// Directory holding firmware files (default ~/.fast/firmware, or $FAST_FIRMWARE_DIR)
+    #[arg(long, value_name = "DIR", global = true)]
+    firmware_dir: Option<PathBuf>,
+    /// Wait for the FAST hardware to appear instead of exiting when it is not found
+    #[arg(long, global = true)]
+    wait: bool,
+    /// Give up waiting for hardware after this many seconds (default: wait forever)
+    #[arg(long, value_name = "SECS", global = true)]
+    wait_timeout: Option<u64>,
+    /// Never go online: no firmware download offers and no release notes lookups
+    #[arg(long, global = true)]
+    offline: bool,
+    /// Proxy for downloads, e.g. http://proxy:3128 (default: $HTTPS_PROXY / $HTTP_PROXY)
+    #[arg(long, value_name = "URL", global = true)]
+    proxy: Option<String>,
+    /// PEM file of extra CA certificates to trust for downloads
+    #[arg(long, value_name = "PATH", global = true)]
+    ca_bundle: Option<PathBuf>,
+    /// How flashes show progress (default auto: a bar on a terminal, plain lines otherwise)
+    #[arg(long, value_enum, value_name = "MODE", global = true)]
+    progress: Option<ProgressMode>,
+    /// Do not color listings (also with the NO_COLOR environment variable set)
+    #[arg(long, global = true)]
+    no_color: bool,
+    /// Output format for listings
+    #[arg(long, value_enum, global = true)]
+    format: Option<OutputFormat>,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let program = args.get(0).map(|s| s.as_str()).unwrap_or("fast-util");
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List connected EXP boards and their versions
+    #[command(visible_alias = "exp")]
+    ListExp(commands::ListExpArgs),
+    /// List connected NET boards and their versions
+    #[command(visible_alias = "net")]
+    ListNet,
+    /// Show the segment display processor and its firmware
+    #[command(visible_alias = "seg")]
+    ListSeg,
+    /// Show the audio interface and its firmware
+    #[command(visible_alias = "aud")]
+    ListAud,
+    /// Show the display controller and its firmware
+    #[command(visible_alias = "dsp")]
+    ListDsp,
+    /// List all boards and processors (default when not on a terminal)
+    #[command(visible_alias = "all")]
+    List,
+    /// One report of the whole machine: ports, controller, boards and firmware status
+    Status(commands::StatusArgs),
+    /// Pick a task from a numbered menu (default on a terminal)
+    Menu,
+    /// Select an EXP board and flash a chosen version
+    #[command(visible_aliases = ["update", "flash"])]
+    UpdateExp(commands::UpdateExpArgs),
+    /// Flash every EXP board that is behind the newest firmware
+    #[command(visible_alias = "update-all")]
+    UpdateAllExp(commands::UpdateAllExpArgs),
+    /// Flash several chosen EXP boards back to back
+    #[command(visible_alias = "batch")]
+    UpdateExpBatch(commands::UpdateExpBatchArgs),
+    /// Flash the NET (CPU) firmware
+    #[command(visible_aliases = ["flash-net", "net-update"])]
+    UpdateNet(commands::UpdateNetArgs),
+    /// Flash the display controller firmware
+    #[command(visible_alias = "flash-dsp")]
+    UpdateDsp(commands::UpdateDspArgs),
+    /// Flash firmware to EXP boards stuck in their bootloader
+    Recover(commands::RecoverArgs),
+    /// Print switch open/close events as they happen
+    #[command(visible_alias = "switches")]
+    MonitorSwitches(commands::MonitorSwitchesArgs),
+    /// Name switches by activating them one after another
+    MapSwitches(commands::MapSwitchesArgs),
+    /// Pulse a driver
+    #[command(visible_alias = "pulse")]
+    TestDriver(commands::TestDriverArgs),
+    /// Step through GI strings and flashers to check that each one lights
+    #[command(visible_alias = "gi")]
+    TestGi(commands::TestGiArgs),
+    /// Drive LEDs on an EXP board
+    #[command(visible_alias = "leds")]
+    TestLeds(commands::TestLedsArgs),
+    /// Light LEDs one at a time and record which playfield insert each one lights
+    MapLeds(commands::MapLedsArgs),
+    /// Sweep or position a servo on an EXP board
+    #[command(visible_alias = "servo")]
+    TestServo(commands::TestServoArgs),
+    /// Show test text or patterns on segment displays
+    #[command(visible_alias = "segs")]
+    TestSeg(commands::TestSegArgs),
+    /// Set the audio interface's main and sub volume
+    #[command(visible_alias = "volume")]
+    SetVolume(commands::SetVolumeArgs),
+    /// Interactive console on the NET (default) or EXP port
+    #[command(visible_aliases = ["terminal", "repl"])]
+    Term(commands::TermArgs),
+    /// Send a raw command on the NET port and print the response
+    SendNet(commands::SendArgs),
+    /// Send a raw command on the EXP port and print the response
+    SendExp(commands::SendArgs),
+    /// Download latest firmware files into ~/.fast/firmware
+    #[command(visible_aliases = ["check-updates", "download-firmware", "check"])]
+    GetLatestFirmware(commands::CheckUpdatesArgs),
+    /// Copy firmware files from a local ZIP or directory into ~/.fast/firmware
+    #[command(visible_alias = "import")]
+    ImportFirmware(commands::ImportFirmwareArgs),
+    /// List or prune the firmware files in ~/.fast/firmware
+    Firmware(commands::FirmwareArgs),
+    /// Show the journal of firmware flashes made on this machine
+    History(commands::HistoryArgs),
+    /// Full-screen view of connected boards and live serial events
+    #[cfg(feature = "tui")]
+    #[command(visible_alias = "dash")]
+    Dashboard(commands::DashboardArgs),
+    /// Save the connected boards to a file, or compare them against one
+    Inventory(commands::InventoryArgs),
+    /// Save the driver configurations to a file, or write them back from one
+    Drivers(commands::DriversArgs),
+    /// Run a playfield test script (pulse drivers, set LEDs, expect switches)
+    Script(commands::ScriptArgs),
+    /// Keep the boards connected and serve a REST API for remote management
+    #[cfg(feature = "server")]
+    Serve(commands::ServeArgs),
+    /// Stay connected, recheck the boards periodically, reconnect when the USB link
+    /// drops, and log every change
+    Daemon(commands::DaemonArgs),
+    /// Publish the board inventory and switch events to an MQTT broker
+    #[cfg(feature = "mqtt")]
+    Mqtt(commands::MqttArgs),
+    /// Measure round-trip latency and throughput of the NET and EXP links
+    Benchmark(commands::BenchmarkArgs),
+    /// Flood the EXP link with LED updates and NET with queries, and report the error rate
+    Stress(commands::StressArgs),
+    /// Reboot the NET processor and wait for it to answer again
+    ResetNet(commands::ResetNetArgs),
+    /// Reset one EXP board and wait for it to answer again
+    ResetExp(commands::ResetExpArgs),
+    /// Wait until the NET and EXP ports appear, then print them
+    #[command(visible_alias = "wait")]
+    WaitForDevice,
+    /// Serve an emulated Neuron and EXP boards over TCP, for use without hardware
+    Emulate(commands::EmulateArgs),
+    /// Run a command with its serial traffic recorded into a file, for `replay`
+    Record {
+        file: PathBuf,
+        /// The command to run, with its options
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            required = true,
+            value_name = "COMMAND"
+        )]
+        command: Vec<String>,
+    },
+    /// Run the command recorded by `record` (or `--capture`) again, answered with the
+    /// recorded replies instead of by the hardware
+    Replay { file: PathBuf },
+}
 
-    let mode = if args.len() <= 1 {
-        "list".to_string()
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let cli = parse_cli(&args);
+    // RUST_LOG still takes precedence when set
+    let default_level = if cli.verbose { "debug" } else { "warn" };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_level))
+        .init();
+    // Settings from ~/.fast/config.toml; flags given on the command line win
+    let mut config = match config::default_path().map(|p| config::load(&p)) {
+        Some(Ok(config)) => config,
+        Some(Err(e)) => {
+            eprintln!("Failed to load config: {}", e);
+            std::process::exit(Exit::Error.code());
+        }
+        None => config::Config::default(),
+    };
+    if let Some(name) = cli.profile.clone().or_else(|| config.profile.clone())
+        && let Err(e) = config.select_profile(&name)
+    {
+        eprintln!("Failed to load config: {}", e);
+        std::process::exit(Exit::Error.code());
+    }
+    let net_port = cli.net_port.or_else(|| config.net_port.clone());
+    let exp_ports = if cli.exp_port.is_empty() {
+        config.exp_ports.clone()
     } else {
-        args[1].to_ascii_lowercase()
+        cli.exp_port
+    };
+    let pins = PinnedPorts {
+        net: net_port,
+        exp: exp_ports,
+        seg: cli.seg_port.or_else(|| config.seg_port.clone()),
+        aud: cli.aud_port.or_else(|| config.aud_port.clone()),
+        dsp: cli.dsp_port.or_else(|| config.dsp_port.clone()),
     };
+    let format_flag = cli.format;
+    let format = cli.format.unwrap_or(config.format);
+    if let Some(secs) = cli.bootloader_timeout {
+        config.timeouts.bootloader_ms = secs * 1000;
+    }
+    if let Some(secs) = cli.verify_timeout {
+        config.timeouts.verify_ms = secs * 1000;
+    }
+    if cli.quiet {
+        config.quiet = true;
+    }
+    if cli.offline {
+        config.offline = true;
+    }
+    if cli.no_color {
+        config.no_color = true;
+    }
+    if let Some(proxy) = cli.proxy {
+        config.proxy = Some(proxy);
+    }
+    if let Some(path) = cli.ca_bundle {
+        config.ca_bundle = Some(path);
+    }
+    if let Some(progress) = cli.progress {
+        config.progress = progress;
+    }
+    if let Some(dir) = cli
+        .firmware_dir
+        .or_else(|| std::env::var_os(FIRMWARE_DIR_ENV).map(PathBuf::from))
+    {
+        config.firmware_dir = Some(dir);
+    }
+    let quiet = config.quiet;
+    config::init(config);
+    match board_map::BoardMap::load() {
+        Ok(map) => board_map::init(map),
+        Err(e) => {
+            eprintln!("Failed to load board map: {}", e);
+            std::process::exit(Exit::Error.code());
+        }
+    }
+    // Ctrl+C during a firmware stream stops after the current line instead of mid-record
+    if let Err(e) = fast_pinball_utilities::interrupt::install() {
+        log::warn!("{}", e);
+    }
 
-    match mode.as_str() {
-        "help" | "-h" | "--help" => {
-            print_help(program);
-            return;
+    if let Some(path) = &cli.capture {
+        if let Err(e) = fast_pinball_utilities::capture::start(path) {
+            eprintln!("Failed to open capture file {}: {}", path, e);
+            std::process::exit(Exit::Error.code());
         }
-        _ => {}
+        // So `replay` knows what to run
+        replay::note_command(&args[1..]);
     }
+    let capture_active = cli.capture.is_some();
+
+    // Without a subcommand, people at a terminal get the menu and scripts the listing
+    let command = cli.command.unwrap_or_else(|| {
+        use std::io::IsTerminal;
+        if std::io::stdin().is_terminal() && std::io::stdout().is_terminal() {
+            Command::Menu
+        } else {
+            Command::List
+        }
+    });
 
     // Handle check-for-updates without requiring hardware
-    if matches!(
-        mode.as_str(),
-        "get-latest-firmware" | "check-updates" | "download-firmware" | "check"
-    ) {
-        match commands::run_check_updates() {
-            Ok(_) => std::process::exit(0),
+    if let Command::GetLatestFirmware(args) = &command {
+        match commands::run_check_updates(args) {
+            Ok(_) => {
+                // Compare against whatever hardware is connected; none is fine
+                if !quiet {
+                    match FastPinballMonitor::connect_with_ports(pins.clone()) {
+                        Ok(mut fpm) => commands::check_updates::report_versions(&mut fpm),
+                        Err(_) => println!(
+                            "No FAST hardware connected; skipping the installed version comparison."
+                        ),
+                    }
+                }
+                std::process::exit(Exit::Success.code())
+            }
             Err(e) => {
                 eprintln!("Failed to download firmware: {}", e);
-                std::process::exit(1);
+                std::process::exit(Exit::DownloadFailed.code());
             }
         }
     }
 
-    let fpm = FastPinballMonitor::connect();
-    let mut fpm = match fpm {
-        Some(fpm) => fpm,
-        None => {
-            eprintln!(
-                "Could not find FAST NET/EXP serial ports. Ensure devices are connected and accessible."
-            );
-            std::process::exit(2);
+    if let Command::ImportFirmware(args) = &command {
+        if let Err(e) = commands::run_import_firmware(args) {
+            eprintln!("Failed to import firmware: {}", e);
+            std::process::exit(Exit::DownloadFailed.code());
         }
+        return;
+    }
+
+    if let Command::Firmware(args) = &command {
+        std::process::exit(commands::run_firmware(args, format).code());
+    }
+
+    if let Command::History(args) = &command {
+        std::process::exit(commands::run_history(args, format).code());
+    }
+
+    if let Command::Replay { file } = &command {
+        std::process::exit(replay(file, format_flag, format).code());
+    }
+
+    if let Command::Emulate(args) = &command {
+        if let Err(e) = commands::run_emulate(args) {
+            eprintln!("Emulator failed: {}", e);
+            std::process::exit(Exit::Error.code());
+        }
+        return;
+    }
+
+    let wait_timeout = cli.wait_timeout.map(Duration::from_secs);
+    if let Command::WaitForDevice = &command {
+        std::process::exit(commands::run_wait_for_device(pins, wait_timeout).code());
+    }
+
+    // The daemon connects (and reconnects) by itself
+    if let Command::Daemon(args) = &command {
+        std::process::exit(commands::run_daemon(pins, args).code());
+    }
+
+    // Flashing from the firmware directory needs firmware; everything else makes do with
+    // whatever is installed
+    let needs_firmware = match &command {
+        Command::UpdateExp(args) => args.file.is_none(),
+        Command::UpdateAllExp(_) => true,
+        Command::UpdateExpBatch(_) => true,
+        Command::UpdateNet(args) => args.file.is_none(),
+        Command::UpdateDsp(args) => args.file.is_none(),
+        Command::Recover(args) => args.file.is_none(),
+        _ => false,
     };
+    if needs_firmware {
+        firmware_repo::init(commands::check_updates::load_firmware(
+            config::get().offline,
+        ));
+    }
+
+    // The flash hooks run around commands that flash, before their ports are opened
+    let flashing = flash_command_name(&command);
+    if let Some(name) = flashing
+        && let Err(e) = hooks::run(Hook::PreFlash, &[("FAST_COMMAND", name.to_string())])
+    {
+        eprintln!("{}; not flashing.", e);
+        std::process::exit(Exit::Error.code());
+    }
 
-    match mode.as_str() {
-        "update-exp" | "update" | "flash" => {
-            commands::run_update_exp(&mut fpm);
+    let connected = if cli.wait {
+        commands::wait_for_device::connect(pins, wait_timeout)
+    } else {
+        FastPinballMonitor::connect_with_ports(pins)
+    };
+    let exit = match connected {
+        Ok(fpm) => {
+            if capture_active {
+                replay::note_ports(&fpm);
+            }
+            run_connected(fpm, command, format)
         }
-        "update-net" | "flash-net" | "net-update" => {
-            commands::run_update_net(&mut fpm);
+        Err(e) => {
+            eprintln!("{}", e);
+            Exit::NoHardware
+        }
+    };
+    if let Some(name) = flashing {
+        let env = [
+            ("FAST_COMMAND", name.to_string()),
+            ("FAST_EXIT_CODE", exit.code().to_string()),
+        ];
+        if let Err(e) = hooks::run(Hook::PostFlash, &env) {
+            eprintln!("Warning: {}", e);
         }
-        "list-exp" | "exp" => {
-            commands::run_list_exp(&mut fpm);
+    }
+    std::process::exit(exit.code());
+}
+
+/// The name of a command that flashes firmware, for the flash hooks.
+fn flash_command_name(command: &Command) -> Option<&'static str> {
+    match command {
+        Command::UpdateExp(_) => Some("update-exp"),
+        Command::UpdateAllExp(_) => Some("update-all-exp"),
+        Command::UpdateExpBatch(_) => Some("update-exp-batch"),
+        Command::UpdateNet(_) => Some("update-net"),
+        Command::UpdateDsp(_) => Some("update-dsp"),
+        Command::Recover(_) => Some("recover"),
+        _ => None,
+    }
+}
+
+// clap exits with 2 on usage errors, which would read as "no hardware"
+fn parse_cli(args: &[String]) -> Cli {
+    try_parse_cli(args).unwrap_or_else(|e| {
+        let _ = e.print();
+        std::process::exit(if e.use_stderr() {
+            Exit::Error.code()
+        } else {
+            Exit::Success.code()
+        })
+    })
+}
+
+/// Parse a command line. `record <file> <command>` comes back as `<command>` with its
+/// traffic captured into `<file>`; options given before `record` still apply unless
+/// `<command>` sets them again.
+fn try_parse_cli(args: &[String]) -> Result<Cli, clap::Error> {
+    let mut cli = Cli::try_parse_from(args)?;
+    while let Some(Command::Record { file, command }) = &cli.command {
+        let command = command.clone();
+        cli.capture = Some(file.display().to_string());
+        cli.command = None;
+        cli.try_update_from(args.iter().take(1).chain(command.iter()))?;
+    }
+    Ok(cli)
+}
+
+/// Run a command that needs the hardware.
+fn run_connected(mut fpm: FastPinballMonitor, command: Command, format: OutputFormat) -> Exit {
+    // The server hands the monitor to its request threads
+    #[cfg(feature = "server")]
+    if let Command::Serve(args) = &command {
+        return commands::run_serve(fpm, args);
+    }
+    let fpm = &mut fpm;
+    match command {
+        Command::UpdateExp(args) => commands::run_update_exp(fpm, &args),
+        Command::UpdateAllExp(args) => commands::run_update_all_exp(fpm, &args),
+        Command::UpdateExpBatch(args) => commands::run_update_exp_batch(fpm, &args),
+        Command::UpdateNet(args) => commands::run_update_net(fpm, &args),
+        Command::UpdateDsp(args) => commands::run_update_dsp(fpm, &args),
+        Command::Recover(args) => commands::run_recover(fpm, &args),
+        Command::Inventory(args) => commands::run_inventory(fpm, &args, format),
+        Command::Status(args) => commands::run_status(fpm, &args, format),
+        Command::Benchmark(args) => commands::run_benchmark(fpm, &args, format),
+        Command::Stress(args) => commands::run_stress(fpm, &args, format),
+        Command::Drivers(args) => commands::run_drivers(fpm, &args, format),
+        Command::Script(args) => commands::run_script(fpm, &args, format),
+        Command::TestGi(args) => commands::run_test_gi(fpm, &args),
+        Command::MapLeds(args) => commands::run_map_leds(fpm, &args),
+        Command::MapSwitches(args) => commands::run_map_switches(fpm, &args),
+        Command::ResetNet(args) => commands::run_reset_net(fpm, &args),
+        Command::ResetExp(args) => commands::run_reset_exp(fpm, &args),
+        Command::Menu => commands::run_menu(fpm, format, config::get().offline),
+        #[cfg(feature = "mqtt")]
+        Command::Mqtt(args) => commands::run_mqtt(fpm, &args),
+        other => run_other(fpm, other, format),
+    }
+}
+
+/// Run the command recorded in the capture at `file` against the recorded replies.
+fn replay(file: &Path, format_flag: Option<OutputFormat>, format: OutputFormat) -> Exit {
+    let recording = match replay::Recording::load(file) {
+        Ok(recording) => recording,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Exit::Error;
         }
-        "list-net" | "net" => {
-            commands::run_list_net(&mut fpm);
+    };
+    let args: Vec<String> = std::iter::once("fast-pinball-utilities".to_string())
+        .chain(recording.args.clone())
+        .collect();
+    // The output format the command was recorded with, unless asked for another one
+    let (command, format) = match try_parse_cli(&args) {
+        Ok(recorded) => (
+            recorded.command.unwrap_or(Command::List),
+            format_flag.or(recorded.format).unwrap_or(format),
+        ),
+        Err(e) => {
+            eprintln!(
+                "{}: the recorded command line is not valid: {}",
+                file.display(),
+                e
+            );
+            return Exit::Error;
         }
-        "list" | "all" | _ => {
-            commands::run_list_exp(&mut fpm);
-            println!();
-            commands::run_list_net(&mut fpm);
+    };
+    if !uses_hardware(&command) {
+        eprintln!(
+            "{}: the recorded command does not talk to the hardware; there is nothing to replay.",
+            file.display()
+        );
+        return Exit::Error;
+    }
+    if format == OutputFormat::Text {
+        status!("Replaying {} ...", recording.args.join(" "));
+    }
+    let (fpm, transports) = recording.monitor();
+    let exit = run_connected(fpm, command, format);
+    for (port, transport) in transports {
+        let unanswered = transport.unanswered();
+        if unanswered > 0 {
+            eprintln!(
+                "{}: {} recorded replies were never asked for; the command took another course than when it was recorded.",
+                port, unanswered
+            );
         }
     }
+    exit
+}
+
+/// Whether `command` runs against connected hardware (see `run_connected`).
+fn uses_hardware(command: &Command) -> bool {
+    !matches!(
+        command,
+        Command::GetLatestFirmware(_)
+            | Command::ImportFirmware(_)
+            | Command::Firmware(_)
+            | Command::History(_)
+            | Command::Emulate(_)
+            | Command::WaitForDevice
+            | Command::Daemon(_)
+            | Command::Record { .. }
+            | Command::Replay { .. }
+    )
+}
+
+/// Commands that list or drive boards; `NoHardware` when none answered.
+fn run_other(fpm: &mut FastPinballMonitor, command: Command, format: OutputFormat) -> Exit {
+    match command {
+        Command::MonitorSwitches(args) => commands::run_monitor_switches(fpm, &args),
+        Command::TestDriver(args) => commands::run_test_driver(fpm, &args),
+        Command::TestLeds(args) => commands::run_test_leds(fpm, &args),
+        Command::TestServo(args) => commands::run_test_servo(fpm, &args),
+        Command::TestSeg(args) => commands::run_test_seg(fpm, &args),
+        Command::SetVolume(args) => commands::run_set_volume(fpm, &args),
+        Command::Term(args) => commands::run_term(fpm, &args),
+        Command::SendNet(args) => commands::run_send_net(fpm, &args),
+        Command::SendExp(args) => commands::run_send_exp(fpm, &args),
+        #[cfg(feature = "tui")]
+        Command::Dashboard(args) => commands::run_dashboard(fpm, &args),
+        Command::ListExp(args) => commands::run_list_exp(fpm, &args, format),
+        Command::ListNet => commands::run_list_net(fpm, format),
+        Command::ListSeg => commands::run_list_seg(fpm, format),
+        Command::ListAud => commands::run_list_aud(fpm, format),
+        Command::ListDsp => commands::run_list_dsp(fpm, format),
+        _ => commands::run_list_all(fpm, format),
+    }
 }
@@ -0,0 +1,292 @@
+// Counters and gauges for the `/metrics` endpoint of `serve`, in the Prometheus text
+// format. Ports opened by the protocols are wrapped with `wrap`, which counts the bytes
+// and errors on each port and times commands: from a write to the first data read back,
+// unless a read times out first.
+// The rest is recorded where it happens (switch events, flashes, board scans).
+
+use crate::protocol::FlashOutcome;
+use crate::protocol::transport::Transport;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::Instant;
+
+#[derive(Default)]
+struct Metrics {
+    ports: Mutex<BTreeMap<String, Arc<PortMetrics>>>,
+    switches_closed: AtomicU64,
+    switches_opened: AtomicU64,
+    /// Boards found by the last scan, by kind (`exp`, `net`).
+    boards: Mutex<BTreeMap<&'static str, usize>>,
+    flash: Mutex<Option<FlashProgress>>,
+    flashes: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+#[derive(Default)]
+struct PortMetrics {
+    rx_bytes: AtomicU64,
+    tx_bytes: AtomicU64,
+    errors: AtomicU64,
+    /// Commands answered, and the seconds they took in total.
+    latency: Mutex<(u64, f64)>,
+    /// When the last command was written, until it is answered or a read times out.
+    pending: Mutex<Option<Instant>>,
+}
+
+/// The flash running (or last run): bytes sent of the file's size.
+struct FlashProgress {
+    label: String,
+    sent: u64,
+    total: u64,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::default)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Wrap `port` so its traffic is counted under `port_name`.
+pub fn wrap(port_name: &str, port: Box<dyn Transport>) -> Box<dyn Transport> {
+    let counters = Arc::clone(
+        lock(&metrics().ports)
+            .entry(port_name.to_string())
+            .or_default(),
+    );
+    Box::new(MeteredPort {
+        inner: port,
+        metrics: counters,
+    })
+}
+
+pub fn record_switch_event(closed: bool) {
+    let counter = if closed {
+        &metrics().switches_closed
+    } else {
+        &metrics().switches_opened
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Number of boards of a kind (`exp` or `net`) the last scan found.
+pub fn set_board_count(kind: &'static str, count: usize) {
+    lock(&metrics().boards).insert(kind, count);
+}
+
+pub fn flash_progress(label: &str, sent: u64, total: u64) {
+    *lock(&metrics().flash) = Some(FlashProgress {
+        label: label.to_string(),
+        sent,
+        total,
+    });
+}
+
+pub fn record_flash(outcome: FlashOutcome) {
+    let name = match outcome {
+        FlashOutcome::Verified => "verified",
+        FlashOutcome::Unverified => "unverified",
+        FlashOutcome::Mismatch => "mismatch",
+        FlashOutcome::Failed => "failed",
+        FlashOutcome::Cancelled => "cancelled",
+    };
+    *lock(&metrics().flashes).entry(name).or_default() += 1;
+}
+
+/// Everything recorded so far, in the Prometheus text exposition format.
+pub fn render() -> String {
+    let m = metrics();
+    let ports: Vec<(String, Arc<PortMetrics>)> = lock(&m.ports)
+        .iter()
+        .map(|(name, p)| (name.clone(), Arc::clone(p)))
+        .collect();
+    let per_port = |value: fn(&PortMetrics) -> String| -> Vec<(String, String)> {
+        ports
+            .iter()
+            .map(|(name, p)| (labels(&[("port", name)]), value(p)))
+            .collect()
+    };
+
+    let mut out = String::new();
+    family(
+        &mut out,
+        "fast_serial_received_bytes_total",
+        "counter",
+        "Bytes read from each port.",
+        per_port(|p| p.rx_bytes.load(Ordering::Relaxed).to_string()),
+    );
+    family(
+        &mut out,
+        "fast_serial_sent_bytes_total",
+        "counter",
+        "Bytes written to each port.",
+        per_port(|p| p.tx_bytes.load(Ordering::Relaxed).to_string()),
+    );
+    family(
+        &mut out,
+        "fast_serial_errors_total",
+        "counter",
+        "Failed reads and writes on each port, other than read timeouts.",
+        per_port(|p| p.errors.load(Ordering::Relaxed).to_string()),
+    );
+    // A summary without quantiles: just the `_sum` and `_count` series
+    let name = "fast_serial_command_latency_seconds";
+    let _ = writeln!(
+        out,
+        "# HELP {} Time from a command to the first data read back.",
+        name
+    );
+    let _ = writeln!(out, "# TYPE {} summary", name);
+    for (port, p) in ports.iter() {
+        let (count, sum) = *lock(&p.latency);
+        let port = labels(&[("port", port)]);
+        let _ = writeln!(out, "{}_sum{} {:.6}", name, port, sum);
+        let _ = writeln!(out, "{}_count{} {}", name, port, count);
+    }
+
+    family(
+        &mut out,
+        "fast_switch_events_total",
+        "counter",
+        "Switch changes reported by the NET processor.",
+        vec![
+            (
+                labels(&[("state", "closed")]),
+                m.switches_closed.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                labels(&[("state", "open")]),
+                m.switches_opened.load(Ordering::Relaxed).to_string(),
+            ),
+        ],
+    );
+    family(
+        &mut out,
+        "fast_boards",
+        "gauge",
+        "Boards found by the last scan.",
+        lock(&m.boards)
+            .iter()
+            .map(|(kind, n)| (labels(&[("kind", kind)]), n.to_string()))
+            .collect(),
+    );
+    let flash = match &*lock(&m.flash) {
+        Some(f) => vec![
+            (
+                labels(&[("file", &f.label), ("quantity", "sent")]),
+                f.sent.to_string(),
+            ),
+            (
+                labels(&[("file", &f.label), ("quantity", "total")]),
+                f.total.to_string(),
+            ),
+        ],
+        None => Vec::new(),
+    };
+    family(
+        &mut out,
+        "fast_flash_bytes",
+        "gauge",
+        "Bytes of the current (or last) firmware file sent, and its size.",
+        flash,
+    );
+    family(
+        &mut out,
+        "fast_flashes_total",
+        "counter",
+        "Flashes run through the API, by outcome.",
+        lock(&m.flashes)
+            .iter()
+            .map(|(outcome, n)| (labels(&[("outcome", outcome)]), n.to_string()))
+            .collect(),
+    );
+    out
+}
+
+// One metric: its help and type lines, then a sample per (labels, value)
+fn family(out: &mut String, name: &str, kind: &str, help: &str, samples: Vec<(String, String)>) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{} {}", name, labels, value);
+    }
+}
+
+// `{a="x",b="y"}`
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<String> = pairs
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+struct MeteredPort {
+    inner: Box<dyn Transport>,
+    metrics: Arc<PortMetrics>,
+}
+
+impl MeteredPort {
+    fn count_error<T>(&self, result: &io::Result<T>) {
+        if let Err(e) = result
+            && !matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock)
+        {
+            self.metrics.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Read for MeteredPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.inner.read(buf);
+        self.count_error(&result);
+        // A read that times out ends the wait for an answer: commands nobody answers
+        // (e.g. probes of empty addresses) are not counted
+        let sent = lock(&self.metrics.pending).take();
+        if let Ok(n) = result
+            && n > 0
+        {
+            self.metrics.rx_bytes.fetch_add(n as u64, Ordering::Relaxed);
+            if let Some(sent) = sent {
+                let mut latency = lock(&self.metrics.latency);
+                latency.0 += 1;
+                latency.1 += sent.elapsed().as_secs_f64();
+            }
+        }
+        result
+    }
+}
+
+impl Write for MeteredPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.inner.write(buf);
+        self.count_error(&result);
+        if let Ok(n) = result {
+            self.metrics.tx_bytes.fetch_add(n as u64, Ordering::Relaxed);
+            *lock(&self.metrics.pending) = Some(Instant::now());
+        }
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Transport for MeteredPort {
+    fn bytes_to_read(&self) -> io::Result<u32> {
+        self.inner.bytes_to_read()
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(MeteredPort {
+            inner: self.inner.try_clone()?,
+            metrics: Arc::clone(&self.metrics),
+        }))
+    }
+}
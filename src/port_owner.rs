@@ -0,0 +1,156 @@
+// Finding out which program holds a serial port. MPF or a serial monitor keeping the
+// NET or EXP port open is the usual reason a port cannot be opened, or is opened but
+// answers someone else; naming the program turns an opaque OS error into something an
+// operator can act on.
+
+use std::fmt;
+use std::path::Path;
+
+/// What to do about a port another program holds.
+const REMEDY: &str = "Stop that program (e.g. quit MPF or close the serial monitor) and try again.";
+
+/// A process that has a serial port open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortOwner {
+    pub pid: u32,
+    /// Command line, or the process name when that is not readable.
+    pub command: String,
+}
+
+impl fmt::Display for PortOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (pid {})", self.command, self.pid)
+    }
+}
+
+/// Other processes that have `port` open. Only Linux tells, through /proc, and only for
+/// processes this user may inspect; elsewhere, and for `tcp://` ports, this is empty.
+pub fn owners(port: &str) -> Vec<PortOwner> {
+    #[cfg(target_os = "linux")]
+    {
+        linux_owners(port)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = port;
+        Vec::new()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn linux_owners(port: &str) -> Vec<PortOwner> {
+    let Ok(device) = std::fs::canonicalize(port) else {
+        return Vec::new();
+    };
+    let me = std::process::id();
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    for process in processes.flatten() {
+        let Some(pid) = process
+            .file_name()
+            .to_str()
+            .and_then(|n| n.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if pid == me {
+            continue;
+        }
+        // Other users' descriptors are not readable; those processes are skipped
+        let Ok(fds) = std::fs::read_dir(process.path().join("fd")) else {
+            continue;
+        };
+        if !fds
+            .flatten()
+            .any(|fd| std::fs::read_link(fd.path()).is_ok_and(|target| target == device))
+        {
+            continue;
+        }
+        let cmdline = std::fs::read(process.path().join("cmdline")).unwrap_or_default();
+        let command = String::from_utf8_lossy(&cmdline)
+            .split('\0')
+            .filter(|arg| !arg.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let command = if command.is_empty() {
+            std::fs::read_to_string(process.path().join("comm"))
+                .map(|c| c.trim().to_string())
+                .unwrap_or_else(|_| "unknown".to_string())
+        } else {
+            command
+        };
+        found.push(PortOwner { pid, command });
+    }
+    found
+}
+
+/// Whether an open error means another program holds the port: serialport reports a
+/// port locked by someone else (on Windows: opened by someone else) as `NoDevice`.
+pub fn is_busy_error(port: &str, error: &serialport::Error) -> bool {
+    error.kind() == serialport::ErrorKind::NoDevice && (cfg!(windows) || Path::new(port).exists())
+}
+
+/// An error when another process has `port` open, naming it. `label` (e.g. `NET`) says
+/// which port it is.
+pub fn check_free(label: &str, port: &str) -> Result<(), String> {
+    let owners = owners(port);
+    if owners.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "{} port {} is in use by {}. {}",
+        label,
+        port,
+        join(&owners),
+        REMEDY
+    ))
+}
+
+/// The message for a failure to open `port`, naming the program that holds it where
+/// that is the cause.
+pub fn describe_open_error(label: &str, port: &str, error: &serialport::Error) -> String {
+    let failed = format!("Failed to open {} port {}: {}", label, port, error);
+    if !is_busy_error(port, error) {
+        return failed;
+    }
+    match owners(port) {
+        owners if owners.is_empty() => format!(
+            "{}. Another program (e.g. MPF or a serial monitor) probably has it open. {}",
+            failed, REMEDY
+        ),
+        owners => format!("{}. It is in use by {}. {}", failed, join(&owners), REMEDY),
+    }
+}
+
+/// A hint naming the ports among `ports` that other programs hold, for when discovery
+/// found nothing on them; `None` when none is known to be held.
+pub fn busy_ports_hint(ports: &[String]) -> Option<String> {
+    let mut busy = Vec::new();
+    for port in ports {
+        let owners = owners(port);
+        if !owners.is_empty() {
+            busy.push(format!("{} is in use by {}.", port, join(&owners)));
+            continue;
+        }
+        // Nothing to go on but trying to open it
+        if let Err(e) = serialport::new(port.as_str(), 921_600).open()
+            && is_busy_error(port, &e)
+        {
+            busy.push(format!("{} is in use by another program.", port));
+        }
+    }
+    if busy.is_empty() {
+        return None;
+    }
+    Some(format!("{} {}", busy.join(" "), REMEDY))
+}
+
+fn join(owners: &[PortOwner]) -> String {
+    owners
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,103 @@
+use crate::protocol::responses::IdResponse;
+use crate::protocol::transport::{TCP_PREFIX, Transport, connect_tcp};
+use crate::protocol::{read_response, split_lines};
+use serialport::{DataBits, FlowControl, Parity, StopBits};
+use std::io::Write;
+use std::time::Duration;
+
+/// Highest volume level accepted by `AV:` and `AS:`.
+pub const AUD_MAX_VOLUME: u8 = 63;
+
+/// The FAST audio interface port, which controls the amplifier volume levels.
+pub struct AudProtocol {
+    pub serial_port: Box<dyn Transport>,
+    pub port_name: String,
+}
+
+impl AudProtocol {
+    /// Open the given serial port (or `tcp://host:port` endpoint).
+    pub fn try_new(port: String) -> serialport::Result<Self> {
+        let timeout = crate::config::get().timeouts.exp_read();
+        let serial_port: Box<dyn Transport> = match port.strip_prefix(TCP_PREFIX) {
+            Some(addr) => Box::new(connect_tcp(addr, timeout)?),
+            None => Box::new(
+                serialport::new(port.clone(), 921_600)
+                    .data_bits(DataBits::Eight)
+                    .parity(Parity::None)
+                    .stop_bits(StopBits::One)
+                    .dtr_on_open(true)
+                    .flow_control(FlowControl::None)
+                    .timeout(timeout)
+                    .open()?,
+            ),
+        };
+        let serial_port = crate::metrics::wrap(&port, crate::capture::wrap(&port, serial_port));
+        Ok(Self::with_transport(port, serial_port))
+    }
+
+    /// Talk over an already open transport, e.g. a `MockTransport` in tests.
+    pub fn with_transport(port_name: String, serial_port: Box<dyn Transport>) -> Self {
+        Self {
+            serial_port,
+            port_name,
+        }
+    }
+
+    /// Query the processor with `ID:`, returning its (board name, firmware version).
+    pub fn id(&mut self) -> Option<(String, String)> {
+        let _ = self.receive();
+        self.send(b"ID:\r");
+        std::thread::sleep(Duration::from_millis(10));
+        let resp = self.receive();
+        IdResponse::find(&resp).map(|id| (id.board, id.version))
+    }
+
+    /// Set the main (speaker) volume, 0 to `AUD_MAX_VOLUME`, with `AV:`.
+    pub fn set_main_volume(&mut self, level: u8) -> String {
+        self.set_volume("AV", level)
+    }
+
+    /// Set the subwoofer volume, 0 to `AUD_MAX_VOLUME`, with `AS:`.
+    pub fn set_sub_volume(&mut self, level: u8) -> String {
+        self.set_volume("AS", level)
+    }
+
+    // Returns whatever the board answered, e.g. `AV:P`
+    fn set_volume(&mut self, command: &str, level: u8) -> String {
+        let _ = self.receive();
+        self.send(format!("{}:{:02X}\r", command, level.min(AUD_MAX_VOLUME)).as_bytes());
+        std::thread::sleep(Duration::from_millis(10));
+        self.receive()
+    }
+
+    pub fn send(&mut self, command: &[u8]) {
+        log::debug!(
+            "AUD {} TX {:?}",
+            self.port_name,
+            String::from_utf8_lossy(command)
+        );
+        // Best-effort write; avoid panicking on errors
+        if let Err(e) = self.serial_port.write_all(command) {
+            log::warn!("AUD {} write failed: {}", self.port_name, e);
+        }
+        let _ = self.serial_port.flush();
+    }
+
+    /// Receive complete response lines, reading until the port goes quiet
+    /// (see `read_response`).
+    pub fn receive_lines(&mut self) -> Vec<String> {
+        let lines = split_lines(&read_response(
+            self.serial_port.as_mut(),
+            crate::config::get().timeouts.receive(),
+        ));
+        for line in lines.iter() {
+            log::debug!("AUD {} RX {:?}", self.port_name, line);
+        }
+        lines
+    }
+
+    /// Receive a response as a single string, one line per message.
+    pub fn receive(&mut self) -> String {
+        self.receive_lines().join("\n")
+    }
+}
@@ -0,0 +1,269 @@
+use crate::cancel::CancelToken;
+use crate::commands::utils::{normalize_version, version_key};
+use crate::flash_lock::FlashLock;
+use crate::history::{self, FlashRecord};
+use crate::protocol::firmware_file::FirmwareFormat;
+use crate::protocol::responses::{IdResponse, ResponseParser};
+use crate::protocol::transport::{TCP_PREFIX, Transport, connect_tcp};
+use crate::protocol::{
+    FlashEvent, FlashObserver, FlashOutcome, ProgressDisplay, TransferStats, flash_done,
+    read_response, split_lines, stream_firmware, stream_stopped,
+};
+use serialport::{DataBits, FlowControl, Parity, StopBits};
+use std::io::Write;
+use std::time::Duration;
+
+/// Bootloader completion tokens accepted after a display controller flash (those of
+/// the NET and of the EXP bootloader).
+const BOOT_TOKENS: [&str; 2] = ["!B:02", "!BL2040:02"];
+
+/// A FAST display controller port (RGB DMD and other display boards).
+pub struct DspProtocol {
+    pub serial_port: Box<dyn Transport>,
+    pub port_name: String,
+    /// Transfer of the firmware last sent by `flash_file`.
+    pub last_transfer: Option<TransferStats>,
+    /// Stops scans and flashes over this port once cancelled (see `cancel`).
+    pub cancel: CancelToken,
+}
+
+impl DspProtocol {
+    /// Open the given serial port (or `tcp://host:port` endpoint).
+    pub fn try_new(port: String) -> serialport::Result<Self> {
+        let timeout = crate::config::get().timeouts.exp_read();
+        let serial_port: Box<dyn Transport> = match port.strip_prefix(TCP_PREFIX) {
+            Some(addr) => Box::new(connect_tcp(addr, timeout)?),
+            None => Box::new(
+                serialport::new(port.clone(), 921_600)
+                    .data_bits(DataBits::Eight)
+                    .parity(Parity::None)
+                    .stop_bits(StopBits::One)
+                    .dtr_on_open(true)
+                    .flow_control(FlowControl::None)
+                    .timeout(timeout)
+                    .open()?,
+            ),
+        };
+        let serial_port = crate::metrics::wrap(&port, crate::capture::wrap(&port, serial_port));
+        Ok(Self::with_transport(port, serial_port))
+    }
+
+    /// Talk over an already open transport, e.g. a `MockTransport` in tests.
+    pub fn with_transport(port_name: String, serial_port: Box<dyn Transport>) -> Self {
+        Self {
+            serial_port,
+            port_name,
+            last_transfer: None,
+            cancel: CancelToken::new(),
+        }
+    }
+
+    /// Query the processor with `ID:`, returning its (board name, firmware version).
+    pub fn id(&mut self) -> Option<(String, String)> {
+        let _ = self.receive();
+        self.send(b"ID:\r");
+        std::thread::sleep(Duration::from_millis(10));
+        let resp = self.receive();
+        IdResponse::find(&resp).map(|id| (id.board, id.version))
+    }
+
+    /// Flash the display controller to `version` from the firmware directory, found
+    /// under `{BoardName}_DSP` for the board name it reports.
+    pub fn update_firmware(&mut self, version: &str) -> FlashOutcome {
+        self.update_firmware_with(version, &mut ProgressDisplay::default())
+    }
+
+    /// `update_firmware`, telling `observer` how the flash goes instead of showing the
+    /// CLI's progress display.
+    pub fn update_firmware_with(
+        &mut self,
+        version: &str,
+        observer: &mut dyn FlashObserver,
+    ) -> FlashOutcome {
+        let Some((board, _)) = self.id() else {
+            eprintln!(
+                "Display controller on {} did not answer ID:.",
+                self.port_name
+            );
+            return flash_done(observer, FlashOutcome::Failed);
+        };
+        let key = format!("{}_DSP", board);
+        let normalized_version = normalize_version(version);
+        let Some(file_path) = crate::firmware_repo::get()
+            .path(&key, &normalized_version)
+            .map(str::to_string)
+        else {
+            eprintln!(
+                "Firmware not found for key '{}' version '{}'.",
+                key, normalized_version
+            );
+            return flash_done(observer, FlashOutcome::Failed);
+        };
+        self.flash_file_with(&file_path, Some(&normalized_version), observer)
+    }
+
+    /// Stream a firmware file the same way as EXP and NET firmware, wait for the
+    /// bootloader to finish, then check `ID:` against `expected_version` (when given).
+    /// The attempt is recorded in the flash history.
+    pub fn flash_file(&mut self, file_path: &str, expected_version: Option<&str>) -> FlashOutcome {
+        self.flash_file_with(file_path, expected_version, &mut ProgressDisplay::default())
+    }
+
+    /// `flash_file`, telling `observer` how the flash goes instead of showing the CLI's
+    /// progress display.
+    pub fn flash_file_with(
+        &mut self,
+        file_path: &str,
+        expected_version: Option<&str>,
+        observer: &mut dyn FlashObserver,
+    ) -> FlashOutcome {
+        if self.cancel.is_cancelled() {
+            return flash_done(observer, FlashOutcome::Cancelled);
+        }
+        let _lock = match FlashLock::acquire(&self.port_name) {
+            Ok(lock) => lock,
+            Err(e) => {
+                eprintln!("{}", e);
+                return flash_done(observer, FlashOutcome::Failed);
+            }
+        };
+        let old_id = self.id();
+        let start = std::time::Instant::now();
+        let outcome = self.stream_and_verify(file_path, expected_version, observer);
+        history::record(&FlashRecord {
+            board: old_id.as_ref().map(|(board, _)| board.clone()),
+            old_version: old_id.map(|(_, version)| version),
+            new_version: expected_version.map(str::to_string),
+            bytes_sent: self.last_transfer.map(|t| t.bytes_sent),
+            ..FlashRecord::new("DSP", &self.port_name, file_path, outcome, start.elapsed())
+        });
+        flash_done(observer, outcome)
+    }
+
+    fn stream_and_verify(
+        &mut self,
+        file_path: &str,
+        expected_version: Option<&str>,
+        observer: &mut dyn FlashObserver,
+    ) -> FlashOutcome {
+        let _ = self.receive();
+        self.last_transfer = None;
+        match stream_firmware(
+            self.serial_port.as_mut(),
+            &format!("DSP {}", self.port_name),
+            file_path,
+            // The display controller's bootloader also takes raw images
+            &FirmwareFormat::ALL,
+            crate::config::get().flash.exp_line_delay(),
+            observer,
+            &self.cancel,
+        ) {
+            Ok(transfer) => self.last_transfer = Some(transfer),
+            Err(e) => {
+                eprintln!("{}", e);
+                if stream_stopped(&self.cancel) {
+                    eprintln!(
+                        "The display controller is left in its bootloader; run update-dsp again."
+                    );
+                    return FlashOutcome::Cancelled;
+                }
+                return FlashOutcome::Failed;
+            }
+        }
+
+        let start_wait = std::time::Instant::now();
+        let boot_timeout = crate::config::get().timeouts.bootloader();
+        // Raw lines, as either bootloader's token may come back
+        let mut parser = ResponseParser::<String>::new();
+        let mut saw_boot_ok = false;
+        while start_wait.elapsed() < boot_timeout {
+            let bytes = read_response(
+                self.serial_port.as_mut(),
+                crate::config::get().timeouts.receive(),
+            );
+            if parser
+                .push(&bytes)
+                .iter()
+                .any(|l| BOOT_TOKENS.contains(&l.as_str()))
+            {
+                saw_boot_ok = true;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        if saw_boot_ok {
+            status!("Bootloader reported completion.");
+            observer.event(&FlashEvent::BootloaderAck);
+        } else {
+            eprintln!("Timed out waiting for bootloader completion. Proceeding to ID check...");
+        }
+
+        let Some(expected_ver) = expected_version else {
+            status!("No expected version given; skipping post-flash verification.");
+            return FlashOutcome::Unverified;
+        };
+        observer.event(&FlashEvent::Verifying);
+        // id() gives up after one read, so keep asking for the verification window
+        let start_verify = std::time::Instant::now();
+        let mut reported = None;
+        while reported.is_none() && start_verify.elapsed() < crate::config::get().timeouts.verify()
+        {
+            reported = self.id();
+        }
+        match reported {
+            Some((board, version)) if version_key(&version) == version_key(expected_ver) => {
+                status!(
+                    "Firmware update verified: display controller {} reports version {}",
+                    board,
+                    version
+                );
+                FlashOutcome::Verified
+            }
+            Some((_, version)) => {
+                eprintln!(
+                    "Warning: Firmware version mismatch. Expected '{}', got '{}'.",
+                    expected_ver, version
+                );
+                FlashOutcome::Mismatch
+            }
+            None => {
+                eprintln!(
+                    "Warning: No ID response; cannot verify flashed version {}.",
+                    expected_ver
+                );
+                FlashOutcome::Mismatch
+            }
+        }
+    }
+
+    pub fn send(&mut self, command: &[u8]) {
+        log::debug!(
+            "DSP {} TX {:?}",
+            self.port_name,
+            String::from_utf8_lossy(command)
+        );
+        // Best-effort write; avoid panicking on errors
+        if let Err(e) = self.serial_port.write_all(command) {
+            log::warn!("DSP {} write failed: {}", self.port_name, e);
+        }
+        let _ = self.serial_port.flush();
+    }
+
+    /// Receive complete response lines, reading until the port goes quiet
+    /// (see `read_response`).
+    pub fn receive_lines(&mut self) -> Vec<String> {
+        let lines = split_lines(&read_response(
+            self.serial_port.as_mut(),
+            crate::config::get().timeouts.receive(),
+        ));
+        for line in lines.iter() {
+            log::debug!("DSP {} RX {:?}", self.port_name, line);
+        }
+        lines
+    }
+
+    /// Receive a response as a single string, one line per message.
+    pub fn receive(&mut self) -> String {
+        self.receive_lines().join("\n")
+    }
+}
@@ -1,175 +1,270 @@
-use indicatif::{ProgressBar, ProgressStyle};
-use serialport::{DataBits, FlowControl, Parity, SerialPort, StopBits};
-use std::io::{BufReader, Read, Write};
-use std::time::Duration;
+use crate::cancel::CancelToken;
+use crate::commands::utils::{normalize_version, version_key};
+use crate::firmware_repo::parse_firmware_filename;
+use crate::flash_lock::FlashLock;
+use crate::history::{self, FlashRecord};
+use crate::protocol::firmware_file::FirmwareFormat;
+use crate::protocol::responses::{ExpResponse, IdResponse, ResponseParser};
+use crate::protocol::transport::{TCP_PREFIX, Transport, connect_tcp};
+use crate::protocol::{
+    FlashEvent, FlashObserver, FlashOutcome, ProgressDisplay, RESET_POLL_INTERVAL, TransferStats,
+    flash_done, read_reply, read_response, split_lines, stream_firmware, stream_stopped,
+};
+use serialport::{DataBits, FlowControl, Parity, StopBits};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How long a board has to answer `ID@` before a flash.
+const VERSION_QUERY_WAIT: Duration = Duration::from_millis(100);
 
 pub struct ExpProtocol {
-    pub serial_port: Box<dyn SerialPort>,
+    pub serial_port: Box<dyn Transport>,
+    pub port_name: String,
+    /// Transfer of the firmware last sent by `flash_file`.
+    pub last_transfer: Option<TransferStats>,
+    /// Let `flash_file` send firmware made for another board than the one that answers
+    /// at the address (`--force`). Off by default.
+    pub allow_board_mismatch: bool,
+    /// Stops scans and flashes over this port once cancelled (see `cancel`).
+    pub cancel: CancelToken,
 }
 
 impl ExpProtocol {
+    /// Open the given serial port (or `tcp://host:port` endpoint).
+    ///
+    /// # Panics
+    ///
+    /// When the port cannot be opened, with a message naming the program holding it
+    /// where that is the cause. Use `try_new` to handle the error instead.
     pub fn new(port: String) -> Self {
-        let serial_port = serialport::new(port, 921_600)
-            .data_bits(DataBits::Eight)
-            .parity(Parity::None)
-            .stop_bits(StopBits::One)
-            .dtr_on_open(true)
-            .flow_control(FlowControl::None)
-            .timeout(Duration::from_millis(5))
-            .open()
-            .unwrap();
-
-        Self { serial_port }
+        Self::try_new(port.clone()).unwrap_or_else(|e| {
+            panic!(
+                "{}",
+                crate::port_owner::describe_open_error("EXP", &port, &e)
+            )
+        })
+    }
+
+    /// Open the given serial port (or `tcp://host:port` endpoint), returning the error
+    /// instead of panicking.
+    pub fn try_new(port: String) -> serialport::Result<Self> {
+        let timeout = crate::config::get().timeouts.exp_read();
+        let serial_port: Box<dyn Transport> = match port.strip_prefix(TCP_PREFIX) {
+            Some(addr) => Box::new(connect_tcp(addr, timeout)?),
+            None => Box::new(
+                serialport::new(port.clone(), 921_600)
+                    .data_bits(DataBits::Eight)
+                    .parity(Parity::None)
+                    .stop_bits(StopBits::One)
+                    .dtr_on_open(true)
+                    .flow_control(FlowControl::None)
+                    .timeout(timeout)
+                    .open()?,
+            ),
+        };
+        let serial_port = crate::metrics::wrap(&port, crate::capture::wrap(&port, serial_port));
+        Ok(Self::with_transport(port, serial_port))
+    }
+
+    /// Talk over an already open transport, e.g. a `MockTransport` in tests.
+    pub fn with_transport(port_name: String, serial_port: Box<dyn Transport>) -> Self {
+        Self {
+            serial_port,
+            port_name,
+            last_transfer: None,
+            allow_board_mismatch: false,
+            cancel: CancelToken::new(),
+        }
     }
 
     /// Update EXP board firmware by board address and version.
     ///
-    /// Looks up the board type using EXP_ADDRESS_MAP and resolves the firmware
-    /// file path from AVAILABLE_FIRMWARE_VERSIONS using key `{BoardType}_EXP`
+    /// Looks up the board type in the EXP address map (see `board_map`) and resolves the
+    /// firmware file path from the firmware repo using key `{BoardType}_EXP`
     /// and the provided version (normalized as `major.minor` with a two-digit
     /// minor, e.g., `1.05`). Streams the file to the serial port.
-    pub fn update_firmware(&mut self, address_hex: &str, version: &str) {
-        use crate::constants::{AVAILABLE_FIRMWARE_VERSIONS, EXP_ADDRESS_MAP};
+    pub fn update_firmware(&mut self, address_hex: &str, version: &str) -> FlashOutcome {
+        self.update_firmware_with(address_hex, version, &mut ProgressDisplay::default())
+    }
 
+    /// `update_firmware`, telling `observer` how the flash goes instead of showing the
+    /// CLI's progress display.
+    pub fn update_firmware_with(
+        &mut self,
+        address_hex: &str,
+        version: &str,
+        observer: &mut dyn FlashObserver,
+    ) -> FlashOutcome {
         // Find the board type by address (case-insensitive match on hex string)
-        let addr_upper = address_hex.to_ascii_uppercase();
-        let board_type = EXP_ADDRESS_MAP
-            .iter()
-            .find(|(addr, _)| addr.to_ascii_uppercase() == addr_upper)
-            .map(|(_, bt)| *bt);
+        let board_type = crate::board_map::get().exp_board_type(address_hex);
 
         if board_type.is_none() {
             eprintln!("Unknown EXP board address: {}", address_hex);
-            return;
+            return flash_done(observer, FlashOutcome::Failed);
         }
         let board_type = board_type.unwrap();
 
         // Normalize version to the stored format (e.g., 1.5 -> 1.05)
-        let normalized_version = {
-            let mut out = version.to_string();
-            if let Some((maj_s, min_s)) = version.split_once('.') {
-                if let (Ok(maj), Ok(min)) = (maj_s.parse::<u32>(), min_s.parse::<u32>()) {
-                    out = format!("{}.{}", maj, format!("{:02}", min));
-                }
-            }
-            out
-        };
+        let normalized_version = normalize_version(version);
 
         // Build key and resolve file path
         let key = format!("{}_{}", board_type, "EXP");
-        let file_path_opt = AVAILABLE_FIRMWARE_VERSIONS
-            .get(&key)
-            .and_then(|inner| inner.get(&normalized_version))
-            .cloned();
+        let repo = crate::firmware_repo::get();
+        let file_path_opt = repo.path(&key, &normalized_version).map(str::to_string);
 
         let Some(file_path) = file_path_opt else {
             eprintln!(
                 "Firmware not found for key '{}' version '{}'. Available: {:?}",
                 key,
                 normalized_version,
-                AVAILABLE_FIRMWARE_VERSIONS
-                    .get(&key)
-                    .map(|m| m.keys().cloned().collect::<Vec<_>>())
+                repo.versions(&key)
             );
-            return;
+            return flash_done(observer, FlashOutcome::Failed);
         };
 
-        // Target the correct board address with the EXP Address command (lowercase per spec example)
-        self.send(format!("ea:{}\r", address_hex).into_bytes());
-        std::thread::sleep(Duration::from_millis(10));
-        // Optionally read any immediate response/echo to clear buffer
-        let _ = self.receive();
+        self.flash_file_with(address_hex, &file_path, Some(&normalized_version), observer)
+    }
 
-        // Open file and stream line by line (as bytes), preserving existing line endings (CRLF)
-        // Display progress using indicatif
-        let total_size = match std::fs::metadata(&file_path) {
-            Ok(m) => m.len(),
-            Err(_) => 0,
-        };
+    /// Stream an arbitrary firmware file to the EXP board at `address_hex`.
+    ///
+    /// When `expected_version` is given the board's ID response is checked against
+    /// it afterwards. The attempt is recorded in the flash history.
+    ///
+    /// Firmware whose file name is for another board than the one answering `ID@` at
+    /// the address is refused (unless `allow_board_mismatch`): a stale address map must
+    /// not get one board's firmware streamed into another. Boards that do not answer,
+    /// e.g. ones stuck in their bootloader, and files not named like firmware files are
+    /// not checked.
+    pub fn flash_file(
+        &mut self,
+        address_hex: &str,
+        file_path: &str,
+        expected_version: Option<&str>,
+    ) -> FlashOutcome {
+        self.flash_file_with(
+            address_hex,
+            file_path,
+            expected_version,
+            &mut ProgressDisplay::default(),
+        )
+    }
 
-        let pb = if total_size > 0 {
-            let pb = ProgressBar::new(total_size);
-            let style = ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) - {msg}")
-                .unwrap()
-                .progress_chars("##-");
-            pb.set_style(style);
-            pb.set_message(format!("Flashing {}", file_path));
-            pb
-        } else {
-            let pb = ProgressBar::new_spinner();
-            pb.enable_steady_tick(Duration::from_millis(100));
-            pb.set_message(format!("Flashing {} (size unknown)", file_path));
-            let style = ProgressStyle::with_template(
-                "{spinner:.green} {elapsed_precise} {bytes} sent - {msg}",
-            )
-            .unwrap();
-            pb.set_style(style);
-            pb
+    /// `flash_file`, telling `observer` how the flash goes instead of showing the CLI's
+    /// progress display.
+    pub fn flash_file_with(
+        &mut self,
+        address_hex: &str,
+        file_path: &str,
+        expected_version: Option<&str>,
+        observer: &mut dyn FlashObserver,
+    ) -> FlashOutcome {
+        let board_type = crate::board_map::get()
+            .exp_board_type(address_hex)
+            .unwrap_or("EXP board");
+
+        if self.cancel.is_cancelled() {
+            return flash_done(observer, FlashOutcome::Cancelled);
+        }
+        let _lock = match FlashLock::acquire(&self.port_name) {
+            Ok(lock) => lock,
+            Err(e) => {
+                eprintln!("{}", e);
+                return flash_done(observer, FlashOutcome::Failed);
+            }
         };
+        let id = self.board_id(address_hex);
+        if let Some(id) = &id
+            && !self.allow_board_mismatch
+            && let Some((file_board, _, _)) = parse_firmware_filename(Path::new(file_path))
+            && !id.board.is_empty()
+            && !file_board.eq_ignore_ascii_case(&id.board)
+        {
+            eprintln!(
+                "Refusing to flash {} firmware ({}) to the {} at address {}. Check the firmware file, or use --force if you are sure.",
+                file_board, file_path, id.board, address_hex
+            );
+            return flash_done(observer, FlashOutcome::Failed);
+        }
+        let old_version = id.map(|id| id.version);
+        let start = Instant::now();
+        let outcome = self.stream_and_verify(
+            board_type,
+            address_hex,
+            file_path,
+            expected_version,
+            observer,
+        );
+        history::record(&FlashRecord {
+            address: Some(address_hex.to_string()),
+            board: Some(board_type.to_string()),
+            old_version,
+            new_version: expected_version.map(str::to_string),
+            bytes_sent: self.last_transfer.map(|t| t.bytes_sent),
+            ..FlashRecord::new("EXP", &self.port_name, file_path, outcome, start.elapsed())
+        });
+        flash_done(observer, outcome)
+    }
 
-        match std::fs::File::open(&file_path) {
-            Ok(file) => {
-                use std::io::BufRead;
-                let mut reader = BufReader::new(file);
-                let mut line: Vec<u8> = Vec::with_capacity(1024);
-                let mut bytes_sent: u64 = 0;
-                loop {
-                    line.clear();
-                    match reader.read_until(b'\r', &mut line) {
-                        Ok(0) => break, // EOF
-                        Ok(_n) => {
-                            let _ = self.serial_port.write_all(&line);
-                            let _ = self.serial_port.flush();
-
-                            // Update progress bar
-                            bytes_sent = bytes_sent.saturating_add(line.len() as u64);
-                            if total_size > 0 {
-                                pb.set_position(bytes_sent.min(total_size));
-                            } else {
-                                pb.set_message(format!(
-                                    "Flashing {} ({} bytes sent)",
-                                    file_path, bytes_sent
-                                ));
-                            }
-
-                            // Small delay between chunks
-                            std::thread::sleep(Duration::from_millis(200));
-                        }
-                        Err(e) => {
-                            eprintln!("Failed while reading firmware file '{}': {}", file_path, e,);
-                            break;
-                        }
-                    }
-                }
+    // What the board at `address_hex` reports itself as, if it answers
+    fn board_id(&mut self, address_hex: &str) -> Option<IdResponse> {
+        let _ = self.receive();
+        self.send(format!("ID@{}:\r", address_hex).into_bytes());
+        IdResponse::find(&self.receive_within(VERSION_QUERY_WAIT))
+    }
 
-                // Finish the progress bar
-                if total_size > 0 {
-                    pb.finish_with_message("Done");
-                } else {
-                    pb.finish_and_clear();
-                }
-            }
+    fn stream_and_verify(
+        &mut self,
+        board_type: &str,
+        address_hex: &str,
+        file_path: &str,
+        expected_version: Option<&str>,
+        observer: &mut dyn FlashObserver,
+    ) -> FlashOutcome {
+        // Target the correct board address with the EXP Address command (lowercase per
+        // spec example)
+        self.send(format!("ea:{}\r", address_hex).into_bytes());
+        std::thread::sleep(Duration::from_millis(10));
+        // Optionally read any immediate response/echo to clear buffer
+        let _ = self.receive();
+
+        // Stream the file line by line, preserving existing line endings
+        self.last_transfer = None;
+        match stream_firmware(
+            self.serial_port.as_mut(),
+            &format!("EXP {}", self.port_name),
+            file_path,
+            &FirmwareFormat::RECORDS,
+            crate::config::get().flash.exp_line_delay(),
+            observer,
+            &self.cancel,
+        ) {
+            Ok(transfer) => self.last_transfer = Some(transfer),
             Err(e) => {
-                pb.finish_and_clear();
-                eprintln!("Failed to open firmware file '{}': {}", file_path, e,);
+                eprintln!("{}", e);
+                if stream_stopped(&self.cancel) {
+                    eprintln!(
+                        "The board at address {} is left in its bootloader; run recover to flash it again.",
+                        address_hex
+                    );
+                    return FlashOutcome::Cancelled;
+                }
+                return FlashOutcome::Failed;
             }
         }
 
         // Wait for bootloader completion acknowledgment "!BL2040:02"
-        let mut accumulate = String::new();
-        let start_wait = std::time::Instant::now();
-        let boot_timeout = Duration::from_secs(30);
+        let mut parser = ResponseParser::new();
+        let start_wait = Instant::now();
+        let boot_timeout = crate::config::get().timeouts.bootloader();
         let mut saw_boot_ok = false;
         while start_wait.elapsed() < boot_timeout {
-            let resp = self.receive();
-            if !resp.is_empty() {
-                accumulate.push_str(&resp);
-                // Print any intermediate output to aid debugging
-                // println!("[RX] {}", resp);
-                if accumulate.contains("!BL2040:02") {
-                    saw_boot_ok = true;
-                    break;
-                }
+            if self
+                .receive_responses(&mut parser)
+                .contains(&ExpResponse::BootloaderDone)
+            {
+                saw_boot_ok = true;
+                break;
             }
             std::thread::sleep(Duration::from_millis(50));
         }
@@ -178,111 +273,243 @@ impl ExpProtocol {
                 "Timed out waiting for bootloader completion (!BL2040:02). Proceeding to ID check anyway..."
             );
         } else {
-            println!("Bootloader reported completion: !BL2040:02");
+            status!("Bootloader reported completion: !BL2040:02");
+            observer.event(&FlashEvent::BootloaderAck);
         }
 
         std::thread::sleep(Duration::from_millis(2_000));
 
         // Query the device ID and firmware version for the target address
+        observer.event(&FlashEvent::Verifying);
         let id_cmd = format!("ID@{}:\r", address_hex);
         self.send(id_cmd.into_bytes());
 
-        // Collect ID response for up to 5 seconds
-        let verify_timeout = Duration::from_secs(5);
-        let start_verify = std::time::Instant::now();
-        let mut id_resp = String::new();
-        while start_verify.elapsed() < verify_timeout {
-            let r = self.receive();
-            if !r.is_empty() {
-                id_resp.push_str(&r);
-            }
-            // If the device echoes or provides line breaks, we may get the full response early
-            if id_resp.len() > 0 {
-                // simple heuristic
-                // try to break early if we already have a newline or colon-rich response
-                if id_resp.contains('\n') || id_resp.contains('\r') {
-                    break;
-                }
+        // Wait for the ID response for up to the verification timeout
+        let verify_timeout = crate::config::get().timeouts.verify();
+        let start_verify = Instant::now();
+        let mut id = None;
+        while id.is_none() && start_verify.elapsed() < verify_timeout {
+            id = self
+                .receive_responses(&mut parser)
+                .into_iter()
+                .find_map(|r| match r {
+                    ExpResponse::Id(id) => Some(id),
+                    _ => None,
+                });
+            if id.is_none() {
+                std::thread::sleep(Duration::from_millis(50));
             }
-            std::thread::sleep(Duration::from_millis(50));
         }
 
-        println!("ID response: {}", id_resp);
-
-        // Parse and validate the expected ID response format: "ID:EXP {BoardName} {version}"
-        let expected_ver = normalized_version;
-        let mut found_line = None::<String>;
-        let mut parsed_version = None::<String>;
-        let mut verified = false;
-
-        for line in id_resp.lines() {
-            let l = line.trim();
-            if l.starts_with("ID:EXP") {
-                found_line = Some(l.to_string());
-                // Tokenize by whitespace; expected tokens: ["ID:EXP", "{BoardName}", "{version}"]
-                let parts: Vec<&str> = l.split_whitespace().collect();
-                if parts.len() >= 3 {
-                    // Extract version token and strip any trailing non-digit/dot characters
-                    let mut ver = parts[2].trim().to_string();
-                    while ver.ends_with(|c: char| !c.is_ascii_digit() && c != '.') {
-                        ver.pop();
-                    }
-                    parsed_version = Some(ver.clone());
-                    if ver == expected_ver {
-                        verified = true;
-                        break;
-                    }
-                }
-            }
+        if let Some(id) = &id {
+            status!("ID response: {}", id);
         }
 
-        if verified {
-            println!(
-                "Firmware update verified: board {} reports version {} at address {}",
-                board_type, expected_ver, address_hex
-            );
-        } else {
-            // Provide helpful diagnostics
-            if let Some(pv) = parsed_version.as_deref() {
-                if pv != expected_ver {
-                    eprintln!(
-                        "Warning: Firmware version mismatch. Expected '{}', got '{}' (line: {:?}).",
-                        expected_ver, pv, found_line
-                    );
-                }
-            } else if let Some(line) = found_line {
+        let Some(expected_ver) = expected_version else {
+            status!("No expected version given; skipping post-flash verification.");
+            return FlashOutcome::Unverified;
+        };
+
+        // Expected ID response: "ID:EXP {BoardName} {version}"
+        match id.filter(|id| id.protocol == "EXP") {
+            Some(id)
+                if version_key(&id.version).is_some()
+                    && version_key(&id.version) == version_key(expected_ver) =>
+            {
+                status!(
+                    "Firmware update verified: board {} reports version {} at address {}",
+                    board_type,
+                    expected_ver,
+                    address_hex
+                );
+                FlashOutcome::Verified
+            }
+            Some(id) => {
                 eprintln!(
-                    "Warning: Could not parse board/version from ID line: {:?}. Expected format: 'ID:EXP {{BoardName}} {{version}}'",
-                    line
+                    "Warning: Firmware version mismatch. Expected '{}', got '{}' ({}).",
+                    expected_ver, id.version, id
                 );
-            } else {
+                FlashOutcome::Mismatch
+            }
+            None => {
                 eprintln!(
-                    "Warning: No 'ID:EXP' line found in response; cannot verify flashed version {} for board {}.",
-                    board_type, expected_ver,
+                    "Warning: No 'ID:EXP' response; cannot verify flashed version {} for board {}.",
+                    expected_ver, board_type,
                 );
+                FlashOutcome::Mismatch
+            }
+        }
+    }
+
+    /// Reset the board at `address_hex` with `BR@xx:` and wait up to `timeout` for it to
+    /// answer `ID@xx:` again.
+    pub fn reset_board(&mut self, address_hex: &str, timeout: Duration) -> Option<IdResponse> {
+        let _ = self.receive();
+        self.send(format!("BR@{}:\r", address_hex).into_bytes());
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            std::thread::sleep(RESET_POLL_INTERVAL);
+            self.send(format!("ID@{}:\r", address_hex).into_bytes());
+            if let Some(id) = IdResponse::find(&self.receive()) {
+                return Some(id);
             }
         }
+        None
+    }
+
+    /// Direct subsequent commands to the given EXP board (and optional breakout) address.
+    pub fn select_address(&mut self, address_hex: &str) {
+        self.send(format!("EA:{}\r", address_hex).into_bytes());
+        std::thread::sleep(Duration::from_millis(10));
+        let _ = self.receive();
+    }
+
+    /// Set consecutive LEDs on the selected board starting at `first_index`.
+    ///
+    /// Colors are sent with the `RD:` command in batches of 16 LEDs.
+    pub fn set_leds(&mut self, first_index: u8, colors: &[(u8, u8, u8)]) {
+        for (batch_no, batch) in colors.chunks(16).enumerate() {
+            let mut cmd = format!("RD:{:02X}", batch.len());
+            for (i, (r, g, b)) in batch.iter().enumerate() {
+                let index = first_index as usize + batch_no * 16 + i;
+                cmd.push_str(&format!("{:02X}{:02X}{:02X}{:02X}", index as u8, r, g, b));
+            }
+            cmd.push('\r');
+            self.send(cmd.into_bytes());
+        }
+    }
+
+    /// Move a servo on the selected board to `position` (0x00-0xFF across its travel)
+    /// with the `MS:` command.
+    pub fn set_servo(&mut self, servo: u8, position: u8) {
+        self.send(format!("MS:{:02X},{:02X}\r", servo, position).into_bytes());
     }
 
     pub fn send(&mut self, command: Vec<u8>) {
+        log::debug!(
+            "EXP {} TX {:?}",
+            self.port_name,
+            String::from_utf8_lossy(&command)
+        );
         // Best-effort write; avoid panicking on errors
-        let _ = self.serial_port.write_all(command.as_slice());
+        if let Err(e) = self.serial_port.write_all(command.as_slice()) {
+            log::warn!("EXP {} write failed: {}", self.port_name, e);
+        }
         let _ = self.serial_port.flush();
     }
 
+    /// Receive complete response lines, reading until the port goes quiet
+    /// (see `read_response`).
+    pub fn receive_lines(&mut self) -> Vec<String> {
+        let lines = split_lines(&read_response(
+            self.serial_port.as_mut(),
+            crate::config::get().timeouts.receive(),
+        ));
+        for line in lines.iter() {
+            log::debug!("EXP {} RX {:?}", self.port_name, line);
+        }
+        lines
+    }
+
+    /// Receive a response as a single string, one line per message.
     pub fn receive(&mut self) -> String {
-        let mut buf_bytes = [0u8; 256];
-        let mut collected = Vec::new();
-
-        match self.serial_port.read(&mut buf_bytes) {
-            Ok(0) => {}
-            Ok(n) => {
-                collected.extend_from_slice(&buf_bytes[..n]);
-                if collected.len() >= 256 {}
-            }
-            Err(_) => {}
+        self.receive_lines().join("\n")
+    }
+
+    /// Receive typed responses. `parser` keeps a message that has only partly arrived
+    /// for the next call, so pass the same one while waiting for a response.
+    pub fn receive_responses(
+        &mut self,
+        parser: &mut ResponseParser<ExpResponse>,
+    ) -> Vec<ExpResponse> {
+        let responses = parser.push(&read_response(
+            self.serial_port.as_mut(),
+            crate::config::get().timeouts.receive(),
+        ));
+        for response in responses.iter() {
+            log::debug!("EXP {} RX {:?}", self.port_name, response);
         }
+        responses
+    }
+
+    /// Wait up to `timeout` for a reply, returning as soon as a complete one has arrived
+    /// (see `read_reply`). One line per message, like `receive`.
+    pub fn receive_within(&mut self, timeout: Duration) -> String {
+        let lines = split_lines(&read_reply(self.serial_port.as_mut(), timeout));
+        for line in lines.iter() {
+            log::debug!("EXP {} RX {:?}", self.port_name, line);
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::mock::{MockTransport, firmware_file};
+
+    fn exp(mock: &MockTransport) -> ExpProtocol {
+        ExpProtocol::with_transport("mock".into(), Box::new(mock.clone()))
+    }
+
+    // Stream a firmware file to B4 as `flash_file` does, without its lock and history
+    fn flash(mock: &MockTransport, name: &str, expected: &str) -> (FlashOutcome, Vec<FlashEvent>) {
+        let file = firmware_file(name);
+        let mut events = Vec::new();
+        let outcome = exp(mock).stream_and_verify(
+            "FP-EXP-0071",
+            "B4",
+            &file,
+            Some(expected),
+            &mut |event: &FlashEvent| events.push(event.clone()),
+        );
+        let _ = std::fs::remove_file(&file);
+        (outcome, events)
+    }
+
+    #[test]
+    fn id_handshake() {
+        let mock = MockTransport::new().respond("ID@B4:", "ID:EXP FP-EXP-0071 0.48\r");
+        let id = exp(&mock).board_id("B4").unwrap();
+        assert_eq!(id.board, "FP-EXP-0071");
+        assert_eq!(id.version, "0.48");
+        assert_eq!(mock.commands(), ["ID@B4:"]);
+    }
+
+    #[test]
+    fn id_timeout() {
+        let mock = MockTransport::new();
+        assert_eq!(exp(&mock).board_id("B4"), None);
+        assert_eq!(mock.commands(), ["ID@B4:"]);
+    }
+
+    #[test]
+    fn flash_completes_and_verifies() {
+        let mock = MockTransport::new()
+            .respond(":00000001FF", "!BL2040:02\r")
+            .respond("ID@B4:", "ID:EXP FP-EXP-0071 0.50\r");
+        let (outcome, events) = flash(&mock, "exp-verified", "0.50");
+        assert_eq!(outcome, FlashOutcome::Verified);
+        assert!(events.contains(&FlashEvent::BootloaderAck));
+        assert_eq!(
+            mock.commands(),
+            ["ea:B4", ":0400000001020304F2", ":00000001FF", "ID@B4:"]
+        );
+    }
+
+    #[test]
+    fn flash_with_other_version_is_a_mismatch() {
+        let mock = MockTransport::new()
+            .respond(":00000001FF", "!BL2040:02\r")
+            .respond("ID@B4:", "ID:EXP FP-EXP-0071 0.48\r");
+        assert_eq!(flash(&mock, "exp-old", "0.50").0, FlashOutcome::Mismatch);
+    }
 
-        String::from_utf8_lossy(&collected).trim().to_string()
+    #[test]
+    fn flash_answered_by_other_protocol_is_a_mismatch() {
+        let mock = MockTransport::new()
+            .respond(":00000001FF", "!BL2040:02\r")
+            .respond("ID@B4:", "ID:NET FP-CPU-2000 0.50\r");
+        assert_eq!(flash(&mock, "exp-net", "0.50").0, FlashOutcome::Mismatch);
     }
 }
@@ -0,0 +1,249 @@
+// Loading a firmware file to stream to a bootloader. Most files are lines of hex records,
+// `:LLAAAATT<data>[CC]` (length, address, record type, data and, in Intel HEX files, a
+// checksum), each terminated with a single `\r`: FAST's `.txt` files and Intel HEX `.hex`
+// files. LF or CRLF line endings, e.g. from a Git checkout with line ending conversion,
+// are turned back into CR; a file that still does not look like firmware, e.g. a download
+// that was cut short, is refused instead of being sent to the board. Raw `.bin` images are
+// sent as they are, to bootloaders that take them.
+
+use serde::Serialize;
+use std::path::Path;
+
+/// Intel HEX record type of the end-of-file record.
+const END_OF_FILE_RECORD: u8 = 0x01;
+
+/// How a firmware file is laid out, told by its extension.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FirmwareFormat {
+    /// `.txt`: FAST's hex records, with or without checksums.
+    #[serde(rename = "txt")]
+    Records,
+    /// `.hex`: Intel HEX, every record checksummed and an end-of-file record last.
+    #[serde(rename = "hex")]
+    IntelHex,
+    /// `.bin`: a raw image.
+    #[serde(rename = "bin")]
+    Binary,
+}
+
+impl FirmwareFormat {
+    /// Every format, the preferred one first.
+    pub const ALL: [FirmwareFormat; 3] = [Self::Records, Self::IntelHex, Self::Binary];
+    /// What the EXP and NET bootloaders take: hex records.
+    pub const RECORDS: [FirmwareFormat; 2] = [Self::Records, Self::IntelHex];
+
+    /// The format of a file named `path`, `None` for other extensions.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|f| f.extension().eq_ignore_ascii_case(extension))
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Records => "txt",
+            Self::IntelHex => "hex",
+            Self::Binary => "bin",
+        }
+    }
+
+    /// Whether the format is lines of hex records rather than a raw image.
+    pub fn is_records(self) -> bool {
+        self != Self::Binary
+    }
+}
+
+impl std::fmt::Display for FirmwareFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, ".{}", self.extension())
+    }
+}
+
+/// Read `file_path`, checking that it looks like firmware a bootloader accepts: records
+/// come back with CR line endings, raw images as they are. Files of other formats than
+/// `accepted` are refused.
+pub fn load(
+    file_path: &str,
+    accepted: &[FirmwareFormat],
+) -> Result<(FirmwareFormat, Vec<u8>), String> {
+    // Unknown extensions are taken for records, as FAST's files always were
+    let format = FirmwareFormat::from_path(Path::new(file_path)).unwrap_or(FirmwareFormat::Records);
+    if !accepted.contains(&format) {
+        let names: Vec<String> = accepted.iter().map(|f| f.to_string()).collect();
+        return Err(format!(
+            "Firmware file '{}' is a {} file; this bootloader takes {} files.",
+            file_path,
+            format,
+            names.join(" or ")
+        ));
+    }
+    let bytes = std::fs::read(file_path)
+        .map_err(|e| format!("Failed to open firmware file '{}': {}", file_path, e))?;
+    if format == FirmwareFormat::Binary {
+        if bytes.is_empty() {
+            return Err(format!("Firmware file '{}' is empty", file_path));
+        }
+        return Ok((format, bytes));
+    }
+    let bytes = if bytes.contains(&b'\n') {
+        status!("Converting the LF line endings of {} to CR.", file_path);
+        normalize_line_endings(&bytes)
+    } else {
+        bytes
+    };
+    validate_bytes(&bytes, format == FirmwareFormat::IntelHex)
+        .map_err(|e| format!("Firmware file '{}' {}", file_path, e))?;
+    Ok((format, bytes))
+}
+
+/// `bytes` with every line ending (`\r\n`, `\n` or `\r`) turned into a single `\r`.
+pub fn normalize_line_endings(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut after_cr = false;
+    for &b in bytes {
+        match b {
+            b'\n' if after_cr => {}
+            b'\n' => out.push(b'\r'),
+            _ => out.push(b),
+        }
+        after_cr = b == b'\r';
+    }
+    out
+}
+
+// The reasons read as the end of a sentence starting with the file name. Intel HEX files
+// must carry checksums.
+fn validate_bytes(bytes: &[u8], intel_hex: bool) -> Result<(), String> {
+    if bytes.iter().all(u8::is_ascii_whitespace) {
+        return Err("is empty".to_string());
+    }
+    if !bytes.ends_with(b"\r") {
+        return Err("ends in the middle of a line; it may be truncated".to_string());
+    }
+
+    let mut checksummed = false;
+    let mut end_of_file = false;
+    for (i, line) in bytes.split(|b| *b == b'\r').enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let record =
+            parse_record(line).ok_or_else(|| format!("line {}: not a firmware record", i + 1))?;
+        let length = record[0] as usize;
+        match record.len() {
+            // Length, address (2 bytes) and type, then the data
+            n if n == length + 4 && !intel_hex => {}
+            n if n == length + 5 => {
+                checksummed = true;
+                let sum = record.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+                if sum != 0 {
+                    return Err(format!("line {}: checksum mismatch", i + 1));
+                }
+            }
+            _ if intel_hex => return Err(format!("line {}: not an Intel HEX record", i + 1)),
+            _ => {
+                return Err(format!(
+                    "line {}: record length does not match its data",
+                    i + 1
+                ));
+            }
+        }
+        end_of_file |= record[3] == END_OF_FILE_RECORD;
+    }
+    if checksummed && !end_of_file {
+        return Err("has no end-of-file record; it may be truncated".to_string());
+    }
+    Ok(())
+}
+
+// The bytes of a `:`-prefixed hex record with at least length, address and type
+fn parse_record(line: &[u8]) -> Option<Vec<u8>> {
+    let hex = std::str::from_utf8(line.strip_prefix(b":")?).ok()?;
+    if hex.len() % 2 != 0 || hex.len() < 8 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            FirmwareFormat::from_path(Path::new("a_EXP_firmware_v_0_48.TXT")),
+            Some(FirmwareFormat::Records)
+        );
+        assert_eq!(
+            FirmwareFormat::from_path(Path::new("a.hex")),
+            Some(FirmwareFormat::IntelHex)
+        );
+        assert_eq!(FirmwareFormat::from_path(Path::new("a.zip")), None);
+    }
+
+    #[test]
+    fn line_endings_become_carriage_returns() {
+        assert_eq!(
+            normalize_line_endings(b":00\r\n:01\n:02\r"),
+            b":00\r:01\r:02\r"
+        );
+    }
+
+    #[test]
+    fn records_with_and_without_checksums() {
+        assert_eq!(validate_bytes(b":0400000001020304\r", false), Ok(()));
+        assert_eq!(
+            validate_bytes(b":0400000001020304F2\r:00000001FF\r", false),
+            Ok(())
+        );
+        assert_eq!(
+            validate_bytes(b":0400000001020304F2\r:00000001FF\r", true),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn refuses_empty_and_truncated_files() {
+        assert_eq!(validate_bytes(b" \r\n", false), Err("is empty".to_string()));
+        assert_eq!(
+            validate_bytes(b":0400000001020304F2\r:000000", false),
+            Err("ends in the middle of a line; it may be truncated".to_string())
+        );
+        assert_eq!(
+            validate_bytes(b":0400000001020304F2\r", false),
+            Err("has no end-of-file record; it may be truncated".to_string())
+        );
+    }
+
+    #[test]
+    fn refuses_bad_records() {
+        assert_eq!(
+            validate_bytes(b"<html>\r", false),
+            Err("line 1: not a firmware record".to_string())
+        );
+        assert_eq!(
+            validate_bytes(b":0400000001020304F3\r:00000001FF\r", false),
+            Err("line 1: checksum mismatch".to_string())
+        );
+        assert_eq!(
+            validate_bytes(b":04000000010203\r", false),
+            Err("line 1: record length does not match its data".to_string())
+        );
+        // Intel HEX records need their checksum
+        assert_eq!(
+            validate_bytes(b":0400000001020304\r", true),
+            Err("line 1: not an Intel HEX record".to_string())
+        );
+    }
+
+    #[test]
+    fn load_refuses_formats_the_bootloader_does_not_take() {
+        let error = load("firmware.bin", &FirmwareFormat::RECORDS).unwrap_err();
+        assert!(error.contains("is a .bin file"), "{}", error);
+    }
+}
@@ -0,0 +1,171 @@
+// In-memory `Transport` that plays the board's side of a conversation from a script,
+// so protocol logic (ID parsing, flash verification) can run without hardware.
+
+use crate::protocol::transport::Transport;
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct MockState {
+    /// (command prefix, response) pairs, each answered once in the order given.
+    script: Vec<(String, Vec<u8>)>,
+    /// Bytes waiting to be read by the protocol.
+    pending: VecDeque<u8>,
+    /// Everything the protocol wrote, in order.
+    written: Vec<u8>,
+    /// Written bytes not yet terminated with `\r`.
+    partial: Vec<u8>,
+}
+
+/// Scripted stand-in for a serial port.
+///
+/// Each command written (terminated with `\r`) is matched case-insensitively against the
+/// prefixes registered with `respond`; the first match is consumed and its response
+/// becomes readable. Clones share state, so a test can keep a clone to inspect what was
+/// written after handing the transport to a protocol.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer the next command starting with `command_prefix` with `response`.
+    pub fn respond(self, command_prefix: &str, response: &str) -> Self {
+        self.lock()
+            .script
+            .push((command_prefix.to_string(), response.as_bytes().to_vec()));
+        self
+    }
+
+    /// Make `data` readable immediately, as if the board sent it unprompted.
+    pub fn push_incoming(&self, data: &str) {
+        self.lock().pending.extend(data.bytes());
+    }
+
+    /// Everything written so far, lossily decoded.
+    pub fn written(&self) -> String {
+        String::from_utf8_lossy(&self.lock().written).into_owned()
+    }
+
+    /// The `\r`-terminated commands written so far, without their terminators.
+    pub fn commands(&self) -> Vec<String> {
+        self.written()
+            .split('\r')
+            .filter(|c| !c.is_empty())
+            .map(|c| c.to_string())
+            .collect()
+    }
+
+    /// Number of scripted responses that were never triggered.
+    pub fn unanswered(&self) -> usize {
+        self.lock().script.len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        if state.pending.is_empty() {
+            // Behave like a serial port whose read timeout expired
+            return Err(io::Error::new(ErrorKind::TimedOut, "no scripted data"));
+        }
+        let n = buf.len().min(state.pending.len());
+        for (slot, byte) in buf.iter_mut().zip(state.pending.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        state.written.extend_from_slice(buf);
+        for &byte in buf {
+            if byte != b'\r' {
+                state.partial.push(byte);
+                continue;
+            }
+            let command = String::from_utf8_lossy(&std::mem::take(&mut state.partial)).into_owned();
+            let matched = state.script.iter().position(|(prefix, _)| {
+                command
+                    .get(..prefix.len())
+                    .is_some_and(|p| p.eq_ignore_ascii_case(prefix))
+            });
+            if let Some(index) = matched {
+                let (_, response) = state.script.remove(index);
+                state.pending.extend(response);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MockTransport {
+    fn bytes_to_read(&self) -> io::Result<u32> {
+        Ok(self.lock().pending.len() as u32)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+/// A two-record firmware file in the temp directory for tests to stream, ending in the
+/// `:00000001FF` end-of-file record a script can answer with the bootloader's reply.
+#[cfg(test)]
+pub(crate) fn firmware_file(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!(
+        "fast-pinball-test-{}-{}.txt",
+        std::process::id(),
+        name
+    ));
+    std::fs::write(&path, ":0400000001020304F2\r:00000001FF\r").unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+/// Make the active firmware repo one in the temp directory holding `FP-CPU-2000` EXP
+/// firmware 9.99 and `FP-EXP-0071` EXP firmware 0.50, so scanned boards have newer
+/// firmware available. Every test gets the same repo.
+#[cfg(test)]
+pub(crate) fn firmware_repo() {
+    use crate::firmware_repo::FirmwareRepo;
+    static REPO: std::sync::OnceLock<std::sync::Arc<FirmwareRepo>> = std::sync::OnceLock::new();
+    let repo = REPO.get_or_init(|| {
+        let dir =
+            std::env::temp_dir().join(format!("fast-pinball-test-{}-firmware", std::process::id()));
+        let exp = dir.join("EXP");
+        std::fs::create_dir_all(&exp).unwrap();
+        for name in [
+            "FP-CPU-2000_EXP_firmware_v_9_99.txt",
+            "FP-EXP-0071_EXP_firmware_v_0_50.txt",
+        ] {
+            std::fs::write(exp.join(name), ":0400000001020304F2\r:00000001FF\r").unwrap();
+        }
+        std::sync::Arc::new(FirmwareRepo::load_from(&dir))
+    });
+    crate::firmware_repo::replace(std::sync::Arc::clone(repo));
+}
+
+/// A monitor whose only EXP port is `exp`, on a silent NET port.
+#[cfg(test)]
+pub(crate) fn monitor(exp: Box<dyn Transport>) -> crate::fast_monitor::FastPinballMonitor {
+    use crate::protocol::exp_protocol::ExpProtocol;
+    use crate::protocol::net_protocol::NetProtocol;
+    let net = NetProtocol::with_transport("net".into(), Box::new(MockTransport::new()));
+    let exp = ExpProtocol::with_transport("exp".into(), exp);
+    crate::fast_monitor::FastPinballMonitor::with_protocols(net, vec![exp], None, None, None)
+}
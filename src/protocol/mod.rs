@@ -1,2 +1,643 @@
+pub mod aud_protocol;
+pub mod dsp_protocol;
 pub mod exp_protocol;
+pub mod firmware_file;
+pub mod mock;
+pub mod net_commands;
+pub mod net_events;
 pub mod net_protocol;
+pub mod responses;
+pub mod seg_protocol;
+pub mod transport;
+
+use crate::cancel::CancelToken;
+use firmware_file::FirmwareFormat;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::cell::RefCell;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+use transport::Transport;
+
+/// Default for how long a single receive keeps reading while a response is still
+/// arriving (`timeouts.receive_ms` in the config file).
+pub const RECEIVE_DEADLINE: Duration = Duration::from_millis(500);
+
+/// How often a reset processor or board is asked for its ID until it answers again.
+pub const RESET_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How a firmware flash ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlashOutcome {
+    /// The board reports the expected version.
+    Verified,
+    /// The firmware was sent, but there was no expected version to check against.
+    Unverified,
+    /// The board did not report the expected version afterwards.
+    Mismatch,
+    /// The firmware could not be found, read or sent.
+    Failed,
+    /// The flash was stopped through its `CancelToken` (or Ctrl+C); see `cancel` for the
+    /// state the board is left in.
+    Cancelled,
+}
+
+impl FlashOutcome {
+    pub fn is_verified(self) -> bool {
+        self == FlashOutcome::Verified
+    }
+
+    /// `Ok` when the firmware went through (verified or not), `Err` otherwise.
+    pub fn into_result(self) -> Result<Self, Self> {
+        match self {
+            FlashOutcome::Verified | FlashOutcome::Unverified => Ok(self),
+            FlashOutcome::Mismatch | FlashOutcome::Failed | FlashOutcome::Cancelled => Err(self),
+        }
+    }
+}
+
+/// How the firmware transfer of a flash went.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct TransferStats {
+    pub bytes_sent: u64,
+    #[serde(rename = "elapsed_secs", serialize_with = "serialize_secs")]
+    pub elapsed: Duration,
+}
+
+impl TransferStats {
+    /// Bytes sent per second.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.bytes_sent as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+fn serialize_secs<S: serde::Serializer>(elapsed: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(elapsed.as_secs_f64())
+}
+
+/// What a flash came to, for showing after it: the outcome, the attempts it took and the
+/// transfer of the last attempt (`None` when the firmware was not sent in full).
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct FlashSummary {
+    pub outcome: FlashOutcome,
+    pub attempts: u32,
+    pub transfer: Option<TransferStats>,
+}
+
+impl FlashSummary {
+    /// The summary of a single attempt.
+    pub fn single(outcome: FlashOutcome, transfer: Option<TransferStats>) -> Self {
+        Self {
+            outcome,
+            attempts: 1,
+            transfer,
+        }
+    }
+}
+
+impl std::fmt::Display for FlashSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.transfer {
+            Some(t) => write!(
+                f,
+                "sent {} bytes in {:.1}s ({:.0} bytes/s)",
+                t.bytes_sent,
+                t.elapsed.as_secs_f64(),
+                t.throughput()
+            )?,
+            None => f.write_str("firmware not sent in full")?,
+        }
+        let plural = if self.attempts == 1 { "" } else { "s" };
+        write!(f, ", {} attempt{}, {}", self.attempts, plural, self.outcome)
+    }
+}
+
+/// Run `flash`, and run it again while the board does not report the expected version:
+/// right away up to `retries` times, then for as long as `retry` agrees. `flash` returns
+/// the outcome and transfer of one attempt. The summary is `Ok` when the firmware went
+/// through in the end, as in `FlashOutcome::into_result`.
+pub fn flash_with_retries(
+    retries: u32,
+    mut retry: impl FnMut() -> bool,
+    mut flash: impl FnMut() -> (FlashOutcome, Option<TransferStats>),
+) -> Result<FlashSummary, FlashSummary> {
+    let mut attempts = 1;
+    loop {
+        let (outcome, transfer) = flash();
+        if outcome != FlashOutcome::Mismatch || (attempts > retries && !retry()) {
+            let summary = FlashSummary {
+                outcome,
+                attempts,
+                transfer,
+            };
+            return match outcome.into_result() {
+                Ok(_) => Ok(summary),
+                Err(_) => Err(summary),
+            };
+        }
+        attempts += 1;
+        status!("Flashing again (attempt {}) ...", attempts);
+    }
+}
+
+impl std::fmt::Display for FlashOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FlashOutcome::Verified => "verified",
+            FlashOutcome::Unverified => "not verified",
+            FlashOutcome::Mismatch => "VERSION MISMATCH",
+            FlashOutcome::Failed => "FLASH FAILED",
+            FlashOutcome::Cancelled => "CANCELLED",
+        })
+    }
+}
+
+/// What happens during a flash, as told to a `FlashObserver`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlashEvent {
+    /// `file` (`total` bytes, 0 when unknown) is about to be sent.
+    Started { file: String, total: u64 },
+    /// `bytes` of `total` have been sent so far.
+    ChunkSent { bytes: u64, total: u64 },
+    /// Sending was cancelled after `bytes`; the board is left in its bootloader.
+    Interrupted { bytes: u64 },
+    /// All `bytes` of the firmware were sent.
+    Sent { bytes: u64 },
+    /// The bootloader reported that it took the firmware.
+    BootloaderAck,
+    /// The board is being asked for its version, to check the flash.
+    Verifying,
+    /// The flash is over, as in `FlashOutcome::into_result`. Every flash ends with this,
+    /// including ones that fail before anything is sent.
+    Done(Result<FlashOutcome, FlashOutcome>),
+}
+
+/// Something following a flash, e.g. a progress display. Closures taking a `&FlashEvent`
+/// are observers too.
+pub trait FlashObserver {
+    fn event(&mut self, event: &FlashEvent);
+}
+
+impl<F: FnMut(&FlashEvent)> FlashObserver for F {
+    fn event(&mut self, event: &FlashEvent) {
+        self(event)
+    }
+}
+
+/// Tell `observer` the flash ended with `outcome`, and return that.
+pub(crate) fn flash_done(observer: &mut dyn FlashObserver, outcome: FlashOutcome) -> FlashOutcome {
+    observer.event(&FlashEvent::Done(outcome.into_result()));
+    outcome
+}
+
+/// Bytes sent at a time of a raw (`.bin`) firmware image.
+const BINARY_CHUNK: usize = 64;
+
+/// Stream a firmware file to `port` line by line, with `\r` line endings (see
+/// `firmware_file`), pausing `line_delay` after each line; raw images go in
+/// `BINARY_CHUNK` byte pieces instead. `observer` hears of the start, each piece sent and
+/// the end of the transfer. Once `cancel` is cancelled, or a stop requested (see
+/// `interrupt`), the stream stops before the next piece. Files that do not look like
+/// firmware, or are not of an `accepted` format, are not sent at all.
+///
+/// `label` (e.g. `EXP /dev/ttyACM1`) prefixes the trace log of every line sent.
+pub fn stream_firmware(
+    port: &mut dyn Transport,
+    label: &str,
+    file_path: &str,
+    accepted: &[FirmwareFormat],
+    line_delay: Duration,
+    observer: &mut dyn FlashObserver,
+    cancel: &CancelToken,
+) -> Result<TransferStats, String> {
+    let (format, firmware) = firmware_file::load(file_path, accepted)?;
+    let start = Instant::now();
+    let total_size = firmware.len() as u64;
+
+    observer.event(&FlashEvent::Started {
+        file: file_path.to_string(),
+        total: total_size,
+    });
+    crate::metrics::flash_progress(file_path, 0, total_size);
+
+    let _streaming = crate::interrupt::Streaming::start();
+    let mut bytes_sent: u64 = 0;
+    let pieces: Vec<&[u8]> = if format.is_records() {
+        firmware.split_inclusive(|b| *b == b'\r').collect()
+    } else {
+        firmware.chunks(BINARY_CHUNK).collect()
+    };
+    for line in pieces {
+        // Whole lines only: the bootloader is left waiting for the next record
+        if stream_stopped(cancel) {
+            observer.event(&FlashEvent::Interrupted { bytes: bytes_sent });
+            return Err(format!(
+                "Flashing {} was interrupted after {} of {} bytes.",
+                file_path, bytes_sent, total_size
+            ));
+        }
+        log::trace!("{} TX {:?}", label, String::from_utf8_lossy(line));
+        let _ = port.write_all(line);
+        let _ = port.flush();
+
+        bytes_sent = bytes_sent.saturating_add(line.len() as u64);
+        observer.event(&FlashEvent::ChunkSent {
+            bytes: bytes_sent,
+            total: total_size,
+        });
+        crate::metrics::flash_progress(file_path, bytes_sent, total_size);
+
+        // Small delay between chunks
+        std::thread::sleep(line_delay);
+    }
+
+    observer.event(&FlashEvent::Sent { bytes: bytes_sent });
+    Ok(TransferStats {
+        bytes_sent,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Whether firmware streams holding `cancel` stop: it is cancelled, or Ctrl+C was pressed.
+pub(crate) fn stream_stopped(cancel: &CancelToken) -> bool {
+    cancel.is_cancelled() || crate::interrupt::stop_requested()
+}
+
+thread_local! {
+    // The batch display that firmware streams on this thread draw into, if any
+    static BATCH_BAR: RefCell<Option<(MultiProgress, ProgressBar)>> = const { RefCell::new(None) };
+}
+
+/// Run `flash` with the firmware streams it makes drawing into `bar` (one of the bars of
+/// `multi`) instead of a bar of their own, and with status lines printed above the bars.
+/// Without a progress bar (`--progress plain`, no terminal) streams report as usual.
+pub fn with_batch_bar<T>(multi: &MultiProgress, bar: &ProgressBar, flash: impl FnOnce() -> T) -> T {
+    BATCH_BAR.with(|b| *b.borrow_mut() = Some((multi.clone(), bar.clone())));
+    let result = flash();
+    BATCH_BAR.with(|b| *b.borrow_mut() = None);
+    result
+}
+
+/// Print a status line, above the bars of a batch flash while one is drawn.
+#[doc(hidden)]
+pub fn print_status(line: std::fmt::Arguments) {
+    BATCH_BAR.with(|b| match &*b.borrow() {
+        Some((multi, _)) => multi.suspend(|| println!("{}", line)),
+        None => println!("{}", line),
+    })
+}
+
+/// Interval between plain progress lines for a file of unknown size.
+const PLAIN_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The CLI's display of a flash: the transfer's progress (see `Progress`), unless
+/// `--quiet`. What the flash does after the transfer is already told by status lines.
+#[derive(Default)]
+pub struct ProgressDisplay {
+    progress: Progress,
+}
+
+impl FlashObserver for ProgressDisplay {
+    fn event(&mut self, event: &FlashEvent) {
+        match event {
+            FlashEvent::Started { file, total } => self.progress = Progress::new(file, *total),
+            FlashEvent::ChunkSent { bytes, .. } => self.progress.update(*bytes),
+            FlashEvent::Interrupted { bytes } => std::mem::take(&mut self.progress).abandon(*bytes),
+            FlashEvent::Sent { bytes } => std::mem::take(&mut self.progress).finish(*bytes),
+            FlashEvent::BootloaderAck | FlashEvent::Verifying | FlashEvent::Done(_) => {}
+        }
+    }
+}
+
+/// Progress of a firmware stream: an indicatif bar on a terminal, or a line per 10%
+/// (per `PLAIN_PROGRESS_INTERVAL` when the size is unknown) under pipes and CI, where
+/// a redrawing bar turns into garbage. `--progress` picks one explicitly.
+#[derive(Default)]
+enum Progress {
+    #[default]
+    Hidden,
+    Bar(ProgressBar),
+    Plain {
+        file_path: String,
+        total_size: u64,
+        /// Last tenth of the file reported.
+        reported_tenth: u64,
+        /// When the last line was printed, for files of unknown size.
+        reported_at: Instant,
+    },
+}
+
+impl Progress {
+    fn new(file_path: &str, total_size: u64) -> Self {
+        let config = crate::config::get();
+        if config.quiet {
+            return Progress::Hidden;
+        }
+        if !config.progress.use_bar() {
+            status!("Flashing {} ({})", file_path, plain_size(total_size));
+            return Progress::Plain {
+                file_path: file_path.to_string(),
+                total_size,
+                reported_tenth: 0,
+                reported_at: Instant::now(),
+            };
+        }
+        if let Some((_, pb)) = BATCH_BAR.with(|b| b.borrow().clone()) {
+            let style = ProgressStyle::with_template("{prefix:<24} [{elapsed_precise}] [{bar:30.cyan/blue}] {bytes}/{total_bytes} - {msg}")
+                .unwrap()
+                .progress_chars("##-");
+            pb.set_style(style);
+            pb.reset();
+            pb.set_length(total_size);
+            pb.set_message("flashing");
+            return Progress::Bar(pb);
+        }
+        if total_size > 0 {
+            let pb = ProgressBar::new(total_size);
+            let style = ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) - {msg}")
+                .unwrap()
+                .progress_chars("##-");
+            pb.set_style(style);
+            pb.set_message(format!("Flashing {}", file_path));
+            Progress::Bar(pb)
+        } else {
+            let pb = ProgressBar::new_spinner();
+            pb.enable_steady_tick(Duration::from_millis(100));
+            pb.set_message(format!("Flashing {} (size unknown)", file_path));
+            let style = ProgressStyle::with_template(
+                "{spinner:.green} {elapsed_precise} {bytes} sent - {msg}",
+            )
+            .unwrap();
+            pb.set_style(style);
+            Progress::Bar(pb)
+        }
+    }
+
+    fn update(&mut self, bytes_sent: u64) {
+        match self {
+            Progress::Hidden => {}
+            Progress::Bar(pb) => match pb.length() {
+                Some(total_size) => pb.set_position(bytes_sent.min(total_size)),
+                None => pb.set_position(bytes_sent),
+            },
+            Progress::Plain {
+                file_path,
+                total_size,
+                reported_tenth,
+                reported_at,
+            } => {
+                if let Some(tenth) = (bytes_sent.min(*total_size) * 10).checked_div(*total_size) {
+                    // The last tenth is reported by `finish`
+                    if tenth > *reported_tenth && tenth < 10 {
+                        *reported_tenth = tenth;
+                        status!(
+                            "  {}: {}% ({}/{} bytes)",
+                            file_path,
+                            tenth * 10,
+                            bytes_sent,
+                            total_size
+                        );
+                    }
+                } else if reported_at.elapsed() >= PLAIN_PROGRESS_INTERVAL {
+                    *reported_at = Instant::now();
+                    status!("  {}: {} bytes sent", file_path, bytes_sent);
+                }
+            }
+        }
+    }
+
+    fn abandon(self, bytes_sent: u64) {
+        match self {
+            Progress::Hidden => {}
+            Progress::Bar(pb) => pb.abandon_with_message("Interrupted"),
+            Progress::Plain { file_path, .. } => {
+                status!("  {}: interrupted ({} bytes sent)", file_path, bytes_sent)
+            }
+        }
+    }
+
+    fn finish(self, bytes_sent: u64) {
+        match self {
+            Progress::Hidden => {}
+            Progress::Bar(pb) if pb.length().is_some() => pb.finish_with_message("Done"),
+            Progress::Bar(pb) => pb.finish_and_clear(),
+            Progress::Plain { file_path, .. } => {
+                status!("  {}: 100% ({} bytes sent)", file_path, bytes_sent)
+            }
+        }
+    }
+}
+
+fn plain_size(total_size: u64) -> String {
+    if total_size > 0 {
+        format!("{} bytes", total_size)
+    } else {
+        "size unknown".to_string()
+    }
+}
+
+/// Read from `port` until a complete response has arrived.
+///
+/// Returns as soon as one port timeout passes with nothing received. Once data starts
+/// arriving, keeps reading until the buffer ends in a `\r`/`\n` terminator and nothing
+/// more is waiting, until `deadline` passes, or until a read fails other than by timing
+/// out.
+pub fn read_response(port: &mut dyn Transport, deadline: Duration) -> Vec<u8> {
+    let start = Instant::now();
+    let mut buf_bytes = [0u8; 256];
+    let mut collected = Vec::new();
+    loop {
+        match port.read(&mut buf_bytes) {
+            // End of file: the other end closed the connection
+            Ok(0) => break,
+            Ok(n) => collected.extend_from_slice(&buf_bytes[..n]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            // Nothing arrived within the port timeout
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {}
+            // The port is gone (e.g. unplugged); keep what arrived before
+            Err(e) => {
+                log::debug!("Read failed: {}", e);
+                break;
+            }
+        }
+        if collected.is_empty() {
+            break;
+        }
+        let terminated = matches!(collected.last(), Some(b'\r' | b'\n'));
+        if terminated && port.bytes_to_read().map(|n| n == 0).unwrap_or(true) {
+            break;
+        }
+        if start.elapsed() >= deadline {
+            break;
+        }
+    }
+    collected
+}
+
+/// How often `read_reply` checks for incoming bytes.
+const REPLY_POLL_INTERVAL: Duration = Duration::from_micros(500);
+
+/// Read from `port` until a `\r`/`\n` terminated reply has arrived and nothing more is
+/// waiting, or until `timeout` passes. For queries with a known reply time: it polls for
+/// waiting bytes instead of blocking in reads, whose timeouts are too coarse for waits of
+/// a few milliseconds.
+pub fn read_reply(port: &mut dyn Transport, timeout: Duration) -> Vec<u8> {
+    let start = Instant::now();
+    let mut buf_bytes = [0u8; 256];
+    let mut collected = Vec::new();
+    loop {
+        match port.bytes_to_read() {
+            Ok(0) => {
+                if matches!(collected.last(), Some(b'\r' | b'\n')) || start.elapsed() >= timeout {
+                    break;
+                }
+                std::thread::sleep(REPLY_POLL_INTERVAL);
+            }
+            // Bytes are waiting, or the port cannot tell and the read blocks up to its
+            // timeout
+            _ => {
+                match port.read(&mut buf_bytes) {
+                    // End of file: the other end closed the connection
+                    Ok(0) => break,
+                    Ok(n) => collected.extend_from_slice(&buf_bytes[..n]),
+                    Err(_) => {}
+                }
+                // A board that keeps sending must not hold us past the timeout
+                if start.elapsed() >= timeout {
+                    break;
+                }
+            }
+        }
+    }
+    collected
+}
+
+/// Split a raw response into trimmed, non-empty lines (FAST terminates messages with `\r`).
+pub fn split_lines(bytes: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(bytes)
+        .split(['\r', '\n'])
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Read, Write};
+
+    // A port that cannot tell how many bytes wait and reads `chunk` forever (or end of
+    // file when it is empty)
+    struct Endless {
+        chunk: &'static [u8],
+    }
+
+    impl Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.chunk.len().min(buf.len());
+            buf[..n].copy_from_slice(&self.chunk[..n]);
+            Ok(n)
+        }
+    }
+
+    impl Write for Endless {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Endless {
+        fn bytes_to_read(&self) -> io::Result<u32> {
+            Err(io::Error::new(ErrorKind::Unsupported, "cannot tell"))
+        }
+
+        fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+            Err(io::Error::new(ErrorKind::Unsupported, "no clones"))
+        }
+    }
+
+    // A port that reads `chunk` once and then fails as if unplugged
+    struct Unplugged {
+        chunk: Option<&'static [u8]>,
+    }
+
+    impl Read for Unplugged {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some(chunk) = self.chunk.take() else {
+                return Err(io::Error::new(ErrorKind::BrokenPipe, "unplugged"));
+            };
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    impl Write for Unplugged {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Unplugged {
+        fn bytes_to_read(&self) -> io::Result<u32> {
+            Ok(0)
+        }
+
+        fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+            Err(io::Error::new(ErrorKind::Unsupported, "no clones"))
+        }
+    }
+
+    #[test]
+    fn response_stops_when_the_port_fails() {
+        let mut port = Unplugged {
+            chunk: Some(b"ID:P"),
+        };
+        let start = Instant::now();
+        assert_eq!(read_response(&mut port, Duration::from_secs(5)), b"ID:P");
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn reply_stops_at_end_of_file() {
+        let start = Instant::now();
+        let reply = read_reply(&mut Endless { chunk: b"" }, Duration::from_secs(5));
+        assert!(reply.is_empty());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn reply_stops_at_timeout_while_bytes_keep_coming() {
+        let start = Instant::now();
+        let reply = read_reply(&mut Endless { chunk: b"X" }, Duration::from_millis(20));
+        assert!(!reply.is_empty());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn response_stops_at_end_of_file() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let mut port = transport::connect_tcp(&addr, Duration::from_millis(500)).unwrap();
+        let (mut board, _) = listener.accept().unwrap();
+        board.write_all(b"ID:P").unwrap();
+        drop(board);
+
+        let start = Instant::now();
+        assert_eq!(read_response(&mut port, Duration::from_secs(5)), b"ID:P");
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,222 @@
+// Typed NET commands, so callers need not know the wire format. `to_bytes` gives the
+// bytes to send (terminated with `\r`); `Display` gives the command text without the
+// terminator, e.g. for logs:
+//
+//     net.send_command(&NetCommand::NodeInfo { node: 0 })?;       // NN:00
+//     net.send_command(&NetCommand::Watchdog { ms: 1000 })?;      // WD:3E8
+//
+// Numbers go out in hex, as the NET protocol expects, except node indexes.
+
+use crate::protocol::responses::{NetResponse, NetResponseKind};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// `DL:` trigger flags that enable a driver and let `TL:` fire it manually.
+const DRIVER_TRIGGER_MANUAL: u8 = 0x81;
+/// `DL:` trigger flag of an enabled driver; the controller clears it when it shuts a
+/// driver off, e.g. after an over-current trip.
+const DRIVER_TRIGGER_ENABLED: u8 = 0x01;
+/// `DL:` mode of a single pulse (param 1: pulse ms, param 2: power).
+const DRIVER_MODE_PULSE: u8 = 0x10;
+/// `DL:` mode of a pulse followed by a hold (param 1: pulse ms, param 2: pulse power,
+/// param 3: hold power); the driver stays on until released with `TL:`.
+const DRIVER_MODE_PULSE_HOLD: u8 = 0x18;
+/// `CH:` hardware code of the Neuron controller.
+pub const NEURON_HARDWARE: u16 = 0x2000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetCommand {
+    /// `ID:` — the controller's board name and firmware version.
+    Id,
+    /// `NN:<node>` — the I/O board at a node index on the loop.
+    NodeInfo { node: u8 },
+    /// `CH:<hardware>,<switch reporting>` — select the controller hardware and how
+    /// switch changes are reported.
+    ConfigureHardware { hardware: u16, switch_reporting: u8 },
+    /// `WD:<ms>` — feed the watchdog; drivers are disabled once it runs out.
+    Watchdog { ms: u16 },
+    /// `SA:` — the state of every switch.
+    SwitchStates,
+    /// `SL:<switch>,<mode>,<debounce close>,<debounce open>` — configure a switch.
+    ConfigureSwitch {
+        switch: u8,
+        mode: u8,
+        debounce_close: u8,
+        debounce_open: u8,
+    },
+    /// `DL:<driver>,<trigger>,<switch>,<mode>,<param 1>,...,<param 5>` — configure a
+    /// driver.
+    ConfigureDriver {
+        driver: u8,
+        trigger: u8,
+        switch: u8,
+        mode: u8,
+        params: [u8; 5],
+    },
+    /// `DL:<driver>` — the configuration of a driver, answered like a `DL:` that sets it.
+    DriverConfig { driver: u8 },
+    /// `TL:<driver>,<control>` — trigger or release a driver by hand.
+    TriggerDriver { driver: u8, control: DriverControl },
+    /// `BR:` — reboot the controller.
+    Reboot,
+    /// `bn:aa55` — push pending firmware to the node boards.
+    UpdateNodes,
+}
+
+/// How a driver is set up by `DL:`: what triggers it, which switch, the mode and the
+/// mode's parameters (e.g. pulse ms and power for a pulse).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriverConfig {
+    pub driver: u8,
+    pub trigger: u8,
+    pub switch: u8,
+    pub mode: u8,
+    pub params: [u8; 5],
+}
+
+impl DriverConfig {
+    /// Whether the driver has a mode set (unset drivers read back as all zeros).
+    pub fn is_configured(&self) -> bool {
+        self.mode != 0
+    }
+
+    /// Whether the driver may fire.
+    pub fn is_enabled(&self) -> bool {
+        self.trigger & DRIVER_TRIGGER_ENABLED != 0
+    }
+}
+
+impl From<DriverConfig> for NetCommand {
+    fn from(config: DriverConfig) -> Self {
+        NetCommand::ConfigureDriver {
+            driver: config.driver,
+            trigger: config.trigger,
+            switch: config.switch,
+            mode: config.mode,
+            params: config.params,
+        }
+    }
+}
+
+/// `TL:` control values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverControl {
+    /// Fire the driver as configured by `DL:`.
+    ManualOn = 0x01,
+    /// Return the driver to its disabled state.
+    ManualOff = 0x02,
+}
+
+impl NetCommand {
+    /// `DL:` for a one-shot pulse of `pulse_ms` milliseconds at `power` (0x00-0xFF),
+    /// fired with `TriggerDriver { control: DriverControl::ManualOn }`.
+    pub fn pulse_driver(driver: u8, pulse_ms: u8, power: u8) -> Self {
+        NetCommand::ConfigureDriver {
+            driver,
+            trigger: DRIVER_TRIGGER_MANUAL,
+            switch: 0,
+            mode: DRIVER_MODE_PULSE,
+            params: [pulse_ms, power, 0, 0, 0],
+        }
+    }
+
+    /// `DL:` for holding a driver on at `power` (0x00-0xFF) from the moment it is fired
+    /// with `DriverControl::ManualOn` until `DriverControl::ManualOff`, e.g. for a GI
+    /// string.
+    pub fn hold_driver(driver: u8, power: u8) -> Self {
+        NetCommand::ConfigureDriver {
+            driver,
+            trigger: DRIVER_TRIGGER_MANUAL,
+            switch: 0,
+            mode: DRIVER_MODE_PULSE_HOLD,
+            params: [0, power, power, 0, 0],
+        }
+    }
+
+    /// The kind of message the controller answers this command with, or `None` for
+    /// commands without a single reply (`BR:`, `bn:aa55`).
+    pub fn reply_kind(&self) -> Option<NetResponseKind> {
+        match self {
+            NetCommand::Id => Some(NetResponseKind::Id),
+            NetCommand::NodeInfo { .. } => Some(NetResponseKind::NodeInfo),
+            NetCommand::DriverConfig { .. } => Some(NetResponseKind::DriverConfig),
+            NetCommand::SwitchStates => Some(NetResponseKind::Other),
+            NetCommand::ConfigureHardware { .. }
+            | NetCommand::Watchdog { .. }
+            | NetCommand::ConfigureSwitch { .. }
+            | NetCommand::ConfigureDriver { .. }
+            | NetCommand::TriggerDriver { .. } => Some(NetResponseKind::Status),
+            NetCommand::Reboot | NetCommand::UpdateNodes => None,
+        }
+    }
+
+    /// Whether `response` is the reply to this command, e.g. `DL:P` to a `DL:` and not a
+    /// late `WD:P` to an earlier watchdog feed.
+    pub fn is_answered_by(&self, response: &NetResponse) -> bool {
+        match (self, response) {
+            (NetCommand::Id, NetResponse::Id(_)) => true,
+            (NetCommand::NodeInfo { .. }, NetResponse::NodeInfo(_) | NetResponse::NodeNotFound) => {
+                true
+            }
+            (NetCommand::DriverConfig { driver }, NetResponse::DriverConfig(config)) => {
+                config.driver == *driver
+            }
+            (NetCommand::SwitchStates, NetResponse::Other(line)) => line.starts_with("SA:"),
+            (_, NetResponse::Processed { command } | NetResponse::Failed { command }) => {
+                self.reply_kind() == Some(NetResponseKind::Status)
+                    && self.to_string().split(':').next() == Some(command.as_str())
+            }
+            _ => false,
+        }
+    }
+
+    /// The command as sent, terminated with `\r`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        format!("{}\r", self).into_bytes()
+    }
+}
+
+impl fmt::Display for NetCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetCommand::Id => write!(f, "ID:"),
+            NetCommand::NodeInfo { node } => write!(f, "NN:{:02}", node),
+            NetCommand::ConfigureHardware {
+                hardware,
+                switch_reporting,
+            } => write!(f, "CH:{:04X},{:02X}", hardware, switch_reporting),
+            NetCommand::Watchdog { ms } => write!(f, "WD:{:X}", ms),
+            NetCommand::SwitchStates => write!(f, "SA:"),
+            NetCommand::ConfigureSwitch {
+                switch,
+                mode,
+                debounce_close,
+                debounce_open,
+            } => write!(
+                f,
+                "SL:{:02X},{:02X},{:02X},{:02X}",
+                switch, mode, debounce_close, debounce_open
+            ),
+            NetCommand::ConfigureDriver {
+                driver,
+                trigger,
+                switch,
+                mode,
+                params,
+            } => {
+                write!(
+                    f,
+                    "DL:{:02X},{:02X},{:02X},{:02X}",
+                    driver, trigger, switch, mode
+                )?;
+                params.iter().try_for_each(|p| write!(f, ",{:02X}", p))
+            }
+            NetCommand::DriverConfig { driver } => write!(f, "DL:{:02X}", driver),
+            NetCommand::TriggerDriver { driver, control } => {
+                write!(f, "TL:{:02X},{:02X}", driver, *control as u8)
+            }
+            NetCommand::Reboot => write!(f, "BR:"),
+            NetCommand::UpdateNodes => write!(f, "bn:aa55"),
+        }
+    }
+}
@@ -0,0 +1,548 @@
+// Background reading of the NET port, so messages nobody asked for (switch changes,
+// node messages, anything the controller reports on its own) reach subscribers instead
+// of being thrown away by the next synchronous call that drains the port, and replies
+// reach the command waiting for them whole, however they are split over reads.
+//
+// Once started, a reader thread owns all reads of the port. It parses what arrives into
+// `NetResponse`s for the subscribers, and queues all but switch changes by
+// `NetResponseKind`, where `NetProtocol::request` waits for the kind its command is
+// answered with. It also queues the raw bytes in an inbox, which the `NetProtocol`
+// reads from in place of the port, so code reading the port directly works as before.
+// A reply taken from its queue takes its own bytes out of the inbox with it, and leaves
+// everything else there. Once the port closes or fails, reads of the inbox return the
+// bytes left and then end of file or the read error, rather than waiting on a dead port.
+//
+// Only NET has a reader thread. EXP boards send nothing unless asked, one board at a
+// time, so a synchronous read after each command sees only that command's reply.
+
+use crate::protocol::responses::{NetResponse, NetResponseKind, Response};
+use crate::protocol::transport::Transport;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, ErrorKind, Read, Write};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, mpsc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Most bytes the inbox holds for synchronous reads; older ones are dropped beyond that,
+/// e.g. while the port is only used through subscriptions.
+const INBOX_LIMIT: usize = 64 * 1024;
+/// Most replies of one kind kept for `take_reply`; older ones are dropped beyond that,
+/// e.g. those of commands whose replies nobody waits for.
+const REPLY_LIMIT: usize = 64;
+/// Pause of the reader thread after a read that returned nothing.
+const READER_IDLE: Duration = Duration::from_millis(1);
+
+/// Something to hand each NET message to.
+pub enum Subscriber {
+    Channel(mpsc::Sender<NetResponse>),
+    /// Runs on the reader thread, so it should return quickly.
+    Callback(Box<dyn FnMut(&NetResponse) + Send>),
+}
+
+/// The reader thread of a NET port, stopped when dropped.
+pub struct NetEventReader {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+    replies: Arc<Replies>,
+    inbox: Arc<Inbox>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl NetEventReader {
+    /// Start reading `port` in the background. Returns the reader and the transport to
+    /// use in place of `port` from now on: it writes to the port and reads what the
+    /// reader thread received.
+    pub fn start(
+        port: &dyn Transport,
+        label: String,
+        read_timeout: Duration,
+    ) -> io::Result<(Self, Box<dyn Transport>)> {
+        let mut reader = port.try_clone()?;
+        let inbox = Arc::new(Inbox::default());
+        let transport = InboxTransport {
+            port: port.try_clone()?,
+            inbox: Arc::clone(&inbox),
+            read_timeout,
+        };
+
+        let subscribers: Arc<Mutex<Vec<Subscriber>>> = Arc::default();
+        let replies: Arc<Replies> = Arc::default();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let subscribers = Arc::clone(&subscribers);
+            let replies = Arc::clone(&replies);
+            let inbox = Arc::clone(&inbox);
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                let mut lines = LineReader::default();
+                let mut buf = [0u8; 256];
+                // Stopping the reader on purpose ends the inbox like a closed port
+                let mut end = ReadEnd::Closed;
+                while !stop.load(Ordering::Relaxed) {
+                    let n = match reader.read(&mut buf) {
+                        Ok(0) => {
+                            log::warn!("{} was closed; no longer reading it", label);
+                            replies.stop(format!("{} was closed", label));
+                            break;
+                        }
+                        Ok(n) => n,
+                        // Nothing arrived within the read timeout
+                        Err(e)
+                            if matches!(
+                                e.kind(),
+                                ErrorKind::TimedOut
+                                    | ErrorKind::WouldBlock
+                                    | ErrorKind::Interrupted
+                            ) =>
+                        {
+                            std::thread::sleep(READER_IDLE);
+                            continue;
+                        }
+                        Err(e) => {
+                            let reason = format!("{} read failed: {}", label, e);
+                            log::warn!("{}; no longer reading it", reason);
+                            replies.stop(reason.clone());
+                            end = ReadEnd::Failed(e.kind(), reason);
+                            break;
+                        }
+                    };
+                    inbox.push(lines.received, &buf[..n]);
+                    let responses = lines.push(&buf[..n]);
+                    if responses.is_empty() {
+                        continue;
+                    }
+                    replies.push(&responses);
+                    let mut subscribers = lock(&subscribers);
+                    for (response, _) in responses.iter() {
+                        log::trace!("{} event {:?}", label, response);
+                        match response {
+                            NetResponse::SwitchClosed { .. } => {
+                                crate::metrics::record_switch_event(true)
+                            }
+                            NetResponse::SwitchOpened { .. } => {
+                                crate::metrics::record_switch_event(false)
+                            }
+                            _ => {}
+                        }
+                        // Drop channels whose receiver is gone
+                        subscribers.retain_mut(|s| match s {
+                            Subscriber::Channel(tx) => tx.send(response.clone()).is_ok(),
+                            Subscriber::Callback(callback) => {
+                                callback(response);
+                                true
+                            }
+                        });
+                    }
+                }
+                inbox.close(end);
+            })
+        };
+
+        Ok((
+            Self {
+                subscribers,
+                replies,
+                inbox,
+                stop,
+                thread: Some(thread),
+            },
+            Box::new(transport),
+        ))
+    }
+
+    pub fn subscribe(&self, subscriber: Subscriber) {
+        lock(&self.subscribers).push(subscriber);
+    }
+
+    /// Stop reading and hand back the subscribers, e.g. to carry them over to a reader
+    /// of the port opened again.
+    pub fn into_subscribers(mut self) -> Vec<Subscriber> {
+        self.stop_thread();
+        std::mem::take(&mut *lock(&self.subscribers))
+    }
+
+    fn stop_thread(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    /// Drop the queued replies of `kind`, like draining the port before a command.
+    pub fn discard(&self, kind: NetResponseKind) {
+        lock(&self.replies.state).by_kind.remove(&kind);
+    }
+
+    /// Wait up to `timeout` for a reply of `kind` that `accept`s, dropping the ones of
+    /// that kind queued ahead of it. The reply's bytes are removed from the synchronous
+    /// reads, so it is not read a second time there. Fails at once when the reader
+    /// thread has stopped, e.g. because the port went away.
+    pub fn take_reply(
+        &self,
+        kind: NetResponseKind,
+        timeout: Duration,
+        accept: impl Fn(&NetResponse) -> bool,
+    ) -> Result<NetResponse, String> {
+        let deadline = Instant::now() + timeout;
+        let mut queues = lock(&self.replies.state);
+        let reply = 'wait: loop {
+            let queue = queues.by_kind.entry(kind).or_default();
+            while let Some((response, span)) = queue.pop_front() {
+                if accept(&response) {
+                    break 'wait Ok((response, span));
+                }
+                log::debug!("Dropping stale NET reply {:?}", response);
+            }
+            if let Some(reason) = &queues.stopped {
+                break Err(reason.clone());
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break Err(format!("no reply within {} ms", timeout.as_millis()));
+            }
+            queues = self
+                .replies
+                .arrived
+                .wait_timeout(queues, left)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        };
+        drop(queues);
+        let (response, span) = reply?;
+        lock(&self.inbox.state).remove(span);
+        Ok(response)
+    }
+}
+
+impl Drop for NetEventReader {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Parsed messages waiting for the command they answer, by kind.
+#[derive(Default)]
+struct Replies {
+    state: Mutex<ReplyQueues>,
+    arrived: Condvar,
+}
+
+#[derive(Default)]
+struct ReplyQueues {
+    /// Each reply with where its bytes are in everything received.
+    by_kind: HashMap<NetResponseKind, VecDeque<(NetResponse, Range<u64>)>>,
+    /// Why the reader thread stopped, once it has.
+    stopped: Option<String>,
+}
+
+impl Replies {
+    fn push(&self, responses: &[(NetResponse, Range<u64>)]) {
+        let mut queues = lock(&self.state);
+        // Switch changes answer no command
+        for (response, span) in responses
+            .iter()
+            .filter(|(r, _)| r.kind() != NetResponseKind::Switch)
+        {
+            let queue = queues.by_kind.entry(response.kind()).or_default();
+            queue.push_back((response.clone(), span.clone()));
+            if queue.len() > REPLY_LIMIT {
+                queue.pop_front();
+            }
+        }
+        self.arrived.notify_all();
+    }
+
+    fn stop(&self, reason: String) {
+        lock(&self.state).stopped = Some(reason);
+        self.arrived.notify_all();
+    }
+}
+
+/// Splits what the reader thread receives into messages, noting where each one's bytes
+/// are in everything received, like `ResponseParser` does without the positions.
+#[derive(Default)]
+struct LineReader {
+    pending: Vec<u8>,
+    /// Bytes received so far.
+    received: u64,
+}
+
+impl LineReader {
+    fn push(&mut self, bytes: &[u8]) -> Vec<(NetResponse, Range<u64>)> {
+        let mut responses = Vec::new();
+        for &byte in bytes {
+            self.pending.push(byte);
+            self.received += 1;
+            if !matches!(byte, b'\r' | b'\n') {
+                continue;
+            }
+            let line = String::from_utf8_lossy(&self.pending).into_owned();
+            let start = self.received - self.pending.len() as u64;
+            self.pending.clear();
+            if !line.trim().is_empty() {
+                responses.push((NetResponse::parse(&line), start..self.received));
+            }
+        }
+        responses
+    }
+}
+
+/// Bytes received by the reader thread, waiting for a synchronous read.
+#[derive(Default)]
+struct Inbox {
+    state: Mutex<InboxBytes>,
+    arrived: Condvar,
+}
+
+impl Inbox {
+    /// Add `data`, which starts at `offset` in everything received.
+    fn push(&self, offset: u64, data: &[u8]) {
+        let mut inbox = lock(&self.state);
+        inbox.chunks.push_back((offset, data.to_vec()));
+        inbox.len += data.len();
+        let excess = inbox.len.saturating_sub(INBOX_LIMIT);
+        inbox.skip(excess);
+        self.arrived.notify_all();
+    }
+
+    /// No more bytes will arrive, for the reason `end`.
+    fn close(&self, end: ReadEnd) {
+        lock(&self.state).end = Some(end);
+        self.arrived.notify_all();
+    }
+}
+
+/// Why the reader thread stopped reading the port.
+enum ReadEnd {
+    /// The port was closed, or the reader stopped.
+    Closed,
+    /// Reading the port failed.
+    Failed(ErrorKind, String),
+}
+
+/// The inbox's bytes in the pieces they arrived in, each with where it starts in
+/// everything received, so a reply's bytes can be found after others were read.
+#[derive(Default)]
+struct InboxBytes {
+    chunks: VecDeque<(u64, Vec<u8>)>,
+    len: usize,
+    /// Set once the reader thread has stopped.
+    end: Option<ReadEnd>,
+}
+
+impl InboxBytes {
+    // Copy the first bytes into `buf` and drop them
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut n = 0;
+        for (_, chunk) in &self.chunks {
+            let take = (buf.len() - n).min(chunk.len());
+            buf[n..n + take].copy_from_slice(&chunk[..take]);
+            n += take;
+            if n == buf.len() {
+                break;
+            }
+        }
+        self.skip(n);
+        n
+    }
+
+    // Drop the first `n` bytes
+    fn skip(&mut self, mut n: usize) {
+        self.len -= n;
+        while n > 0
+            && let Some((offset, chunk)) = self.chunks.front_mut()
+        {
+            let take = n.min(chunk.len());
+            chunk.drain(..take);
+            *offset += take as u64;
+            n -= take;
+            if chunk.is_empty() {
+                self.chunks.pop_front();
+            }
+        }
+    }
+
+    // Drop what is still here of the bytes at `span` in everything received
+    fn remove(&mut self, span: Range<u64>) {
+        let mut kept = VecDeque::with_capacity(self.chunks.len());
+        for (offset, chunk) in self.chunks.drain(..) {
+            let end = offset + chunk.len() as u64;
+            if span.end <= offset || end <= span.start {
+                kept.push_back((offset, chunk));
+                continue;
+            }
+            let from = (span.start.max(offset) - offset) as usize;
+            let to = (span.end.min(end) - offset) as usize;
+            if from > 0 {
+                kept.push_back((offset, chunk[..from].to_vec()));
+            }
+            if to < chunk.len() {
+                kept.push_back((offset + to as u64, chunk[to..].to_vec()));
+            }
+            self.len -= to - from;
+        }
+        self.chunks = kept;
+    }
+}
+
+/// Writes go to the port; reads take from the inbox, waiting up to `read_timeout` like
+/// a serial port read. Once the inbox is empty and the reader thread has stopped, reads
+/// return end of file, or the error reading the port failed with.
+struct InboxTransport {
+    port: Box<dyn Transport>,
+    inbox: Arc<Inbox>,
+    read_timeout: Duration,
+}
+
+impl Read for InboxTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let inbox = lock(&self.inbox.state);
+        let (mut inbox, _) = self
+            .inbox
+            .arrived
+            .wait_timeout_while(inbox, self.read_timeout, |b| b.len == 0 && b.end.is_none())
+            .unwrap_or_else(|e| e.into_inner());
+        if inbox.len > 0 {
+            return Ok(inbox.read(buf));
+        }
+        match &inbox.end {
+            None => Err(io::Error::new(
+                ErrorKind::TimedOut,
+                "no data from reader thread",
+            )),
+            Some(ReadEnd::Closed) => Ok(0),
+            Some(ReadEnd::Failed(kind, reason)) => Err(io::Error::new(*kind, reason.clone())),
+        }
+    }
+}
+
+impl Write for InboxTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.port.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.port.flush()
+    }
+}
+
+impl Transport for InboxTransport {
+    fn bytes_to_read(&self) -> io::Result<u32> {
+        Ok(lock(&self.inbox.state).len as u32)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(InboxTransport {
+            port: self.port.try_clone()?,
+            inbox: Arc::clone(&self.inbox),
+            read_timeout: self.read_timeout,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::mock::MockTransport;
+
+    fn read_all(transport: &mut dyn Transport) -> String {
+        let mut received = Vec::new();
+        let mut buf = [0u8; 64];
+        while let Ok(n) = transport.read(&mut buf) {
+            received.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8_lossy(&received).into_owned()
+    }
+
+    #[test]
+    fn taken_reply_leaves_other_bytes() {
+        let mock = MockTransport::new();
+        let (reader, mut transport) =
+            NetEventReader::start(&mock, "NET".into(), Duration::from_millis(50)).unwrap();
+        mock.push_incoming("-L:05\rID:NET FP-CPU-2000 02.28\rWD:P\r");
+        let reply = reader.take_reply(NetResponseKind::Id, Duration::from_secs(1), |_| true);
+        assert!(matches!(reply, Ok(NetResponse::Id(_))));
+        assert_eq!(read_all(transport.as_mut()), "-L:05\rWD:P\r");
+    }
+
+    // A port that is closed, or whose reads fail with `error`
+    struct DeadPort(Option<ErrorKind>);
+
+    impl Read for DeadPort {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            match self.0 {
+                Some(kind) => Err(io::Error::new(kind, "unplugged")),
+                None => Ok(0),
+            }
+        }
+    }
+
+    impl Write for DeadPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for DeadPort {
+        fn bytes_to_read(&self) -> io::Result<u32> {
+            Ok(0)
+        }
+
+        fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+            Ok(Box::new(DeadPort(self.0)))
+        }
+    }
+
+    #[test]
+    fn closed_port_reads_as_end_of_file() {
+        let (reader, mut transport) =
+            NetEventReader::start(&DeadPort(None), "NET".into(), Duration::from_secs(5)).unwrap();
+        let mut buf = [0u8; 8];
+        let start = Instant::now();
+        assert_eq!(transport.read(&mut buf).unwrap(), 0);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(
+            reader
+                .take_reply(NetResponseKind::Id, Duration::from_secs(5), |_| true)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn failed_port_reads_as_its_error() {
+        let port = DeadPort(Some(ErrorKind::BrokenPipe));
+        let (_reader, mut transport) =
+            NetEventReader::start(&port, "NET".into(), Duration::from_secs(5)).unwrap();
+        let mut buf = [0u8; 8];
+        let e = transport.read(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::BrokenPipe);
+        assert!(e.to_string().contains("unplugged"));
+    }
+
+    #[test]
+    fn remove_spans_chunks_and_skips_read_bytes() {
+        let mut inbox = InboxBytes::default();
+        for (offset, chunk) in [(0, "ab"), (2, "cde"), (5, "fg")] {
+            inbox.chunks.push_back((offset, chunk.as_bytes().to_vec()));
+            inbox.len += chunk.len();
+        }
+        let mut buf = [0u8; 1];
+        assert_eq!(inbox.read(&mut buf), 1);
+        // "a" was read already; "b" through "f" go
+        inbox.remove(0..6);
+        let mut buf = [0u8; 8];
+        let n = inbox.read(&mut buf);
+        assert_eq!(&buf[..n], b"g");
+        assert_eq!(inbox.len, 0);
+    }
+}
@@ -1,154 +1,221 @@
-use indicatif::{ProgressBar, ProgressStyle};
-use serialport::{DataBits, FlowControl, Parity, SerialPort, StopBits};
-use std::io::Read;
-use std::time::Duration;
+use crate::cancel::CancelToken;
+use crate::commands::utils::{Target, normalize_version, version_key};
+use crate::fast_monitor::NetBoardInfo;
+use crate::flash_lock::FlashLock;
+use crate::history::{self, FlashRecord};
+use crate::protocol::firmware_file::FirmwareFormat;
+use crate::protocol::net_commands::{DriverConfig, DriverControl, NetCommand};
+use crate::protocol::net_events::{NetEventReader, Subscriber};
+use crate::protocol::responses::{IdResponse, NetResponse, ResponseParser};
+use crate::protocol::transport::{TCP_PREFIX, Transport, connect_tcp};
+use crate::protocol::{
+    FlashEvent, FlashObserver, FlashOutcome, ProgressDisplay, RESET_POLL_INTERVAL, TransferStats,
+    flash_done, read_reply, read_response, split_lines, stream_firmware, stream_stopped,
+};
+use serialport::{DataBits, FlowControl, Parity, StopBits};
+use std::time::{Duration, Instant};
+
+/// Highest node index probed with `NN:`.
+const MAX_NODES: u8 = 64;
+/// Longest the node board update may take in total.
+const NODE_UPDATE_TIMEOUT: Duration = Duration::from_secs(120);
+/// How long the bus may stay silent before the node update is considered finished.
+const NODE_UPDATE_QUIET: Duration = Duration::from_secs(15);
+
+/// Firmware of one node board before and after `bn:aa55`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeUpdate {
+    pub node_id: String,
+    pub node_name: String,
+    pub before: String,
+    /// `None` when the node stopped answering `NN:`.
+    pub after: Option<String>,
+}
 
 pub struct NetProtocol {
-    pub serial_port: Box<dyn SerialPort>,
+    pub serial_port: Box<dyn Transport>,
+    pub port_name: String,
+    /// Transfer of the firmware last sent by `flash_file`.
+    pub last_transfer: Option<TransferStats>,
+    /// Stops scans and flashes over this port once cancelled (see `cancel`).
+    pub cancel: CancelToken,
+    /// Background reader of the port, started by the first `request` or `subscribe`.
+    reader: Option<NetEventReader>,
 }
 
 impl NetProtocol {
+    /// Open the given serial port (or `tcp://host:port` endpoint).
+    ///
+    /// # Panics
+    ///
+    /// When the port cannot be opened, with a message naming the program holding it
+    /// where that is the cause. Use `try_new` to handle the error instead.
     pub fn new(port: String) -> Self {
-        let serial_port = serialport::new(port, 921_600)
-            .data_bits(DataBits::Eight)
-            .flow_control(FlowControl::None)
-            .stop_bits(StopBits::One)
-            .parity(Parity::None)
-            .dtr_on_open(true)
-            .timeout(Duration::from_millis(200))
-            .open()
-            .unwrap();
+        Self::try_new(port.clone()).unwrap_or_else(|e| {
+            panic!(
+                "{}",
+                crate::port_owner::describe_open_error("NET", &port, &e)
+            )
+        })
+    }
+
+    /// Open the given serial port (or `tcp://host:port` endpoint), returning the error
+    /// instead of panicking.
+    pub fn try_new(port: String) -> serialport::Result<Self> {
+        let timeout = crate::config::get().timeouts.net_read();
+        let serial_port: Box<dyn Transport> = match port.strip_prefix(TCP_PREFIX) {
+            Some(addr) => Box::new(connect_tcp(addr, timeout)?),
+            None => Box::new(
+                serialport::new(port.clone(), 921_600)
+                    .data_bits(DataBits::Eight)
+                    .flow_control(FlowControl::None)
+                    .stop_bits(StopBits::One)
+                    .parity(Parity::None)
+                    .dtr_on_open(true)
+                    .timeout(timeout)
+                    .open()?,
+            ),
+        };
+        let serial_port = crate::metrics::wrap(&port, crate::capture::wrap(&port, serial_port));
+        Ok(Self::with_transport(port, serial_port))
+    }
 
-        Self { serial_port }
+    /// Talk over an already open transport, e.g. a `MockTransport` in tests.
+    pub fn with_transport(port_name: String, serial_port: Box<dyn Transport>) -> Self {
+        Self {
+            serial_port,
+            port_name,
+            last_transfer: None,
+            cancel: CancelToken::new(),
+            reader: None,
+        }
     }
 
     /// Update NET (CPU) firmware by version string (e.g., "2.28" or "2.8").
     ///
     /// Looks up the firmware file using the key "FP-CPU-2000_NET" within
-    /// AVAILABLE_FIRMWARE_VERSIONS, streams it to the NET port, waits for the
+    /// the firmware repo, streams it to the NET port, waits for the
     /// bootloader completion token, then verifies via ID. No address is required.
-    pub fn update_firmware(&mut self, version: &str) {
-        use crate::constants::AVAILABLE_FIRMWARE_VERSIONS;
+    pub fn update_firmware(&mut self, version: &str) -> FlashOutcome {
+        self.update_firmware_with(version, &mut ProgressDisplay::default())
+    }
 
+    /// `update_firmware`, telling `observer` how the flash goes instead of showing the
+    /// CLI's progress display.
+    pub fn update_firmware_with(
+        &mut self,
+        version: &str,
+        observer: &mut dyn FlashObserver,
+    ) -> FlashOutcome {
         // Normalize version to the stored format (e.g., 2.8 -> 2.08)
-        let normalized_version = {
-            let mut out = version.to_string();
-            if let Some((maj_s, min_s)) = version.split_once('.') {
-                if let (Ok(maj), Ok(min)) = (maj_s.parse::<u32>(), min_s.parse::<u32>()) {
-                    out = format!("{}.{}", maj, format!("{:02}", min));
-                }
-            }
-            out
-        };
+        let normalized_version = normalize_version(version);
 
         let key = "FP-CPU-2000_NET".to_string();
-        let file_path_opt = AVAILABLE_FIRMWARE_VERSIONS
-            .get(&key)
-            .and_then(|inner| inner.get(&normalized_version))
-            .cloned();
+        let repo = crate::firmware_repo::get();
+        let file_path_opt = repo.path(&key, &normalized_version).map(str::to_string);
 
         let Some(file_path) = file_path_opt else {
             eprintln!(
                 "NET firmware not found for version '{}'. Available: {:?}",
                 normalized_version,
-                AVAILABLE_FIRMWARE_VERSIONS
-                    .get(&key)
-                    .map(|m| m.keys().cloned().collect::<Vec<_>>())
+                repo.versions(&key)
             );
-            return;
+            return flash_done(observer, FlashOutcome::Failed);
         };
 
-        // Drain any pending input
-        let _ = self.receive();
+        self.flash_file_with(&file_path, Some(&normalized_version), observer)
+    }
 
-        // Display progress using indicatif
-        let total_size = match std::fs::metadata(&file_path) {
-            Ok(m) => m.len(),
-            Err(_) => 0,
-        };
+    /// Stream an arbitrary NET firmware file, then verify against `expected_version`
+    /// (when given) and kick off the remaining node board updates. The attempt is
+    /// recorded in the flash history.
+    pub fn flash_file(&mut self, file_path: &str, expected_version: Option<&str>) -> FlashOutcome {
+        self.flash_file_with(file_path, expected_version, &mut ProgressDisplay::default())
+    }
 
-        let pb = if total_size > 0 {
-            let pb = ProgressBar::new(total_size);
-            let style = ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) - {msg}")
-                .unwrap()
-                .progress_chars("##-");
-            pb.set_style(style);
-            pb.set_message(format!("Flashing {}", file_path));
-            pb
-        } else {
-            let pb = ProgressBar::new_spinner();
-            pb.enable_steady_tick(Duration::from_millis(100));
-            pb.set_message(format!("Flashing {} (size unknown)", file_path));
-            let style = ProgressStyle::with_template(
-                "{spinner:.green} {elapsed_precise} {bytes} sent - {msg}",
-            )
-            .unwrap();
-            pb.set_style(style);
-            pb
+    /// `flash_file`, telling `observer` how the flash goes instead of showing the CLI's
+    /// progress display.
+    pub fn flash_file_with(
+        &mut self,
+        file_path: &str,
+        expected_version: Option<&str>,
+        observer: &mut dyn FlashObserver,
+    ) -> FlashOutcome {
+        if self.cancel.is_cancelled() {
+            return flash_done(observer, FlashOutcome::Cancelled);
+        }
+        let _lock = match FlashLock::acquire(&self.port_name) {
+            Ok(lock) => lock,
+            Err(e) => {
+                eprintln!("{}", e);
+                return flash_done(observer, FlashOutcome::Failed);
+            }
         };
+        let old_id = self.id();
+        let start = Instant::now();
+        let outcome = self.stream_and_verify(file_path, expected_version, observer);
+        history::record(&FlashRecord {
+            board: old_id.as_ref().map(|id| id.board.clone()),
+            old_version: old_id.map(|id| id.version),
+            new_version: expected_version.map(str::to_string),
+            bytes_sent: self.last_transfer.map(|t| t.bytes_sent),
+            ..FlashRecord::new("NET", &self.port_name, file_path, outcome, start.elapsed())
+        });
+        flash_done(observer, outcome)
+    }
 
-        match std::fs::File::open(&file_path) {
-            Ok(file) => {
-                use std::io::BufRead;
-                let mut reader = std::io::BufReader::new(file);
-                let mut line: Vec<u8> = Vec::with_capacity(1024);
-                let mut bytes_sent: u64 = 0;
-                loop {
-                    line.clear();
-                    match reader.read_until(b'\r', &mut line) {
-                        Ok(0) => break, // EOF
-                        Ok(_) => {
-                            let _ = self.serial_port.write_all(&line);
-                            let _ = self.serial_port.flush();
-
-                            bytes_sent = bytes_sent.saturating_add(line.len() as u64);
-                            if total_size > 0 {
-                                pb.set_position(bytes_sent.min(total_size));
-                            } else {
-                                pb.set_message(format!(
-                                    "Flashing {} ({} bytes sent)",
-                                    file_path, bytes_sent
-                                ));
-                            }
-
-                            std::thread::sleep(Duration::from_millis(400));
-                        }
-                        Err(e) => {
-                            eprintln!(
-                                "Failed while reading NET firmware file '{}': {}",
-                                file_path, e
-                            );
-                            break;
-                        }
-                    }
-                }
+    /// The NET processor's `ID:` response, if it answers.
+    pub fn id(&mut self) -> Option<IdResponse> {
+        match self.request(&NetCommand::Id)? {
+            NetResponse::Id(id) => Some(id),
+            _ => None,
+        }
+    }
 
-                if total_size > 0 {
-                    pb.finish_with_message("Done");
-                } else {
-                    pb.finish_and_clear();
-                }
-            }
+    fn stream_and_verify(
+        &mut self,
+        file_path: &str,
+        expected_version: Option<&str>,
+        observer: &mut dyn FlashObserver,
+    ) -> FlashOutcome {
+        // Drain any pending input
+        let _ = self.receive();
+
+        self.last_transfer = None;
+        match stream_firmware(
+            self.serial_port.as_mut(),
+            &format!("NET {}", self.port_name),
+            file_path,
+            &FirmwareFormat::RECORDS,
+            crate::config::get().flash.net_line_delay(),
+            observer,
+            &self.cancel,
+        ) {
+            Ok(transfer) => self.last_transfer = Some(transfer),
             Err(e) => {
-                pb.finish_and_clear();
-                eprintln!("Failed to open NET firmware file '{}': {}", file_path, e);
-                return;
+                eprintln!("{}", e);
+                if stream_stopped(&self.cancel) {
+                    eprintln!(
+                        "The NET processor is left in its bootloader; run update-net again (power-cycle the machine first if it no longer answers)."
+                    );
+                    return FlashOutcome::Cancelled;
+                }
+                return FlashOutcome::Failed;
             }
         }
 
-        // Wait for bootloader completion acknowledgment "!BL2040:02"
-        let mut accumulate = String::new();
-        let start_wait = std::time::Instant::now();
-        let boot_timeout = Duration::from_secs(30);
+        // Wait for the bootloader completion acknowledgment "!B:02"
+        let mut parser = ResponseParser::new();
+        let start_wait = Instant::now();
+        let boot_timeout = crate::config::get().timeouts.bootloader();
         let mut saw_boot_ok = false;
         while start_wait.elapsed() < boot_timeout {
-            let resp = self.receive();
-            if !resp.is_empty() {
-                accumulate.push_str(&resp);
-                if accumulate.contains("!B:02") {
-                    saw_boot_ok = true;
-                    break;
-                }
+            if self
+                .receive_responses(&mut parser)
+                .contains(&NetResponse::BootloaderDone)
+            {
+                saw_boot_ok = true;
+                break;
             }
             std::thread::sleep(Duration::from_millis(50));
         }
@@ -157,110 +224,307 @@ impl NetProtocol {
                 "Timed out waiting for bootloader completion (!B:02). Proceeding to ID check..."
             );
         } else {
-            println!("Bootloader reported completion: !B:02");
+            status!("Bootloader reported completion: !B:02");
+            observer.event(&FlashEvent::BootloaderAck);
         }
 
         // Query the device ID and firmware version for NET
-        let _ = self.send(b"ID:\r");
-
-        // Collect ID response for up to 5 seconds
-        let verify_timeout = Duration::from_secs(5);
-        let start_verify = std::time::Instant::now();
-        let mut id_resp = String::new();
-        while start_verify.elapsed() < verify_timeout {
-            let r = self.receive();
-            if !r.is_empty() {
-                id_resp.push_str(&r);
+        observer.event(&FlashEvent::Verifying);
+        let _ = self.send_command(&NetCommand::Id);
+
+        // Wait for the ID response for up to the verification timeout
+        let verify_timeout = crate::config::get().timeouts.verify();
+        let start_verify = Instant::now();
+        let mut id = None;
+        while id.is_none() && start_verify.elapsed() < verify_timeout {
+            id = self
+                .receive_responses(&mut parser)
+                .into_iter()
+                .find_map(|r| match r {
+                    NetResponse::Id(id) => Some(id),
+                    _ => None,
+                });
+            if id.is_none() {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        }
+
+        if let Some(id) = &id {
+            status!("ID response: {}", id);
+        }
+
+        let outcome = match expected_version {
+            Some(expected_ver) if verify_id_response(id.as_ref(), expected_ver) => {
+                FlashOutcome::Verified
+            }
+            Some(_) => FlashOutcome::Mismatch,
+            None => {
+                status!("No expected version given; skipping post-flash verification.");
+                FlashOutcome::Unverified
             }
-            if id_resp.contains('\n') || id_resp.contains('\r') {
+        };
+
+        let updates = self.update_nodes();
+        report_node_updates(&updates);
+        outcome
+    }
+
+    /// Enumerate the I/O boards on the NET loop with `NN:`, stopping at the first
+    /// node that does not answer.
+    pub fn list_nodes(&mut self) -> Vec<NetBoardInfo> {
+        let mut nodes = Vec::new();
+        for index in 0..MAX_NODES {
+            if self.cancel.is_cancelled() {
                 break;
             }
-            std::thread::sleep(Duration::from_millis(50));
+            match self.request(&NetCommand::NodeInfo { node: index }) {
+                Some(NetResponse::NodeInfo(info)) => nodes.push(info),
+                _ => break,
+            }
+            // Be gentle on the bus
+            std::thread::sleep(Duration::from_millis(5));
         }
+        nodes
+    }
 
-        println!("ID response: {}", id_resp);
-
-        // Parse and validate the expected ID response format: "ID:NET {BoardName} {version}"
-        let expected_board = "FP-CPU-2000".to_string();
-        let expected_ver = normalized_version;
-        let mut found_line = None::<String>;
-        let mut parsed_board = None::<String>;
-        let mut parsed_version = None::<String>;
-        let mut verified = false;
-        for line in id_resp.lines() {
-            let l = line.trim();
-            if l.starts_with("ID:NET") {
-                found_line = Some(l.to_string());
-                let parts: Vec<&str> = l.split_whitespace().collect();
-                if parts.len() >= 3 {
-                    parsed_board = Some(parts[1].to_string());
-                    let mut ver = parts[2].trim().to_string();
-                    // Remove any trailing non-digit/dot characters (e.g., CR/LF or annotations)
-                    while ver.ends_with(|c: char| !c.is_ascii_digit() && c != '.') {
-                        ver.pop();
-                    }
-                    // Trim leading zeros from the major portion (e.g., "02.28" -> "2.28")
-                    let ver = if let Some((maj, rest)) = ver.split_once('.') {
-                        let maj_trim = maj.trim_start_matches('0');
-                        let maj_norm = if maj_trim.is_empty() { "0" } else { maj_trim };
-                        format!("{}.{}", maj_norm, rest)
-                    } else {
-                        // No dot present; just trim leading zeros of the whole string
-                        let trimmed = ver.trim_start_matches('0');
-                        if trimmed.is_empty() { "0".to_string() } else { trimmed.to_string() }
-                    };
-
-                    parsed_version = Some(ver.clone());
-                    if parts[1] == expected_board && ver == expected_ver {
-                        verified = true;
-                        break;
-                    }
-                }
+    /// Push pending firmware to the node boards with `bn:aa55` and report each node's
+    /// firmware before and after.
+    ///
+    /// Waits until every node has acknowledged with its bootloader completion token, or
+    /// until the bus stays quiet for `NODE_UPDATE_QUIET` (nodes without an update say
+    /// nothing), before enumerating the nodes again.
+    pub fn update_nodes(&mut self) -> Vec<NodeUpdate> {
+        let before = self.list_nodes();
+        status!(
+            "Updating {} node board(s). Not all I/O boards may have an update.",
+            before.len()
+        );
+        let _ = self.send_command(&NetCommand::UpdateNodes);
+
+        let start = Instant::now();
+        let mut last_activity = Instant::now();
+        let mut acks = 0usize;
+        let mut parser = ResponseParser::new();
+        while acks < before.len()
+            && start.elapsed() < NODE_UPDATE_TIMEOUT
+            && last_activity.elapsed() < NODE_UPDATE_QUIET
+        {
+            let responses = self.receive_responses(&mut parser);
+            if !responses.is_empty() {
+                last_activity = Instant::now();
             }
+            acks += responses
+                .iter()
+                .filter(|r| **r == NetResponse::BootloaderDone)
+                .count();
+            std::thread::sleep(Duration::from_millis(50));
         }
+        log::debug!(
+            "{} of {} node(s) acknowledged the update",
+            acks,
+            before.len()
+        );
+
+        let after = self.list_nodes();
+        before
+            .into_iter()
+            .map(|b| NodeUpdate {
+                after: after
+                    .iter()
+                    .find(|a| a.node_id == b.node_id)
+                    .map(|a| a.firmware.clone()),
+                node_id: b.node_id,
+                node_name: b.node_name,
+                before: b.firmware,
+            })
+            .collect()
+    }
+
+    /// Fire a single pulse on a driver.
+    ///
+    /// Feeds the watchdog (drivers stay disabled without it), configures the driver
+    /// for a one-shot pulse of `pulse_ms` milliseconds at `power` (0x00-0xFF), triggers
+    /// it manually, then returns the driver to a disabled state.
+    pub fn pulse_driver(&mut self, driver: u8, pulse_ms: u8, power: u8) -> std::io::Result<()> {
+        self.send_command(&NetCommand::Watchdog { ms: 1000 })?;
+        self.send_command(&NetCommand::pulse_driver(driver, pulse_ms, power))?;
+        self.send_command(&NetCommand::TriggerDriver {
+            driver,
+            control: DriverControl::ManualOn,
+        })?;
+        std::thread::sleep(Duration::from_millis(pulse_ms as u64 + 50));
+        self.send_command(&NetCommand::TriggerDriver {
+            driver,
+            control: DriverControl::ManualOff,
+        })?;
+        let _ = self.receive();
+        Ok(())
+    }
 
-        if verified {
-            println!(
-                "NET firmware update verified: board {} reports version {}",
-                expected_board, expected_ver
+    /// Hold a driver on at `power` (0x00-0xFF) for `on_time`, then disable it again.
+    ///
+    /// The watchdog is fed while the driver is on, so the controller does not shut it off
+    /// early.
+    pub fn hold_driver(&mut self, driver: u8, power: u8, on_time: Duration) -> std::io::Result<()> {
+        self.send_command(&NetCommand::Watchdog { ms: 1000 })?;
+        self.send_command(&NetCommand::hold_driver(driver, power))?;
+        self.send_command(&NetCommand::TriggerDriver {
+            driver,
+            control: DriverControl::ManualOn,
+        })?;
+        let start = Instant::now();
+        while start.elapsed() < on_time {
+            std::thread::sleep(
+                on_time
+                    .saturating_sub(start.elapsed())
+                    .min(Duration::from_millis(250)),
             );
-        } else {
-            if let (Some(pb), Some(pv)) = (parsed_board.as_deref(), parsed_version.as_deref()) {
-                if pb != expected_board {
-                    eprintln!(
-                        "Warning: ID board mismatch. Expected '{}', got '{}' (line: {:?}).",
-                        expected_board, pb, found_line
-                    );
-                }
-                if pv != expected_ver {
-                    eprintln!(
-                        "Warning: Firmware version mismatch. Expected '{}', got '{}' (line: {:?}).",
-                        expected_ver, pv, found_line
-                    );
+            self.send_command(&NetCommand::Watchdog { ms: 1000 })?;
+        }
+        self.send_command(&NetCommand::TriggerDriver {
+            driver,
+            control: DriverControl::ManualOff,
+        })?;
+        let _ = self.receive();
+        Ok(())
+    }
+
+    /// Reboot the controller with `BR:` and wait up to `timeout` for it to answer `ID:`
+    /// again. Should the port drop out while the Neuron restarts, it is opened again,
+    /// keeping the subscribers and the cancel token.
+    pub fn reboot(&mut self, timeout: Duration) -> Option<IdResponse> {
+        let _ = self.receive();
+        self.send_command(&NetCommand::Reboot).ok()?;
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            std::thread::sleep(RESET_POLL_INTERVAL);
+            if self.send_command(&NetCommand::Id).is_err() {
+                match Self::try_new(self.port_name.clone()) {
+                    Ok(reopened) => self.replace_port(reopened.serial_port),
+                    Err(e) => log::debug!("NET {} not back yet: {}", self.port_name, e),
                 }
-            } else if let Some(line) = found_line {
-                eprintln!(
-                    "Warning: Could not parse board/version from ID line: {:?}. Expected format: 'ID:NET {{BoardName}} {{version}}'",
-                    line
-                );
-            } else {
-                eprintln!(
-                    "Warning: No 'ID:NET' line found in response; cannot verify flashed version {} for board {}.",
-                    expected_ver, expected_board
-                );
+                continue;
+            }
+            if let Some(id) = IdResponse::find(&self.receive()) {
+                return Some(id);
+            }
+        }
+        None
+    }
+
+    /// Read a driver's configuration with a `DL:` query; `None` if it does not answer.
+    pub fn driver_config(&mut self, driver: u8) -> Option<DriverConfig> {
+        match self.request(&NetCommand::DriverConfig { driver })? {
+            NetResponse::DriverConfig(config) => Some(config),
+            _ => None,
+        }
+    }
+
+    /// Configure a driver with `DL:`, returning whether the controller processed it.
+    pub fn configure_driver(&mut self, config: DriverConfig) -> bool {
+        matches!(
+            self.request(&config.into()),
+            Some(NetResponse::Processed { .. })
+        )
+    }
+
+    /// Send `command` and wait for its reply (see `NetCommand::reply_kind`), which the
+    /// background reader hands over whole, however it is split over reads and whatever
+    /// the controller sends in between. `None` when no reply arrives within the receive
+    /// timeout, or for commands without one.
+    pub fn request(&mut self, command: &NetCommand) -> Option<NetResponse> {
+        let kind = command.reply_kind()?;
+        if let Err(e) = self.start_reader() {
+            log::debug!("{}; reading replies directly", e);
+            return self.request_directly(command);
+        }
+        if let Some(reader) = self.reader.as_ref() {
+            reader.discard(kind);
+        }
+        self.send_command(command).ok()?;
+        let reply =
+            self.reader
+                .as_ref()?
+                .take_reply(kind, crate::config::get().timeouts.receive(), |r| {
+                    command.is_answered_by(r)
+                });
+        match reply {
+            Ok(reply) => {
+                log::debug!("NET {} reply to {}: {:?}", self.port_name, command, reply);
+                Some(reply)
+            }
+            Err(e) => {
+                log::debug!("NET {} no reply to {}: {}", self.port_name, command, e);
+                None
             }
         }
+    }
 
-        println!("Attempting to update remaining node boards. Not all I/O boards may have an update.");
-        // Update the remaining node boards
-        _ =self.send(b"bn:aa55\r");
+    // `request` for ports the reader cannot share: one read after a short pause
+    fn request_directly(&mut self, command: &NetCommand) -> Option<NetResponse> {
+        let _ = self.receive();
+        self.send_command(command).ok()?;
+        std::thread::sleep(Duration::from_millis(10));
+        self.receive_responses(&mut ResponseParser::new())
+            .into_iter()
+            .find(|r| command.is_answered_by(r))
+    }
 
+    /// Hand every message from now on to `subscriber`, starting the background reader
+    /// (see `net_events`) if it is not running yet.
+    pub fn subscribe(&mut self, subscriber: Subscriber) -> Result<(), String> {
+        self.start_reader()?;
+        if let Some(reader) = self.reader.as_ref() {
+            reader.subscribe(subscriber);
+        }
+        Ok(())
+    }
+
+    // Talk over `port` from now on, moving the subscribers over to a reader of it
+    fn replace_port(&mut self, port: Box<dyn Transport>) {
+        let reader = self.reader.take();
+        self.serial_port = port;
+        let Some(reader) = reader else {
+            return;
+        };
+        let subscribers = reader.into_subscribers();
+        if let Err(e) = self.start_reader() {
+            log::warn!("{}; {} subscriber(s) dropped", e, subscribers.len());
+            return;
+        }
+        for subscriber in subscribers {
+            self.subscribe(subscriber).ok();
+        }
+    }
 
+    // From now on the reader thread reads the port, and `serial_port` what it received
+    fn start_reader(&mut self) -> Result<(), String> {
+        if self.reader.is_some() {
+            return Ok(());
+        }
+        let (reader, transport) = NetEventReader::start(
+            self.serial_port.as_ref(),
+            format!("NET {}", self.port_name),
+            crate::config::get().timeouts.net_read(),
+        )
+        .map_err(|e| format!("Failed to read NET port {}: {}", self.port_name, e))?;
+        self.serial_port = transport;
+        self.reader = Some(reader);
+        Ok(())
+    }
 
+    /// Send a typed command (see `net_commands`).
+    pub fn send_command(&mut self, command: &NetCommand) -> std::io::Result<()> {
+        self.send(&command.to_bytes())
     }
 
     pub fn send(&mut self, command: &[u8]) -> std::io::Result<()> {
         use std::io::{ErrorKind, Write};
+        log::debug!(
+            "NET {} TX {:?}",
+            self.port_name,
+            String::from_utf8_lossy(command)
+        );
         // Retry on Interrupted, propagate other errors
         loop {
             match self.serial_port.write_all(command) {
@@ -270,24 +534,206 @@ impl NetProtocol {
                     return Ok(());
                 }
                 Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
-                Err(e) => return Err(e),
+                Err(e) => {
+                    log::warn!("NET {} write failed: {}", self.port_name, e);
+                    return Err(e);
+                }
             }
         }
     }
 
+    /// Receive complete response lines, reading until the port goes quiet
+    /// (see `read_response`).
+    pub fn receive_lines(&mut self) -> Vec<String> {
+        let lines = split_lines(&read_response(
+            self.serial_port.as_mut(),
+            crate::config::get().timeouts.receive(),
+        ));
+        for line in lines.iter() {
+            log::debug!("NET {} RX {:?}", self.port_name, line);
+        }
+        lines
+    }
+
+    /// Receive a response as a single string, one line per message.
     pub fn receive(&mut self) -> String {
-        let mut buf_bytes = [0u8; 256];
-        let mut collected = Vec::new();
-
-        match self.serial_port.read(&mut buf_bytes) {
-            Ok(0) => {}
-            Ok(n) => {
-                collected.extend_from_slice(&buf_bytes[..n]);
-                if collected.len() >= 256 {}
-            }
-            Err(_e) => {}
+        self.receive_lines().join("\n")
+    }
+
+    /// Wait up to `timeout` for a reply, returning as soon as a complete one has arrived
+    /// (see `read_reply`). One line per message, like `receive`.
+    pub fn receive_within(&mut self, timeout: Duration) -> String {
+        let lines = split_lines(&read_reply(self.serial_port.as_mut(), timeout));
+        for line in lines.iter() {
+            log::debug!("NET {} RX {:?}", self.port_name, line);
         }
+        lines.join("\n")
+    }
+
+    /// Receive typed responses. `parser` keeps a message that has only partly arrived
+    /// for the next call, so pass the same one while waiting for a response.
+    pub fn receive_responses(
+        &mut self,
+        parser: &mut ResponseParser<NetResponse>,
+    ) -> Vec<NetResponse> {
+        let responses = parser.push(&read_response(
+            self.serial_port.as_mut(),
+            crate::config::get().timeouts.receive(),
+        ));
+        for response in responses.iter() {
+            log::debug!("NET {} RX {:?}", self.port_name, response);
+        }
+        responses
+    }
+}
+
+/// Print one line per node: updated, unchanged, or failed (still behind the newest
+/// available firmware or off its pinned version, or no longer answering).
+fn report_node_updates(updates: &[NodeUpdate]) {
+    for n in updates {
+        let target = Target::for_board(
+            &n.node_name,
+            &format!("NET {}", n.node_id),
+            &crate::firmware_repo::get().versions(&format!("{}_NET", n.node_name)),
+        );
+        let status = match n.after.as_deref() {
+            None => "FAILED: node no longer answers".to_string(),
+            Some(after) => match &target {
+                Some(target) if target.needs_update(after) && target.pinned => {
+                    format!("FAILED: still {}, pinned to {}", after, target.version)
+                }
+                Some(target) if target.needs_update(after) => {
+                    format!("FAILED: still {}, {} available", after, target.version)
+                }
+                _ if after != n.before => format!("updated {} -> {}", n.before, after),
+                _ => format!("unchanged ({})", after),
+            },
+        };
+        status!("  Node {} ({}): {}", n.node_id, n.node_name, status);
+    }
+}
+
+/// Check the `ID:` response after a flash against the expected NET board and version,
+/// printing diagnostics on mismatch. Returns true when both match.
+fn verify_id_response(id: Option<&IdResponse>, expected_ver: &str) -> bool {
+    const EXPECTED_BOARD: &str = "FP-CPU-2000";
+    let Some(id) = id.filter(|id| id.protocol == "NET") else {
+        eprintln!(
+            "Warning: No 'ID:NET' response; cannot verify flashed version {} for board {}.",
+            expected_ver, EXPECTED_BOARD
+        );
+        return false;
+    };
+    let board_ok = id.board == EXPECTED_BOARD;
+    // Compare numerically, so "02.28" matches "2.28"
+    let version_ok =
+        version_key(&id.version).is_some() && version_key(&id.version) == version_key(expected_ver);
+    if board_ok && version_ok {
+        status!(
+            "NET firmware update verified: board {} reports version {}",
+            EXPECTED_BOARD,
+            expected_ver
+        );
+        return true;
+    }
+    if !board_ok {
+        eprintln!(
+            "Warning: ID board mismatch. Expected '{}', got '{}' ({}).",
+            EXPECTED_BOARD, id.board, id
+        );
+    }
+    if !version_ok {
+        eprintln!(
+            "Warning: Firmware version mismatch. Expected '{}', got '{}' ({}).",
+            expected_ver, id.version, id
+        );
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::mock::{MockTransport, firmware_file};
+
+    fn net(mock: &MockTransport) -> NetProtocol {
+        NetProtocol::with_transport("mock".into(), Box::new(mock.clone()))
+    }
+
+    // Stream a firmware file as `flash_file` does, without its lock and history
+    fn flash(mock: &MockTransport, name: &str, expected: &str) -> (FlashOutcome, Vec<FlashEvent>) {
+        let file = firmware_file(name);
+        let mut events = Vec::new();
+        let outcome =
+            net(mock).stream_and_verify(&file, Some(expected), &mut |event: &FlashEvent| {
+                events.push(event.clone())
+            });
+        let _ = std::fs::remove_file(&file);
+        (outcome, events)
+    }
+
+    #[test]
+    fn id_handshake() {
+        let mock = MockTransport::new().respond("ID:", "ID:NET FP-CPU-2000 02.28\r");
+        let id = net(&mock).id().unwrap();
+        assert_eq!(id.board, "FP-CPU-2000");
+        assert_eq!(id.version, "02.28");
+        assert_eq!(mock.commands(), ["ID:"]);
+    }
+
+    #[test]
+    fn id_timeout() {
+        let mock = MockTransport::new();
+        assert_eq!(net(&mock).id(), None);
+        assert_eq!(mock.commands(), ["ID:"]);
+    }
+
+    #[test]
+    fn nodes_until_not_found() {
+        let mock = MockTransport::new()
+            .respond("NN:00", "NN:00,FP-I/O-3208,1.05,08,20\r")
+            .respond("NN:01", "!Node Not Found!\r");
+        let nodes = net(&mock).list_nodes();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].node_name, "FP-I/O-3208");
+        assert_eq!(mock.commands(), ["NN:00", "NN:01"]);
+    }
+
+    #[test]
+    fn flash_completes_and_verifies() {
+        let mock = MockTransport::new()
+            .respond(":00000001FF", "!B:02\r")
+            .respond("ID:", "ID:NET FP-CPU-2000 02.30\r")
+            .respond("NN:00", "!Node Not Found!\r")
+            .respond("NN:00", "!Node Not Found!\r");
+        let (outcome, events) = flash(&mock, "net-verified", "2.30");
+        assert_eq!(outcome, FlashOutcome::Verified);
+        assert!(events.contains(&FlashEvent::BootloaderAck));
+    }
+
+    #[test]
+    fn flash_answered_by_other_board_is_a_mismatch() {
+        let mock = MockTransport::new()
+            .respond(":00000001FF", "!B:02\r")
+            .respond("ID:", "ID:NET FP-CPU-1000 02.30\r")
+            .respond("NN:00", "!Node Not Found!\r")
+            .respond("NN:00", "!Node Not Found!\r");
+        assert_eq!(flash(&mock, "net-board", "2.30").0, FlashOutcome::Mismatch);
+    }
+
+    #[test]
+    fn reopened_port_keeps_subscribers_and_cancel() {
+        let mut net = net(&MockTransport::new());
+        let (tx, rx) = std::sync::mpsc::channel();
+        net.subscribe(Subscriber::Channel(tx)).unwrap();
+        let cancel = net.cancel.clone();
 
-        String::from_utf8_lossy(&collected).trim().to_string()
+        let reopened = MockTransport::new();
+        net.replace_port(Box::new(reopened.clone()));
+        reopened.push_incoming("-L:05\r");
+        let event = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(event, NetResponse::SwitchClosed { switch: 5 });
+        cancel.cancel();
+        assert!(net.cancel.is_cancelled());
     }
 }
@@ -0,0 +1,436 @@
+// Typed responses of the NET and EXP processors.
+//
+// `NetResponse::parse` and `ExpResponse::parse` classify one complete message. Serial
+// reads can end in the middle of a message, so code that reads raw bytes feeds them to
+// a `ResponseParser`, which only hands out messages once their `\r` terminator is in:
+//
+//     let mut parser = ResponseParser::<NetResponse>::new();
+//     for response in parser.push(&bytes) {
+//         if response == NetResponse::BootloaderDone { ... }
+//     }
+
+use crate::fast_monitor::NetBoardInfo;
+use crate::protocol::net_commands::DriverConfig;
+use crate::protocol::split_lines;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// A message from the processor, parsed from one line.
+pub trait Response: Sized {
+    fn parse(line: &str) -> Self;
+}
+
+/// Lines as they are, for protocols without a response type of their own.
+impl Response for String {
+    fn parse(line: &str) -> Self {
+        line.to_string()
+    }
+}
+
+/// `ID:{protocol} {board} {version}`, e.g. `ID:EXP FP-EXP-0071 0.48`; commas after the
+/// protocol are tolerated (`ID:EXP, FP-EXP-0091 v0.48`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdResponse {
+    pub protocol: String,
+    pub board: String,
+    pub version: String,
+}
+
+impl IdResponse {
+    /// Parse one line, which must be an `ID:` response.
+    pub fn parse(line: &str) -> Option<Self> {
+        let rest = line.trim().strip_prefix("ID:")?.replace(',', " ");
+        let mut parts = rest.split_whitespace();
+        Some(Self {
+            protocol: parts.next()?.to_string(),
+            board: parts.next()?.to_string(),
+            version: parts.next()?.to_string(),
+        })
+    }
+
+    /// The first `ID:` response among the lines of `text` (e.g. a `receive()` result).
+    pub fn find(text: &str) -> Option<Self> {
+        text.lines().find_map(Self::parse)
+    }
+}
+
+impl fmt::Display for IdResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ID:{} {} {}", self.protocol, self.board, self.version)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetResponse {
+    /// `ID:NET FP-CPU-2000 02.28`
+    Id(IdResponse),
+    /// `NN:{node},{name},{firmware},{drivers},{switches},...`
+    NodeInfo(NetBoardInfo),
+    /// `DL:{driver},{trigger},{switch},{mode},{param 1},...,{param 5}`, the reply to a
+    /// `DL:{driver}` query.
+    DriverConfig(DriverConfig),
+    /// `!Node Not Found!`, the reply to `NN:` past the last node.
+    NodeNotFound,
+    /// `!B:02`: the bootloader finished flashing the controller or a node board.
+    BootloaderDone,
+    /// `-L:{switch}`
+    SwitchClosed { switch: u16 },
+    /// `/L:{switch}`
+    SwitchOpened { switch: u16 },
+    /// `{command}:P`: the command was processed.
+    Processed { command: String },
+    /// `{command}:F`: the command failed.
+    Failed { command: String },
+    /// Anything else, as received.
+    Other(String),
+}
+
+/// What a `NetResponse` is, for handing replies to the command waiting for them (see
+/// `NetCommand::reply_kind`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NetResponseKind {
+    Id,
+    /// `NN:` replies, including `!Node Not Found!`.
+    NodeInfo,
+    DriverConfig,
+    BootloaderDone,
+    /// Switch changes, which the controller sends on its own.
+    Switch,
+    /// `{command}:P` and `{command}:F`.
+    Status,
+    Other,
+}
+
+impl NetResponse {
+    pub fn kind(&self) -> NetResponseKind {
+        match self {
+            NetResponse::Id(_) => NetResponseKind::Id,
+            NetResponse::NodeInfo(_) | NetResponse::NodeNotFound => NetResponseKind::NodeInfo,
+            NetResponse::DriverConfig(_) => NetResponseKind::DriverConfig,
+            NetResponse::BootloaderDone => NetResponseKind::BootloaderDone,
+            NetResponse::SwitchClosed { .. } | NetResponse::SwitchOpened { .. } => {
+                NetResponseKind::Switch
+            }
+            NetResponse::Processed { .. } | NetResponse::Failed { .. } => NetResponseKind::Status,
+            NetResponse::Other(_) => NetResponseKind::Other,
+        }
+    }
+}
+
+impl Response for NetResponse {
+    fn parse(line: &str) -> Self {
+        let line = line.trim();
+        if let Some(id) = IdResponse::parse(line) {
+            return NetResponse::Id(id);
+        }
+        if let Some(info) = line.strip_prefix("NN:").and_then(parse_node_info) {
+            return NetResponse::NodeInfo(info);
+        }
+        if let Some(config) = line.strip_prefix("DL:").and_then(parse_driver_config) {
+            return NetResponse::DriverConfig(config);
+        }
+        let switch = |rest: &str| u16::from_str_radix(rest.trim(), 16).ok();
+        if let Some(switch) = line.strip_prefix("-L:").and_then(switch) {
+            return NetResponse::SwitchClosed { switch };
+        }
+        if let Some(switch) = line.strip_prefix("/L:").and_then(switch) {
+            return NetResponse::SwitchOpened { switch };
+        }
+        match line {
+            "!Node Not Found!" => NetResponse::NodeNotFound,
+            "!B:02" => NetResponse::BootloaderDone,
+            _ => match parse_status(line) {
+                Some((command, true)) => NetResponse::Processed { command },
+                Some((command, false)) => NetResponse::Failed { command },
+                None => NetResponse::Other(line.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpResponse {
+    /// `ID:EXP FP-EXP-0071 0.48`
+    Id(IdResponse),
+    /// `!BL2040:02`: the bootloader finished flashing the addressed board.
+    BootloaderDone,
+    /// Any other `!BL...` line: a board sitting in its bootloader, e.g. after a failed
+    /// flash, announcing itself instead of answering with its ID.
+    Bootloader(String),
+    /// `{command}:P`: the command was processed.
+    Processed { command: String },
+    /// `{command}:F`: the command failed.
+    Failed { command: String },
+    /// Anything else, as received.
+    Other(String),
+}
+
+impl Response for ExpResponse {
+    fn parse(line: &str) -> Self {
+        let line = line.trim();
+        if let Some(id) = IdResponse::parse(line) {
+            return ExpResponse::Id(id);
+        }
+        if line == "!BL2040:02" {
+            return ExpResponse::BootloaderDone;
+        }
+        if line.starts_with("!BL") {
+            return ExpResponse::Bootloader(line.to_string());
+        }
+        match parse_status(line) {
+            Some((command, true)) => ExpResponse::Processed { command },
+            Some((command, false)) => ExpResponse::Failed { command },
+            None => ExpResponse::Other(line.to_string()),
+        }
+    }
+}
+
+/// Turns raw bytes, in whatever pieces they are read, into responses.
+#[derive(Debug, Clone)]
+pub struct ResponseParser<R> {
+    pending: Vec<u8>,
+    _response: PhantomData<R>,
+}
+
+impl<R: Response> Default for ResponseParser<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: Response> ResponseParser<R> {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            _response: PhantomData,
+        }
+    }
+
+    /// Add bytes as they were read, returning the responses they complete. The start of
+    /// a message that is still arriving is kept for the next call.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<R> {
+        self.pending.extend_from_slice(bytes);
+        let Some(end) = self
+            .pending
+            .iter()
+            .rposition(|b| matches!(b, b'\r' | b'\n'))
+        else {
+            return Vec::new();
+        };
+        let complete: Vec<u8> = self.pending.drain(..=end).collect();
+        split_lines(&complete).iter().map(|l| R::parse(l)).collect()
+    }
+}
+
+// The part of an `NN:` response after the prefix
+fn parse_node_info(rest: &str) -> Option<NetBoardInfo> {
+    let parts: Vec<&str> = rest.split(',').map(|s| s.trim()).collect();
+    if parts.len() < 3 {
+        return None;
+    }
+    let extra_fields: Vec<String> = parts[3..].iter().map(|s| s.to_string()).collect();
+    // Counts follow the firmware as hex: drivers first, then switches
+    let count = |i: usize| {
+        extra_fields
+            .get(i)
+            .and_then(|f| u16::from_str_radix(f, 16).ok())
+    };
+    Some(NetBoardInfo {
+        node_id: parts[0].to_string(),
+        node_name: parts[1].to_string(),
+        firmware: parts[2].to_string(),
+        driver_count: count(0),
+        switch_count: count(1),
+        extra_fields,
+    })
+}
+
+// The part of a `DL:` response after the prefix: nine hex fields
+fn parse_driver_config(rest: &str) -> Option<DriverConfig> {
+    let fields: Vec<u8> = rest
+        .split(',')
+        .map(|f| u8::from_str_radix(f.trim(), 16).ok())
+        .collect::<Option<_>>()?;
+    let [driver, trigger, switch, mode, p1, p2, p3, p4, p5] = fields[..] else {
+        return None;
+    };
+    Some(DriverConfig {
+        driver,
+        trigger,
+        switch,
+        mode,
+        params: [p1, p2, p3, p4, p5],
+    })
+}
+
+// `XX:P` / `XX:F` into (command, processed)
+fn parse_status(line: &str) -> Option<(String, bool)> {
+    let (command, status) = line.split_once(':')?;
+    if command.is_empty() || !command.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    match status {
+        "P" => Some((command.to_string(), true)),
+        "F" => Some((command.to_string(), false)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_split_across_reads() {
+        let mut parser = ResponseParser::<NetResponse>::new();
+        assert!(parser.push(b"ID:NET FP-CPU").is_empty());
+        assert_eq!(
+            parser.push(b"-2000 02.28\rWD"),
+            [NetResponse::Id(IdResponse {
+                protocol: "NET".into(),
+                board: "FP-CPU-2000".into(),
+                version: "02.28".into(),
+            })]
+        );
+        assert_eq!(
+            parser.push(b":P\r"),
+            [NetResponse::Processed {
+                command: "WD".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn carriage_return_and_newline_terminators() {
+        let mut parser = ResponseParser::<NetResponse>::new();
+        assert_eq!(
+            parser.push(b"WD:P\nDL:F\r\n!B:02\r"),
+            [
+                NetResponse::Processed {
+                    command: "WD".into()
+                },
+                NetResponse::Failed {
+                    command: "DL".into()
+                },
+                NetResponse::BootloaderDone,
+            ]
+        );
+    }
+
+    #[test]
+    fn id_with_comma_after_protocol() {
+        assert_eq!(
+            ExpResponse::parse("ID:EXP, FP-EXP-0091 v0.48"),
+            ExpResponse::Id(IdResponse {
+                protocol: "EXP".into(),
+                board: "FP-EXP-0091".into(),
+                version: "v0.48".into(),
+            })
+        );
+        assert_eq!(IdResponse::parse("ID:EXP FP-EXP-0091"), None);
+    }
+
+    #[test]
+    fn node_info() {
+        let NetResponse::NodeInfo(info) = NetResponse::parse("NN:01,FP-I/O-0804,1.05,04,08") else {
+            panic!("not parsed as node info");
+        };
+        assert_eq!(info.node_id, "01");
+        assert_eq!(info.node_name, "FP-I/O-0804");
+        assert_eq!(info.firmware, "1.05");
+        assert_eq!(info.driver_count, Some(4));
+        assert_eq!(info.switch_count, Some(8));
+    }
+
+    #[test]
+    fn node_info_with_too_few_fields() {
+        assert_eq!(
+            NetResponse::parse("NN:01,FP-I/O-0804"),
+            NetResponse::Other("NN:01,FP-I/O-0804".into())
+        );
+    }
+
+    #[test]
+    fn node_info_with_non_hex_counts() {
+        let NetResponse::NodeInfo(info) = NetResponse::parse("NN:01,FP-I/O-0804,1.05,XX,20") else {
+            panic!("not parsed as node info");
+        };
+        assert_eq!(info.driver_count, None);
+        assert_eq!(info.switch_count, Some(0x20));
+        assert_eq!(info.extra_fields, ["XX", "20"]);
+    }
+
+    #[test]
+    fn node_not_found() {
+        assert_eq!(
+            NetResponse::parse("!Node Not Found!"),
+            NetResponse::NodeNotFound
+        );
+        assert_eq!(
+            NetResponse::parse("!Node Not Found!").kind(),
+            NetResponseKind::NodeInfo
+        );
+    }
+
+    #[test]
+    fn driver_config() {
+        assert_eq!(
+            NetResponse::parse("DL:05,81,00,10,1E,FF,00,00,00"),
+            NetResponse::DriverConfig(DriverConfig {
+                driver: 5,
+                trigger: 0x81,
+                switch: 0,
+                mode: 0x10,
+                params: [0x1E, 0xFF, 0, 0, 0],
+            })
+        );
+    }
+
+    #[test]
+    fn driver_config_with_wrong_field_count() {
+        for line in [
+            "DL:05,81,00,10,1E,FF,00,00",
+            "DL:05,81,00,10,1E,FF,00,00,00,00",
+        ] {
+            assert_eq!(NetResponse::parse(line), NetResponse::Other(line.into()));
+        }
+    }
+
+    #[test]
+    fn command_status() {
+        assert_eq!(
+            NetResponse::parse("WD:P"),
+            NetResponse::Processed {
+                command: "WD".into()
+            }
+        );
+        assert_eq!(
+            NetResponse::parse("DL:F"),
+            NetResponse::Failed {
+                command: "DL".into()
+            }
+        );
+        assert_eq!(
+            ExpResponse::parse("RS:F"),
+            ExpResponse::Failed {
+                command: "RS".into()
+            }
+        );
+        assert_eq!(
+            NetResponse::parse("W1:P"),
+            NetResponse::Other("W1:P".into())
+        );
+    }
+
+    #[test]
+    fn switch_changes() {
+        assert_eq!(
+            NetResponse::parse("-L:1A"),
+            NetResponse::SwitchClosed { switch: 0x1A }
+        );
+        assert_eq!(
+            NetResponse::parse("/L:1A"),
+            NetResponse::SwitchOpened { switch: 0x1A }
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use crate::protocol::responses::IdResponse;
+use crate::protocol::transport::{TCP_PREFIX, Transport, connect_tcp};
+use crate::protocol::{read_response, split_lines};
+use serialport::{DataBits, FlowControl, Parity, StopBits};
+use std::io::Write;
+use std::time::Duration;
+
+/// Characters on one segment display.
+pub const SEG_DIGITS: usize = 7;
+
+/// The segment display processor port, which drives the alphanumeric displays of
+/// FAST retro builds.
+pub struct SegProtocol {
+    pub serial_port: Box<dyn Transport>,
+    pub port_name: String,
+}
+
+impl SegProtocol {
+    /// Open the given serial port (or `tcp://host:port` endpoint).
+    pub fn try_new(port: String) -> serialport::Result<Self> {
+        let timeout = crate::config::get().timeouts.exp_read();
+        let serial_port: Box<dyn Transport> = match port.strip_prefix(TCP_PREFIX) {
+            Some(addr) => Box::new(connect_tcp(addr, timeout)?),
+            None => Box::new(
+                serialport::new(port.clone(), 921_600)
+                    .data_bits(DataBits::Eight)
+                    .parity(Parity::None)
+                    .stop_bits(StopBits::One)
+                    .dtr_on_open(true)
+                    .flow_control(FlowControl::None)
+                    .timeout(timeout)
+                    .open()?,
+            ),
+        };
+        let serial_port = crate::metrics::wrap(&port, crate::capture::wrap(&port, serial_port));
+        Ok(Self::with_transport(port, serial_port))
+    }
+
+    /// Talk over an already open transport, e.g. a `MockTransport` in tests.
+    pub fn with_transport(port_name: String, serial_port: Box<dyn Transport>) -> Self {
+        Self {
+            serial_port,
+            port_name,
+        }
+    }
+
+    /// Query the processor with `ID:`, returning its (board name, firmware version).
+    pub fn id(&mut self) -> Option<(String, String)> {
+        let _ = self.receive();
+        self.send(b"ID:\r");
+        std::thread::sleep(Duration::from_millis(10));
+        let resp = self.receive();
+        IdResponse::find(&resp).map(|id| (id.board, id.version))
+    }
+
+    /// Show `text` on a display with `PA:`, padded or cut to `SEG_DIGITS` characters.
+    pub fn write_text(&mut self, display: u8, text: &str) {
+        let text: String = text
+            .chars()
+            .chain(std::iter::repeat(' '))
+            .take(SEG_DIGITS)
+            .collect();
+        self.send(format!("PA:{:X},{}\r", display, text).as_bytes());
+    }
+
+    /// Set the color of every character on a display with `PC:`.
+    pub fn set_color(&mut self, display: u8, (r, g, b): (u8, u8, u8)) {
+        let color = format!("{:02X}{:02X}{:02X}", r, g, b);
+        self.send(format!("PC:{:X},{}\r", display, color.repeat(SEG_DIGITS)).as_bytes());
+    }
+
+    pub fn send(&mut self, command: &[u8]) {
+        log::debug!(
+            "SEG {} TX {:?}",
+            self.port_name,
+            String::from_utf8_lossy(command)
+        );
+        // Best-effort write; avoid panicking on errors
+        if let Err(e) = self.serial_port.write_all(command) {
+            log::warn!("SEG {} write failed: {}", self.port_name, e);
+        }
+        let _ = self.serial_port.flush();
+    }
+
+    /// Receive complete response lines, reading until the port goes quiet
+    /// (see `read_response`).
+    pub fn receive_lines(&mut self) -> Vec<String> {
+        let lines = split_lines(&read_response(
+            self.serial_port.as_mut(),
+            crate::config::get().timeouts.receive(),
+        ));
+        for line in lines.iter() {
+            log::debug!("SEG {} RX {:?}", self.port_name, line);
+        }
+        lines
+    }
+
+    /// Receive a response as a single string, one line per message.
+    pub fn receive(&mut self) -> String {
+        self.receive_lines().join("\n")
+    }
+}
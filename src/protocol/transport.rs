@@ -0,0 +1,184 @@
+// The byte link a protocol talks over. Real hardware uses a serial port; tests can use
+// `MockTransport` to script the board's side of the conversation, and the emulator is
+// reached over TCP.
+
+use serialport::SerialPort;
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Port names starting with this are TCP endpoints (e.g. `tcp://127.0.0.1:7700`).
+pub const TCP_PREFIX: &str = "tcp://";
+
+pub trait Transport: Read + Write + Send {
+    /// Number of received bytes waiting to be read.
+    fn bytes_to_read(&self) -> io::Result<u32>;
+
+    /// Open a second handle on the same link, e.g. for a reader thread.
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>>;
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn bytes_to_read(&self) -> io::Result<u32> {
+        Ok((**self).bytes_to_read()?)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new((**self).try_clone()?))
+    }
+}
+
+/// A TCP connection read like a serial port: a thread of its own reads the socket into a
+/// buffer that every clone shares, so `bytes_to_read` needs no change to the socket's
+/// blocking mode, which would also change it under the other clones. Reads wait up to
+/// the read timeout for data, then fail with `TimedOut`.
+pub struct TcpTransport {
+    link: Arc<TcpLink>,
+    read_timeout: Duration,
+}
+
+// The socket as written to; shut down once the last clone is gone, which ends the reader
+struct TcpLink {
+    stream: TcpStream,
+    received: Arc<Received>,
+}
+
+#[derive(Default)]
+struct Received {
+    state: Mutex<ReceivedState>,
+    arrived: Condvar,
+}
+
+#[derive(Default)]
+struct ReceivedState {
+    bytes: VecDeque<u8>,
+    /// Set once the reader stopped: `None` when the other end closed the connection.
+    closed: Option<Option<io::Error>>,
+}
+
+impl Received {
+    fn lock(&self) -> MutexGuard<'_, ReceivedState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for TcpLink {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let received = &self.link.received;
+        let (mut state, _) = received
+            .arrived
+            .wait_timeout_while(received.lock(), self.read_timeout, |s| {
+                s.bytes.is_empty() && s.closed.is_none()
+            })
+            .unwrap_or_else(|e| e.into_inner());
+        if state.bytes.is_empty() {
+            return match &state.closed {
+                None => Err(io::Error::new(
+                    ErrorKind::TimedOut,
+                    "no data from TCP endpoint",
+                )),
+                Some(None) => Ok(0),
+                Some(Some(e)) => Err(io::Error::new(e.kind(), e.to_string())),
+            };
+        }
+        let n = buf.len().min(state.bytes.len());
+        for (slot, byte) in buf.iter_mut().zip(state.bytes.drain(..n)) {
+            *slot = byte;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&self.link.stream).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&self.link.stream).flush()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn bytes_to_read(&self) -> io::Result<u32> {
+        Ok(self.link.received.lock().bytes.len() as u32)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(TcpTransport {
+            link: Arc::clone(&self.link),
+            read_timeout: self.read_timeout,
+        }))
+    }
+}
+
+/// Connect to a TCP endpoint, with reads timing out like a serial port would.
+pub fn connect_tcp(addr: &str, timeout: Duration) -> io::Result<TcpTransport> {
+    let stream = TcpStream::connect(addr)?;
+    stream.set_nodelay(true)?;
+    let mut reader = stream.try_clone()?;
+    let received: Arc<Received> = Arc::default();
+    {
+        let received = Arc::clone(&received);
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            let closed = loop {
+                match reader.read(&mut buf) {
+                    Ok(0) => break None,
+                    Ok(n) => {
+                        received.lock().bytes.extend(&buf[..n]);
+                        received.arrived.notify_all();
+                    }
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => break Some(e),
+                }
+            };
+            received.lock().closed = Some(closed);
+            received.arrived.notify_all();
+        });
+    }
+    Ok(TcpTransport {
+        link: Arc::new(TcpLink { stream, received }),
+        read_timeout: timeout,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn tcp_clones_share_received_bytes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let mut port = connect_tcp(&addr, Duration::from_millis(50)).unwrap();
+        let mut clone = port.try_clone().unwrap();
+        let (mut board, _) = listener.accept().unwrap();
+
+        let mut buf = [0u8; 16];
+        let timed_out = clone.read(&mut buf).unwrap_err();
+        assert_eq!(timed_out.kind(), ErrorKind::TimedOut);
+
+        board.write_all(b"ID:P\r").unwrap();
+        let start = std::time::Instant::now();
+        while port.bytes_to_read().unwrap() < 5 && start.elapsed() < Duration::from_secs(2) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(clone.bytes_to_read().unwrap(), 5);
+        assert_eq!(clone.read(&mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], b"ID:P\r");
+        assert_eq!(port.bytes_to_read().unwrap(), 0);
+
+        drop(board);
+        assert_eq!(port.read(&mut buf).unwrap(), 0);
+    }
+}
@@ -0,0 +1,146 @@
+// Release notes of a firmware version, shown before a flash is confirmed so users know
+// what they are getting. They come from a Markdown file next to the firmware file
+// (`FP-EXP-0071_EXP_firmware_v_0_50.md` for `FP-EXP-0071_EXP_firmware_v_0_50.txt`), which
+// downloads and imports bring along with the firmware, or else from the GitHub release
+// of the firmware repository that names the board and version.
+
+use crate::firmware_repo::parse_firmware_filename;
+use crate::firmware_source::DEFAULT_GITHUB_REPO;
+use crate::version::Version;
+use serde::Deserialize;
+use std::path::Path;
+use std::time::Duration;
+
+/// Lines of notes shown before the rest is cut off.
+const MAX_LINES: usize = 30;
+/// How long the GitHub lookup may take before the flash goes on without notes.
+const GITHUB_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where release notes were found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseNotes {
+    /// The notes file or release page.
+    pub source: String,
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    #[serde(default)]
+    tag_name: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    html_url: String,
+}
+
+/// Whether `path` is a release notes file: a `.md` file named like a firmware file.
+pub fn is_notes_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("md"))
+        && parse_firmware_filename(path).is_some()
+}
+
+/// The release notes of the firmware file at `firmware_path`: the notes file next to it,
+/// or, unless `offline`, the GitHub release for its board and version.
+pub fn find(firmware_path: &str, offline: bool) -> Option<ReleaseNotes> {
+    let path = Path::new(firmware_path);
+    let notes_path = path.with_extension("md");
+    if let Ok(text) = std::fs::read_to_string(&notes_path)
+        && !text.trim().is_empty()
+    {
+        return Some(ReleaseNotes {
+            source: notes_path.display().to_string(),
+            text,
+        });
+    }
+    if offline {
+        return None;
+    }
+    let (board, _, version) = parse_firmware_filename(path)?;
+    match from_github(DEFAULT_GITHUB_REPO, &board, version) {
+        Ok(notes) => notes,
+        Err(e) => {
+            log::warn!("Could not look up release notes on GitHub: {}", e);
+            None
+        }
+    }
+}
+
+/// The notes of the newest release of `repo` whose tag or name mentions `board` and the
+/// `version` (written `0.50`, `0_50` or `0-50`).
+pub fn from_github(
+    repo: &str,
+    board: &str,
+    version: Version,
+) -> Result<Option<ReleaseNotes>, String> {
+    let client = crate::http::builder()?
+        .timeout(GITHUB_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let url = format!(
+        "https://api.github.com/repos/{}/releases?per_page=100",
+        repo
+    );
+    let resp = crate::http::get(&client, &url)
+        .send()
+        .map_err(|e| crate::http::describe_error(&e))?;
+    if let Some(e) = crate::http::rate_limit_error(&resp) {
+        return Err(e);
+    }
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !resp.status().is_success() {
+        return Err(format!("HTTP error: {}", resp.status()));
+    }
+    let text = resp.text().map_err(|e| e.to_string())?;
+    let releases: Vec<Release> =
+        serde_json::from_str(&text).map_err(|e| format!("unexpected response: {}", e))?;
+
+    let board = board.to_ascii_lowercase();
+    let versions = [
+        version.to_string(),
+        format!("{}_{:02}", version.major, version.minor),
+        format!("{}-{:02}", version.major, version.minor),
+    ];
+    // GitHub lists the newest release first
+    Ok(releases.into_iter().find_map(|release| {
+        let title = format!("{} {}", release.tag_name, release.name.unwrap_or_default())
+            .to_ascii_lowercase();
+        if !title.contains(&board) || !versions.iter().any(|v| title.contains(v.as_str())) {
+            return None;
+        }
+        let text = release.body.filter(|b| !b.trim().is_empty())?;
+        Some(ReleaseNotes {
+            source: release.html_url,
+            text,
+        })
+    }))
+}
+
+/// Print the release notes of `firmware_path` (see `find`; offline as configured), cut off
+/// after `MAX_LINES` lines, or say that there are none. `what` names the firmware, e.g.
+/// `FP-EXP-0071 0.50`.
+pub fn show(what: &str, firmware_path: &str) {
+    let Some(notes) = find(firmware_path, crate::config::get().offline) else {
+        status!("No release notes found for {}.", what);
+        return;
+    };
+    status!("Release notes for {} ({}):", what, notes.source);
+    let lines: Vec<&str> = notes.text.trim_end().lines().collect();
+    for line in lines.iter().take(MAX_LINES) {
+        status!("  {}", line);
+    }
+    if lines.len() > MAX_LINES {
+        status!(
+            "  ... {} more line(s) in {}",
+            lines.len() - MAX_LINES,
+            notes.source
+        );
+    }
+    status!();
+}
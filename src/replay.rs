@@ -0,0 +1,201 @@
+// Replay of a serial capture (`--capture`, or `record`): the traffic of each port is
+// turned into a `MockTransport` that answers every command written with the reply the
+// device gave when it was recorded, in the recorded order. Running the recorded command
+// against those transports goes through the same parsing as with the hardware, so a
+// parsing problem seen on a machine can be reproduced, and turned into a test, without it.
+//
+// Besides the traffic, a capture notes the command line and the port of each role:
+//
+//     # command: ["--offline","list-exp"]
+//     # port net /dev/ttyACM0
+//     # port exp /dev/ttyACM1
+//         0.001234 /dev/ttyACM0 TX "ID:\r"
+//         0.003456 /dev/ttyACM0 RX "ID:NET FP-CPU-2000 02.13\r"
+
+use crate::fast_monitor::FastPinballMonitor;
+use crate::protocol::aud_protocol::AudProtocol;
+use crate::protocol::dsp_protocol::DspProtocol;
+use crate::protocol::exp_protocol::ExpProtocol;
+use crate::protocol::mock::MockTransport;
+use crate::protocol::net_protocol::NetProtocol;
+use crate::protocol::seg_protocol::SegProtocol;
+use std::path::Path;
+
+const COMMAND_NOTE: &str = "command: ";
+const PORT_NOTE: &str = "port ";
+
+/// A capture file read back.
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    /// The command line, without the program name.
+    pub args: Vec<String>,
+    /// (role, port) of each port connected: `net`, `exp`, `seg`, `aud` or `dsp`.
+    pub ports: Vec<(String, String)>,
+    /// (port, written, data) in recorded order.
+    pub traffic: Vec<(String, bool, String)>,
+}
+
+/// Note the command line in the active capture.
+pub fn note_command(args: &[String]) {
+    crate::capture::note(&format!(
+        "{}{}",
+        COMMAND_NOTE,
+        serde_json::to_string(args).unwrap_or_default()
+    ));
+}
+
+/// Note the port of each role in the active capture.
+pub fn note_ports(fpm: &FastPinballMonitor) {
+    let mut ports = vec![("net", fpm.net.port_name.as_str())];
+    ports.extend(fpm.exp_ports.iter().map(|e| ("exp", e.port_name.as_str())));
+    ports.extend(fpm.seg.iter().map(|p| ("seg", p.port_name.as_str())));
+    ports.extend(fpm.aud.iter().map(|p| ("aud", p.port_name.as_str())));
+    ports.extend(fpm.dsp.iter().map(|p| ("dsp", p.port_name.as_str())));
+    for (role, port) in ports {
+        crate::capture::note(&format!("{}{} {}", PORT_NOTE, role, port));
+    }
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut recording = Recording::default();
+        for (i, line) in text.lines().enumerate() {
+            let invalid = || format!("{}:{}: not a capture line", path.display(), i + 1);
+            if let Some(note) = line.strip_prefix("# ") {
+                if let Some(args) = note.strip_prefix(COMMAND_NOTE) {
+                    recording.args = serde_json::from_str(args).map_err(|_| invalid())?;
+                } else if let Some((role, port)) =
+                    note.strip_prefix(PORT_NOTE).and_then(|p| p.split_once(' '))
+                {
+                    recording.ports.push((role.to_string(), port.to_string()));
+                }
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            // <seconds> <port> <TX|RX> <quoted data>
+            let mut fields = line.trim_start().splitn(4, ' ');
+            let (Some(_), Some(port), Some(direction), Some(data)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            let written = match direction {
+                "TX" => true,
+                "RX" => false,
+                _ => return Err(invalid()),
+            };
+            let data = unquote(data).ok_or_else(invalid)?;
+            recording.traffic.push((port.to_string(), written, data));
+        }
+        if recording.ports.iter().all(|(role, _)| role != "net") {
+            return Err(format!(
+                "{} does not say which port was NET; it was not captured by a command that connects to the hardware",
+                path.display()
+            ));
+        }
+        Ok(recording)
+    }
+
+    /// A transport answering each command written to `port` like the device did.
+    pub fn transport(&self, port: &str) -> MockTransport {
+        let mut mock = MockTransport::new();
+        // Commands written, each with the reply that followed it
+        let mut exchanges: Vec<(String, String)> = Vec::new();
+        let mut partial = String::new();
+        for (p, written, data) in self.traffic.iter() {
+            if p != port {
+                continue;
+            }
+            if !*written {
+                match exchanges.last_mut() {
+                    Some((_, reply)) => reply.push_str(data),
+                    // Sent before anything was asked
+                    None => mock.push_incoming(data),
+                }
+                continue;
+            }
+            partial.push_str(data);
+            while let Some(end) = partial.find('\r') {
+                let command: String = partial.drain(..=end).collect();
+                let command = command.trim_end_matches('\r');
+                if !command.is_empty() {
+                    exchanges.push((command.to_string(), String::new()));
+                }
+            }
+        }
+        for (command, reply) in exchanges {
+            mock = mock.respond(&command, &reply);
+        }
+        mock
+    }
+
+    /// The hardware as recorded, and the transport of each port, e.g. to check afterwards
+    /// which replies were never asked for.
+    pub fn monitor(&self) -> (FastPinballMonitor, Vec<(String, MockTransport)>) {
+        let mut transports: Vec<(String, MockTransport)> = Vec::new();
+        let mut open = |port: &str| {
+            let mock = self.transport(port);
+            transports.push((port.to_string(), mock.clone()));
+            Box::new(mock)
+        };
+        let port_of = |role: &str| -> Vec<String> {
+            self.ports
+                .iter()
+                .filter(|(r, _)| r == role)
+                .map(|(_, port)| port.clone())
+                .collect()
+        };
+        let net_port = port_of("net").into_iter().next().unwrap_or_default();
+        let net = NetProtocol::with_transport(net_port.clone(), open(&net_port));
+        let exp_ports: Vec<ExpProtocol> = port_of("exp")
+            .into_iter()
+            .map(|port| ExpProtocol::with_transport(port.clone(), open(&port)))
+            .collect();
+        let seg = port_of("seg")
+            .into_iter()
+            .next()
+            .map(|port| SegProtocol::with_transport(port.clone(), open(&port)));
+        let aud = port_of("aud")
+            .into_iter()
+            .next()
+            .map(|port| AudProtocol::with_transport(port.clone(), open(&port)));
+        let dsp = port_of("dsp")
+            .into_iter()
+            .next()
+            .map(|port| DspProtocol::with_transport(port.clone(), open(&port)));
+        let fpm = FastPinballMonitor::with_protocols(net, exp_ports, seg, aud, dsp);
+        (fpm, transports)
+    }
+}
+
+// The data of a capture line, a string written with `{:?}`
+fn unquote(quoted: &str) -> Option<String> {
+    let inner = quoted.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next()? {
+            'r' => '\r',
+            'n' => '\n',
+            't' => '\t',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '\'' => '\'',
+            'u' => {
+                let code: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+                char::from_u32(u32::from_str_radix(&code, 16).ok()?)?
+            }
+            _ => return None,
+        });
+    }
+    Some(out)
+}
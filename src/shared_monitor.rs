@@ -0,0 +1,231 @@
+// A handle on the hardware for several threads at once, e.g. a server answering status
+// queries while a flash runs. Every port sits behind a lock of its own, so a flash over
+// one EXP interface holds up only that interface; NET, the other EXP interfaces and the
+// SEG, AUD and DSP ports stay free. Clones of the handle share the ports:
+//
+//     let shared = SharedMonitor::new(FastPinballMonitor::connect().unwrap());
+//     let flasher = shared.clone();
+//     std::thread::spawn(move || {
+//         if let Some(mut exp) = flasher.exp_port_for_address("B4") {
+//             exp.update_firmware("B4", "0.50");
+//         }
+//     });
+//     println!("{:?}", shared.list_connected_net_boards());
+//
+// Give the monitor its `CancelToken` (`set_cancel_token`) before sharing it: setting one
+// afterwards would have to wait for every port.
+
+use crate::fast_monitor::{
+    ExpBoardInfo, FastPinballMonitor, NetBoardInfo, ProcessorInfo, answers_at, list_net_boards,
+    scan_exp_port,
+};
+use crate::protocol::aud_protocol::AudProtocol;
+use crate::protocol::dsp_protocol::DspProtocol;
+use crate::protocol::exp_protocol::ExpProtocol;
+use crate::protocol::net_events::Subscriber;
+use crate::protocol::net_protocol::NetProtocol;
+use crate::protocol::responses::NetResponse;
+use crate::protocol::seg_protocol::SegProtocol;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError, mpsc};
+
+/// A `FastPinballMonitor` that can be used from several threads at once; see above.
+#[derive(Clone)]
+pub struct SharedMonitor {
+    ports: Arc<Ports>,
+}
+
+struct Ports {
+    net: Mutex<NetProtocol>,
+    // Names apart from the ports, so finding a port does not wait for the others
+    exp: Vec<(String, Mutex<ExpProtocol>)>,
+    seg: Option<Mutex<SegProtocol>>,
+    aud: Option<Mutex<AudProtocol>>,
+    dsp: Option<Mutex<DspProtocol>>,
+    // Boards each EXP interface had at its last scan, by port name
+    exp_boards: Mutex<HashMap<String, Vec<ExpBoardInfo>>>,
+}
+
+impl SharedMonitor {
+    pub fn new(fpm: FastPinballMonitor) -> Self {
+        let FastPinballMonitor {
+            net,
+            exp_ports,
+            seg,
+            aud,
+            dsp,
+            cancel: _,
+        } = fpm;
+        Self {
+            ports: Arc::new(Ports {
+                net: Mutex::new(net),
+                exp: exp_ports
+                    .into_iter()
+                    .map(|exp| (exp.port_name.clone(), Mutex::new(exp)))
+                    .collect(),
+                seg: seg.map(Mutex::new),
+                aud: aud.map(Mutex::new),
+                dsp: dsp.map(Mutex::new),
+                exp_boards: Mutex::default(),
+            }),
+        }
+    }
+
+    /// The NET port, once no other thread uses it.
+    pub fn net(&self) -> MutexGuard<'_, NetProtocol> {
+        lock(&self.ports.net)
+    }
+
+    /// Names of the EXP interfaces, in the monitor's order.
+    pub fn exp_port_names(&self) -> Vec<&str> {
+        self.ports
+            .exp
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// The EXP interface opened on `port_name`, once no other thread uses it.
+    pub fn exp_port(&self, port_name: &str) -> Option<MutexGuard<'_, ExpProtocol>> {
+        self.ports
+            .exp
+            .iter()
+            .find(|(name, _)| name == port_name)
+            .map(|(_, exp)| lock(exp))
+    }
+
+    /// The EXP interface the board at `address_hex` is behind: the one it was found on
+    /// by the last listing, or else the first that answers an `ID@{address}:` query.
+    /// Waits for interfaces other threads use.
+    pub fn exp_port_for_address(&self, address_hex: &str) -> Option<MutexGuard<'_, ExpProtocol>> {
+        let known = lock(&self.ports.exp_boards)
+            .iter()
+            .find(|(_, boards)| boards.iter().any(|b| b.address == address_hex))
+            .map(|(port, _)| port.clone());
+        if let Some(exp) = known.and_then(|port| self.exp_port(&port)) {
+            return Some(exp);
+        }
+        self.ports.exp.iter().find_map(|(_, exp)| {
+            let mut exp = lock(exp);
+            answers_at(&mut exp, address_hex).then_some(exp)
+        })
+    }
+
+    pub fn seg(&self) -> Option<MutexGuard<'_, SegProtocol>> {
+        self.ports.seg.as_ref().map(lock)
+    }
+
+    pub fn aud(&self) -> Option<MutexGuard<'_, AudProtocol>> {
+        self.ports.aud.as_ref().map(lock)
+    }
+
+    pub fn dsp(&self) -> Option<MutexGuard<'_, DspProtocol>> {
+        self.ports.dsp.as_ref().map(lock)
+    }
+
+    /// Like `FastPinballMonitor::list_connected_exp_boards`, but an EXP interface that
+    /// another thread uses, e.g. for a flash, is not waited for: its boards are those of
+    /// its last scan.
+    pub fn list_connected_exp_boards(&self) -> Vec<ExpBoardInfo> {
+        let scans: Vec<(String, Option<Vec<ExpBoardInfo>>)> = std::thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .ports
+                .exp
+                .iter()
+                .map(|(name, exp)| {
+                    scope.spawn(move || {
+                        let boards = try_lock(exp).map(|mut exp| scan_exp_port(&mut exp));
+                        (name.clone(), boards)
+                    })
+                })
+                .collect();
+            handles.into_iter().filter_map(|h| h.join().ok()).collect()
+        });
+        let mut known = lock(&self.ports.exp_boards);
+        let mut boards = Vec::new();
+        for (port, scanned) in scans {
+            match scanned {
+                Some(scanned) => {
+                    known.insert(port, scanned.clone());
+                    boards.extend(scanned);
+                }
+                None => {
+                    log::debug!("EXP {} is busy; listing the boards of its last scan", port);
+                    boards.extend(known.get(&port).cloned().unwrap_or_default());
+                }
+            }
+        }
+        crate::metrics::set_board_count("exp", boards.len());
+        boards
+    }
+
+    /// See `FastPinballMonitor::list_connected_net_boards`.
+    pub fn list_connected_net_boards(&self) -> HashMap<usize, NetBoardInfo> {
+        list_net_boards(&mut self.net())
+    }
+
+    /// ID and firmware of the segment display processor, if one is connected.
+    pub fn seg_info(&self) -> Option<ProcessorInfo> {
+        let mut seg = self.seg()?;
+        let (board_name, version) = seg.id()?;
+        Some(ProcessorInfo {
+            port: seg.port_name.clone(),
+            board_name,
+            version,
+        })
+    }
+
+    /// ID and firmware of the audio interface, if one is connected.
+    pub fn aud_info(&self) -> Option<ProcessorInfo> {
+        let mut aud = self.aud()?;
+        let (board_name, version) = aud.id()?;
+        Some(ProcessorInfo {
+            port: aud.port_name.clone(),
+            board_name,
+            version,
+        })
+    }
+
+    /// ID and firmware of the display controller, if one is connected.
+    pub fn dsp_info(&self) -> Option<ProcessorInfo> {
+        let mut dsp = self.dsp()?;
+        let (board_name, version) = dsp.id()?;
+        Some(ProcessorInfo {
+            port: dsp.port_name.clone(),
+            board_name,
+            version,
+        })
+    }
+
+    /// See `FastPinballMonitor::subscribe_net_events`.
+    pub fn subscribe_net_events(&self) -> Result<mpsc::Receiver<NetResponse>, String> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribe(Subscriber::Channel(tx))?;
+        Ok(rx)
+    }
+
+    /// See `FastPinballMonitor::on_net_event`.
+    pub fn on_net_event(
+        &self,
+        callback: impl FnMut(&NetResponse) + Send + 'static,
+    ) -> Result<(), String> {
+        self.subscribe(Subscriber::Callback(Box::new(callback)))
+    }
+
+    fn subscribe(&self, subscriber: Subscriber) -> Result<(), String> {
+        self.net().subscribe(subscriber)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// The guard, or `None` while another thread holds the lock
+fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
@@ -0,0 +1,93 @@
+// Firmware versions. FAST firmware is versioned `major.minor` with a two-digit minor
+// (`0.48`, `2.28`). Versions compare by number, so `1.10` is newer than `1.9` and `10.00`
+// newer than `9.99`; comparing the strings gets both wrong.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A firmware version, ordered oldest to newest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl Version {
+    pub fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+}
+
+/// Parses `0.48`, `v0.48` or `1.5` (the same as `1.05`); anything trailing the minor
+/// that is not a digit (e.g. `0.48-beta`) is ignored.
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = || {
+            let v = s.trim().trim_start_matches(|c: char| !c.is_ascii_digit());
+            let (major, minor) = v.split_once('.')?;
+            let minor = minor.trim_end_matches(|c: char| !c.is_ascii_digit());
+            Some(Version::new(major.parse().ok()?, minor.parse().ok()?))
+        };
+        parse().ok_or_else(|| format!("'{}' is not a version (e.g., 0.48)", s))
+    }
+}
+
+/// The stored form, with a two-digit minor: `1.05`.
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:02}", self.major, self.minor)
+    }
+}
+
+/// Sort version strings newest first; strings that are not versions go last.
+pub fn sort_newest_first(versions: &mut [String]) {
+    versions.sort_by_key(|v| std::cmp::Reverse(v.parse::<Version>().ok()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_prefixed_and_padded_versions() {
+        assert_eq!(v("0.48"), Version::new(0, 48));
+        assert_eq!(v("v0.48"), Version::new(0, 48));
+        assert_eq!(v(" 0.48-beta "), Version::new(0, 48));
+        assert_eq!(v("02.28"), v("2.28"));
+    }
+
+    #[test]
+    fn single_digit_minor_is_the_two_digit_one() {
+        assert_eq!(v("1.5"), v("1.05"));
+        assert_eq!(v("1.5").to_string(), "1.05");
+        // So `0.5` is 0.05, older than 0.48
+        assert!(v("0.5") < v("0.48"));
+    }
+
+    #[test]
+    fn compares_by_number() {
+        assert!(v("1.10") > v("1.09"));
+        assert!(v("10.00") > v("9.99"));
+        assert!(v("2.28") < v("2.30"));
+    }
+
+    #[test]
+    fn refuses_malformed_versions() {
+        for s in ["", "abc", "1", "1.", ".5", "1.x", "1.2.3", "v"] {
+            assert!(s.parse::<Version>().is_err(), "{:?} parsed", s);
+        }
+    }
+
+    #[test]
+    fn sorts_newest_first_with_others_last() {
+        let mut versions: Vec<String> = ["0.9", "junk", "0.48", "1.00"].map(String::from).into();
+        sort_newest_first(&mut versions);
+        assert_eq!(versions, ["1.00", "0.48", "0.9", "junk"]);
+    }
+}
@@ -0,0 +1,71 @@
+// `record` a command against the emulator, then `replay` it without the emulator, as
+// someone sending in a capture from their machine would have it replayed.
+
+use fast_pinball_utilities::emulator::{Emulator, EmulatorConfig};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Command, Output};
+
+// Two addresses on localhost nothing listens on right now
+fn free_addresses() -> (String, String) {
+    let first = TcpListener::bind("127.0.0.1:0").unwrap();
+    let second = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = |l: &TcpListener| l.local_addr().unwrap().to_string();
+    (address(&first), address(&second))
+}
+
+fn run(home: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fast-pinball-utilities"))
+        .args(args)
+        .env("HOME", home)
+        .env_remove("FAST_FIRMWARE_DIR")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn recorded_command_replays_without_hardware() {
+    let (net, exp) = free_addresses();
+    {
+        let (net, exp) = (net.clone(), exp.clone());
+        std::thread::spawn(move || {
+            Emulator::new(EmulatorConfig::default())
+                .serve_tcp(&net, &exp)
+                .unwrap()
+        });
+    }
+    let home = std::env::temp_dir().join(format!(
+        "fast-pinball-test-{}-record-replay",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&home).unwrap();
+    let capture = home.join("capture.txt");
+    let capture = capture.to_str().unwrap();
+    let net_port = format!("tcp://{}", net);
+    let exp_port = format!("tcp://{}", exp);
+
+    let recorded = run(
+        &home,
+        &[
+            "--net-port",
+            &net_port,
+            "--exp-port",
+            &exp_port,
+            "record",
+            capture,
+            "--offline",
+            "--format",
+            "json",
+            "list",
+        ],
+    );
+    assert!(recorded.status.success(), "{:?}", recorded);
+    let replayed = run(&home, &["replay", capture]);
+    std::fs::remove_dir_all(&home).unwrap();
+
+    assert!(replayed.status.success(), "{:?}", replayed);
+    let recorded = String::from_utf8_lossy(&recorded.stdout);
+    assert!(recorded.contains("FP-EXP-0071") && recorded.contains("FP-I/O-3208"));
+    assert_eq!(String::from_utf8_lossy(&replayed.stdout), recorded);
+    assert!(!String::from_utf8_lossy(&replayed.stderr).contains("never asked for"));
+}